@@ -2,7 +2,7 @@ use core::{cmp, ffi::c_int};
 
 use bitflags::bitflags;
 
-use crate::path::{Path, PathBuf};
+use crate::path::{FileName, FileNameBuf, Path, PathBuf, PathError};
 
 bitflags! {
     /// Definition of file open flags which can be mixed and matched as appropriate. These definitions
@@ -79,6 +79,63 @@ impl Metadata {
     }
 }
 
+/// Parameters of a mounted filesystem, see
+/// [`DynFilesystem::fs_info`](crate::DynFilesystem::fs_info).
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct FsInfo {
+    /// Size of a block in bytes.
+    pub block_size: usize,
+    /// Number of blocks.
+    pub block_count: usize,
+    /// Maximum length of a file name in bytes.
+    pub name_max: usize,
+    /// Maximum size of a file in bytes.
+    pub file_max: usize,
+    /// Maximum size of an attribute in bytes.
+    pub attr_max: usize,
+}
+
+/// Options for writing a chunk of a file, see
+/// [`DynFilesystem::write_chunk_with_options`](crate::DynFilesystem::write_chunk_with_options).
+///
+/// By default, the file must exist and the chunk must start within the file or directly at its
+/// end.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct WriteChunkOptions {
+    create: bool,
+    extend: bool,
+}
+
+impl WriteChunkOptions {
+    pub const fn new() -> Self {
+        Self {
+            create: false,
+            extend: false,
+        }
+    }
+
+    /// Create the file if it does not exist.
+    pub const fn create(mut self, create: bool) -> Self {
+        self.create = create;
+        self
+    }
+
+    /// Fill the file with zeros up to the start of the chunk if the chunk starts after the end of
+    /// the file.
+    pub const fn extend(mut self, extend: bool) -> Self {
+        self.extend = extend;
+        self
+    }
+
+    pub const fn is_create(&self) -> bool {
+        self.create
+    }
+
+    pub const fn is_extend(&self) -> bool {
+        self.extend
+    }
+}
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 /// Custom user attribute that can be set on files and directories.
 ///
@@ -110,21 +167,49 @@ impl<'a> Attribute<'a> {
     }
 }
 
+/// Position within a directory iteration, see
+/// [`DynFilesystem::read_dir_from_and_then`](crate::DynFilesystem#method.read_dir_from_and_then).
+///
+/// The position is only valid for the directory it was obtained from, and only as long as the
+/// directory is not modified.  The default value is the start of the directory.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DirIterationTell(u32);
+
+impl DirIterationTell {
+    /// Creates a position from its raw value, for example after receiving it from a client.
+    pub const fn from_raw(tell: u32) -> Self {
+        Self(tell)
+    }
+
+    /// Returns the raw value of the position.
+    pub const fn into_raw(self) -> u32 {
+        self.0
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DirEntry {
-    file_name: PathBuf,
+    file_name: FileNameBuf,
     metadata: Metadata,
     path: PathBuf,
 }
 
 impl DirEntry {
-    pub fn new(file_name: PathBuf, metadata: Metadata, path: PathBuf) -> Self {
-        Self {
-            file_name,
+    /// Creates a new directory entry.
+    ///
+    /// Returns an error if `file_name` is not a valid [`FileName`][].
+    pub fn new(
+        file_name: PathBuf,
+        metadata: Metadata,
+        path: PathBuf,
+    ) -> core::result::Result<Self, PathError> {
+        Ok(Self {
+            file_name: file_name.try_into()?,
             metadata,
             path,
-        }
+        })
     }
 
     // Returns the metadata for the file that this entry points at.
@@ -138,7 +223,7 @@ impl DirEntry {
     }
 
     // Returns the bare file name of this directory entry without any other leading path component.
-    pub fn file_name(&self) -> &Path {
+    pub fn file_name(&self) -> &FileName {
         &self.file_name
     }
 
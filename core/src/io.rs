@@ -54,6 +54,31 @@ pub trait Write {
     }
 }
 
+/// Copies all remaining data from `reader` to `writer`, returning the number of copied bytes.
+///
+/// The data is copied in chunks through `buf`, so the memory usage does not depend on the amount
+/// of data.  This also works for files on different filesystems, for example a
+/// [`DynFile`](crate::DynFile) on an internal and one on an external flash.  Fails with
+/// [`Error::INVALID`][] if `buf` is empty.
+pub fn copy<R: Read + ?Sized, W: Write + ?Sized>(
+    reader: &R,
+    writer: &W,
+    buf: &mut [u8],
+) -> Result<u64> {
+    if buf.is_empty() {
+        return Err(Error::INVALID);
+    }
+    let mut copied = 0;
+    loop {
+        let n = reader.read(buf)?;
+        if n == 0 {
+            return Ok(copied);
+        }
+        writer.write_all(&buf[..n])?;
+        copied += n as u64;
+    }
+}
+
 /** Enumeration of possible methods to seek within an I/O object.
 
 Use the [`Seek`](../io/trait.Seek.html) trait.
@@ -115,7 +140,15 @@ pub type Result<T> = core::result::Result<T, Error>;
 
 /// The error type for filesystem operations.
 ///
-/// Specific error codes are available as associated constants of this type.
+/// Specific error codes are available as associated constants of this type.  The codes of the
+/// errors returned by littlefs are the `LFS_ERR_*` codes of the C library, so [`Error::new`][]
+/// and [`Error::code`][] convert from and to littlefs error codes without a lookup table.  Codes
+/// without an associated constant are preserved, too.
+///
+/// [`Error::READ_ONLY`][], [`Error::PATH_REJECTED`][], [`Error::PATH_PROTECTED`][] and
+/// [`Error::UNKNOWN`][] are never returned by littlefs itself.  They are produced by this crate
+/// for checks that littlefs does not perform and for return values that cannot be converted, and
+/// their codes do not collide with the `LFS_ERR_*` codes.
 ///
 /// ```
 /// # use littlefs2_core::Error;
@@ -170,6 +203,24 @@ impl Error {
     /// Filename too long
     pub const FILENAME_TOO_LONG: Self = Self::new_const(-36);
 
+    /// Write operation on a filesystem that has been switched to read-only mode.
+    pub const READ_ONLY: Self = Self::new_const(-30);
+
+    /// Modification of a path that is not allowed by the path validator of the filesystem.
+    pub const PATH_REJECTED: Self = Self::new_const(-13);
+
+    /// Modification of a protected path without privilege.
+    pub const PATH_PROTECTED: Self = Self::new_const(-1);
+
+    /// Return value of littlefs that cannot be represented, for example a size that does not fit
+    /// into a `usize`.
+    ///
+    /// ```
+    /// # use littlefs2_core::Error;
+    /// assert_eq!(Error::new(core::ffi::c_int::MIN), Some(Error::UNKNOWN));
+    /// ```
+    pub const UNKNOWN: Self = Self::new_const(c_int::MIN);
+
     /// Construct an `Error` from an error code.
     ///
     /// Return values that are greater or equals to zero represent success.  In this case, `None`
@@ -11,10 +11,18 @@ mod io;
 mod object_safe;
 mod path;
 
-pub use fs::{Attribute, DirEntry, FileOpenFlags, FileType, Metadata};
-pub use io::{Error, OpenSeekFrom, Read, Result, Seek, SeekFrom, Write};
-pub use object_safe::{DirEntriesCallback, DynFile, DynFilesystem, FileCallback, Predicate, Vec};
-pub use path::{Ancestors, Iter, Path, PathBuf, PathError};
+pub use fs::{
+    Attribute, DirEntry, DirIterationTell, FileOpenFlags, FileType, FsInfo, Metadata,
+    WriteChunkOptions,
+};
+pub use io::{copy, Error, OpenSeekFrom, Read, Result, Seek, SeekFrom, Write};
+pub use object_safe::{
+    DirEntriesCallback, DirEntriesTellCallback, DynFile, DynFilesystem, FileCallback, Predicate,
+    Vec,
+};
+#[cfg(feature = "serde")]
+pub use path::serde_str;
+pub use path::{Ancestors, FileName, FileNameBuf, Iter, Path, PathBuf, PathError};
 
 /// Creates a path from a string without a trailing null.
 ///
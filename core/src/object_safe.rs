@@ -1,7 +1,9 @@
 use crate::{
-    fs::{Attribute, DirEntry, FileOpenFlags, Metadata},
-    io::{Error, OpenSeekFrom, Read, Result, Seek, Write},
-    path::Path,
+    fs::{
+        Attribute, DirEntry, DirIterationTell, FileOpenFlags, FsInfo, Metadata, WriteChunkOptions,
+    },
+    io::{Error, OpenSeekFrom, Read, Result, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
 };
 
 // Make sure that the traits actually are object safe.
@@ -10,6 +12,8 @@ const _: Option<&dyn DynFilesystem> = None;
 
 pub type DirEntriesCallback<'a, R = ()> =
     &'a mut dyn FnMut(&mut dyn Iterator<Item = Result<DirEntry>>) -> Result<R>;
+pub type DirEntriesTellCallback<'a, R = ()> =
+    &'a mut dyn FnMut(&mut dyn Iterator<Item = Result<(DirEntry, DirIterationTell)>>) -> Result<R>;
 pub type FileCallback<'a, R = ()> = &'a mut dyn FnMut(&dyn DynFile) -> Result<R>;
 pub type Predicate<'a> = &'a dyn Fn(&DirEntry) -> bool;
 
@@ -75,6 +79,16 @@ pub trait DynFile: Read + Seek + Write {
     fn len(&self) -> Result<usize>;
     fn is_empty(&self) -> Result<bool>;
     fn set_len(&self, size: usize) -> Result<()>;
+
+    /// Returns the current position in the file.
+    fn tell(&self) -> Result<usize> {
+        self.seek(SeekFrom::Current(0))
+    }
+
+    /// Moves the position to the start of the file.
+    fn rewind(&self) -> Result<()> {
+        self.seek(SeekFrom::Start(0)).map(drop)
+    }
 }
 
 impl dyn DynFile + '_ {
@@ -94,22 +108,45 @@ impl dyn DynFile + '_ {
 /// - [`DynFilesystem::open_file_and_then_unit`][]
 /// - [`DynFilesystem::open_file_with_flags_and_then_unit`][]
 /// - [`DynFilesystem::read_dir_and_then_unit`][]
+/// - [`DynFilesystem::read_dir_from_and_then_unit`][]
+/// - [`DynFilesystem::walk_dir_and_then_unit`][]
 ///
 /// Use these helper functions instead:
 /// - [`DynFilesystem::create_file_and_then`](#method.create_file_and_then)
 /// - [`DynFilesystem::open_file_and_then`](#method.open_file_and_then)
 /// - [`DynFilesystem::open_file_with_flags_and_then`](#method.open_file_with_flags_and_then)
 /// - [`DynFilesystem::read_dir_and_then`](#method.read_dir_and_then)
+/// - [`DynFilesystem::read_dir_from_and_then`](#method.read_dir_from_and_then)
+/// - [`DynFilesystem::walk_dir_and_then`](#method.walk_dir_and_then)
 pub trait DynFilesystem {
     fn total_blocks(&self) -> usize;
     fn total_space(&self) -> usize;
     fn available_blocks(&self) -> Result<usize>;
     fn available_space(&self) -> Result<usize>;
+    fn fs_info(&self) -> FsInfo;
     fn remove(&self, path: &Path) -> Result<()>;
     fn remove_dir(&self, path: &Path) -> Result<()>;
     fn remove_dir_all(&self, path: &Path) -> Result<()>;
     fn remove_dir_all_where(&self, path: &Path, predicate: Predicate<'_>) -> Result<usize>;
+    fn remove_dir_all_with_progress(
+        &self,
+        path: &Path,
+        predicate: Predicate<'_>,
+        on_removed: &mut dyn FnMut(&Path),
+    ) -> Result<usize>;
     fn rename(&self, from: &Path, to: &Path) -> Result<()>;
+
+    /// Renames `from` to `to`, failing with [`Error::ENTRY_ALREADY_EXISTED`][] if `to` exists.
+    fn rename_noreplace(&self, from: &Path, to: &Path) -> Result<()> {
+        self.metadata(from)?;
+        if self.exists(to) {
+            return Err(Error::ENTRY_ALREADY_EXISTED);
+        }
+        self.rename(from, to)
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> Result<usize>;
+    fn copy_dir_all(&self, from: &Path, to: &Path) -> Result<()>;
     fn exists(&self, path: &Path) -> bool;
     fn metadata(&self, path: &Path) -> Result<Metadata>;
     fn create_file_and_then_unit(&self, path: &Path, f: FileCallback<'_>) -> Result<()>;
@@ -128,11 +165,44 @@ pub trait DynFilesystem {
     ) -> Result<Option<Attribute<'a>>>;
     fn remove_attribute(&self, path: &Path, id: u8) -> Result<()>;
     fn set_attribute(&self, path: &Path, id: u8, data: &[u8]) -> Result<()>;
+
+    /// Calls `f` with the ID and the data of every attribute of `path`, in the order of the IDs.
+    fn attributes(&self, path: &Path, f: &mut dyn FnMut(u8, &[u8]) -> Result<()>) -> Result<()> {
+        let mut buffer = [0; Attribute::MAX_SIZE as _];
+        for id in 0..=u8::MAX {
+            if let Some(attribute) = self.attribute(path, id, &mut buffer)? {
+                f(id, attribute.data())?;
+            }
+        }
+        Ok(())
+    }
+
     fn read_dir_and_then_unit(&self, path: &Path, f: DirEntriesCallback<'_>) -> Result<()>;
+    fn read_dir_from_and_then_unit(
+        &self,
+        path: &Path,
+        tell: DirIterationTell,
+        f: DirEntriesTellCallback<'_>,
+    ) -> Result<()>;
+    fn walk_dir_and_then_unit(&self, path: &Path, f: DirEntriesCallback<'_>) -> Result<()>;
     fn create_dir(&self, path: &Path) -> Result<()>;
     fn create_dir_all(&self, path: &Path) -> Result<()>;
     fn write(&self, path: &Path, contents: &[u8]) -> Result<()>;
+
+    /// Appends `contents` to the file at `path`, creating it if it does not exist.
+    fn append(&self, path: &Path, contents: &[u8]) -> Result<()> {
+        let flags = FileOpenFlags::WRITE | FileOpenFlags::CREATE | FileOpenFlags::APPEND;
+        self.open_file_with_flags_and_then_unit(flags, path, &mut |file| file.write_all(contents))
+    }
+
     fn write_chunk(&self, path: &Path, contents: &[u8], pos: OpenSeekFrom) -> Result<()>;
+    fn write_chunk_with_options(
+        &self,
+        path: &Path,
+        contents: &[u8],
+        pos: OpenSeekFrom,
+        options: WriteChunkOptions,
+    ) -> Result<()>;
 }
 
 impl dyn DynFilesystem + '_ {
@@ -157,6 +227,67 @@ impl dyn DynFilesystem + '_ {
         Ok((contents, file_len))
     }
 
+    /// Copies the contents of the file `from` on this filesystem to the file `to` on `target`,
+    /// replacing an existing file, and returns the number of copied bytes.
+    ///
+    /// The data is streamed through a small buffer on the stack, see [`copy`](crate::copy).
+    /// Attributes are not copied.
+    pub fn copy_to(&self, target: &dyn DynFilesystem, from: &Path, to: &Path) -> Result<u64> {
+        let mut buf = [0; 256];
+        self.open_file_and_then(from, &mut |from| {
+            target.create_file_and_then(to, &mut |to| crate::io::copy(from, to, &mut buf))
+        })
+    }
+
+    /// Reads the attribute `id` of `path` into a new buffer, or returns `None` if it is not set.
+    ///
+    /// If the attribute is larger than the capacity of the buffer, only its beginning is read,
+    /// like [`DynFilesystem::read`](#method.read) for files.
+    pub fn read_attribute<V: Vec>(&self, path: &Path, id: u8) -> Result<Option<V>> {
+        let mut value = V::default();
+        value.resize_to_capacity();
+        let len = match self.attribute(path, id, value.as_mut())? {
+            Some(attribute) => attribute.data().len(),
+            None => return Ok(None),
+        };
+        value.truncate(len);
+        Ok(Some(value))
+    }
+
+    /// Calls `f` for every attribute of `path` and collects the returned values.
+    pub fn collect_attributes<C, T>(
+        &self,
+        path: &Path,
+        mut f: impl FnMut(u8, &[u8]) -> Result<T>,
+    ) -> Result<C>
+    where
+        C: Default + Extend<T>,
+    {
+        let mut collection = C::default();
+        self.attributes(path, &mut |id, data| {
+            collection.extend(Some(f(id, data)?));
+            Ok(())
+        })?;
+        Ok(collection)
+    }
+
+    /// Like [`DynFilesystem::remove_dir_all_where`][], but returns the paths of all removed files
+    /// and directories instead of the number of removed files.
+    pub fn remove_dir_all_where_collect<C>(
+        &self,
+        path: &Path,
+        predicate: Predicate<'_>,
+    ) -> Result<C>
+    where
+        C: Default + Extend<PathBuf>,
+    {
+        let mut removed = C::default();
+        self.remove_dir_all_with_progress(path, predicate, &mut |path| {
+            removed.extend(Some(PathBuf::from(path)));
+        })?;
+        Ok(removed)
+    }
+
     pub fn create_file_and_then<R>(&self, path: &Path, f: FileCallback<'_, R>) -> Result<R> {
         let mut result = Err(Error::IO);
         self.create_file_and_then_unit(path, &mut |file| {
@@ -197,4 +328,32 @@ impl dyn DynFilesystem + '_ {
         })?;
         result
     }
+
+    /// Calls `f` with an iterator over the entries of the directory at `path`, starting at `tell`.
+    ///
+    /// Every entry is returned together with the position after it, so that a later call can
+    /// continue the iteration after that entry, for example to list a large directory in pages.
+    /// The iteration starts with the `.` and `..` entries if `tell` is the default value.
+    pub fn read_dir_from_and_then<R>(
+        &self,
+        path: &Path,
+        tell: DirIterationTell,
+        f: DirEntriesTellCallback<'_, R>,
+    ) -> Result<R> {
+        let mut result = Err(Error::IO);
+        self.read_dir_from_and_then_unit(path, tell, &mut |entries| {
+            result = Ok(f(entries)?);
+            Ok(())
+        })?;
+        result
+    }
+
+    pub fn walk_dir_and_then<R>(&self, path: &Path, f: DirEntriesCallback<'_, R>) -> Result<R> {
+        let mut result = Err(Error::IO);
+        self.walk_dir_and_then_unit(path, &mut |entries| {
+            result = Ok(f(entries)?);
+            Ok(())
+        })?;
+        result
+    }
 }
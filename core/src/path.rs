@@ -80,6 +80,191 @@ impl Path {
     }
 }
 
+/// A file name, i. e. a single component of a [`Path`][].
+///
+/// File names must not contain a `/`.  The length of a file name is not checked here: littlefs
+/// rejects names that are longer than the `FILENAME_MAX` of the storage with
+/// `Error::FILENAME_TOO_LONG` when the path is passed to the filesystem.
+// Invariants:
+// 1. the invariants of Path
+// 2. !inner.as_str().contains('/')
+#[derive(PartialEq, Eq)]
+#[repr(transparent)]
+pub struct FileName {
+    inner: Path,
+}
+
+impl FileName {
+    /// The maximum length of a file name, matching `LFS_NAME_MAX`.
+    pub const MAX_SIZE: usize = 255;
+    pub const MAX_SIZE_PLUS_ONE: usize = Self::MAX_SIZE + 1;
+
+    /// Creates a file name from a path.
+    ///
+    /// Returns an error if the path contains a `/`.
+    ///
+    /// ```
+    ///# use littlefs2_core::{path, FileName};
+    /// assert!(FileName::new(path!("file.extension")).is_ok());
+    /// assert!(FileName::new(path!("some/file.extension")).is_err());
+    /// ```
+    pub fn new(path: &Path) -> Result<&Self> {
+        if path.as_str().contains('/') {
+            return Err(PathError::NotFileName);
+        }
+        Ok(unsafe { Self::new_unchecked(path) })
+    }
+
+    /// # Safety
+    /// `path` must satisfy the invariants of `FileName`.
+    unsafe fn new_unchecked(path: &Path) -> &Self {
+        // SAFETY: FileName is a transparent wrapper around Path
+        &*(path as *const Path as *const FileName)
+    }
+
+    pub const fn as_path(&self) -> &Path {
+        &self.inner
+    }
+}
+
+impl ops::Deref for FileName {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.inner
+    }
+}
+
+impl AsRef<Path> for FileName {
+    fn as_ref(&self) -> &Path {
+        &self.inner
+    }
+}
+
+impl AsRef<str> for FileName {
+    fn as_ref(&self) -> &str {
+        self.inner.as_str()
+    }
+}
+
+impl PartialEq<Path> for FileName {
+    fn eq(&self, other: &Path) -> bool {
+        self.inner == *other
+    }
+}
+
+impl PartialEq<str> for FileName {
+    fn eq(&self, other: &str) -> bool {
+        self.inner.as_str() == other
+    }
+}
+
+impl fmt::Debug for FileName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        <Path as fmt::Debug>::fmt(&self.inner, f)
+    }
+}
+
+impl fmt::Display for FileName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        <Path as fmt::Display>::fmt(&self.inner, f)
+    }
+}
+
+/// An owned, validated [`FileName`][].
+///
+/// ```
+///# use littlefs2_core::{path, FileNameBuf, PathBuf};
+/// let name = FileNameBuf::new(path!("file.extension")).unwrap();
+/// assert_eq!(name.as_str(), "file.extension");
+/// assert!(FileNameBuf::try_from(PathBuf::from(path!("some/file.extension"))).is_err());
+/// ```
+// Invariants:
+// 1. the invariants of FileName for inner
+#[derive(Clone, PartialEq, Eq)]
+pub struct FileNameBuf {
+    inner: PathBuf,
+}
+
+impl FileNameBuf {
+    /// Creates an owned file name from a path.
+    ///
+    /// Returns an error if the path is not a valid [`FileName`][].
+    pub fn new(path: &Path) -> Result<Self> {
+        FileName::new(path).map(From::from)
+    }
+
+    pub fn as_file_name(&self) -> &FileName {
+        // SAFETY: inner is validated when the FileNameBuf is created
+        unsafe { FileName::new_unchecked(&self.inner) }
+    }
+}
+
+impl From<&FileName> for FileNameBuf {
+    fn from(file_name: &FileName) -> Self {
+        Self {
+            inner: file_name.as_path().into(),
+        }
+    }
+}
+
+impl TryFrom<PathBuf> for FileNameBuf {
+    type Error = PathError;
+
+    fn try_from(path: PathBuf) -> Result<Self> {
+        FileName::new(&path)?;
+        Ok(Self { inner: path })
+    }
+}
+
+impl From<FileNameBuf> for PathBuf {
+    fn from(file_name: FileNameBuf) -> Self {
+        file_name.inner
+    }
+}
+
+impl ops::Deref for FileNameBuf {
+    type Target = FileName;
+
+    fn deref(&self) -> &FileName {
+        self.as_file_name()
+    }
+}
+
+impl fmt::Debug for FileNameBuf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        <FileName as fmt::Debug>::fmt(self, f)
+    }
+}
+
+impl fmt::Display for FileNameBuf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        <FileName as fmt::Display>::fmt(self, f)
+    }
+}
+
+/// Serializes the file name like a [`PathBuf`][].
+#[cfg(feature = "serde")]
+impl serde::Serialize for FileNameBuf {
+    fn serialize<S>(&self, serializer: S) -> core::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        self.inner.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for FileNameBuf {
+    fn deserialize<D>(deserializer: D) -> core::result::Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let path = PathBuf::deserialize(deserializer)?;
+        Self::try_from(path).map_err(|_| serde::de::Error::custom("invalid file name"))
+    }
+}
+
 /// Iterator over the ancestors of a Path
 ///
 /// See documentation for [`Path::ancestors`][]
@@ -120,6 +305,80 @@ impl<'a> Iterator for Ancestors<'a> {
 
 impl<'a> FusedIterator for Ancestors<'a> {}
 
+/// Matches a single path component against a glob pattern without `/`.
+fn glob_component(pattern: &[u8], name: &[u8]) -> bool {
+    let (mut p, mut n) = (0, 0);
+    // position after the last `*` and the number of characters that it has matched
+    let mut star = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                star = Some((p + 1, n));
+                p += 1;
+                continue;
+            }
+            Some(b'?') => {
+                p += 1;
+                n += 1;
+                continue;
+            }
+            Some(b'[') if glob_class(&pattern[p..], name[n]).is_some() => {
+                let (matched, len) = glob_class(&pattern[p..], name[n]).unwrap();
+                if matched {
+                    p += len;
+                    n += 1;
+                    continue;
+                }
+            }
+            Some(&c) if c == name[n] => {
+                p += 1;
+                n += 1;
+                continue;
+            }
+            _ => {}
+        }
+        let Some((star_p, star_n)) = &mut star else {
+            return false;
+        };
+        // let the `*` match one more character
+        *star_n += 1;
+        p = *star_p;
+        n = *star_n;
+    }
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+/// Matches a character against a character class at the start of `pattern`.
+///
+/// Returns whether the character matches and the length of the class, or `None` if the class is
+/// not terminated.  In that case, the `[` is matched literally.
+fn glob_class(pattern: &[u8], c: u8) -> Option<(bool, usize)> {
+    let mut i = 1;
+    let negate = matches!(pattern.get(i), Some(b'!' | b'^'));
+    if negate {
+        i += 1;
+    }
+    let mut matched = false;
+    let mut first = true;
+    loop {
+        let start = *pattern.get(i)?;
+        if start == b']' && !first {
+            return Some((matched != negate, i + 1));
+        }
+        first = false;
+        match (pattern.get(i + 1), pattern.get(i + 2)) {
+            (Some(b'-'), Some(&end)) if end != b']' => {
+                matched |= (start..=end).contains(&c);
+                i += 3;
+            }
+            _ => {
+                matched |= start == c;
+                i += 1;
+            }
+        }
+    }
+}
+
 /// Iterator over the components of a Path
 ///
 /// See documentation for [`Path::iter`][]
@@ -214,6 +473,134 @@ impl Path {
         }
     }
 
+    /// Returns the absolute path that littlefs resolves this path to.
+    ///
+    /// littlefs treats relative paths as relative to the root directory, ignores repeated
+    /// separators and `.` components, and skips components that are followed by a matching
+    /// `..` component.  This method applies the same rules, so that two paths refer to the same
+    /// entry if and only if their normalized paths are equal.  The normalized path starts with a
+    /// `/` and has no trailing `/`.
+    ///
+    /// Returns [`PathError::TooLarge`][] if the normalized path is longer than
+    /// [`PathBuf::MAX_SIZE`][], which can only happen for relative paths.
+    ///
+    /// ```
+    ///# use littlefs2_core::path;
+    /// assert_eq!(path!("a//b/").normalize().unwrap().as_str(), "/a/b");
+    /// assert_eq!(path!("/a/./b").normalize().unwrap().as_str(), "/a/b");
+    /// assert_eq!(path!("/x/../a/b").normalize().unwrap().as_str(), "/a/b");
+    /// assert_eq!(path!("/../a").normalize().unwrap().as_str(), "/a");
+    /// assert_eq!(path!("").normalize().unwrap().as_str(), "/");
+    /// ```
+    pub fn normalize(&self) -> Result<PathBuf> {
+        let mut buf = [0; PathBuf::MAX_SIZE];
+        let mut len = 0;
+        let mut name = self.as_str();
+        // this follows lfs_dir_find
+        'names: loop {
+            name = name.trim_start_matches('/');
+            let name_len = name.find('/').unwrap_or(name.len());
+            let component = &name[..name_len];
+            name = &name[name_len..];
+            match component {
+                "" => break,
+                "." | ".." => continue,
+                _ => {}
+            }
+
+            // skip the component if it is matched by a `..` later in the path
+            let mut suffix = name;
+            let mut depth = 1;
+            loop {
+                suffix = suffix.trim_start_matches('/');
+                let suffix_len = suffix.find('/').unwrap_or(suffix.len());
+                match &suffix[..suffix_len] {
+                    "" => break,
+                    ".." => {
+                        depth -= 1;
+                        if depth == 0 {
+                            name = &suffix[suffix_len..];
+                            continue 'names;
+                        }
+                    }
+                    _ => depth += 1,
+                }
+                suffix = &suffix[suffix_len..];
+            }
+
+            let end = len + 1 + component.len();
+            if end > buf.len() {
+                return Err(PathError::TooLarge);
+            }
+            buf[len] = b'/';
+            buf[len + 1..end].copy_from_slice(component.as_bytes());
+            len = end;
+        }
+        if len == 0 {
+            Ok(path!("/").into())
+        } else {
+            PathBuf::try_from(&buf[..len])
+        }
+    }
+
+    /// Check whether the path matches a glob pattern.
+    ///
+    /// The pattern supports these wildcards:
+    /// - `?` matches any character except `/`.
+    /// - `*` matches any sequence of characters except `/`.
+    /// - `[abc]`, `[a-z]` and `[!a-z]` match a character in or not in a set.
+    /// - `**` as a complete component matches any number of components.
+    ///
+    /// If the pattern contains a `/`, it is matched against the components of the entire path,
+    /// ignoring leading, trailing and repeated separators.  Otherwise, it is only matched against
+    /// the last component.  The matcher does not allocate and does not recurse.
+    ///
+    /// ```
+    ///# use littlefs2_core::path;
+    /// assert!(path!("/logs/boot.log").matches("*.log"));
+    /// assert!(path!("/logs/boot.log").matches("/logs/*.log"));
+    /// assert!(path!("/logs/2024/boot.log").matches("/logs/**/*.log"));
+    /// assert!(!path!("/logs/2024/boot.log").matches("/logs/*.log"));
+    /// assert!(path!("/keys/k1").matches("/keys/k[0-9]"));
+    /// ```
+    pub fn matches(&self, pattern: &str) -> bool {
+        let components = |s| str::split(s, '/').filter(|component| !component.is_empty());
+        if !pattern.contains('/') {
+            return components(self.as_str())
+                .next_back()
+                .is_some_and(|name| glob_component(pattern.as_bytes(), name.as_bytes()));
+        }
+
+        let mut patterns = components(pattern);
+        let mut names = components(self.as_str());
+        // position after the last `**` and the names that it has not matched yet
+        let mut star = None;
+        while let Some(name) = names.clone().next() {
+            let mut next = patterns.clone();
+            match next.next() {
+                Some("**") => {
+                    star = Some((next.clone(), names.clone()));
+                    patterns = next;
+                    continue;
+                }
+                Some(pattern) if glob_component(pattern.as_bytes(), name.as_bytes()) => {
+                    patterns = next;
+                    names.next();
+                    continue;
+                }
+                _ => {}
+            }
+            let Some((star_patterns, star_names)) = &mut star else {
+                return false;
+            };
+            // let the `**` match one more component
+            star_names.next();
+            patterns = star_patterns.clone();
+            names = star_names.clone();
+        }
+        patterns.all(|pattern| pattern == "**")
+    }
+
     /// Iterate over the components of the path
     ///
     /// ```
@@ -450,6 +837,10 @@ impl PathBuf {
     }
 
     /// Extends `self` with `path`
+    ///
+    /// # Panics
+    ///
+    /// Panics if the resulting path is longer than [`PathBuf::MAX_SIZE`][].
     pub fn push(&mut self, path: &Path) {
         match path.as_ref() {
             // no-operation
@@ -567,6 +958,10 @@ impl ops::Deref for PathBuf {
     }
 }
 
+/// Serializes the path as bytes.
+///
+/// This representation is compact for binary formats like postcard and will not be changed.  To
+/// serialize a path as a string, use the [`serde_str`](crate::serde_str) module.
 #[cfg(feature = "serde")]
 impl serde::Serialize for PathBuf {
     fn serialize<S>(&self, serializer: S) -> core::result::Result<S::Ok, S::Error>
@@ -603,12 +998,93 @@ impl<'de> serde::Deserialize<'de> for PathBuf {
                 }
                 PathBuf::try_from(v).map_err(|_| E::custom("invalid path buffer"))
             }
+
+            fn visit_str<E>(self, v: &str) -> core::result::Result<Self::Value, E>
+            where
+                E: serde::de::Error,
+            {
+                self.visit_bytes(v.as_bytes())
+            }
         }
 
         deserializer.deserialize_bytes(ValueVisitor(PhantomData))
     }
 }
 
+/// Serialize a [`PathBuf`][] as a string.
+///
+/// By default, a `PathBuf` is serialized as bytes, which is compact for binary formats but
+/// awkward for human-readable formats like JSON.  This module can be used with the
+/// `#[serde(with = "...")]` attribute to serialize a path as a string instead.  Deserialization
+/// validates the path like [`PathBuf::try_from`][].
+///
+/// Both representations are stable: paths serialized as bytes are always serialized as bytes and
+/// paths serialized with this module are always serialized as strings.  The deserializers accept
+/// both representations if the format supports it.
+///
+/// ```
+/// use littlefs2_core::PathBuf;
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Deserialize, Serialize)]
+/// struct Manifest {
+///     #[serde(with = "littlefs2_core::serde_str")]
+///     path: PathBuf,
+/// }
+/// ```
+#[cfg(feature = "serde")]
+pub mod serde_str {
+    use core::fmt;
+
+    use serde::{de, Deserializer, Serializer};
+
+    use super::PathBuf;
+
+    pub fn serialize<S>(path: &PathBuf, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(path.as_str())
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<PathBuf, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct StrVisitor;
+
+        impl de::Visitor<'_> for StrVisitor {
+            type Value = PathBuf;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+                formatter.write_str("a path string")
+            }
+
+            fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                if v.len() > PathBuf::MAX_SIZE {
+                    return Err(E::invalid_length(v.len(), &self));
+                }
+                PathBuf::try_from(v).map_err(|_| E::custom("invalid path"))
+            }
+
+            fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                if v.len() > PathBuf::MAX_SIZE {
+                    return Err(E::invalid_length(v.len(), &self));
+                }
+                PathBuf::try_from(v).map_err(|_| E::custom("invalid path"))
+            }
+        }
+
+        deserializer.deserialize_str(StrVisitor)
+    }
+}
+
 impl fmt::Debug for PathBuf {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         <Path as fmt::Debug>::fmt(self, f)
@@ -659,14 +1135,16 @@ pub enum PathError {
     NotCStr,
     /// Byte buffer is too long (longer than [`PathBuf::MAX_SIZE_PLUS_ONE`][])
     TooLarge,
+    /// Path is not a single file name (contains a `/`)
+    NotFileName,
 }
 
 type Result<T> = core::result::Result<T, PathError>;
 
 #[cfg(test)]
 mod tests {
-    use super::{Path, PathBuf};
-    use crate::path;
+    use super::{FileName, FileNameBuf, Path, PathBuf};
+    use crate::{path, DirEntry, FileType, Metadata};
 
     const EMPTY: &Path = path!("");
     const SLASH: &Path = path!("/");
@@ -691,6 +1169,64 @@ mod tests {
         assert!(Path::from_str_with_nul("über").is_err());
     }
 
+    #[test]
+    fn matches() {
+        let path = path!("/logs/2024/boot.log");
+        assert!(path.matches("*.log"));
+        assert!(path.matches("boot.*"));
+        assert!(path.matches("b??t.log"));
+        assert!(path.matches("*"));
+        assert!(!path.matches("*.txt"));
+        assert!(!path.matches("boot"));
+        assert!(path.matches("/logs/2024/boot.log"));
+        assert!(path.matches("logs/2024/boot.log"));
+        assert!(path.matches("/logs/*/boot.log"));
+        assert!(path.matches("/logs/**/*.log"));
+        assert!(path.matches("/**/boot.log"));
+        assert!(path.matches("/**"));
+        assert!(path.matches("/logs/2024/boot.log/**"));
+        assert!(path.matches("/**/2024/**"));
+        assert!(!path.matches("/logs/*.log"));
+        assert!(!path.matches("/logs/**/2023/*"));
+        assert!(!path.matches("/*/boot.log"));
+
+        assert!(path!("a*b").matches("a[*]b"));
+        assert!(path!("k7").matches("k[0-9]"));
+        assert!(!path!("kx").matches("k[0-9]"));
+        assert!(path!("kx").matches("k[!0-9]"));
+        assert!(path!("a]").matches("a[]]"));
+        assert!(path!("a-").matches("[a-][-]"));
+        assert!(path!("[a").matches("[a"));
+        assert!(path!("aaab").matches("*a*b"));
+        assert!(!path!("aaa").matches("*a*b"));
+        assert!(path!("").matches("/**"));
+        assert!(!path!("/").matches("*"));
+    }
+
+    #[test]
+    fn normalize() {
+        let normalize = |path: &Path| path.normalize().unwrap();
+        assert_eq!(normalize(path!("/")).as_str(), "/");
+        assert_eq!(normalize(path!("a")).as_str(), "/a");
+        assert_eq!(normalize(path!("/a//b///")).as_str(), "/a/b");
+        assert_eq!(normalize(path!("./a/./b/.")).as_str(), "/a/b");
+        assert_eq!(normalize(path!("/a/b/../c")).as_str(), "/a/c");
+        assert_eq!(normalize(path!("/a/b/../../c")).as_str(), "/c");
+        assert_eq!(normalize(path!("/a/../../c")).as_str(), "/c");
+        assert_eq!(normalize(path!("/a/b/..")).as_str(), "/a");
+        // `.` counts as a component when littlefs matches `..`
+        assert_eq!(normalize(path!("/a/./../b")).as_str(), "/a/b");
+
+        let relative = PathBuf::try_from([b'a'; PathBuf::MAX_SIZE].as_slice()).unwrap();
+        assert!(relative.normalize().is_err());
+        assert_eq!(
+            normalize(&PathBuf::try_from([b'a'; PathBuf::MAX_SIZE - 1].as_slice()).unwrap())
+                .as_str()
+                .len(),
+            PathBuf::MAX_SIZE
+        );
+    }
+
     #[test]
     fn join() {
         let empty = Path::from_bytes_with_nul(b"\0").unwrap();
@@ -719,6 +1255,34 @@ mod tests {
         assert_eq!(b.join(b).as_ref(), "b/b");
     }
 
+    #[test]
+    fn file_name_new() {
+        assert_eq!(FileName::new(path!("file")).unwrap(), path!("file"));
+        assert_eq!(FileName::new(path!("..")).unwrap(), "..");
+        assert!(FileName::new(path!("")).is_ok());
+        assert!(FileName::new(path!("/")).is_err());
+        assert!(FileName::new(path!("dir/file")).is_err());
+        assert!(FileName::new(path!("file/")).is_err());
+    }
+
+    #[test]
+    fn file_name_buf() {
+        let name = FileNameBuf::new(path!("file")).unwrap();
+        assert_eq!(*name, *path!("file"));
+        assert_eq!(PathBuf::from(name.clone()).as_str(), "file");
+        assert!(FileNameBuf::try_from(PathBuf::from(path!("dir/file"))).is_err());
+
+        let metadata = Metadata::new(FileType::File, 0);
+        let entry = DirEntry::new(name.into(), metadata.clone(), path!("/file").into()).unwrap();
+        assert_eq!(entry.file_name(), path!("file"));
+        assert!(DirEntry::new(
+            path!("dir/file").into(),
+            metadata,
+            path!("/dir/file").into()
+        )
+        .is_err());
+    }
+
     #[test]
     fn nulls() {
         assert!(Path::from_bytes_with_nul(b"abc\0def").is_err());
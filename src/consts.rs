@@ -5,7 +5,64 @@ pub use generic_array::typenum::consts::*;
 
 pub const PATH_MAX: usize = littlefs2_core::PathBuf::MAX_SIZE;
 pub const PATH_MAX_PLUS_ONE: usize = littlefs2_core::PathBuf::MAX_SIZE_PLUS_ONE;
-pub const FILENAME_MAX_PLUS_ONE: u32 = 255 + 1;
+pub const FILENAME_MAX_PLUS_ONE: u32 = littlefs2_core::FileName::MAX_SIZE_PLUS_ONE as _;
 pub const FILEBYTES_MAX: u32 = crate::ll::LFS_FILE_MAX as _;
 pub const ATTRBYTES_MAX: u32 = littlefs2_core::Attribute::MAX_SIZE;
 pub const LOOKAHEADWORDS_SIZE: u32 = 16;
+
+/// The `LFS_ERR_*` codes of the littlefs backend and the corresponding errors.
+///
+/// [`Error`](crate::io::Error) stores the code returned by littlefs, so
+/// [`Error::new`](crate::io::Error::new) and [`Error::code`](crate::io::Error::code) convert
+/// from and to these codes.  Codes that are not listed here are preserved as they are.
+pub const LFS_ERRORS: [(crate::ll::lfs_error, crate::io::Error); 14] = {
+    use crate::{io::Error, ll};
+    [
+        (ll::lfs_error_LFS_ERR_IO, Error::IO),
+        (ll::lfs_error_LFS_ERR_CORRUPT, Error::CORRUPTION),
+        (ll::lfs_error_LFS_ERR_NOENT, Error::NO_SUCH_ENTRY),
+        (ll::lfs_error_LFS_ERR_EXIST, Error::ENTRY_ALREADY_EXISTED),
+        (ll::lfs_error_LFS_ERR_NOTDIR, Error::PATH_NOT_DIR),
+        (ll::lfs_error_LFS_ERR_ISDIR, Error::PATH_IS_DIR),
+        (ll::lfs_error_LFS_ERR_NOTEMPTY, Error::DIR_NOT_EMPTY),
+        (ll::lfs_error_LFS_ERR_BADF, Error::BAD_FILE_DESCRIPTOR),
+        (ll::lfs_error_LFS_ERR_FBIG, Error::FILE_TOO_BIG),
+        (ll::lfs_error_LFS_ERR_INVAL, Error::INVALID),
+        (ll::lfs_error_LFS_ERR_NOSPC, Error::NO_SPACE),
+        (ll::lfs_error_LFS_ERR_NOMEM, Error::NO_MEMORY),
+        (ll::lfs_error_LFS_ERR_NOATTR, Error::NO_ATTRIBUTE),
+        (ll::lfs_error_LFS_ERR_NAMETOOLONG, Error::FILENAME_TOO_LONG),
+    ]
+};
+
+#[cfg(feature = "buffer-align-64")]
+pub(crate) type BufferAlignment = aligned::A64;
+#[cfg(all(feature = "buffer-align-32", not(feature = "buffer-align-64")))]
+pub(crate) type BufferAlignment = aligned::A32;
+#[cfg(not(any(feature = "buffer-align-32", feature = "buffer-align-64")))]
+pub(crate) type BufferAlignment = aligned::A4;
+
+/// Alignment in bytes of the read, write and file caches and of the lookahead buffer.
+///
+/// This is 4 bytes by default, as required by littlefs.  The `buffer-align-32` and
+/// `buffer-align-64` features increase the alignment, for example for storages that transfer
+/// data with DMA.  [`Storage::write`](crate::driver::Storage::write) is always called with data
+/// from one of the caches.  [`Storage::read`](crate::driver::Storage::read) is called with one of
+/// the caches or, for large reads, directly with the buffer passed to
+/// [`File::read`](crate::fs::File::read).
+pub const BUFFER_ALIGNMENT: usize = core::mem::align_of::<BufferAlignment>();
+/// Maximum depth of nested directories below the starting point of a directory walk, see
+/// [`Filesystem::remove_dir_all`](crate::fs::Filesystem::remove_dir_all).
+pub const DIR_DEPTH_MAX: usize = 32;
+/// Maximum number of conflicting paths for one entry, see
+/// [`Filesystem::move_merge`](crate::fs::Filesystem::move_merge).
+pub const MOVE_MERGE_RENAMES_MAX: usize = 8;
+/// Attribute of the root directory that stores the layout version, see
+/// [`Filesystem::ensure_version`](crate::fs::Filesystem::ensure_version).
+pub const LAYOUT_VERSION_ATTRIBUTE_ID: u8 = 0xff;
+/// Attribute of files that stores their original name, see
+/// [`Filesystem::import_file`](crate::fs::Filesystem::import_file).
+pub const ORIGINAL_NAME_ATTRIBUTE_ID: u8 = 0xfe;
+/// Attribute of files that stores their modification time, see `Filesystem::mtime`.  Requires
+/// the `timestamps` feature.
+pub const MTIME_ATTRIBUTE_ID: u8 = 0xfd;
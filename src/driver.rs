@@ -1,14 +1,24 @@
 //! The `Storage`, `Read`, `Write` and `Seek` driver.
 #![allow(non_camel_case_types)]
 
-use generic_array::ArrayLength;
+use core::{
+    cmp,
+    future::Future,
+    marker::PhantomData,
+    pin::pin,
+    task::{Context, Poll, Waker},
+};
 
-use crate::io::Result;
+use generic_array::{
+    typenum::{consts, Unsigned},
+    ArrayLength, GenericArray,
+};
+
+use crate::io::{Error, Result};
 
 /// Users of this library provide a "storage driver" by implementing this trait.
 ///
-/// The `write` method is assumed to be synchronized to storage immediately.
-/// littlefs provides more flexibility - if required, this could also be exposed.
+/// Writes only need to be durable after [`Storage::sync`][] has been called.
 /// Do note that due to caches, files still must be synched. And unfortunately,
 /// this can't be automatically done in `drop`, since it needs mut refs to both
 /// filesystem and storage.
@@ -51,13 +61,12 @@ pub trait Storage {
     /// Size of the lookahead buffer used by littlefs, measured in multiples of 8 bytes.
     type LOOKAHEAD_SIZE: ArrayLength<u64>;
 
-    ///// Maximum length of a filename plus one. Stored in superblock.
-    ///// Should default to 255+1, but associated type defaults don't exist currently.
-    ///// At most 1_022+1.
-    /////
-    ///// TODO: We can't actually change this - need to pass on as compile flag
-    ///// to the C backend.
-    //type FILENAME_MAX_PLUS_ONE: ArrayLength<u8>;
+    /// Maximum length of a file name in bytes.  Stored in superblock.
+    /// Defaults to and must not be larger than 255.
+    ///
+    /// A smaller value can be used to enforce short file names.  A filesystem cannot be mounted
+    /// with a smaller limit than it has been formatted with.
+    const FILENAME_MAX: usize = 255;
 
     // /// Maximum length of a path plus one. Necessary to convert Rust string slices
     // /// to C strings, which requires an allocation for the terminating
@@ -65,13 +74,11 @@ pub trait Storage {
     // /// Must be larger than `FILENAME_MAX_PLUS_ONE`.
     // type PATH_MAX_PLUS_ONE: ArrayLength<u8>;
 
-    ///// Maximum size of file. Stored in superblock.
-    ///// Defaults to 2_147_483_647 (or u31, to avoid sign issues in the C code).
-    ///// At most 2_147_483_647.
-    /////
-    ///// TODO: We can't actually change this - need to pass on as compile flag
-    ///// to the C backend.
-    //const FILEBYTES_MAX: usize = ll::LFS_FILE_MAX as _;
+    /// Maximum size of a file in bytes.  Stored in superblock.
+    /// Defaults to and must not be larger than 2_147_483_647.
+    ///
+    /// A filesystem cannot be mounted with a smaller limit than it has been formatted with.
+    const FILEBYTES_MAX: u32 = 2_147_483_647;
 
     ///// Maximum size of custom attributes.
     ///// Should default to 1_022, but associated type defaults don't exists currently.
@@ -83,13 +90,1292 @@ pub trait Storage {
 
     /// Read data from the storage device.
     /// Guaranteed to be called only with bufs of length a multiple of READ_SIZE.
+    ///
+    /// `buf` is the target buffer of littlefs, not an intermediate copy: the read cache, a file
+    /// cache or, for large reads that littlefs does not cache, the buffer passed to
+    /// [`File::read`](crate::fs::File::read).  A driver can transfer the data directly into
+    /// `buf`, for example with DMA.  The caches are aligned to
+    /// [`BUFFER_ALIGNMENT`](crate::consts::BUFFER_ALIGNMENT); the buffers passed by the caller
+    /// may have any alignment, so DMA drivers must check the alignment of `buf`.
     fn read(&mut self, off: usize, buf: &mut [u8]) -> Result<usize>;
     /// Write data to the storage device.
     /// Guaranteed to be called only with bufs of length a multiple of WRITE_SIZE.
+    ///
+    /// `data` is always the write cache or a file cache, see
+    /// [`BUFFER_ALIGNMENT`](crate::consts::BUFFER_ALIGNMENT).
     fn write(&mut self, off: usize, data: &[u8]) -> Result<usize>;
     /// Erase data from the storage device.
     /// Guaranteed to be called only with bufs of length a multiple of BLOCK_SIZE.
     fn erase(&mut self, off: usize, len: usize) -> Result<usize>;
-    // /// Synchronize writes to the storage device.
-    // fn sync(&mut self) -> Result<usize>;
+    /// Synchronize writes to the storage device.
+    ///
+    /// littlefs calls this method when it needs previous writes to be durable, for example when
+    /// a file is synced or closed.  Storages that buffer writes, like flash controllers that wait
+    /// for a flush command, must write the buffered data before returning.  The default
+    /// implementation does nothing.
+    fn sync(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<S: Storage> Storage for &mut S {
+    const READ_SIZE: usize = S::READ_SIZE;
+    const WRITE_SIZE: usize = S::WRITE_SIZE;
+    const BLOCK_SIZE: usize = S::BLOCK_SIZE;
+    const BLOCK_COUNT: usize = S::BLOCK_COUNT;
+    const BLOCK_CYCLES: isize = S::BLOCK_CYCLES;
+    const FILENAME_MAX: usize = S::FILENAME_MAX;
+    const FILEBYTES_MAX: u32 = S::FILEBYTES_MAX;
+    type CACHE_SIZE = S::CACHE_SIZE;
+    type LOOKAHEAD_SIZE = S::LOOKAHEAD_SIZE;
+
+    fn read(&mut self, off: usize, buf: &mut [u8]) -> Result<usize> {
+        S::read(self, off, buf)
+    }
+
+    fn write(&mut self, off: usize, data: &[u8]) -> Result<usize> {
+        S::write(self, off, data)
+    }
+
+    fn erase(&mut self, off: usize, len: usize) -> Result<usize> {
+        S::erase(self, off, len)
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        S::sync(self)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<S: Storage> Storage for alloc::boxed::Box<S> {
+    const READ_SIZE: usize = S::READ_SIZE;
+    const WRITE_SIZE: usize = S::WRITE_SIZE;
+    const BLOCK_SIZE: usize = S::BLOCK_SIZE;
+    const BLOCK_COUNT: usize = S::BLOCK_COUNT;
+    const BLOCK_CYCLES: isize = S::BLOCK_CYCLES;
+    const FILENAME_MAX: usize = S::FILENAME_MAX;
+    const FILEBYTES_MAX: u32 = S::FILEBYTES_MAX;
+    type CACHE_SIZE = S::CACHE_SIZE;
+    type LOOKAHEAD_SIZE = S::LOOKAHEAD_SIZE;
+
+    fn read(&mut self, off: usize, buf: &mut [u8]) -> Result<usize> {
+        S::read(self, off, buf)
+    }
+
+    fn write(&mut self, off: usize, data: &[u8]) -> Result<usize> {
+        S::write(self, off, data)
+    }
+
+    fn erase(&mut self, off: usize, len: usize) -> Result<usize> {
+        S::erase(self, off, len)
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        S::sync(self)
+    }
+}
+
+/// Async variant of [`Storage`][] for storage devices with an async driver.
+///
+/// The constants and types have the same meaning as for [`Storage`][].  Use [`BusyPoll`][] to
+/// use an `AsyncStorage` where a [`Storage`][] is required, or the
+/// [`fs_busy_poll`](crate::fs_busy_poll) module for a filesystem API with `async` functions.
+///
+/// # Requirements
+///
+/// littlefs cannot suspend an operation, so the futures returned by this trait are not awaited
+/// by an executor.  [`BusyPoll`][] polls them in a loop with a no-op waker until they complete,
+/// so they must complete after being polled repeatedly without being woken, for example by
+/// checking a hardware status flag that is set by the peripheral or an interrupt handler on
+/// every poll.  Futures that only make progress after their waker has been called, or that wait
+/// for another task of the same executor, never complete: the busy loop spins forever and blocks
+/// the executor.
+pub trait AsyncStorage {
+    const READ_SIZE: usize;
+    const WRITE_SIZE: usize;
+    const BLOCK_SIZE: usize;
+    const BLOCK_COUNT: usize;
+    const BLOCK_CYCLES: isize = -1;
+    const FILENAME_MAX: usize = 255;
+    const FILEBYTES_MAX: u32 = 2_147_483_647;
+    type CACHE_SIZE: ArrayLength<u8>;
+    type LOOKAHEAD_SIZE: ArrayLength<u64>;
+
+    /// Read data from the storage device.
+    /// Guaranteed to be called only with bufs of length a multiple of READ_SIZE.
+    fn read(&mut self, off: usize, buf: &mut [u8]) -> impl Future<Output = Result<usize>>;
+    /// Write data to the storage device.
+    /// Guaranteed to be called only with bufs of length a multiple of WRITE_SIZE.
+    fn write(&mut self, off: usize, data: &[u8]) -> impl Future<Output = Result<usize>>;
+    /// Erase data from the storage device.
+    /// Guaranteed to be called only with bufs of length a multiple of BLOCK_SIZE.
+    fn erase(&mut self, off: usize, len: usize) -> impl Future<Output = Result<usize>>;
+    /// Synchronize writes to the storage device, see [`Storage::sync`][].
+    fn sync(&mut self) -> impl Future<Output = Result<()>> {
+        async { Ok(()) }
+    }
+}
+
+impl<S: AsyncStorage> AsyncStorage for &mut S {
+    const READ_SIZE: usize = S::READ_SIZE;
+    const WRITE_SIZE: usize = S::WRITE_SIZE;
+    const BLOCK_SIZE: usize = S::BLOCK_SIZE;
+    const BLOCK_COUNT: usize = S::BLOCK_COUNT;
+    const BLOCK_CYCLES: isize = S::BLOCK_CYCLES;
+    const FILENAME_MAX: usize = S::FILENAME_MAX;
+    const FILEBYTES_MAX: u32 = S::FILEBYTES_MAX;
+    type CACHE_SIZE = S::CACHE_SIZE;
+    type LOOKAHEAD_SIZE = S::LOOKAHEAD_SIZE;
+
+    fn read(&mut self, off: usize, buf: &mut [u8]) -> impl Future<Output = Result<usize>> {
+        S::read(self, off, buf)
+    }
+
+    fn write(&mut self, off: usize, data: &[u8]) -> impl Future<Output = Result<usize>> {
+        S::write(self, off, data)
+    }
+
+    fn erase(&mut self, off: usize, len: usize) -> impl Future<Output = Result<usize>> {
+        S::erase(self, off, len)
+    }
+
+    fn sync(&mut self) -> impl Future<Output = Result<()>> {
+        S::sync(self)
+    }
+}
+
+/// Adapter that implements [`Storage`][] for an [`AsyncStorage`][] by busy polling.
+///
+/// littlefs calls the storage driver synchronously, so the futures returned by the async driver
+/// are polled in a busy loop with a no-op waker until they complete.  The current task never
+/// yields to the executor while it waits for the storage.  See the
+/// [requirements](AsyncStorage#requirements) for the futures of the driver.
+pub struct BusyPoll<S: AsyncStorage> {
+    storage: S,
+}
+
+impl<S: AsyncStorage> BusyPoll<S> {
+    pub fn new(storage: S) -> Self {
+        Self { storage }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.storage
+    }
+
+    pub fn inner_mut(&mut self) -> &mut S {
+        &mut self.storage
+    }
+
+    pub fn into_inner(self) -> S {
+        self.storage
+    }
+}
+
+fn busy_poll<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let mut context = Context::from_waker(Waker::noop());
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+            return output;
+        }
+        core::hint::spin_loop();
+    }
+}
+
+impl<S: AsyncStorage> Storage for BusyPoll<S> {
+    const READ_SIZE: usize = S::READ_SIZE;
+    const WRITE_SIZE: usize = S::WRITE_SIZE;
+    const BLOCK_SIZE: usize = S::BLOCK_SIZE;
+    const BLOCK_COUNT: usize = S::BLOCK_COUNT;
+    const BLOCK_CYCLES: isize = S::BLOCK_CYCLES;
+    const FILENAME_MAX: usize = S::FILENAME_MAX;
+    const FILEBYTES_MAX: u32 = S::FILEBYTES_MAX;
+    type CACHE_SIZE = S::CACHE_SIZE;
+    type LOOKAHEAD_SIZE = S::LOOKAHEAD_SIZE;
+
+    fn read(&mut self, off: usize, buf: &mut [u8]) -> Result<usize> {
+        busy_poll(self.storage.read(off, buf))
+    }
+
+    fn write(&mut self, off: usize, data: &[u8]) -> Result<usize> {
+        busy_poll(self.storage.write(off, data))
+    }
+
+    fn erase(&mut self, off: usize, len: usize) -> Result<usize> {
+        busy_poll(self.storage.erase(off, len))
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        busy_poll(self.storage.sync())
+    }
+}
+
+/// Source of timestamps for features that record the time of an operation.
+///
+/// The unit and epoch of the timestamps are defined by the application, for example seconds since
+/// the Unix epoch.  Install a clock with `Filesystem::set_clock`, which requires the `timestamps`
+/// feature.
+pub trait Clock {
+    /// Returns the current time.
+    fn now(&self) -> u64;
+}
+
+/// Storage wrapper that reads back and compares every write.
+///
+/// If the data read back from the storage does not match the written data, the write fails with
+/// [`Error::CORRUPTION`][].  littlefs then treats the block as bad and relocates its contents, so
+/// marginal flash cells are detected when they are programmed instead of when they are read.
+///
+/// This doubles the amount of data transferred from the storage device for every write.  The
+/// read-back uses a buffer of `CACHE_SIZE` bytes on the stack.
+pub struct Verified<S: Storage> {
+    storage: S,
+}
+
+impl<S: Storage> Verified<S> {
+    pub fn new(storage: S) -> Self {
+        Self { storage }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.storage
+    }
+
+    pub fn inner_mut(&mut self) -> &mut S {
+        &mut self.storage
+    }
+
+    pub fn into_inner(self) -> S {
+        self.storage
+    }
+
+    /// Compare `data` with the contents of the storage at `off`.
+    ///
+    /// Reads are widened to multiples of `READ_SIZE` as writes are only aligned to `WRITE_SIZE`.
+    fn verify(&mut self, off: usize, data: &[u8]) -> Result<()> {
+        let mut buf: GenericArray<u8, S::CACHE_SIZE> = Default::default();
+        let end = off + data.len();
+        let mut pos = off - off % S::READ_SIZE;
+        while pos < end {
+            let remaining = (end - pos).div_ceil(S::READ_SIZE) * S::READ_SIZE;
+            let n = cmp::min(buf.len(), remaining);
+            let read = &mut buf[..n];
+            self.storage.read(pos, read)?;
+
+            let start = cmp::max(pos, off);
+            let stop = cmp::min(pos + n, end);
+            if read[start - pos..stop - pos] != data[start - off..stop - off] {
+                return Err(Error::CORRUPTION);
+            }
+            pos += n;
+        }
+        Ok(())
+    }
+}
+
+impl<S: Storage> Storage for Verified<S> {
+    const READ_SIZE: usize = S::READ_SIZE;
+    const WRITE_SIZE: usize = S::WRITE_SIZE;
+    const BLOCK_SIZE: usize = S::BLOCK_SIZE;
+    const BLOCK_COUNT: usize = S::BLOCK_COUNT;
+    const BLOCK_CYCLES: isize = S::BLOCK_CYCLES;
+    const FILENAME_MAX: usize = S::FILENAME_MAX;
+    const FILEBYTES_MAX: u32 = S::FILEBYTES_MAX;
+    type CACHE_SIZE = S::CACHE_SIZE;
+    type LOOKAHEAD_SIZE = S::LOOKAHEAD_SIZE;
+
+    fn read(&mut self, off: usize, buf: &mut [u8]) -> Result<usize> {
+        self.storage.read(off, buf)
+    }
+
+    fn write(&mut self, off: usize, data: &[u8]) -> Result<usize> {
+        let written = self.storage.write(off, data)?;
+        self.verify(off, &data[..written])?;
+        Ok(written)
+    }
+
+    fn erase(&mut self, off: usize, len: usize) -> Result<usize> {
+        self.storage.erase(off, len)
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        self.storage.sync()
+    }
+}
+
+/// Storage wrapper that calls a hook before every read, write and erase.
+///
+/// Long filesystem operations like formatting, removing a large directory tree or compacting
+/// metadata can take seconds and trip a hardware watchdog.  As littlefs accesses the storage
+/// many times during such an operation, the hook can be used to feed the watchdog or to update
+/// a progress indicator.  The hook is called from within littlefs, so it must not access the
+/// filesystem.
+pub struct ProgressHook<S: Storage> {
+    storage: S,
+    hook: fn(),
+}
+
+impl<S: Storage> ProgressHook<S> {
+    pub fn new(storage: S, hook: fn()) -> Self {
+        Self { storage, hook }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.storage
+    }
+
+    pub fn inner_mut(&mut self) -> &mut S {
+        &mut self.storage
+    }
+
+    pub fn into_inner(self) -> S {
+        self.storage
+    }
+}
+
+impl<S: Storage> Storage for ProgressHook<S> {
+    const READ_SIZE: usize = S::READ_SIZE;
+    const WRITE_SIZE: usize = S::WRITE_SIZE;
+    const BLOCK_SIZE: usize = S::BLOCK_SIZE;
+    const BLOCK_COUNT: usize = S::BLOCK_COUNT;
+    const BLOCK_CYCLES: isize = S::BLOCK_CYCLES;
+    const FILENAME_MAX: usize = S::FILENAME_MAX;
+    const FILEBYTES_MAX: u32 = S::FILEBYTES_MAX;
+    type CACHE_SIZE = S::CACHE_SIZE;
+    type LOOKAHEAD_SIZE = S::LOOKAHEAD_SIZE;
+
+    fn read(&mut self, off: usize, buf: &mut [u8]) -> Result<usize> {
+        (self.hook)();
+        self.storage.read(off, buf)
+    }
+
+    fn write(&mut self, off: usize, data: &[u8]) -> Result<usize> {
+        (self.hook)();
+        self.storage.write(off, data)
+    }
+
+    fn erase(&mut self, off: usize, len: usize) -> Result<usize> {
+        (self.hook)();
+        self.storage.erase(off, len)
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        self.storage.sync()
+    }
+}
+
+/// Storage wrapper that retries operations that fail with [`Error::IO`][].
+///
+/// Some storage devices, for example SPI flash under brownout conditions, report transient
+/// errors.  `Retry` repeats a failed read, write or erase up to `retries` times and calls the
+/// optional delay hook with the number of the retry before each attempt, which can be used to
+/// implement a backoff.  Other errors are returned immediately.
+///
+/// To let persistent failures surface promptly, operations are not retried after an operation
+/// has failed despite all retries, until an operation succeeds again.  [`Retry::stats`][]
+/// reports how many retries were necessary.
+pub struct Retry<S: Storage> {
+    storage: S,
+    retries: usize,
+    delay: Option<fn(usize)>,
+    failing: bool,
+    stats: RetryStats,
+}
+
+/// Counters of a [`Retry`][] wrapper.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RetryStats {
+    /// Number of retried attempts.
+    pub retries: usize,
+    /// Number of operations that succeeded after a retry.
+    pub recovered: usize,
+    /// Number of operations that failed with [`Error::IO`][] after all attempts.
+    pub failures: usize,
+}
+
+impl<S: Storage> Retry<S> {
+    pub fn new(storage: S, retries: usize) -> Self {
+        Self {
+            storage,
+            retries,
+            delay: None,
+            failing: false,
+            stats: Default::default(),
+        }
+    }
+
+    /// Sets a hook that is called with the number of the retry, starting at 1, before each
+    /// retry.
+    pub fn with_delay(mut self, delay: fn(usize)) -> Self {
+        self.delay = Some(delay);
+        self
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.storage
+    }
+
+    pub fn inner_mut(&mut self) -> &mut S {
+        &mut self.storage
+    }
+
+    pub fn into_inner(self) -> S {
+        self.storage
+    }
+
+    pub fn stats(&self) -> RetryStats {
+        self.stats
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.stats = Default::default();
+    }
+
+    fn retry<T>(&mut self, mut f: impl FnMut(&mut S) -> Result<T>) -> Result<T> {
+        let retries = if self.failing { 0 } else { self.retries };
+        let mut attempt = 0;
+        loop {
+            match f(&mut self.storage) {
+                Err(Error::IO) if attempt < retries => {
+                    attempt += 1;
+                    self.stats.retries += 1;
+                    if let Some(delay) = self.delay {
+                        delay(attempt);
+                    }
+                }
+                Err(Error::IO) => {
+                    self.failing = true;
+                    self.stats.failures += 1;
+                    return Err(Error::IO);
+                }
+                result => {
+                    if result.is_ok() {
+                        self.failing = false;
+                        if attempt > 0 {
+                            self.stats.recovered += 1;
+                        }
+                    }
+                    return result;
+                }
+            }
+        }
+    }
+}
+
+impl<S: Storage> Storage for Retry<S> {
+    const READ_SIZE: usize = S::READ_SIZE;
+    const WRITE_SIZE: usize = S::WRITE_SIZE;
+    const BLOCK_SIZE: usize = S::BLOCK_SIZE;
+    const BLOCK_COUNT: usize = S::BLOCK_COUNT;
+    const BLOCK_CYCLES: isize = S::BLOCK_CYCLES;
+    const FILENAME_MAX: usize = S::FILENAME_MAX;
+    const FILEBYTES_MAX: u32 = S::FILEBYTES_MAX;
+    type CACHE_SIZE = S::CACHE_SIZE;
+    type LOOKAHEAD_SIZE = S::LOOKAHEAD_SIZE;
+
+    fn read(&mut self, off: usize, buf: &mut [u8]) -> Result<usize> {
+        self.retry(|storage| storage.read(off, buf))
+    }
+
+    fn write(&mut self, off: usize, data: &[u8]) -> Result<usize> {
+        self.retry(|storage| storage.write(off, data))
+    }
+
+    fn erase(&mut self, off: usize, len: usize) -> Result<usize> {
+        self.retry(|storage| storage.erase(off, len))
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        self.retry(|storage| storage.sync())
+    }
+}
+
+/// Storage wrapper that keeps recently read data in RAM.
+///
+/// littlefs has a single read cache that is shared by all operations, so small reads from many
+/// files or directories evict each other's data.  `BlockCache` adds a second cache level below
+/// littlefs that stores up to `N_BLOCKS` cache blocks of `CACHE_SIZE` bytes each and replaces
+/// the least recently used block on a miss.  Writes and erases invalidate the affected blocks.
+///
+/// This is most useful for storages with slow reads, for example SPI flash.  The cache uses
+/// `N_BLOCKS * CACHE_SIZE` bytes of RAM.
+pub struct BlockCache<S: Storage, const N_BLOCKS: usize> {
+    storage: S,
+    blocks: [GenericArray<u8, S::CACHE_SIZE>; N_BLOCKS],
+    // index of the cached block (in units of CACHE_SIZE), if any
+    tags: [Option<usize>; N_BLOCKS],
+    last_used: [u32; N_BLOCKS],
+    counter: u32,
+    hits: usize,
+    misses: usize,
+}
+
+impl<S: Storage, const N_BLOCKS: usize> BlockCache<S, N_BLOCKS> {
+    pub fn new(storage: S) -> Self {
+        Self {
+            storage,
+            blocks: core::array::from_fn(|_| Default::default()),
+            tags: [None; N_BLOCKS],
+            last_used: [0; N_BLOCKS],
+            counter: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.storage
+    }
+
+    /// Returns a mutable reference to the wrapped storage.
+    ///
+    /// Modifications of the storage through this reference are not tracked, so the cache should
+    /// be [`invalidate`](Self::invalidate)d afterwards.
+    pub fn inner_mut(&mut self) -> &mut S {
+        &mut self.storage
+    }
+
+    pub fn into_inner(self) -> S {
+        self.storage
+    }
+
+    /// Discards all cached data.
+    pub fn invalidate(&mut self) {
+        self.tags = [None; N_BLOCKS];
+    }
+
+    /// Returns the number of cache blocks that were served from the cache.
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+
+    /// Returns the number of cache blocks that had to be read from the storage.
+    pub fn misses(&self) -> usize {
+        self.misses
+    }
+
+    /// Returns the slot containing the cache block `tag`, reading it from the storage if
+    /// necessary.
+    fn load(&mut self, tag: usize) -> Result<usize> {
+        self.counter = self.counter.wrapping_add(1);
+        if let Some(slot) = self.tags.iter().position(|t| *t == Some(tag)) {
+            self.hits += 1;
+            self.last_used[slot] = self.counter;
+            return Ok(slot);
+        }
+
+        self.misses += 1;
+        let slot = self
+            .tags
+            .iter()
+            .position(Option::is_none)
+            .or_else(|| {
+                // evict the least recently used block
+                (0..N_BLOCKS).max_by_key(|&slot| self.counter.wrapping_sub(self.last_used[slot]))
+            })
+            .unwrap_or_default();
+        self.tags[slot] = None;
+        let block = &mut self.blocks[slot];
+        self.storage.read(tag * block.len(), block)?;
+        self.tags[slot] = Some(tag);
+        self.last_used[slot] = self.counter;
+        Ok(slot)
+    }
+
+    fn invalidate_range(&mut self, off: usize, len: usize) {
+        let size = S::CACHE_SIZE::USIZE;
+        let range = off / size..(off + len).div_ceil(size);
+        for tag in &mut self.tags {
+            if tag.is_some_and(|tag| range.contains(&tag)) {
+                *tag = None;
+            }
+        }
+    }
+}
+
+impl<S: Storage, const N_BLOCKS: usize> Storage for BlockCache<S, N_BLOCKS> {
+    const READ_SIZE: usize = S::READ_SIZE;
+    const WRITE_SIZE: usize = S::WRITE_SIZE;
+    const BLOCK_SIZE: usize = S::BLOCK_SIZE;
+    const BLOCK_COUNT: usize = S::BLOCK_COUNT;
+    const BLOCK_CYCLES: isize = S::BLOCK_CYCLES;
+    const FILENAME_MAX: usize = S::FILENAME_MAX;
+    const FILEBYTES_MAX: u32 = S::FILEBYTES_MAX;
+    type CACHE_SIZE = S::CACHE_SIZE;
+    type LOOKAHEAD_SIZE = S::LOOKAHEAD_SIZE;
+
+    fn read(&mut self, off: usize, buf: &mut [u8]) -> Result<usize> {
+        if N_BLOCKS == 0 {
+            return self.storage.read(off, buf);
+        }
+        let size = S::CACHE_SIZE::USIZE;
+        let mut pos = 0;
+        while pos < buf.len() {
+            let start = (off + pos) % size;
+            let n = cmp::min(size - start, buf.len() - pos);
+            let slot = self.load((off + pos) / size)?;
+            buf[pos..pos + n].copy_from_slice(&self.blocks[slot][start..start + n]);
+            pos += n;
+        }
+        Ok(buf.len())
+    }
+
+    fn write(&mut self, off: usize, data: &[u8]) -> Result<usize> {
+        self.invalidate_range(off, data.len());
+        self.storage.write(off, data)
+    }
+
+    fn erase(&mut self, off: usize, len: usize) -> Result<usize> {
+        self.invalidate_range(off, len);
+        self.storage.erase(off, len)
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        self.storage.sync()
+    }
+}
+
+/// Storage wrapper that exposes `BLOCK_COUNT` blocks of the wrapped storage, starting at a block
+/// offset.
+///
+/// This allows several filesystems, or a filesystem and a raw data area like a firmware slot, to
+/// share one flash chip.  All offsets are relative to the start of the partition, and accesses
+/// outside of the partition fail with [`Error::INVALID`][] without reaching the wrapped storage.
+/// The partition uses the block size of the wrapped storage.
+///
+/// To use several partitions of the same storage one after another, create them with a mutable
+/// reference to the storage, which implements [`Storage`][] as well.
+pub struct Partition<S: Storage, const BLOCK_COUNT: usize> {
+    storage: S,
+    first_block: usize,
+}
+
+impl<S: Storage, const BLOCK_COUNT: usize> Partition<S, BLOCK_COUNT> {
+    /// Creates a partition that starts at the block `first_block` of `storage`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the partition is empty or does not fit into the wrapped storage.
+    pub fn new(storage: S, first_block: usize) -> Self {
+        assert!(BLOCK_COUNT > 0);
+        assert!(first_block
+            .checked_add(BLOCK_COUNT)
+            .is_some_and(|end| end <= S::BLOCK_COUNT));
+        Self {
+            storage,
+            first_block,
+        }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.storage
+    }
+
+    pub fn inner_mut(&mut self) -> &mut S {
+        &mut self.storage
+    }
+
+    pub fn into_inner(self) -> S {
+        self.storage
+    }
+
+    /// Returns the first block of the partition in the wrapped storage.
+    pub fn first_block(&self) -> usize {
+        self.first_block
+    }
+
+    /// Translates a range of the partition into an offset of the wrapped storage.
+    fn offset(&self, off: usize, len: usize) -> Result<usize> {
+        let size = BLOCK_COUNT * S::BLOCK_SIZE;
+        match off.checked_add(len) {
+            Some(end) if end <= size => Ok(self.first_block * S::BLOCK_SIZE + off),
+            _ => Err(Error::INVALID),
+        }
+    }
+}
+
+impl<S: Storage, const BLOCK_COUNT: usize> Storage for Partition<S, BLOCK_COUNT> {
+    const READ_SIZE: usize = S::READ_SIZE;
+    const WRITE_SIZE: usize = S::WRITE_SIZE;
+    const BLOCK_SIZE: usize = S::BLOCK_SIZE;
+    const BLOCK_COUNT: usize = BLOCK_COUNT;
+    const BLOCK_CYCLES: isize = S::BLOCK_CYCLES;
+    const FILENAME_MAX: usize = S::FILENAME_MAX;
+    const FILEBYTES_MAX: u32 = S::FILEBYTES_MAX;
+    type CACHE_SIZE = S::CACHE_SIZE;
+    type LOOKAHEAD_SIZE = S::LOOKAHEAD_SIZE;
+
+    fn read(&mut self, off: usize, buf: &mut [u8]) -> Result<usize> {
+        let off = self.offset(off, buf.len())?;
+        self.storage.read(off, buf)
+    }
+
+    fn write(&mut self, off: usize, data: &[u8]) -> Result<usize> {
+        let off = self.offset(off, data.len())?;
+        self.storage.write(off, data)
+    }
+
+    fn erase(&mut self, off: usize, len: usize) -> Result<usize> {
+        let off = self.offset(off, len)?;
+        self.storage.erase(off, len)
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        self.storage.sync()
+    }
+}
+
+/// Adapter that implements [`Storage`][] for a NOR flash driver implementing
+/// [`embedded_storage::nor_flash::NorFlash`][].
+///
+/// This requires the `embedded-storage` feature.  The read size, write size and block size are
+/// the `READ_SIZE`, `WRITE_SIZE` and `ERASE_SIZE` of the flash, so littlefs only issues reads
+/// and writes that are aligned as required by the driver and erases whole sectors.  The
+/// filesystem uses the first `BLOCK_COUNT` sectors of the flash; use a [`Partition`][] to place
+/// it elsewhere.
+///
+/// `CacheSize` must be a multiple of the read and write size and a factor of the erase size.
+/// Driver errors are reported as [`Error::INVALID`][] for unaligned or out-of-bounds accesses and
+/// as [`Error::IO`][] otherwise.
+///
+/// ```
+///# use embedded_storage::nor_flash::NorFlash;
+///# use littlefs2::{driver::NorFlashStorage, fs::Filesystem, path};
+///# fn f(flash: impl NorFlash) -> littlefs2::io::Result<()> {
+/// // a flash with 4 KiB sectors, for example 256 KiB at the end of the RP2040 flash
+/// let mut storage = NorFlashStorage::<_, 64>::new(flash);
+/// Filesystem::mount_and_then(&mut storage, |fs| fs.write(path!("boot-count"), &[1]))
+///# }
+/// ```
+#[cfg(feature = "embedded-storage")]
+pub struct NorFlashStorage<
+    T: embedded_storage::nor_flash::NorFlash,
+    const BLOCK_COUNT: usize,
+    CacheSize = consts::U256,
+    LookaheadSize = consts::U1,
+> {
+    flash: T,
+    _sizes: PhantomData<(CacheSize, LookaheadSize)>,
+}
+
+#[cfg(feature = "embedded-storage")]
+impl<T, const BLOCK_COUNT: usize, CacheSize, LookaheadSize>
+    NorFlashStorage<T, BLOCK_COUNT, CacheSize, LookaheadSize>
+where
+    T: embedded_storage::nor_flash::NorFlash,
+{
+    /// Creates a storage for the first `BLOCK_COUNT` sectors of `flash`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the capacity of the flash is less than `BLOCK_COUNT * ERASE_SIZE` bytes.
+    pub fn new(flash: T) -> Self {
+        assert!(BLOCK_COUNT
+            .checked_mul(T::ERASE_SIZE)
+            .is_some_and(|size| size <= flash.capacity()));
+        Self {
+            flash,
+            _sizes: PhantomData,
+        }
+    }
+
+    pub fn inner(&self) -> &T {
+        &self.flash
+    }
+
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.flash
+    }
+
+    pub fn into_inner(self) -> T {
+        self.flash
+    }
+}
+
+#[cfg(feature = "embedded-storage")]
+fn nor_flash_result(
+    result: core::result::Result<(), impl embedded_storage::nor_flash::NorFlashError>,
+    len: usize,
+) -> Result<usize> {
+    use embedded_storage::nor_flash::NorFlashErrorKind;
+
+    match result {
+        Ok(()) => Ok(len),
+        Err(error) => match error.kind() {
+            NorFlashErrorKind::NotAligned | NorFlashErrorKind::OutOfBounds => Err(Error::INVALID),
+            _ => Err(Error::IO),
+        },
+    }
+}
+
+#[cfg(feature = "embedded-storage")]
+impl<T, const BLOCK_COUNT: usize, CacheSize, LookaheadSize> Storage
+    for NorFlashStorage<T, BLOCK_COUNT, CacheSize, LookaheadSize>
+where
+    T: embedded_storage::nor_flash::NorFlash,
+    CacheSize: ArrayLength<u8>,
+    LookaheadSize: ArrayLength<u64>,
+{
+    const READ_SIZE: usize = T::READ_SIZE;
+    const WRITE_SIZE: usize = T::WRITE_SIZE;
+    const BLOCK_SIZE: usize = T::ERASE_SIZE;
+    const BLOCK_COUNT: usize = BLOCK_COUNT;
+    type CACHE_SIZE = CacheSize;
+    type LOOKAHEAD_SIZE = LookaheadSize;
+
+    fn read(&mut self, off: usize, buf: &mut [u8]) -> Result<usize> {
+        let off = u32::try_from(off).map_err(|_| Error::INVALID)?;
+        nor_flash_result(self.flash.read(off, buf), buf.len())
+    }
+
+    fn write(&mut self, off: usize, data: &[u8]) -> Result<usize> {
+        let off = u32::try_from(off).map_err(|_| Error::INVALID)?;
+        nor_flash_result(self.flash.write(off, data), data.len())
+    }
+
+    fn erase(&mut self, off: usize, len: usize) -> Result<usize> {
+        let from = u32::try_from(off).map_err(|_| Error::INVALID)?;
+        let to = off
+            .checked_add(len)
+            .and_then(|to| u32::try_from(to).ok())
+            .ok_or(Error::INVALID)?;
+        nor_flash_result(self.flash.erase(from, to), len)
+    }
+}
+
+/// Adapter that implements [`AsyncStorage`][] for an async NOR flash driver implementing
+/// [`embedded_storage_async::nor_flash::NorFlash`][].
+///
+/// This requires the `embedded-storage-async` feature.  It is the async variant of
+/// [`NorFlashStorage`][] with the same mapping of the sizes and errors, and can be used with the
+/// [`fs_busy_poll`](crate::fs_busy_poll) API if the futures of the flash driver meet the
+/// [requirements](AsyncStorage#requirements) for busy polling.
+///
+/// ```
+///# use embedded_storage_async::nor_flash::NorFlash;
+///# use littlefs2::{driver::AsyncNorFlashStorage, fs_busy_poll::Filesystem, path};
+///# async fn f(flash: impl NorFlash) -> littlefs2::io::Result<()> {
+/// let mut storage = AsyncNorFlashStorage::<_, 64>::new(flash);
+/// Filesystem::mount_and_then(&mut storage, async |fs| {
+///     fs.write(path!("boot-count"), &[1]).await
+/// })
+/// .await
+///# }
+/// ```
+#[cfg(feature = "embedded-storage-async")]
+pub struct AsyncNorFlashStorage<
+    T: embedded_storage_async::nor_flash::NorFlash,
+    const BLOCK_COUNT: usize,
+    CacheSize = consts::U256,
+    LookaheadSize = consts::U1,
+> {
+    flash: T,
+    _sizes: PhantomData<(CacheSize, LookaheadSize)>,
+}
+
+#[cfg(feature = "embedded-storage-async")]
+impl<T, const BLOCK_COUNT: usize, CacheSize, LookaheadSize>
+    AsyncNorFlashStorage<T, BLOCK_COUNT, CacheSize, LookaheadSize>
+where
+    T: embedded_storage_async::nor_flash::NorFlash,
+{
+    /// Creates a storage for the first `BLOCK_COUNT` sectors of `flash`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the capacity of the flash is less than `BLOCK_COUNT * ERASE_SIZE` bytes.
+    pub fn new(flash: T) -> Self {
+        assert!(BLOCK_COUNT
+            .checked_mul(T::ERASE_SIZE)
+            .is_some_and(|size| size <= flash.capacity()));
+        Self {
+            flash,
+            _sizes: PhantomData,
+        }
+    }
+
+    pub fn inner(&self) -> &T {
+        &self.flash
+    }
+
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.flash
+    }
+
+    pub fn into_inner(self) -> T {
+        self.flash
+    }
+}
+
+#[cfg(feature = "embedded-storage-async")]
+impl<T, const BLOCK_COUNT: usize, CacheSize, LookaheadSize> AsyncStorage
+    for AsyncNorFlashStorage<T, BLOCK_COUNT, CacheSize, LookaheadSize>
+where
+    T: embedded_storage_async::nor_flash::NorFlash,
+    CacheSize: ArrayLength<u8>,
+    LookaheadSize: ArrayLength<u64>,
+{
+    const READ_SIZE: usize = T::READ_SIZE;
+    const WRITE_SIZE: usize = T::WRITE_SIZE;
+    const BLOCK_SIZE: usize = T::ERASE_SIZE;
+    const BLOCK_COUNT: usize = BLOCK_COUNT;
+    type CACHE_SIZE = CacheSize;
+    type LOOKAHEAD_SIZE = LookaheadSize;
+
+    async fn read(&mut self, off: usize, buf: &mut [u8]) -> Result<usize> {
+        let off = u32::try_from(off).map_err(|_| Error::INVALID)?;
+        nor_flash_result(self.flash.read(off, buf).await, buf.len())
+    }
+
+    async fn write(&mut self, off: usize, data: &[u8]) -> Result<usize> {
+        let off = u32::try_from(off).map_err(|_| Error::INVALID)?;
+        nor_flash_result(self.flash.write(off, data).await, data.len())
+    }
+
+    async fn erase(&mut self, off: usize, len: usize) -> Result<usize> {
+        let from = u32::try_from(off).map_err(|_| Error::INVALID)?;
+        let to = off
+            .checked_add(len)
+            .and_then(|to| u32::try_from(to).ok())
+            .ok_or(Error::INVALID)?;
+        nor_flash_result(self.flash.erase(from, to).await, len)
+    }
+}
+
+/// Adapter that implements [`Storage`][] for byte-addressable media without erase blocks, for
+/// example FRAM, EEPROM or battery-backed RAM, with a driver implementing
+/// [`embedded_storage::Storage`][].
+///
+/// This requires the `embedded-storage` feature.  The adapter presents the first
+/// `BLOCK_SIZE * BLOCK_COUNT` bytes of the device as `BLOCK_COUNT` blocks with a read and write
+/// size of one byte.  Such media can be overwritten in place, so erasing a block does nothing.
+/// Driver errors are reported as [`Error::IO`][].
+///
+/// The block size is a trade-off: every directory occupies at least two blocks and every file
+/// that does not fit into its directory entry at least one, so small blocks use the space more
+/// efficiently.  On the other hand, littlefs compacts the metadata of a directory whenever its
+/// block is full, which happens more often with small blocks, and the block size must be at least
+/// 128 bytes.  As the media do not wear out like flash, wear leveling should stay disabled, which
+/// is the default `BLOCK_CYCLES` of `-1`.
+///
+/// ```
+///# use littlefs2::{driver::ByteStorage, fs::Filesystem, path};
+///# fn f(fram: impl embedded_storage::Storage) -> littlefs2::io::Result<()> {
+/// // 32 KiB FRAM as 128 blocks of 256 bytes
+/// let mut storage = ByteStorage::<_, 256, 128>::new(fram);
+/// Filesystem::format(&mut storage)?;
+/// Filesystem::mount_and_then(&mut storage, |fs| fs.write(path!("counter"), &[0; 4]))
+///# }
+/// ```
+#[cfg(feature = "embedded-storage")]
+pub struct ByteStorage<
+    S: embedded_storage::Storage,
+    const BLOCK_SIZE: usize,
+    const BLOCK_COUNT: usize,
+    CacheSize = consts::U128,
+    LookaheadSize = consts::U1,
+> {
+    device: S,
+    _sizes: PhantomData<(CacheSize, LookaheadSize)>,
+}
+
+#[cfg(feature = "embedded-storage")]
+impl<S, const BLOCK_SIZE: usize, const BLOCK_COUNT: usize, CacheSize, LookaheadSize>
+    ByteStorage<S, BLOCK_SIZE, BLOCK_COUNT, CacheSize, LookaheadSize>
+where
+    S: embedded_storage::Storage,
+{
+    /// Creates a storage for the first `BLOCK_SIZE * BLOCK_COUNT` bytes of `device`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the capacity of the device is less than `BLOCK_SIZE * BLOCK_COUNT` bytes.
+    pub fn new(device: S) -> Self {
+        assert!(BLOCK_SIZE
+            .checked_mul(BLOCK_COUNT)
+            .is_some_and(|size| size <= device.capacity()));
+        Self {
+            device,
+            _sizes: PhantomData,
+        }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.device
+    }
+
+    pub fn inner_mut(&mut self) -> &mut S {
+        &mut self.device
+    }
+
+    pub fn into_inner(self) -> S {
+        self.device
+    }
+}
+
+#[cfg(feature = "embedded-storage")]
+impl<S, const BLOCK_SIZE: usize, const BLOCK_COUNT: usize, CacheSize, LookaheadSize> Storage
+    for ByteStorage<S, BLOCK_SIZE, BLOCK_COUNT, CacheSize, LookaheadSize>
+where
+    S: embedded_storage::Storage,
+    CacheSize: ArrayLength<u8>,
+    LookaheadSize: ArrayLength<u64>,
+{
+    const READ_SIZE: usize = 1;
+    const WRITE_SIZE: usize = 1;
+    const BLOCK_SIZE: usize = BLOCK_SIZE;
+    const BLOCK_COUNT: usize = BLOCK_COUNT;
+    type CACHE_SIZE = CacheSize;
+    type LOOKAHEAD_SIZE = LookaheadSize;
+
+    fn read(&mut self, off: usize, buf: &mut [u8]) -> Result<usize> {
+        let off = u32::try_from(off).map_err(|_| Error::INVALID)?;
+        self.device.read(off, buf).map_err(|_| Error::IO)?;
+        Ok(buf.len())
+    }
+
+    fn write(&mut self, off: usize, data: &[u8]) -> Result<usize> {
+        let off = u32::try_from(off).map_err(|_| Error::INVALID)?;
+        self.device.write(off, data).map_err(|_| Error::IO)?;
+        Ok(data.len())
+    }
+
+    fn erase(&mut self, _off: usize, len: usize) -> Result<usize> {
+        Ok(len)
+    }
+}
+
+/// Read-only storage backed by a byte slice.
+///
+/// This can be used to bundle a littlefs image with the firmware, for example with
+/// `include_bytes!`, and to read it with the same APIs as a writable filesystem.  The image must
+/// have been created with the block size `BLOCK_SIZE` and contain exactly `BLOCK_COUNT` blocks.
+/// Writes and erases fail with [`Error::READ_ONLY`][].  With the `read-only` feature, the
+/// filesystem can be mounted with `Filesystem::mount_read_only_and_then` to reject modifications
+/// before they reach the storage.
+///
+/// ```
+///# use littlefs2::{driver::RomStorage, fs::Filesystem, path};
+///# fn f(image: &'static [u8]) -> littlefs2::io::Result<()> {
+/// // let image = include_bytes!("assets.lfs");
+/// let mut storage = RomStorage::<'_, 512, 16>::new(image);
+/// Filesystem::mount_and_then(&mut storage, |fs| {
+///     let index: heapless::Vec<u8, 256> = fs.read(path!("/www/index.html"))?;
+///     Ok(())
+/// })
+///# }
+/// ```
+pub struct RomStorage<
+    'a,
+    const BLOCK_SIZE: usize,
+    const BLOCK_COUNT: usize,
+    CacheSize = consts::U128,
+    LookaheadSize = consts::U1,
+> {
+    data: &'a [u8],
+    _sizes: PhantomData<(CacheSize, LookaheadSize)>,
+}
+
+impl<'a, const BLOCK_SIZE: usize, const BLOCK_COUNT: usize, CacheSize, LookaheadSize>
+    RomStorage<'a, BLOCK_SIZE, BLOCK_COUNT, CacheSize, LookaheadSize>
+{
+    /// Creates a storage for the given image.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the length of `data` is not `BLOCK_SIZE * BLOCK_COUNT`.
+    pub const fn new(data: &'a [u8]) -> Self {
+        assert!(data.len() == BLOCK_SIZE * BLOCK_COUNT);
+        Self {
+            data,
+            _sizes: PhantomData,
+        }
+    }
+
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+}
+
+impl<const BLOCK_SIZE: usize, const BLOCK_COUNT: usize, CacheSize, LookaheadSize> Storage
+    for RomStorage<'_, BLOCK_SIZE, BLOCK_COUNT, CacheSize, LookaheadSize>
+where
+    CacheSize: ArrayLength<u8>,
+    LookaheadSize: ArrayLength<u64>,
+{
+    const READ_SIZE: usize = 1;
+    const WRITE_SIZE: usize = 1;
+    const BLOCK_SIZE: usize = BLOCK_SIZE;
+    const BLOCK_COUNT: usize = BLOCK_COUNT;
+    type CACHE_SIZE = CacheSize;
+    type LOOKAHEAD_SIZE = LookaheadSize;
+
+    fn read(&mut self, off: usize, buf: &mut [u8]) -> Result<usize> {
+        let data = self.data.get(off..off + buf.len()).ok_or(Error::INVALID)?;
+        buf.copy_from_slice(data);
+        Ok(buf.len())
+    }
+
+    fn write(&mut self, _off: usize, _data: &[u8]) -> Result<usize> {
+        Err(Error::READ_ONLY)
+    }
+
+    fn erase(&mut self, _off: usize, _len: usize) -> Result<usize> {
+        Err(Error::READ_ONLY)
+    }
+}
+
+/// Storage in RAM, for tests and for temporary filesystems.
+///
+/// The storage holds `BLOCK_COUNT` blocks of `BLOCK_SIZE` bytes inline, so it should be placed in
+/// a `static` or on the heap if it is large.  The read and write sizes default to one byte and
+/// erased bytes have the value `ERASE_VALUE`.  Unlike the [`ram_storage!`](crate::ram_storage)
+/// and [`const_ram_storage!`](crate::const_ram_storage) macros, which are implemented with this
+/// type, it can be used directly and with generic geometries.  Accesses outside of the storage
+/// fail with [`Error::INVALID`][].
+///
+/// ```
+///# use littlefs2::{driver::RamStorage, fs::Filesystem, path};
+/// let mut storage = RamStorage::<256, 64>::new();
+/// Filesystem::format(&mut storage).unwrap();
+/// Filesystem::mount_and_then(&mut storage, |fs| fs.write(path!("file"), b"data")).unwrap();
+/// ```
+pub struct RamStorage<
+    const BLOCK_SIZE: usize,
+    const BLOCK_COUNT: usize,
+    CacheSize = consts::U128,
+    LookaheadSize = consts::U1,
+    const READ_SIZE: usize = 1,
+    const WRITE_SIZE: usize = 1,
+    const ERASE_VALUE: u8 = 0xff,
+> {
+    blocks: [[u8; BLOCK_SIZE]; BLOCK_COUNT],
+    _sizes: PhantomData<(CacheSize, LookaheadSize)>,
+}
+
+impl<
+        const BLOCK_SIZE: usize,
+        const BLOCK_COUNT: usize,
+        CacheSize,
+        LookaheadSize,
+        const READ_SIZE: usize,
+        const WRITE_SIZE: usize,
+        const ERASE_VALUE: u8,
+    >
+    RamStorage<
+        BLOCK_SIZE,
+        BLOCK_COUNT,
+        CacheSize,
+        LookaheadSize,
+        READ_SIZE,
+        WRITE_SIZE,
+        ERASE_VALUE,
+    >
+{
+    /// Creates an erased storage.
+    pub const fn new() -> Self {
+        Self {
+            blocks: [[ERASE_VALUE; BLOCK_SIZE]; BLOCK_COUNT],
+            _sizes: PhantomData,
+        }
+    }
+
+    /// Returns the contents of the storage, for example to save the image of a filesystem.
+    pub fn as_bytes(&self) -> &[u8] {
+        self.blocks.as_flattened()
+    }
+
+    /// Returns the mutable contents of the storage, for example to load or corrupt an image.
+    pub fn as_bytes_mut(&mut self) -> &mut [u8] {
+        self.blocks.as_flattened_mut()
+    }
+}
+
+impl<
+        const BLOCK_SIZE: usize,
+        const BLOCK_COUNT: usize,
+        CacheSize,
+        LookaheadSize,
+        const READ_SIZE: usize,
+        const WRITE_SIZE: usize,
+        const ERASE_VALUE: u8,
+    > Default
+    for RamStorage<
+        BLOCK_SIZE,
+        BLOCK_COUNT,
+        CacheSize,
+        LookaheadSize,
+        READ_SIZE,
+        WRITE_SIZE,
+        ERASE_VALUE,
+    >
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<
+        const BLOCK_SIZE: usize,
+        const BLOCK_COUNT: usize,
+        CacheSize,
+        LookaheadSize,
+        const READ_SIZE: usize,
+        const WRITE_SIZE: usize,
+        const ERASE_VALUE: u8,
+    > Storage
+    for RamStorage<
+        BLOCK_SIZE,
+        BLOCK_COUNT,
+        CacheSize,
+        LookaheadSize,
+        READ_SIZE,
+        WRITE_SIZE,
+        ERASE_VALUE,
+    >
+where
+    CacheSize: ArrayLength<u8>,
+    LookaheadSize: ArrayLength<u64>,
+{
+    const READ_SIZE: usize = READ_SIZE;
+    const WRITE_SIZE: usize = WRITE_SIZE;
+    const BLOCK_SIZE: usize = BLOCK_SIZE;
+    const BLOCK_COUNT: usize = BLOCK_COUNT;
+    type CACHE_SIZE = CacheSize;
+    type LOOKAHEAD_SIZE = LookaheadSize;
+
+    fn read(&mut self, off: usize, buf: &mut [u8]) -> Result<usize> {
+        debug_assert!(off.is_multiple_of(READ_SIZE));
+        debug_assert!(buf.len().is_multiple_of(READ_SIZE));
+        let data = self
+            .as_bytes()
+            .get(off..)
+            .and_then(|data| data.get(..buf.len()))
+            .ok_or(Error::INVALID)?;
+        buf.copy_from_slice(data);
+        Ok(buf.len())
+    }
+
+    fn write(&mut self, off: usize, data: &[u8]) -> Result<usize> {
+        debug_assert!(off.is_multiple_of(WRITE_SIZE));
+        debug_assert!(data.len().is_multiple_of(WRITE_SIZE));
+        self.as_bytes_mut()
+            .get_mut(off..)
+            .and_then(|buf| buf.get_mut(..data.len()))
+            .ok_or(Error::INVALID)?
+            .copy_from_slice(data);
+        Ok(data.len())
+    }
+
+    fn erase(&mut self, off: usize, len: usize) -> Result<usize> {
+        debug_assert!(off.is_multiple_of(BLOCK_SIZE));
+        debug_assert!(len.is_multiple_of(BLOCK_SIZE));
+        self.as_bytes_mut()
+            .get_mut(off..)
+            .and_then(|buf| buf.get_mut(..len))
+            .ok_or(Error::INVALID)?
+            .fill(ERASE_VALUE);
+        Ok(len)
+    }
 }
@@ -0,0 +1,265 @@
+//! Streaming hex and base64 encoding of file contents.
+//!
+//! These utilities export and import files over text-based transports, for example AT commands
+//! or JSON-RPC, with a small fixed-size buffer.  The encoders read from any [`io::Read`][]
+//! implementation, typically a [`File`](crate::fs::File) or a
+//! [`DynFile`](crate::object_safe::DynFile), and the [`Decoder`][] writes to any [`io::Write`][]
+//! implementation.  Data can be passed to the decoder in chunks of any size: the encoding groups
+//! may be split across chunks.
+//!
+//! ```
+//!# use littlefs2::{encoding::{self, Decoder, Encoding}, fs::Filesystem, path, ram_storage, driver, io::Result};
+//!# ram_storage!(tiny);
+//!# let mut ram = Ram::default();
+//!# let mut storage = RamStorage::new(&mut ram);
+//!# Filesystem::format(&mut storage).unwrap();
+//!# Filesystem::mount_and_then(&mut storage, |fs| {
+//! fs.write(path!("key"), b"secret")?;
+//!
+//! let mut text = String::new();
+//! fs.open_file_and_then(path!("key"), |file| {
+//!     encoding::encode(file, Encoding::Base64, &mut text)
+//! })?;
+//! assert_eq!(text, "c2VjcmV0");
+//!
+//! fs.create_file_and_then(path!("copy"), |file| {
+//!     let mut decoder = Decoder::new(file, Encoding::Base64);
+//!     decoder.write_str("c2Vj")?;
+//!     decoder.write_str("cmV0")?;
+//!     decoder.finish()
+//! })?;
+//! assert_eq!(fs.read::<10>(path!("copy"))?, b"secret");
+//!# Ok(())
+//!# }).unwrap();
+//! ```
+
+use core::fmt;
+
+use crate::io::{self, Error, Result};
+
+const HEX: &[u8; 16] = b"0123456789abcdef";
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Size of the input chunks of the encoders.  Must be a multiple of three.
+const CHUNK_SIZE: usize = 48;
+
+/// Returns the two lowercase hex digits of a byte.
+pub(crate) fn hex_byte(byte: u8) -> [u8; 2] {
+    [HEX[usize::from(byte >> 4)], HEX[usize::from(byte & 0xf)]]
+}
+
+/// A text encoding for binary data.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Encoding {
+    /// Lowercase hexadecimal digits.  The decoder also accepts uppercase digits.
+    Hex,
+    /// Base64 with the standard alphabet and padding.  The decoder also accepts missing padding.
+    Base64,
+}
+
+impl Encoding {
+    /// Number of characters of a complete group.
+    fn group_len(self) -> usize {
+        match self {
+            Self::Hex => 2,
+            Self::Base64 => 4,
+        }
+    }
+
+    /// Returns the length of the encoding of `len` bytes.
+    pub const fn encoded_len(self, len: usize) -> usize {
+        match self {
+            Self::Hex => 2 * len,
+            Self::Base64 => len.div_ceil(3) * 4,
+        }
+    }
+
+    fn encode_chunk<'a>(self, data: &[u8], buf: &'a mut [u8; 2 * CHUNK_SIZE]) -> &'a str {
+        let mut n = 0;
+        match self {
+            Self::Hex => {
+                for &byte in data {
+                    buf[n..n + 2].copy_from_slice(&hex_byte(byte));
+                    n += 2;
+                }
+            }
+            Self::Base64 => {
+                for group in data.chunks(3) {
+                    let b = [
+                        group[0],
+                        group.get(1).copied().unwrap_or_default(),
+                        group.get(2).copied().unwrap_or_default(),
+                    ];
+                    buf[n] = BASE64[usize::from(b[0] >> 2)];
+                    buf[n + 1] = BASE64[usize::from((b[0] & 0x03) << 4 | b[1] >> 4)];
+                    buf[n + 2] = if group.len() > 1 {
+                        BASE64[usize::from((b[1] & 0x0f) << 2 | b[2] >> 6)]
+                    } else {
+                        b'='
+                    };
+                    buf[n + 3] = if group.len() > 2 {
+                        BASE64[usize::from(b[2] & 0x3f)]
+                    } else {
+                        b'='
+                    };
+                    n += 4;
+                }
+            }
+        }
+        // the encoding only produces ASCII characters
+        core::str::from_utf8(&buf[..n]).unwrap()
+    }
+
+    fn decode_char(self, c: u8) -> Option<u8> {
+        match (self, c) {
+            (Self::Hex, b'0'..=b'9') => Some(c - b'0'),
+            (Self::Hex, b'a'..=b'f') => Some(c - b'a' + 10),
+            (Self::Hex, b'A'..=b'F') => Some(c - b'A' + 10),
+            (Self::Base64, b'A'..=b'Z') => Some(c - b'A'),
+            (Self::Base64, b'a'..=b'z') => Some(c - b'a' + 26),
+            (Self::Base64, b'0'..=b'9') => Some(c - b'0' + 52),
+            (Self::Base64, b'+') => Some(62),
+            (Self::Base64, b'/') => Some(63),
+            _ => None,
+        }
+    }
+}
+
+/// Reads `reader` until the end and writes its encoding to `out`.
+///
+/// Errors of `out` are returned as [`Error::IO`][].
+pub fn encode<R: io::Read + ?Sized>(
+    reader: &R,
+    encoding: Encoding,
+    out: &mut dyn fmt::Write,
+) -> Result<()> {
+    encode_with(reader, encoding, &mut |s| {
+        out.write_str(s).map_err(|_| Error::IO)
+    })
+}
+
+/// Reads `reader` until the end and writes its encoding to `writer`.
+pub fn encode_to<R: io::Read + ?Sized, W: io::Write + ?Sized>(
+    reader: &R,
+    encoding: Encoding,
+    writer: &W,
+) -> Result<()> {
+    encode_with(reader, encoding, &mut |s| writer.write_all(s.as_bytes()))
+}
+
+fn encode_with<R: io::Read + ?Sized>(
+    reader: &R,
+    encoding: Encoding,
+    f: &mut dyn FnMut(&str) -> Result<()>,
+) -> Result<()> {
+    let mut data = [0; CHUNK_SIZE];
+    let mut text = [0; 2 * CHUNK_SIZE];
+    let mut filled = 0;
+    loop {
+        let n = reader.read(&mut data[filled..])?;
+        filled += n;
+        // encode complete groups and keep the rest for the next read
+        let complete = if n == 0 { filled } else { filled - filled % 3 };
+        if complete > 0 {
+            f(encoding.encode_chunk(&data[..complete], &mut text))?;
+            data.copy_within(complete..filled, 0);
+            filled -= complete;
+        }
+        if n == 0 {
+            return Ok(());
+        }
+    }
+}
+
+/// Decodes text and writes the data to a sink, typically a [`File`](crate::fs::File).
+///
+/// ASCII whitespace is ignored.  Invalid characters, data after base64 padding and incomplete
+/// groups at the end of the input are rejected with [`Error::INVALID`][].  The decoded data is
+/// buffered in chunks, so [`Decoder::finish`][] must be called after the last input.
+pub struct Decoder<'a, W: io::Write + ?Sized> {
+    writer: &'a W,
+    encoding: Encoding,
+    group: [u8; 4],
+    group_len: usize,
+    padding: usize,
+    buf: [u8; CHUNK_SIZE],
+    buf_len: usize,
+}
+
+impl<'a, W: io::Write + ?Sized> Decoder<'a, W> {
+    pub fn new(writer: &'a W, encoding: Encoding) -> Self {
+        Self {
+            writer,
+            encoding,
+            group: [0; 4],
+            group_len: 0,
+            padding: 0,
+            buf: [0; CHUNK_SIZE],
+            buf_len: 0,
+        }
+    }
+
+    /// Decodes a chunk of the input.
+    pub fn write_str(&mut self, s: &str) -> Result<()> {
+        for c in s.bytes() {
+            if c.is_ascii_whitespace() {
+                continue;
+            }
+            if self.encoding == Encoding::Base64 && c == b'=' && self.group_len >= 2 {
+                self.padding += 1;
+                self.group_len += 1;
+            } else if self.padding > 0 {
+                return Err(Error::INVALID);
+            } else {
+                let value = self.encoding.decode_char(c).ok_or(Error::INVALID)?;
+                self.group[self.group_len] = value;
+                self.group_len += 1;
+            }
+            if self.group_len == self.encoding.group_len() {
+                self.decode_group()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn decode_group(&mut self) -> Result<()> {
+        let g = self.group;
+        let data_len = self.group_len - self.padding;
+        let (bytes, len) = match self.encoding {
+            Encoding::Hex => ([g[0] << 4 | g[1], 0, 0], 1),
+            Encoding::Base64 => (
+                [
+                    g[0] << 2 | g[1] >> 4,
+                    g[1] << 4 | g[2] >> 2,
+                    g[2] << 6 | g[3],
+                ],
+                data_len - 1,
+            ),
+        };
+        if self.buf_len + len > self.buf.len() {
+            self.flush()?;
+        }
+        self.buf[self.buf_len..][..len].copy_from_slice(&bytes[..len]);
+        self.buf_len += len;
+        self.group = [0; 4];
+        self.group_len = 0;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.writer.write_all(&self.buf[..self.buf_len])?;
+        self.buf_len = 0;
+        Ok(())
+    }
+
+    /// Decodes the remaining input and writes all buffered data.
+    pub fn finish(mut self) -> Result<()> {
+        match (self.encoding, self.group_len) {
+            (_, 0) => {}
+            // unpadded base64
+            (Encoding::Base64, 2 | 3) if self.padding == 0 => self.decode_group()?,
+            _ => return Err(Error::INVALID),
+        }
+        self.flush()
+    }
+}
@@ -4,7 +4,9 @@ use core::ffi::{c_int, c_void};
 use core::ptr::addr_of;
 use core::ptr::addr_of_mut;
 use core::{
-    cell::{RefCell, UnsafeCell},
+    cell::{Cell, RefCell, UnsafeCell},
+    hash::Hasher,
+    marker::PhantomData,
     mem, slice,
 };
 use generic_array::typenum::marker_traits::Unsigned;
@@ -13,11 +15,15 @@ use littlefs2_sys as ll;
 // so far, don't need `heapless-bytes`.
 pub type Bytes<SIZE> = generic_array::GenericArray<u8, SIZE>;
 
-pub use littlefs2_core::{Attribute, DirEntry, FileOpenFlags, FileType, Metadata};
+pub use littlefs2_core::{
+    Attribute, DirEntry, DirIterationTell, FileOpenFlags, FileType, FsInfo, Metadata,
+    WriteChunkOptions,
+};
 
 use crate::{
     driver,
     io::{self, Error, OpenSeekFrom, Result},
+    object_safe::DynFilesystem,
     path::{Path, PathBuf},
 };
 
@@ -35,27 +41,38 @@ fn result_from<T>(return_value: T, error_code: ll::lfs_error) -> Result<T> {
     }
 }
 
+/// Converts a size or an error code returned by littlefs into a `Result`.
+///
+/// Negative values are returned as an error with the same code, or as [`Error::UNKNOWN`][] if
+/// the code cannot be represented as a `c_int`.
 pub fn u32_result(return_value: i32) -> Result<u32> {
     u32::try_from(return_value).map_err(|_| {
-        let error_code = c_int::try_from(return_value).unwrap_or(c_int::MIN);
-        Error::new(error_code).unwrap()
+        c_int::try_from(return_value)
+            .ok()
+            .and_then(Error::new)
+            .unwrap_or(Error::UNKNOWN)
     })
 }
 
+/// Like [`u32_result`][], but returns [`Error::UNKNOWN`][] if the size does not fit into a
+/// `usize`.
+fn usize_result(return_value: i32) -> Result<usize> {
+    u32_result(return_value).and_then(|value| usize::try_from(value).map_err(|_| Error::UNKNOWN))
+}
+
+/// A buffer passed to littlefs, aligned to [`BUFFER_ALIGNMENT`](crate::consts::BUFFER_ALIGNMENT).
+type Buffer<T> = UnsafeCell<aligned::Aligned<crate::consts::BufferAlignment, T>>;
+
 struct Cache<Storage: driver::Storage> {
-    read: UnsafeCell<Bytes<Storage::CACHE_SIZE>>,
-    write: UnsafeCell<Bytes<Storage::CACHE_SIZE>>,
-    // lookahead: aligned::Aligned<aligned::A4, Bytes<Storage::LOOKAHEAD_SIZE>>,
-    lookahead: UnsafeCell<generic_array::GenericArray<u64, Storage::LOOKAHEAD_SIZE>>,
+    read: Buffer<Bytes<Storage::CACHE_SIZE>>,
+    write: Buffer<Bytes<Storage::CACHE_SIZE>>,
+    lookahead: Buffer<generic_array::GenericArray<u64, Storage::LOOKAHEAD_SIZE>>,
 }
 
 impl<S: driver::Storage> Cache<S> {
-    pub fn new() -> Self {
-        Self {
-            read: Default::default(),
-            write: Default::default(),
-            lookahead: Default::default(),
-        }
+    pub const fn new() -> Self {
+        // SAFETY: the buffers are arrays of integers, for which all zeroes is a valid value
+        unsafe { mem::MaybeUninit::zeroed().assume_init() }
     }
 }
 
@@ -79,7 +96,63 @@ impl<Storage: driver::Storage> Default for Allocation<Storage> {
     }
 }
 impl<Storage: driver::Storage> Allocation<Storage> {
-    pub fn new() -> Allocation<Storage> {
+    /// Check that the configuration given by the [`Storage`](driver::Storage) implementation
+    /// satisfies the constraints of littlefs.
+    ///
+    /// [`Filesystem::format`][] and all functions that mount a filesystem perform this check and
+    /// fail with [`Error::INVALID`][] if the configuration is invalid.  This function returns the
+    /// violated constraint.
+    pub fn validate_config() -> core::result::Result<(), ConfigError> {
+        let read_size = Storage::READ_SIZE;
+        let write_size = Storage::WRITE_SIZE;
+        let block_size = Storage::BLOCK_SIZE;
+        let cache_size = <Storage as driver::Storage>::CACHE_SIZE::USIZE;
+        let lookahead_size = 8 * <Storage as driver::Storage>::LOOKAHEAD_SIZE::USIZE;
+
+        if Storage::BLOCK_CYCLES == 0 || Storage::BLOCK_CYCLES < -1 {
+            return Err(ConfigError::BlockCycles);
+        }
+        if Storage::BLOCK_COUNT == 0 || u32::try_from(Storage::BLOCK_COUNT).is_err() {
+            return Err(ConfigError::BlockCount);
+        }
+        if read_size == 0 {
+            return Err(ConfigError::ReadSize);
+        }
+        if write_size == 0 {
+            return Err(ConfigError::WriteSize);
+        }
+        // https://github.com/littlefs-project/littlefs/issues/264
+        // Technically, 104 is enough.
+        if block_size < 128 || u32::try_from(block_size).is_err() {
+            return Err(ConfigError::BlockSize);
+        }
+        if cache_size == 0 || cache_size % read_size != 0 || cache_size % write_size != 0 {
+            return Err(ConfigError::CacheSize);
+        }
+        if block_size % cache_size != 0 {
+            return Err(ConfigError::BlockSizeNotMultipleOfCacheSize);
+        }
+        if lookahead_size == 0 {
+            return Err(ConfigError::LookaheadSize);
+        }
+        if Storage::FILENAME_MAX == 0 || Storage::FILENAME_MAX > littlefs2_core::FileName::MAX_SIZE
+        {
+            return Err(ConfigError::FilenameMax);
+        }
+        if Storage::FILEBYTES_MAX == 0 || Storage::FILEBYTES_MAX > 2_147_483_647 {
+            return Err(ConfigError::FilebytesMax);
+        }
+        Ok(())
+    }
+
+    fn check_config() -> Result<()> {
+        Self::validate_config().map_err(|_err| {
+            debug_now!("invalid storage configuration: {:?}", _err);
+            Error::INVALID
+        })
+    }
+
+    pub const fn new() -> Allocation<Storage> {
         let read_size: u32 = Storage::READ_SIZE as _;
         let write_size: u32 = Storage::WRITE_SIZE as _;
         let block_size: u32 = Storage::BLOCK_SIZE as _;
@@ -88,45 +161,13 @@ impl<Storage: driver::Storage> Allocation<Storage> {
         let block_cycles: i32 = Storage::BLOCK_CYCLES as _;
         let block_count: u32 = Storage::BLOCK_COUNT as _;
 
-        debug_assert!(block_cycles >= -1);
-        debug_assert!(block_cycles != 0);
-        debug_assert!(block_count > 0);
-
-        debug_assert!(read_size > 0);
-        debug_assert!(write_size > 0);
-        // https://github.com/littlefs-project/littlefs/issues/264
-        // Technically, 104 is enough.
-        debug_assert!(block_size >= 128);
-        debug_assert!(cache_size > 0);
-        debug_assert!(lookahead_size > 0);
-
-        // cache must be multiple of read
-        debug_assert!(read_size <= cache_size);
-        debug_assert!(cache_size % read_size == 0);
-
-        // cache must be multiple of write
-        debug_assert!(write_size <= cache_size);
-        debug_assert!(cache_size % write_size == 0);
-
-        // block must be multiple of cache
-        debug_assert!(cache_size <= block_size);
-        debug_assert!(block_size % cache_size == 0);
+        // the storage configuration is validated by `validate_config` when the filesystem is
+        // formatted or mounted
 
         let cache = Cache::new();
 
-        let filename_max_plus_one: u32 = crate::consts::FILENAME_MAX_PLUS_ONE;
-        debug_assert!(filename_max_plus_one > 1);
-        debug_assert!(filename_max_plus_one <= 1_022 + 1);
-        // limitation of ll-bindings
-        debug_assert!(filename_max_plus_one == 255 + 1);
-        let path_max_plus_one: u32 = crate::consts::PATH_MAX_PLUS_ONE as _;
-        // TODO: any upper limit?
-        debug_assert!(path_max_plus_one >= filename_max_plus_one);
-        let file_max = crate::consts::FILEBYTES_MAX;
-        assert!(file_max > 0);
-        assert!(file_max <= 2_147_483_647);
-        // limitation of ll-bindings
-        assert!(file_max == 2_147_483_647);
+        let name_max: u32 = Storage::FILENAME_MAX as _;
+        let file_max: u32 = Storage::FILEBYTES_MAX;
         let attr_max: u32 = crate::consts::ATTRBYTES_MAX;
         assert!(attr_max > 0);
         assert!(attr_max <= 1_022);
@@ -155,7 +196,7 @@ impl<Storage: driver::Storage> Allocation<Storage> {
             prog_buffer: core::ptr::null_mut(),
             lookahead_buffer: core::ptr::null_mut(),
 
-            name_max: filename_max_plus_one.wrapping_sub(1),
+            name_max,
             file_max,
             attr_max,
         };
@@ -168,6 +209,33 @@ impl<Storage: driver::Storage> Allocation<Storage> {
     }
 }
 
+/// Constraint of the storage configuration that is violated, see
+/// [`Allocation::validate_config`][].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ConfigError {
+    /// `BLOCK_CYCLES` must be positive or -1.
+    BlockCycles,
+    /// `BLOCK_COUNT` must be positive and fit into a `u32`.
+    BlockCount,
+    /// `READ_SIZE` must be positive.
+    ReadSize,
+    /// `WRITE_SIZE` must be positive.
+    WriteSize,
+    /// `BLOCK_SIZE` must be at least 128 and fit into a `u32`.
+    BlockSize,
+    /// `CACHE_SIZE` must be a positive multiple of `READ_SIZE` and `WRITE_SIZE`.
+    CacheSize,
+    /// `BLOCK_SIZE` must be a multiple of `CACHE_SIZE`.
+    BlockSizeNotMultipleOfCacheSize,
+    /// `LOOKAHEAD_SIZE` must be positive.
+    LookaheadSize,
+    /// `FILENAME_MAX` must be positive and at most 255.
+    FilenameMax,
+    /// `FILEBYTES_MAX` must be positive and at most 2_147_483_647.
+    FilebytesMax,
+}
+
 // pub struct Filesystem<'alloc, 'storage, Storage: driver::Storage> {
 //     pub(crate) alloc: &'alloc mut Allocation<Storage>,
 //     pub(crate) storage: &'storage mut Storage,
@@ -178,6 +246,210 @@ impl<Storage: driver::Storage> Allocation<Storage> {
 pub struct Filesystem<'a, Storage: driver::Storage> {
     alloc: RefCell<&'a mut Allocation<Storage>>,
     storage: &'a mut Storage,
+    #[cfg(feature = "read-only")]
+    read_only: Cell<bool>,
+    #[cfg(feature = "path-validator")]
+    path_validator: Cell<Option<PathValidator>>,
+    #[cfg(feature = "usage-cache")]
+    used_blocks: Cell<Option<usize>>,
+    #[cfg(feature = "timestamps")]
+    clock: Cell<Option<&'a dyn driver::Clock>>,
+    #[cfg(feature = "change-tracking")]
+    changes: Cell<Changes>,
+    #[cfg(feature = "protected-paths")]
+    protected: Cell<&'a [&'a Path]>,
+    #[cfg(feature = "protected-paths")]
+    privileged: Cell<bool>,
+}
+
+/// Settings and cached state of a [`Filesystem`][] that an [`OwnedFilesystem`][] keeps between
+/// calls of [`OwnedFilesystem::with`][].
+#[derive(Clone, Copy)]
+struct Settings {
+    #[cfg(feature = "read-only")]
+    read_only: bool,
+    #[cfg(feature = "path-validator")]
+    path_validator: Option<PathValidator>,
+    #[cfg(feature = "usage-cache")]
+    used_blocks: Option<usize>,
+    #[cfg(feature = "change-tracking")]
+    changes: Changes,
+}
+
+impl Settings {
+    const fn new() -> Self {
+        Self {
+            #[cfg(feature = "read-only")]
+            read_only: false,
+            #[cfg(feature = "path-validator")]
+            path_validator: None,
+            #[cfg(feature = "usage-cache")]
+            used_blocks: None,
+            #[cfg(feature = "change-tracking")]
+            changes: Changes::new(),
+        }
+    }
+}
+
+/// Capability to modify protected paths, see [`Filesystem::mount_protected`][].
+///
+/// A `Privilege` can only be obtained when mounting a filesystem with protected paths.
+#[cfg(feature = "protected-paths")]
+#[derive(Debug)]
+pub struct Privilege {
+    _private: (),
+}
+
+/// Token for [`Filesystem::changed_since`][] describing the state of the filesystem at the time it
+/// was issued.
+///
+/// Tokens are only meaningful for the `Filesystem` instance that issued them.  The default token
+/// precedes all modifications since the filesystem was mounted.
+#[cfg(feature = "change-tracking")]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Ord, PartialOrd)]
+pub struct ChangeToken(u64);
+
+#[cfg(feature = "change-tracking")]
+const RECENT_CHANGES: usize = 4;
+
+/// Mutation counter for [`Filesystem::changed_since`][].
+#[cfg(feature = "change-tracking")]
+#[derive(Clone, Copy)]
+struct Changes {
+    generation: u64,
+    /// Path hash and generation of the most recently modified paths.
+    recent: [(u32, u64); RECENT_CHANGES],
+    /// Latest generation that has been evicted from `recent`.
+    evicted: u64,
+}
+
+#[cfg(feature = "change-tracking")]
+impl Changes {
+    const fn new() -> Self {
+        Self {
+            generation: 0,
+            recent: [(0, 0); RECENT_CHANGES],
+            evicted: 0,
+        }
+    }
+
+    fn record(&mut self, hash: u32) {
+        self.generation += 1;
+        let slot = self
+            .recent
+            .iter()
+            .position(|&(h, generation)| generation != 0 && h == hash)
+            .unwrap_or_else(|| {
+                (0..RECENT_CHANGES)
+                    .min_by_key(|&slot| self.recent[slot].1)
+                    .unwrap()
+            });
+        if self.recent[slot].0 != hash {
+            self.evicted = self.evicted.max(self.recent[slot].1);
+        }
+        self.recent[slot] = (hash, self.generation);
+    }
+
+    /// The generation of the last change that could have affected the path.
+    fn last_change(&self, path: &Path) -> u64 {
+        let mut last = self.evicted;
+        path_hashes(path, |hash| {
+            for &(h, generation) in &self.recent {
+                if h == hash {
+                    last = last.max(generation);
+                }
+            }
+        });
+        last
+    }
+}
+
+const FNV_OFFSET_BASIS: u32 = 0x811c_9dc5;
+
+/// Continues the FNV-1a hash `hash` with `bytes`.
+fn fnv1a(hash: u32, bytes: &[u8]) -> u32 {
+    bytes.iter().fold(hash, |hash, &byte| {
+        (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+    })
+}
+
+/// Calls `f` with the FNV-1a hashes of the path and all its ancestors.
+fn path_hashes(path: &Path, mut f: impl FnMut(u32)) {
+    let mut hash = FNV_OFFSET_BASIS;
+    for &byte in path.as_str().trim_matches('/').as_bytes() {
+        if byte == b'/' {
+            f(hash);
+        }
+        hash = fnv1a(hash, &[byte]);
+    }
+    f(hash);
+}
+
+/// Returns `true` if `ancestor` is an ancestor of `path`, or `path` itself unless `strict` is set.
+///
+/// Both paths are resolved like littlefs resolves them, see [`Path::normalize`][].  Paths that
+/// cannot be resolved are treated as related.
+fn is_ancestor(ancestor: &Path, path: &Path, strict: bool) -> bool {
+    match (ancestor.normalize(), path.normalize()) {
+        (Ok(ancestor), Ok(path)) => path
+            .ancestors()
+            .skip(strict.into())
+            .any(|path| path == ancestor),
+        _ => true,
+    }
+}
+
+fn path_hash(path: &Path) -> u32 {
+    let mut hash = 0;
+    path_hashes(path, |h| hash = h);
+    hash
+}
+
+/// Formats a value as eight lowercase hex digits.
+fn hex_u32(value: u32) -> [u8; 8] {
+    let mut digits = [0; 8];
+    for (digits, byte) in digits.chunks_exact_mut(2).zip(value.to_be_bytes()) {
+        digits.copy_from_slice(&crate::encoding::hex_byte(byte));
+    }
+    digits
+}
+
+/// Derives a file name that is valid on the device and at most `max` bytes long from a host
+/// file name, see [`Filesystem::import_file`][].
+fn short_name(host_name: &str, max: usize) -> Result<PathBuf> {
+    let is_valid = |c: char| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_');
+    if host_name.len() <= max
+        && host_name.chars().all(is_valid)
+        && !matches!(host_name, "" | "." | "..")
+    {
+        return PathBuf::try_from(host_name).map_err(|_| Error::INVALID);
+    }
+
+    // sanitized stem, `~`, hash of the host name and the sanitized extension if it is short
+    let hash = fnv1a(FNV_OFFSET_BASIS, host_name.as_bytes());
+    let (stem, extension) = match host_name.rsplit_once('.') {
+        Some((stem, extension))
+            if !extension.is_empty() && extension.len() <= 8 && max >= 10 + extension.len() =>
+        {
+            (stem, Some(extension))
+        }
+        _ => (host_name, None),
+    };
+    let suffix_len = 9 + extension.map_or(0, |extension| extension.len() + 1);
+    let stem_len = max
+        .checked_sub(suffix_len)
+        .ok_or(Error::FILENAME_TOO_LONG)?;
+
+    let mut name: heapless::Vec<u8, { littlefs2_core::FileName::MAX_SIZE }> = heapless::Vec::new();
+    let sanitize = |c: char| if is_valid(c) { c as u8 } else { b'_' };
+    name.extend(stem.chars().take(stem_len).map(sanitize));
+    name.push(b'~').ok();
+    name.extend_from_slice(&hex_u32(hash)).ok();
+    if let Some(extension) = extension {
+        name.push(b'.').ok();
+        name.extend(extension.chars().map(sanitize));
+    }
+    PathBuf::try_from(&name[..]).map_err(|_| Error::INVALID)
 }
 
 fn metadata(info: ll::lfs_info) -> Metadata {
@@ -192,17 +464,13 @@ fn metadata(info: ll::lfs_info) -> Metadata {
     Metadata::new(file_type, info.size as usize)
 }
 
-struct RemoveDirAllProgress {
-    files_removed: usize,
-    skipped_any: bool,
-}
-
 impl<Storage: driver::Storage> Filesystem<'_, Storage> {
     pub fn allocate() -> Allocation<Storage> {
         Allocation::new()
     }
 
     pub fn format(storage: &mut Storage) -> Result<()> {
+        Allocation::<Storage>::check_config()?;
         let alloc = &mut Allocation::new();
         let fs = Filesystem::new(alloc, storage);
         let mut alloc = fs.alloc.borrow_mut();
@@ -216,6 +484,125 @@ impl<Storage: driver::Storage> Filesystem<'_, Storage> {
         Filesystem::mount(alloc, storage).is_ok()
     }
 
+    /// Check the consistency of the filesystem on `storage` without modifying it.
+    ///
+    /// This reads every block of the storage, mounts the filesystem, traverses its blocks with
+    /// [`Filesystem::traverse_blocks`][] and walks the directory tree, reading every directory and
+    /// every file completely.  Problems are collected in the returned [`FsckReport`][] instead of
+    /// aborting the check.  An error is only returned if the storage configuration is invalid.
+    ///
+    /// littlefs does not expose the details of its metadata, so the check is limited to what can
+    /// be observed through its API: CRC mismatches and broken metadata pairs are reported by
+    /// littlefs as [`Error::CORRUPTION`][] when the affected directory or file is read, and
+    /// orphaned metadata pairs are removed by littlefs itself on the first write after mounting.
+    ///
+    /// ```
+    ///# use littlefs2::{fs::Filesystem, path, ram_storage, driver, io::Result};
+    ///# ram_storage!(tiny);
+    ///# let mut ram = Ram::default();
+    ///# let mut storage = RamStorage::new(&mut ram);
+    /// Filesystem::format(&mut storage).unwrap();
+    /// Filesystem::mount_and_then(&mut storage, |fs| fs.write(path!("file"), b"data")).unwrap();
+    ///
+    /// let report = Filesystem::check(&mut storage).unwrap();
+    /// assert!(report.is_clean());
+    /// assert_eq!(report.files, 1);
+    /// ```
+    pub fn check(storage: &mut Storage) -> Result<FsckReport> {
+        Allocation::<Storage>::check_config()?;
+        let alloc = &mut Allocation::new();
+        let fs = Filesystem::new(alloc, storage);
+        let mut report = FsckReport {
+            mount_error: None,
+            traverse_error: None,
+            unreadable_blocks: 0,
+            first_unreadable_block: None,
+            unreadable_used_blocks: 0,
+            used_blocks: 0,
+            dirs: 0,
+            files: 0,
+            damaged_entries: 0,
+            first_damaged_entry: None,
+        };
+
+        for block in 0..Storage::BLOCK_COUNT as u32 {
+            if fs.read_raw_block(block).is_err() {
+                report.unreadable_blocks += 1;
+                report.first_unreadable_block.get_or_insert(block);
+            }
+        }
+
+        if let Err(error) = fs.raw_mount() {
+            report.mount_error = Some(error);
+            return Ok(report);
+        }
+
+        let traversed = fs.traverse_blocks(|_| {
+            report.used_blocks += 1;
+            Ok(())
+        });
+        if let Err(error) = traversed {
+            report.traverse_error = Some(error);
+        } else if report.unreadable_blocks > 0 {
+            report.unreadable_used_blocks = fs.scrub(|_| {})?;
+        }
+
+        // Walk the tree, but continue with the next directory on errors.
+        let mut walk = fs.walk_dir(crate::path!("/"));
+        report.dirs += 1;
+        let mut buf: Bytes<Storage::CACHE_SIZE> = Default::default();
+
+        while let Some(step) = walk.step() {
+            let entry = match step {
+                Ok(WalkStep::Entry(entry)) => entry,
+                Ok(WalkStep::Leave(..)) => continue,
+                Err(error) => {
+                    report.add_damaged_entry(walk.dir(), error);
+                    walk.leave();
+                    continue;
+                }
+            };
+
+            if entry.file_type().is_dir() {
+                report.dirs += 1;
+                if walk.level() == crate::consts::DIR_DEPTH_MAX {
+                    report.add_damaged_entry(entry.path(), Error::NO_MEMORY);
+                    walk.skip_dir();
+                }
+            } else {
+                report.files += 1;
+                let read = fs.open_file_and_then(entry.path(), |file| {
+                    while file.read(&mut buf)? > 0 {}
+                    Ok(())
+                });
+                if let Err(error) = read {
+                    report.add_damaged_entry(entry.path(), error);
+                }
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Read a complete block from the storage, bypassing littlefs.
+    fn read_raw_block(&self, block: u32) -> Result<()> {
+        let mut buf: Bytes<Storage::CACHE_SIZE> = Default::default();
+        for off in (0..Storage::BLOCK_SIZE).step_by(buf.len()) {
+            let return_code = {
+                let alloc = self.alloc.borrow();
+                Self::lfs_config_read(
+                    &alloc.config,
+                    block,
+                    off as u32,
+                    buf.as_mut_ptr() as *mut c_void,
+                    buf.len() as u32,
+                )
+            };
+            result_from((), return_code)?;
+        }
+        Ok(())
+    }
+
     // Can BorrowMut be implemented "unsafely" instead?
     // This is intended to be a second option, besides `into_inner`, to
     // get access to the Flash peripheral in Storage.
@@ -233,6 +620,64 @@ impl<Storage: driver::Storage> Filesystem<'_, Storage> {
         f(&fs)
     }
 
+    /// Mount the filesystem in read-only mode and call `f` with it.
+    ///
+    /// This is equivalent to calling [`Filesystem::set_read_only`][] directly after mounting.  It
+    /// can be used for storages that do not support writes, for example
+    /// [`RomStorage`](crate::driver::RomStorage).
+    #[cfg(feature = "read-only")]
+    pub fn mount_read_only_and_then<R>(
+        storage: &mut Storage,
+        f: impl FnOnce(&Filesystem<'_, Storage>) -> Result<R>,
+    ) -> Result<R> {
+        let mut alloc = Allocation::new();
+        let fs = Filesystem::mount(&mut alloc, storage)?;
+        fs.set_read_only(true);
+        f(&fs)
+    }
+
+    /// Format the storage, populate it with `f` and verify the result.
+    ///
+    /// This packages a factory provisioning flow into a single call: the storage is formatted
+    /// and mounted, `f` writes the initial content and the filesystem is unmounted.  Then it is
+    /// mounted again and all files are read back.  Their paths, sizes and
+    /// contents are fed into `hasher` in the order of the directory walk, and the resulting
+    /// digest is returned so that it can be compared with the digest of a reference device.
+    /// Empty directories and attributes do not contribute to the digest.
+    pub fn provision<H: Hasher>(
+        storage: &mut Storage,
+        mut hasher: H,
+        f: impl FnOnce(&Filesystem<'_, Storage>) -> Result<()>,
+    ) -> Result<u64> {
+        Self::format(storage)?;
+        {
+            let mut alloc = Allocation::new();
+            let fs = Filesystem::mount(&mut alloc, storage)?;
+            f(&fs)?;
+            let return_code = unsafe { ll::lfs_unmount(&mut fs.alloc.borrow_mut().state) };
+            result_from((), return_code)?;
+        }
+
+        Self::mount_and_then(storage, |fs| {
+            #[cfg(feature = "read-only")]
+            fs.set_read_only(true);
+            let mut buf: Bytes<Storage::CACHE_SIZE> = Default::default();
+            fs.walk_files(crate::path!("/"), &mut |entry| {
+                let size = entry.metadata().len() as u64;
+                hasher.write(entry.path().as_str_ref_with_trailing_nul().as_bytes());
+                hasher.write(&size.to_le_bytes());
+                fs.open_file_and_then(entry.path(), |file| loop {
+                    let n = file.read(&mut buf)?;
+                    if n == 0 {
+                        return Ok(());
+                    }
+                    hasher.write(&buf[..n]);
+                })
+            })
+        })?;
+        Ok(hasher.finish())
+    }
+
     /// Total number of blocks in the filesystem
     pub fn total_blocks(&self) -> usize {
         Storage::BLOCK_COUNT
@@ -243,6 +688,23 @@ impl<Storage: driver::Storage> Filesystem<'_, Storage> {
         Storage::BLOCK_COUNT * Storage::BLOCK_SIZE
     }
 
+    /// Parameters of the mounted filesystem, for example for diagnostics.
+    ///
+    /// The limits for names, files and attributes are the values that were stored in the
+    /// superblock when the filesystem was formatted, which may be smaller than the limits of this
+    /// crate.  The on-disk version is not available because littlefs does not keep it after
+    /// mounting; it is compatible with the format version of [`crate::version`][].
+    pub fn fs_info(&self) -> FsInfo {
+        let state = &self.alloc.borrow().state;
+        FsInfo {
+            block_size: Storage::BLOCK_SIZE,
+            block_count: Storage::BLOCK_COUNT,
+            name_max: state.name_max as usize,
+            file_max: state.file_max as usize,
+            attr_max: state.attr_max as usize,
+        }
+    }
+
     /// Available number of unused blocks in the filesystem
     ///
     /// Upstream littlefs documentation notes (on its "current size" function):
@@ -251,10 +713,110 @@ impl<Storage: driver::Storage> Filesystem<'_, Storage> {
     ///
     /// So it would seem that there are *at least* the number of blocks returned
     /// by this method available, at any given time.
+    ///
+    /// Determining the number of used blocks requires a traversal of the whole filesystem.  With
+    /// the `usage-cache` feature, the result is cached until the next operation that may modify
+    /// the filesystem, so repeated calls are cheap, see `Filesystem::available_blocks_cached`.
     pub fn available_blocks(&self) -> Result<usize> {
+        #[cfg(feature = "usage-cache")]
+        if let Some(used_blocks) = self.used_blocks.get() {
+            return Ok(self.total_blocks().saturating_sub(used_blocks));
+        }
         let return_code = unsafe { ll::lfs_fs_size(&mut self.alloc.borrow_mut().state) };
-        u32_result(return_code)
-            .map(|blocks| usize::try_from(blocks).unwrap_or(usize::MAX))
+        let used_blocks =
+            u32_result(return_code).map(|blocks| usize::try_from(blocks).unwrap_or(usize::MAX))?;
+        #[cfg(feature = "usage-cache")]
+        self.used_blocks.set(Some(used_blocks));
+        Ok(self.total_blocks().saturating_sub(used_blocks))
+    }
+
+    /// Call `f` for every block that is in use by the filesystem.
+    ///
+    /// This wraps `lfs_fs_traverse`.  Blocks that are shared by copy-on-write structures may be
+    /// reported multiple times.  Errors returned by `f` stop the traversal and are passed on.
+    /// `f` must not access the filesystem.
+    ///
+    /// ```
+    ///# use littlefs2::{fs::Filesystem, path, ram_storage, driver, io::Result};
+    ///# ram_storage!(tiny);
+    ///# let mut ram = Ram::default();
+    ///# let mut storage = RamStorage::new(&mut ram);
+    ///# Filesystem::format(&mut storage).unwrap();
+    ///# Filesystem::mount_and_then(&mut storage, |fs| {
+    /// let mut used = [false; 8];
+    /// fs.traverse_blocks(|block| {
+    ///     used[block as usize] = true;
+    ///     Ok(())
+    /// })?;
+    /// // the root directory is stored in blocks 0 and 1
+    /// assert!(used[0] && used[1]);
+    ///# Ok(())
+    ///# }).unwrap();
+    /// ```
+    pub fn traverse_blocks(&self, mut f: impl FnMut(u32) -> Result<()>) -> Result<()> {
+        let mut f: &mut dyn FnMut(u32) -> Result<()> = &mut f;
+        let return_code = unsafe {
+            ll::lfs_fs_traverse(
+                &mut self.alloc.borrow_mut().state,
+                Some(Self::lfs_traverse_callback),
+                &mut f as *mut _ as *mut c_void,
+            )
+        };
+        result_from((), return_code)
+    }
+
+    extern "C" fn lfs_traverse_callback(data: *mut c_void, block: ll::lfs_block_t) -> c_int {
+        let f = unsafe { &mut *(data as *mut &mut dyn FnMut(u32) -> Result<()>) };
+        error_code_from(f(block))
+    }
+
+    /// Read every block that is in use by the filesystem and call `on_bad_block` for every block
+    /// that could not be read.
+    ///
+    /// The blocks are read directly from the storage, bypassing the caches of littlefs, so that
+    /// storage implementations that detect marginal data, for example with ECC, get the chance to
+    /// report it.  Every block is read at most once, even if it is reported multiple times by
+    /// [`Filesystem::traverse_blocks`][].  littlefs only relocates blocks if programming or
+    /// erasing them fails, not on read errors, so the data in a reported block has to be restored
+    /// by the application, for example by rewriting the affected file from a backup.
+    ///
+    /// Returns the number of blocks that could not be read.  Errors during the traversal are
+    /// passed on.  The blocks are collected in windows of 256 blocks, so the filesystem is
+    /// traversed once for every window.
+    pub fn scrub(&self, mut on_bad_block: impl FnMut(u32)) -> Result<usize> {
+        const WINDOW: usize = 256;
+        let mut bad_blocks = 0;
+        for start in (0..Storage::BLOCK_COUNT).step_by(WINDOW) {
+            let mut used = [0u32; WINDOW / 32];
+            self.traverse_blocks(|block| {
+                if let Some(i) = (block as usize).checked_sub(start).filter(|i| *i < WINDOW) {
+                    used[i / 32] |= 1 << (i % 32);
+                }
+                Ok(())
+            })?;
+            for i in 0..WINDOW.min(Storage::BLOCK_COUNT - start) {
+                let block = (start + i) as u32;
+                if used[i / 32] & (1 << (i % 32)) != 0 && self.read_raw_block(block).is_err() {
+                    bad_blocks += 1;
+                    on_bad_block(block);
+                }
+            }
+        }
+        Ok(bad_blocks)
+    }
+
+    /// Available number of unused blocks in the filesystem, if it is known without a traversal.
+    ///
+    /// The number of used blocks is determined by [`Filesystem::available_blocks`][] and cached
+    /// until the filesystem is modified: any operation that creates, writes, renames or removes a
+    /// file, directory or attribute, and syncing or closing a file, invalidates the cache.  This
+    /// method returns the cached result, which is as accurate as the result of
+    /// [`Filesystem::available_blocks`][] at the time it was cached, or `None` if the cache has
+    /// been invalidated since then or has not been filled since the filesystem was mounted.
+    #[cfg(feature = "usage-cache")]
+    pub fn available_blocks_cached(&self) -> Option<usize> {
+        self.used_blocks
+            .get()
             .map(|blocks| self.total_blocks().saturating_sub(blocks))
     }
 
@@ -268,8 +830,183 @@ impl<Storage: driver::Storage> Filesystem<'_, Storage> {
             .map(|blocks| blocks * Storage::BLOCK_SIZE)
     }
 
+    /// Switch the filesystem to read-only mode, or back to normal operation.
+    ///
+    /// In read-only mode, all operations that modify the filesystem fail with
+    /// [`Error::READ_ONLY`][] before accessing the storage, for example to stop writes when the
+    /// supply voltage is too low to program the flash safely.  This includes opening files for
+    /// writing and writing to or resizing files that are already open.
+    ///
+    /// Synchronizing or closing a file that was modified before the switch still writes its
+    /// pending changes to the storage.
+    #[cfg(feature = "read-only")]
+    pub fn set_read_only(&self, read_only: bool) {
+        self.read_only.set(read_only);
+    }
+
+    /// Returns whether the filesystem is in read-only mode, see
+    /// [`Filesystem::set_read_only`][].
+    #[cfg(feature = "read-only")]
+    pub fn is_read_only(&self) -> bool {
+        self.read_only.get()
+    }
+
+    /// Checks that the filesystem may be modified, and invalidates the cached block usage as the
+    /// caller is about to modify it.
+    fn check_writable(&self) -> Result<()> {
+        #[cfg(feature = "read-only")]
+        if self.is_read_only() {
+            return Err(Error::READ_ONLY);
+        }
+        self.invalidate_used_blocks();
+        Ok(())
+    }
+
+    /// Invalidates the cached block usage, see [`Filesystem::available_blocks`][].
+    fn invalidate_used_blocks(&self) {
+        #[cfg(feature = "usage-cache")]
+        self.used_blocks.set(None);
+    }
+
+    /// Install a validator for paths that are modified, or remove it with `None`.
+    ///
+    /// The validator is called for all paths that are created, written, renamed or removed,
+    /// including the attributes of a path and files that are opened for writing.  If it returns
+    /// `false`, the operation fails with [`Error::PATH_REJECTED`][] before accessing the storage.
+    /// Paths that are only read are not validated.
+    ///
+    /// This can be used to enforce invariants on paths that are provided by external clients,
+    /// see [`PathPolicy`][] for common restrictions.
+    ///
+    /// ```
+    ///# use littlefs2::{driver, fs::{Filesystem, PathPolicy}, io::{Error, Result}, path, ram_storage};
+    ///# ram_storage!(tiny);
+    ///# let mut ram = Ram::default();
+    ///# let mut storage = RamStorage::new(&mut ram);
+    ///# Filesystem::format(&mut storage).unwrap();
+    ///# Filesystem::mount_and_then(&mut storage, |fs| {
+    /// const POLICY: PathPolicy = PathPolicy::new().max_depth(2).reserved(&[path!("/.lfs")]);
+    ///
+    /// fs.set_path_validator(Some(|path| POLICY.allows(path)));
+    /// assert_eq!(fs.create_dir(path!("/.lfs")), Err(Error::PATH_REJECTED));
+    /// assert_eq!(fs.create_dir_all(path!("/a/b/c")), Err(Error::PATH_REJECTED));
+    /// fs.create_dir_all(path!("/a/b"))?;
+    ///# Ok(())
+    ///# }).unwrap();
+    /// ```
+    #[cfg(feature = "path-validator")]
+    pub fn set_path_validator(&self, validator: Option<PathValidator>) {
+        self.path_validator.set(validator);
+    }
+
+    /// Returns `true` if the path is one of the protected paths of the filesystem or below
+    /// one of them, see [`Filesystem::mount_protected`][].
+    ///
+    /// The paths are compared after resolving `.`, `..`, repeated separators and relative paths
+    /// like littlefs, see [`Path::normalize`][].
+    #[cfg(feature = "protected-paths")]
+    pub fn is_protected(&self, path: &Path) -> bool {
+        self.protected
+            .get()
+            .iter()
+            .any(|protected| is_ancestor(protected, path, false))
+    }
+
+    /// Returns `true` if the path is an ancestor of a protected path.
+    #[cfg(feature = "protected-paths")]
+    fn contains_protected(&self, path: &Path) -> bool {
+        !self.privileged.get()
+            && self
+                .protected
+                .get()
+                .iter()
+                .any(|protected| is_ancestor(path, protected, true))
+    }
+
+    /// Call `f` with the filesystem and allow it to modify protected paths.
+    #[cfg(feature = "protected-paths")]
+    pub fn privileged_and_then<R>(
+        &self,
+        _privilege: &Privilege,
+        f: impl FnOnce(&Self) -> Result<R>,
+    ) -> Result<R> {
+        let privileged = self.privileged.replace(true);
+        let result = f(self);
+        self.privileged.set(privileged);
+        result
+    }
+
+    #[cfg_attr(
+        not(any(
+            feature = "path-validator",
+            feature = "protected-paths",
+            feature = "change-tracking"
+        )),
+        allow(unused_variables)
+    )]
+    fn check_path(&self, path: &Path) -> Result<()> {
+        self.check_writable()?;
+        #[cfg(feature = "path-validator")]
+        if let Some(validator) = self.path_validator.get() {
+            if !validator(path) {
+                return Err(Error::PATH_REJECTED);
+            }
+        }
+        #[cfg(feature = "protected-paths")]
+        if self.is_protected(path) && !self.privileged.get() {
+            return Err(Error::PATH_PROTECTED);
+        }
+        #[cfg(feature = "change-tracking")]
+        self.record_change(path_hash(path));
+        Ok(())
+    }
+
+    #[cfg(feature = "change-tracking")]
+    fn record_change(&self, hash: u32) {
+        let mut changes = self.changes.get();
+        changes.record(hash);
+        self.changes.set(changes);
+    }
+
+    /// Check whether a path may have been modified since `token` was issued.
+    ///
+    /// Returns the result and a new token for the next call.  This does not access the storage:
+    /// the filesystem counts all modifications and remembers the paths that were modified
+    /// recently.  A path counts as modified if it or one of its ancestors has been created,
+    /// written, renamed, removed or had an attribute changed.  Changes below a directory are not
+    /// reported for the directory itself.
+    ///
+    /// The result may be a false positive, for example if many other paths have been modified
+    /// since the token was issued, but never a false negative for modifications through this
+    /// `Filesystem`.
+    ///
+    /// ```
+    ///# use littlefs2::{fs::{ChangeToken, Filesystem}, path, ram_storage, driver, io::Result};
+    ///# ram_storage!(tiny);
+    ///# let mut ram = Ram::default();
+    ///# let mut storage = RamStorage::new(&mut ram);
+    ///# Filesystem::format(&mut storage).unwrap();
+    ///# Filesystem::mount_and_then(&mut storage, |fs| {
+    /// let (_, token) = fs.changed_since(path!("config"), ChangeToken::default());
+    /// fs.write(path!("log"), b"entry")?;
+    /// let (changed, token) = fs.changed_since(path!("config"), token);
+    /// assert!(!changed);
+    /// fs.write(path!("config"), b"new")?;
+    /// let (changed, _) = fs.changed_since(path!("config"), token);
+    /// assert!(changed);
+    ///# Ok(())
+    ///# }).unwrap();
+    /// ```
+    #[cfg(feature = "change-tracking")]
+    pub fn changed_since(&self, path: &Path, token: ChangeToken) -> (bool, ChangeToken) {
+        let changes = self.changes.get();
+        let changed = changes.last_change(path) > token.0;
+        (changed, ChangeToken(changes.generation))
+    }
+
     /// Remove a file or directory.
     pub fn remove(&self, path: &Path) -> Result<()> {
+        self.check_path(path)?;
         let return_code =
             unsafe { ll::lfs_remove(&mut self.alloc.borrow_mut().state, path.as_ptr()) };
         result_from((), return_code)
@@ -280,80 +1017,142 @@ impl<Storage: driver::Storage> Filesystem<'_, Storage> {
         self.remove(path)
     }
 
-    /// TODO: This method fails if some `println!` calls are removed.
-    /// Whyy?
+    /// Remove a directory and all of its contents.
+    ///
+    /// The directory tree is walked iteratively, so the stack usage does not depend on the depth
+    /// of the tree.  The state of the walk consists of one counter per level and a few
+    /// [`PathBuf`][] and [`DirEntry`][] values, about 1.3 KiB on 32-bit targets, in addition to
+    /// the stack used by littlefs itself.  At most
+    /// [`DIR_DEPTH_MAX`](crate::consts::DIR_DEPTH_MAX) levels of nested directories below `path`
+    /// are supported, deeper trees result in [`Error::NO_MEMORY`][].  Use
+    /// [`Filesystem::remove_dir_all_where_with_depth`][] for a lower limit.
     pub fn remove_dir_all(&self, path: &Path) -> Result<()> {
         self.remove_dir_all_where(path, &|_| true).map(|_| ())
     }
 
-    /// Returns number of deleted files + whether the directory was fully deleted or not
-    fn remove_dir_all_where_inner<P>(
+    /// Remove the files of a directory tree that match `predicate`, and the directories that
+    /// become empty in the process.
+    ///
+    /// Returns the number of deleted files.  See [`Filesystem::remove_dir_all`][] for the
+    /// supported nesting depth.
+    pub fn remove_dir_all_where<P>(&self, path: &Path, predicate: &P) -> Result<usize>
+    where
+        P: Fn(&DirEntry) -> bool,
+    {
+        self.remove_dir_all_where_with_depth(path, crate::consts::DIR_DEPTH_MAX, predicate)
+    }
+
+    /// Like [`Filesystem::remove_dir_all_where`][], but with at most `max_depth` levels of nested
+    /// directories below `path`.
+    ///
+    /// If the walk reaches a directory below the limit, it stops with [`Error::NO_MEMORY`][].
+    /// Files that have been visited before are already removed at that point.  The stack usage
+    /// is the same for all limits.  `max_depth` must not be larger than
+    /// [`DIR_DEPTH_MAX`](crate::consts::DIR_DEPTH_MAX), otherwise [`Error::INVALID`][] is
+    /// returned.
+    pub fn remove_dir_all_where_with_depth<P>(
+        &self,
+        path: &Path,
+        max_depth: usize,
+        predicate: &P,
+    ) -> Result<usize>
+    where
+        P: Fn(&DirEntry) -> bool,
+    {
+        self.remove_tree(path, max_depth, predicate, &mut |_| {})
+    }
+
+    /// Like [`Filesystem::remove_dir_all_where`][], but calls `on_removed` with the path of
+    /// every removed file and directory, for example to display the progress of a factory reset.
+    pub fn remove_dir_all_with_progress<P>(
         &self,
         path: &Path,
         predicate: &P,
-    ) -> Result<RemoveDirAllProgress>
+        mut on_removed: impl FnMut(&Path),
+    ) -> Result<usize>
     where
         P: Fn(&DirEntry) -> bool,
     {
+        self.remove_tree(
+            path,
+            crate::consts::DIR_DEPTH_MAX,
+            predicate,
+            &mut on_removed,
+        )
+    }
+
+    fn remove_tree(
+        &self,
+        path: &Path,
+        max_depth: usize,
+        predicate: &dyn Fn(&DirEntry) -> bool,
+        on_removed: &mut dyn FnMut(&Path),
+    ) -> Result<usize> {
         use crate::path;
 
+        if max_depth > crate::consts::DIR_DEPTH_MAX {
+            return Err(Error::INVALID);
+        }
+
+        self.check_path(path)?;
+        #[cfg(feature = "protected-paths")]
+        if self.contains_protected(path) {
+            return Err(Error::PATH_PROTECTED);
+        }
+
         if !self.exists(path) {
             debug_now!("no such directory {}, early return", path);
-            return Ok(RemoveDirAllProgress {
-                files_removed: 0,
-                skipped_any: false,
-            });
+            return Ok(0);
         }
-        let mut skipped_any = false;
+
+        let mut walk = self.walk_dir(path);
         let mut files_removed = 0;
-        debug_now!("starting to remove_dir_all_where in {}", path);
-        self.read_dir_and_then(path, |read_dir| {
-            // skip "." and ".."
-            for entry in read_dir.skip(2) {
-                let entry = entry?;
 
-                if entry.file_type().is_file() {
+        debug_now!("starting to remove_dir_all_where in {}", path);
+        while let Some(step) = walk.step() {
+            match step? {
+                WalkStep::Entry(entry) if entry.file_type().is_dir() => {
+                    debug_now!("descending into directory {}", &entry.path());
+                    if walk.level() == max_depth {
+                        return Err(Error::NO_MEMORY);
+                    }
+                }
+                WalkStep::Entry(entry) => {
                     if predicate(&entry) {
                         debug_now!("removing file {}", &entry.path());
                         self.remove(entry.path())?;
                         debug_now!("...done");
+                        on_removed(entry.path());
+                        walk.removed();
                         files_removed += 1;
                     } else {
                         debug_now!("skipping file {}", &entry.path());
-                        skipped_any = true;
                     }
                 }
-                if entry.file_type().is_dir() {
-                    debug_now!("recursing into directory {}", &entry.path());
-                    let progress = self.remove_dir_all_where_inner(entry.path(), predicate)?;
-                    files_removed += progress.files_removed;
-                    skipped_any |= progress.skipped_any;
-                    debug_now!("...back");
+                WalkStep::Leave(dir, remaining) => {
+                    let is_root =
+                        dir.as_str() == path.as_str() && (path == path!("") || path == path!("/"));
+                    if remaining == 0 && !is_root {
+                        debug_now!("removing directory {} too", &dir);
+                        self.remove_dir(&dir)?;
+                        debug_now!("..worked");
+                        on_removed(&dir);
+                        walk.removed();
+                    }
                 }
             }
-            Ok(())
-        })?;
-        if !skipped_any && path != path!("") && path != path!("/") {
-            debug_now!("removing directory {} too", &path);
-            self.remove_dir(path)?;
-            debug_now!("..worked");
-        }
-        Ok(RemoveDirAllProgress {
-            files_removed,
-            skipped_any,
-        })
-    }
-
-    pub fn remove_dir_all_where<P>(&self, path: &Path, predicate: &P) -> Result<usize>
-    where
-        P: Fn(&DirEntry) -> bool,
-    {
-        self.remove_dir_all_where_inner(path, predicate)
-            .map(|progress| progress.files_removed)
+        }
+        Ok(files_removed)
     }
 
     /// Rename or move a file or directory.
     pub fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+        self.check_path(from)?;
+        self.check_path(to)?;
+        #[cfg(feature = "protected-paths")]
+        if self.contains_protected(from) {
+            return Err(Error::PATH_PROTECTED);
+        }
         let return_code = unsafe {
             ll::lfs_rename(
                 &mut self.alloc.borrow_mut().state,
@@ -364,6 +1163,355 @@ impl<Storage: driver::Storage> Filesystem<'_, Storage> {
         result_from((), return_code)
     }
 
+    /// Rename or move a file or directory without replacing an existing entry.
+    ///
+    /// Unlike [`Filesystem::rename`][], this fails with [`Error::ENTRY_ALREADY_EXISTED`][] if `to`
+    /// already exists.  The check and the rename are separate operations, so this only protects
+    /// against entries that are created before the call, for example a previous commit in a
+    /// two-phase commit.
+    pub fn rename_noreplace(&self, from: &Path, to: &Path) -> Result<()> {
+        self.metadata(from)?;
+        if self.exists(to) {
+            return Err(Error::ENTRY_ALREADY_EXISTED);
+        }
+        self.rename(from, to)
+    }
+
+    /// Move the contents of the directory `from` into the directory `to`, merging the directory
+    /// trees.
+    ///
+    /// Unlike [`Filesystem::rename`][], this also works if `to` already exists and is not empty.
+    /// Entries that do not exist in `to` are moved with [`Filesystem::rename`][], and directories
+    /// that exist in both trees are merged.  For all other entries that already exist in `to`,
+    /// `on_conflict` is called with the source entry and the target path and decides how to
+    /// proceed, see [`ConflictPolicy`][].  Directories in `from` that are empty after the merge
+    /// are removed, including `from` itself.
+    ///
+    /// `from` and `to` must not be the same directory and neither may be inside the other,
+    /// otherwise this fails with [`Error::INVALID`][] before anything is moved.  A path returned
+    /// with [`ConflictPolicy::Rename`][] must not be inside `from`, otherwise this fails with
+    /// [`Error::INVALID`][].  If an entry already exists at that path, `on_conflict` is
+    /// called again with the new path.  After
+    /// [`MOVE_MERGE_RENAMES_MAX`](crate::consts::MOVE_MERGE_RENAMES_MAX) conflicting paths for
+    /// the same entry, this fails with [`Error::ENTRY_ALREADY_EXISTED`][].
+    ///
+    /// The directory tree is walked iteratively.  At most
+    /// [`DIR_DEPTH_MAX`](crate::consts::DIR_DEPTH_MAX) levels of nested directories are
+    /// supported, deeper trees result in [`Error::NO_MEMORY`][].
+    pub fn move_merge<F>(&self, from: &Path, to: &Path, mut on_conflict: F) -> Result<()>
+    where
+        F: FnMut(&DirEntry, &Path) -> ConflictPolicy,
+    {
+        if is_ancestor(from, to, false) || is_ancestor(to, from, false) {
+            return Err(Error::INVALID);
+        }
+        match self.metadata(to) {
+            Ok(metadata) if !metadata.is_dir() => return Err(Error::PATH_NOT_DIR),
+            Err(error) if error == Error::NO_SUCH_ENTRY => return self.rename(from, to),
+            result => result.map(drop)?,
+        }
+
+        let mut walk = self.walk_dir(from);
+        while let Some(step) = walk.step() {
+            let entry = match step? {
+                WalkStep::Entry(entry) => entry,
+                WalkStep::Leave(dir, remaining) => {
+                    if remaining == 0 {
+                        self.remove_dir(&dir)?;
+                        walk.removed();
+                    }
+                    continue;
+                }
+            };
+
+            let mut target = to.join(walk.relative(entry.path()));
+            let mut target_metadata = match self.metadata(&target) {
+                Ok(metadata) => metadata,
+                Err(error) if error == Error::NO_SUCH_ENTRY => {
+                    self.rename(entry.path(), &target)?;
+                    walk.removed();
+                    continue;
+                }
+                Err(error) => return Err(error),
+            };
+
+            // directories that exist in both trees are merged
+            if target_metadata.is_dir() && entry.file_type().is_dir() {
+                continue;
+            }
+            walk.skip_dir();
+
+            let mut attempts = 0;
+            loop {
+                match on_conflict(&entry, &target) {
+                    ConflictPolicy::Skip => {}
+                    ConflictPolicy::Overwrite => {
+                        if target_metadata.is_dir() {
+                            self.remove_dir_all(&target)?;
+                        } else if entry.file_type().is_dir() {
+                            self.remove(&target)?;
+                        }
+                        self.rename(entry.path(), &target)?;
+                        walk.removed();
+                    }
+                    ConflictPolicy::Rename(path) => {
+                        // the entry would be visited again, possibly forever
+                        if is_ancestor(from, &path, false) {
+                            return Err(Error::INVALID);
+                        }
+                        match self.metadata(&path) {
+                            Ok(metadata) => {
+                                attempts += 1;
+                                if attempts == crate::consts::MOVE_MERGE_RENAMES_MAX {
+                                    return Err(Error::ENTRY_ALREADY_EXISTED);
+                                }
+                                target = path;
+                                target_metadata = metadata;
+                                continue;
+                            }
+                            Err(error) if error == Error::NO_SUCH_ENTRY => {
+                                self.rename(entry.path(), &path)?;
+                                walk.removed();
+                            }
+                            Err(error) => return Err(error),
+                        }
+                    }
+                }
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Report the disk usage of the directory tree at `path`, aggregated at `depth`.
+    ///
+    /// For every directory `depth` levels below `path`, `f` is called with the number and the
+    /// total size of the files in its subtree.  Files in directories above that level are
+    /// reported for the directory that directly contains them, so that every file is counted
+    /// exactly once.  Directories above `depth` without files are not reported.  With a depth of
+    /// zero, `f` is called once with the totals of the entire tree.
+    ///
+    /// Fails with [`Error::NO_MEMORY`][] if `depth` is larger than
+    /// [`DIR_DEPTH_MAX`](crate::consts::DIR_DEPTH_MAX) or the tree is nested deeper than that.
+    pub fn usage_by_dir(
+        &self,
+        path: &Path,
+        depth: usize,
+        mut f: impl FnMut(&DirUsage<'_>) -> Result<()>,
+    ) -> Result<()> {
+        if depth > crate::consts::DIR_DEPTH_MAX {
+            return Err(Error::NO_MEMORY);
+        }
+        if depth == 0 {
+            return f(&self.tree_usage(path)?);
+        }
+
+        let mut walk = self.walk_dir(path);
+        self.report_files_usage(path, &mut f)?;
+        while let Some(step) = walk.step() {
+            let WalkStep::Entry(entry) = step? else {
+                continue;
+            };
+            if !entry.file_type().is_dir() {
+                continue;
+            }
+            if walk.level() + 1 == depth {
+                f(&self.tree_usage(entry.path())?)?;
+                walk.skip_dir();
+            } else {
+                self.report_files_usage(entry.path(), &mut f)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Call `f` with the usage of the files directly in `path` if there are any.
+    fn report_files_usage(
+        &self,
+        path: &Path,
+        f: &mut impl FnMut(&DirUsage<'_>) -> Result<()>,
+    ) -> Result<()> {
+        let mut usage = DirUsage {
+            path,
+            files: 0,
+            bytes: 0,
+        };
+        self.read_dir_and_then(path, |read_dir| {
+            for entry in read_dir.skip_dots() {
+                let entry = entry?;
+                if entry.file_type().is_file() {
+                    usage.files += 1;
+                    usage.bytes += entry.metadata().len();
+                }
+            }
+            Ok(())
+        })?;
+        if usage.files > 0 {
+            f(&usage)?;
+        }
+        Ok(())
+    }
+
+    /// Call `f` for all files and directories in the directory tree at `path` that match a glob
+    /// pattern, see [`Path::matches`][].
+    ///
+    /// Patterns with a `/` are matched against the full path of the entries, so they should start
+    /// with `path`, for example `/logs/**/*.log` for `/logs`.  Patterns without a `/` are matched
+    /// against the file names.  The tree is walked with [`Filesystem::walk_dir_and_then`][] and
+    /// must not be modified by `f`.  Returns the number of matches.
+    pub fn find(
+        &self,
+        path: &Path,
+        pattern: &str,
+        mut f: impl FnMut(&DirEntry) -> Result<()>,
+    ) -> Result<usize> {
+        self.walk_dir_and_then(path, |walk| {
+            let mut count = 0;
+            for entry in walk {
+                let entry = entry?;
+                if entry.path().matches(pattern) {
+                    f(&entry)?;
+                    count += 1;
+                }
+            }
+            Ok(count)
+        })
+    }
+
+    /// Total size in bytes of all files in the directory tree at `path`.
+    ///
+    /// This is the sum of the file sizes, not the space used on the storage.  To check every
+    /// entry, for example to apply a quota only to some files, use
+    /// [`Filesystem::walk_dir_and_then`][].
+    pub fn dir_size(&self, path: &Path) -> Result<usize> {
+        self.tree_usage(path).map(|usage| usage.bytes)
+    }
+
+    /// Number of files and directories in the directory tree at `path`, not including `path`
+    /// itself.
+    pub fn dir_entry_count(&self, path: &Path) -> Result<usize> {
+        self.walk_dir_and_then(path, |walk| {
+            walk.try_fold(0, |count, entry| entry.map(|_| count + 1))
+        })
+    }
+
+    fn tree_usage<'p>(&self, path: &'p Path) -> Result<DirUsage<'p>> {
+        let mut usage = DirUsage {
+            path,
+            files: 0,
+            bytes: 0,
+        };
+        self.walk_files(path, &mut |entry| {
+            usage.files += 1;
+            usage.bytes += entry.metadata().len();
+            Ok(())
+        })?;
+        Ok(usage)
+    }
+
+    /// Find files with identical content in the directory tree at `path`.
+    ///
+    /// All non-empty files in the tree are read in chunks and hashed with a clone of `hasher`.
+    /// Files with the same size and hash are then reported to `callback` as [`Duplicate`][]s.
+    /// Files of the same group share the same group index, but are reported in the order of the
+    /// walk, so other files may be reported between two files of a group.  As the hash is not
+    /// cryptographically strong in general, applications should compare the contents before
+    /// deduplicating files.
+    ///
+    /// At most `N` files can be hashed, larger trees result in [`Error::NO_MEMORY`][].  The
+    /// callback may remove the reported file but must not make any other changes to the tree.
+    ///
+    /// Returns the number of groups of identical files.
+    pub fn find_duplicates<H, const N: usize>(
+        &self,
+        path: &Path,
+        hasher: H,
+        mut callback: impl FnMut(&Duplicate<'_>) -> Result<()>,
+    ) -> Result<usize>
+    where
+        H: Hasher + Clone,
+    {
+        // (size, hash, index of the file in the walk)
+        let mut files: heapless::Vec<(usize, u64, usize), N> = heapless::Vec::new();
+        let mut buf: Bytes<Storage::CACHE_SIZE> = Default::default();
+        self.walk_files(path, &mut |entry| {
+            let size = entry.metadata().len();
+            if size > 0 {
+                let mut hasher = hasher.clone();
+                self.open_file_and_then(entry.path(), |file| loop {
+                    let n = file.read(&mut buf)?;
+                    if n == 0 {
+                        return Ok(());
+                    }
+                    hasher.write(&buf[..n]);
+                })?;
+                let index = files.len();
+                files
+                    .push((size, hasher.finish(), index))
+                    .map_err(|_| Error::NO_MEMORY)?;
+            }
+            Ok(())
+        })?;
+
+        // assign group indices in the order of the walk, None for unique files
+        files.sort_unstable();
+        let mut groups: heapless::Vec<(usize, Option<usize>, usize, u64), N> = heapless::Vec::new();
+        let mut count = 0;
+        for chunk in files.chunk_by(|a, b| (a.0, a.1) == (b.0, b.1)) {
+            let group = (chunk.len() > 1).then(|| {
+                count += 1;
+                count - 1
+            });
+            for &(size, hash, index) in chunk {
+                groups.push((index, group, size, hash)).ok();
+            }
+        }
+        groups.sort_unstable();
+
+        if count > 0 {
+            let mut groups = groups.iter();
+            self.walk_files(path, &mut |entry| {
+                if entry.metadata().is_empty() {
+                    return Ok(());
+                }
+                let &(_, group, size, hash) = groups.next().ok_or(Error::INVALID)?;
+                if size != entry.metadata().len() {
+                    return Err(Error::INVALID);
+                }
+                if let Some(group) = group {
+                    callback(&Duplicate {
+                        group,
+                        path: entry.path(),
+                        size,
+                        hash,
+                    })?;
+                }
+                Ok(())
+            })?;
+        }
+
+        Ok(count)
+    }
+
+    /// Call `f` for all files in the directory tree at `path`.
+    ///
+    /// `f` is called while no directory is open and may remove the file that is passed to it.
+    fn walk_files(&self, path: &Path, f: &mut dyn FnMut(&DirEntry) -> Result<()>) -> Result<()> {
+        let mut walk = self.walk_dir(path);
+        while let Some(step) = walk.step() {
+            let WalkStep::Entry(entry) = step? else {
+                continue;
+            };
+            if entry.file_type().is_file() {
+                f(&entry)?;
+                if !self.exists(entry.path()) {
+                    walk.removed();
+                }
+            }
+        }
+        Ok(())
+    }
+
     /// Check whether a file or directory exists at a path.
     ///
     /// This is equivalent to calling [`Filesystem::metadata`][] and checking for an `Ok` return
@@ -406,6 +1554,232 @@ impl<Storage: driver::Storage> Filesystem<'_, Storage> {
         File::open_and_then(self, path, f)
     }
 
+    /// Open several files at the same time and call `f` with them.
+    ///
+    /// The files are opened in the given order with the given flags.  After `f` returns, or if
+    /// one of the files cannot be opened, all opened files are closed in reverse order.  The
+    /// result of `f` or the first error is returned.
+    ///
+    /// ```
+    ///# use littlefs2::{fs::{FileOpenFlags, Filesystem}, path, ram_storage, driver, io::{Read, Result, Write}};
+    ///# ram_storage!(tiny);
+    ///# let mut ram = Ram::default();
+    ///# let mut storage = RamStorage::new(&mut ram);
+    ///# Filesystem::format(&mut storage).unwrap();
+    ///# Filesystem::mount_and_then(&mut storage, |fs| {
+    /// fs.write(path!("a"), b"data")?;
+    /// let files = [
+    ///     (path!("a"), FileOpenFlags::READ),
+    ///     (path!("b"), FileOpenFlags::WRITE | FileOpenFlags::CREATE),
+    /// ];
+    /// fs.open_files_and_then(files, |[a, b]| {
+    ///     let mut buf = [0; 4];
+    ///     a.read_exact(&mut buf)?;
+    ///     b.write_all(&buf)
+    /// })?;
+    /// assert_eq!(fs.read::<4>(path!("b"))?, b"data");
+    ///# Ok(())
+    ///# }).unwrap();
+    /// ```
+    pub fn open_files_and_then<R, const N: usize>(
+        &self,
+        files: [(&Path, FileOpenFlags); N],
+        f: impl FnOnce(&[File<'_, '_, Storage>; N]) -> Result<R>,
+    ) -> Result<R> {
+        let mut allocs: [FileAllocation<Storage>; N] =
+            core::array::from_fn(|_| FileAllocation::new());
+        let mut opened: heapless::Vec<File<'_, '_, Storage>, N> = heapless::Vec::new();
+        for (alloc, (path, flags)) in allocs.iter_mut().zip(files) {
+            match unsafe { OpenOptions::from(flags).open(self, alloc, path) } {
+                Ok(file) => opened
+                    .push(file)
+                    .unwrap_or_else(|_| unreachable!("capacity is sufficient for all files")),
+                Err(err) => {
+                    while let Some(file) = opened.pop() {
+                        unsafe { file.close() }.ok();
+                    }
+                    return Err(err);
+                }
+            }
+        }
+
+        let Ok(files) = opened.into_array::<N>() else {
+            unreachable!("all files are opened");
+        };
+        let result = f(&files);
+        let mut closed = Ok(());
+        for file in files.into_iter().rev() {
+            closed = closed.and(unsafe { file.close() });
+        }
+        let result = result?;
+        closed.map(|()| result)
+    }
+
+    /// Copy the contents and attributes of a file to a new file, replacing an existing file at
+    /// `to`.
+    ///
+    /// The contents are copied in chunks of `CACHE_SIZE` bytes.  Returns the number of copied
+    /// bytes.  Fails with [`Error::INVALID`][] if `from` and `to` resolve to the same path, see
+    /// [`Path::normalize`][].
+    pub fn copy(&self, from: &Path, to: &Path) -> Result<usize> {
+        let normalize = |path: &Path| path.normalize().map_err(|_| Error::INVALID);
+        if normalize(from)? == normalize(to)? {
+            return Err(Error::INVALID);
+        }
+        copy_file(self, from, self, to)
+    }
+
+    /// Recursively copy a directory with all its contents and attributes.
+    ///
+    /// `to` is created if it does not exist yet, and existing files in it are replaced.  Fails
+    /// with [`Error::INVALID`][] if `to` resolves to `from` or a path below `from`, see
+    /// [`Path::normalize`][], and with [`Error::NO_MEMORY`][] if the tree is nested deeper than
+    /// [`DIR_DEPTH_MAX`](crate::consts::DIR_DEPTH_MAX).
+    pub fn copy_dir_all(&self, from: &Path, to: &Path) -> Result<()> {
+        if is_ancestor(from, to, false) {
+            return Err(Error::INVALID);
+        }
+        if !self.metadata(from)?.is_dir() {
+            return Err(Error::PATH_NOT_DIR);
+        }
+        self.create_dir_all(to)?;
+        copy_tree(self, from, self, to, &mut |_| {})
+    }
+
+    /// Serialize the whole directory tree, including the file contents and all attributes, to
+    /// `writer`.
+    ///
+    /// The stream can be restored with [`Filesystem::restore_from`][], for example on a
+    /// replacement device with a different storage geometry.  It starts with the magic bytes
+    /// `LFSB` and the format version 1, followed by a sequence of records.  Every record starts
+    /// with a tag byte, and all integers are little-endian:
+    ///
+    /// - `D`, path length (`u16`), path: a directory.  The first record is the root directory.
+    /// - `F`, path length (`u16`), path, data length (`u32`), data: a file.
+    /// - `A`, ID (`u8`), value length (`u16`), value: an attribute of the preceding directory or
+    ///   file.
+    /// - `Z`: the end of the stream.
+    ///
+    /// Directories are written before their contents.  The file contents are copied through a
+    /// stack buffer of [`Attribute::MAX_SIZE`][] bytes, so the memory usage does not depend on the
+    /// size of the filesystem.  Fails with [`Error::NO_MEMORY`][] if the tree is nested deeper
+    /// than [`DIR_DEPTH_MAX`](crate::consts::DIR_DEPTH_MAX).
+    ///
+    /// ```
+    ///# use littlefs2::{driver, fs::Filesystem, io::{Result, Write}, path, ram_storage};
+    ///# use core::cell::RefCell;
+    ///# ram_storage!(tiny);
+    ///# struct Stream(RefCell<Vec<u8>>, RefCell<usize>);
+    ///# impl Write for Stream {
+    ///#     fn write(&self, data: &[u8]) -> Result<usize> { self.0.borrow_mut().extend_from_slice(data); Ok(data.len()) }
+    ///#     fn flush(&self) -> Result<()> { Ok(()) }
+    ///# }
+    ///# impl littlefs2::io::Read for Stream {
+    ///#     fn read(&self, buf: &mut [u8]) -> Result<usize> {
+    ///#         let data = self.0.borrow();
+    ///#         let mut pos = self.1.borrow_mut();
+    ///#         let n = buf.len().min(data.len() - *pos);
+    ///#         buf[..n].copy_from_slice(&data[*pos..][..n]);
+    ///#         *pos += n;
+    ///#         Ok(n)
+    ///#     }
+    ///# }
+    ///# let stream = Stream(RefCell::new(Vec::new()), RefCell::new(0));
+    ///# let mut ram = Ram::default();
+    ///# let mut storage = RamStorage::new(&mut ram);
+    ///# Filesystem::format(&mut storage).unwrap();
+    /// // `stream` implements `io::Write` and `io::Read`, for example a serial connection
+    /// Filesystem::mount_and_then(&mut storage, |fs| {
+    ///     fs.create_dir(path!("keys"))?;
+    ///     fs.write(path!("keys/1"), b"secret")?;
+    ///     fs.set_attribute(path!("keys/1"), 1, b"counter")?;
+    ///     fs.backup_to(&stream)
+    /// })
+    /// .unwrap();
+    ///
+    ///# let mut ram = Ram::default();
+    ///# let mut storage = RamStorage::new(&mut ram);
+    /// // on the replacement device
+    /// Filesystem::format(&mut storage).unwrap();
+    /// Filesystem::mount_and_then(&mut storage, |fs| {
+    ///     fs.restore_from(&stream)?;
+    ///     assert_eq!(fs.read::<6>(path!("keys/1"))?, b"secret");
+    ///     Ok(())
+    /// })
+    /// .unwrap();
+    /// ```
+    pub fn backup_to(&self, writer: &dyn io::Write) -> Result<()> {
+        writer.write_all(BACKUP_MAGIC)?;
+        write_backup_entry(self, writer, crate::path!("/"), true)?;
+        self.walk_dir_and_then(crate::path!("/"), |walk| {
+            for entry in walk {
+                let entry = entry?;
+                write_backup_entry(self, writer, entry.path(), entry.file_type().is_dir())?;
+            }
+            Ok(())
+        })?;
+        writer.write_all(b"Z")?;
+        writer.flush()
+    }
+
+    /// Restore a directory tree serialized with [`Filesystem::backup_to`][] from `reader`.
+    ///
+    /// The entries are merged into the current tree: missing directories are created, and
+    /// existing files and attributes are replaced.  Fails with [`Error::CORRUPTION`][] if the
+    /// stream is not a valid backup, and with [`Error::IO`][] if it ends before the end record.
+    /// The entries that have been restored before an error are kept.
+    pub fn restore_from(&self, reader: &dyn io::Read) -> Result<()> {
+        let mut buffer = [0; Attribute::MAX_SIZE as _];
+        read_backup_exact(reader, &mut buffer[..BACKUP_MAGIC.len()])?;
+        if buffer[..BACKUP_MAGIC.len()] != *BACKUP_MAGIC {
+            return Err(Error::CORRUPTION);
+        }
+
+        let mut path: Option<PathBuf> = None;
+        loop {
+            read_backup_exact(reader, &mut buffer[..1])?;
+            match buffer[0] {
+                tag @ (b'D' | b'F') => {
+                    let entry = read_backup_path(reader, &mut buffer)?;
+                    if tag == b'D' {
+                        if let Err(error) = self.create_dir(&entry) {
+                            if error != Error::ENTRY_ALREADY_EXISTED
+                                || !self.metadata(&entry)?.is_dir()
+                            {
+                                return Err(error);
+                            }
+                        }
+                    } else {
+                        read_backup_exact(reader, &mut buffer[..4])?;
+                        let len = u32::from_le_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]);
+                        let mut len = len as usize;
+                        self.create_file_and_then(&entry, |file| {
+                            while len > 0 {
+                                let n = len.min(buffer.len());
+                                read_backup_exact(reader, &mut buffer[..n])?;
+                                io::Write::write_all(file, &buffer[..n])?;
+                                len -= n;
+                            }
+                            Ok(())
+                        })?;
+                    }
+                    path = Some(entry);
+                }
+                b'A' => {
+                    let path = path.as_ref().ok_or(Error::CORRUPTION)?;
+                    read_backup_exact(reader, &mut buffer[..3])?;
+                    let id = buffer[0];
+                    let len = usize::from(u16::from_le_bytes([buffer[1], buffer[2]]));
+                    let value = buffer.get_mut(..len).ok_or(Error::CORRUPTION)?;
+                    read_backup_exact(reader, value)?;
+                    self.set_attribute(path, id, value)?;
+                }
+                b'Z' => return Ok(()),
+                _ => return Err(Error::CORRUPTION),
+            }
+        }
+    }
+
     pub fn with_options() -> OpenOptions {
         OpenOptions::new()
     }
@@ -455,13 +1829,122 @@ impl<Storage: driver::Storage> Filesystem<'_, Storage> {
 
     /// Remove attribute.
     pub fn remove_attribute(&self, path: &Path, id: u8) -> Result<()> {
+        self.check_path(path)?;
         let return_code =
             unsafe { ll::lfs_removeattr(&mut self.alloc.borrow_mut().state, path.as_ptr(), id) };
         result_from((), return_code)
     }
 
+    /// Calls `f` with the ID and the value of every attribute that is set on `path`.
+    ///
+    /// littlefs cannot list the attributes of a path, so this probes all 256 IDs in ascending
+    /// order, using a stack buffer of [`Attribute::MAX_SIZE`][] bytes.
+    ///
+    /// ```
+    ///# use littlefs2::{driver, fs::Filesystem, io::Result, path, ram_storage};
+    ///# ram_storage!(tiny);
+    ///# let mut ram = Ram::default();
+    ///# let mut storage = RamStorage::new(&mut ram);
+    ///# Filesystem::format(&mut storage).unwrap();
+    ///# Filesystem::mount_and_then(&mut storage, |fs| {
+    /// fs.write(path!("file"), b"data")?;
+    /// fs.set_attribute(path!("file"), 7, b"seven")?;
+    /// fs.set_attribute(path!("file"), 3, b"three")?;
+    ///
+    /// let mut ids = Vec::new();
+    /// fs.attributes(path!("file"), |id, data| {
+    ///     ids.push((id, data.to_vec()));
+    ///     Ok(())
+    /// })?;
+    /// assert_eq!(ids, [(3, b"three".to_vec()), (7, b"seven".to_vec())]);
+    ///# Ok(())
+    ///# }).unwrap();
+    /// ```
+    pub fn attributes(
+        &self,
+        path: &Path,
+        mut f: impl FnMut(u8, &[u8]) -> Result<()>,
+    ) -> Result<()> {
+        let mut buffer = [0; Attribute::MAX_SIZE as _];
+        for id in 0..=u8::MAX {
+            if let Some(attribute) = self.attribute(path, id, &mut buffer)? {
+                f(id, attribute.data())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Read the layout version stored by [`Filesystem::ensure_version`][].
+    ///
+    /// Returns `None` if no version has been stored yet.
+    pub fn layout_version(&self) -> Result<Option<u32>> {
+        let mut buffer = [0; 4];
+        let Some(attribute) = self.attribute(
+            crate::path!("/"),
+            crate::consts::LAYOUT_VERSION_ATTRIBUTE_ID,
+            &mut buffer,
+        )?
+        else {
+            return Ok(None);
+        };
+        let data = attribute.data().try_into().map_err(|_| Error::CORRUPTION)?;
+        Ok(Some(u32::from_le_bytes(data)))
+    }
+
+    /// Make sure that the application data on the filesystem uses the layout version `current`.
+    ///
+    /// The layout version is stored in the attribute
+    /// [`LAYOUT_VERSION_ATTRIBUTE_ID`](crate::consts::LAYOUT_VERSION_ATTRIBUTE_ID) of the root directory.  A
+    /// filesystem without a stored version is treated as version 0.  If the stored version is
+    /// lower than `current`, `migrate` is called once for every version in between with the
+    /// filesystem and the version to migrate from, i. e. with `found`, `found + 1`, ...,
+    /// `current - 1`.  After every successful migration step, the new version is stored.
+    ///
+    /// If a migration step is interrupted, for example by a power loss, it is repeated on the
+    /// next call, so migration steps should be idempotent.  If `migrate` returns an error, the
+    /// version of the last successful step is kept and the error is returned.
+    ///
+    /// Returns the version that was found on the filesystem.  If the stored version is higher
+    /// than `current`, nothing is changed and [`Error::INVALID`][] is returned.
+    ///
+    /// ```
+    ///# use littlefs2::{driver, fs::Filesystem, io::Result, path, ram_storage};
+    ///# ram_storage!(tiny);
+    ///# let mut ram = Ram::default();
+    ///# let mut storage = RamStorage::new(&mut ram);
+    ///# Filesystem::format(&mut storage).unwrap();
+    ///# Filesystem::mount_and_then(&mut storage, |fs| {
+    /// fs.ensure_version(2, |fs, version| match version {
+    ///     0 => fs.create_dir_all(path!("/config")),
+    ///     1 => fs.write(path!("/config/defaults"), b"v2"),
+    ///     _ => unreachable!(),
+    /// })?;
+    /// assert_eq!(fs.layout_version()?, Some(2));
+    ///# Ok(())
+    ///# }).unwrap();
+    /// ```
+    pub fn ensure_version<F>(&self, current: u32, mut migrate: F) -> Result<u32>
+    where
+        F: FnMut(&Self, u32) -> Result<()>,
+    {
+        let found = self.layout_version()?.unwrap_or(0);
+        if found > current {
+            return Err(Error::INVALID);
+        }
+        for version in found..current {
+            migrate(self, version)?;
+            self.set_attribute(
+                crate::path!("/"),
+                crate::consts::LAYOUT_VERSION_ATTRIBUTE_ID,
+                &(version + 1).to_le_bytes(),
+            )?;
+        }
+        Ok(found)
+    }
+
     /// Set attribute.
     pub fn set_attribute(&self, path: &Path, id: u8, data: &[u8]) -> Result<()> {
+        self.check_path(path)?;
         let return_code = unsafe {
             ll::lfs_setattr(
                 &mut self.alloc.borrow_mut().state,
@@ -476,7 +1959,7 @@ impl<Storage: driver::Storage> Filesystem<'_, Storage> {
     }
 
     /// C callback interface used by LittleFS to read data with the lower level system below the
-    /// filesystem.
+    /// filesystem.  The storage reads directly into the buffer provided by LittleFS.
     extern "C" fn lfs_config_read(
         c: *const ll::lfs_config,
         block: ll::lfs_block_t,
@@ -525,19 +2008,28 @@ impl<Storage: driver::Storage> Filesystem<'_, Storage> {
     }
 
     /// C callback interface used by LittleFS to sync data with the lower level interface below the
-    /// filesystem. Note that this function currently does nothing.
-    extern "C" fn lfs_config_sync(_c: *const ll::lfs_config) -> c_int {
-        // println!("in lfs_config_sync");
-        // Do nothing; we presume that data is synchronized.
-        0
+    /// filesystem.
+    extern "C" fn lfs_config_sync(c: *const ll::lfs_config) -> c_int {
+        let storage = unsafe { &mut *((*c).context as *mut Storage) };
+        error_code_from(storage.sync())
     }
 }
 
 /// The state of a `File`. Pre-allocate with `File::allocate`.
 pub struct FileAllocation<S: driver::Storage> {
-    cache: UnsafeCell<Bytes<S::CACHE_SIZE>>,
+    cache: Buffer<Bytes<S::CACHE_SIZE>>,
     state: ll::lfs_file_t,
     config: ll::lfs_file_config,
+    /// The modification time that is written when a modified file is closed.
+    #[cfg(feature = "timestamps")]
+    mtime: [u8; 8],
+    /// The attribute for `mtime` if the file has been opened without other attributes.
+    #[cfg(feature = "timestamps")]
+    mtime_attr: ll::lfs_attr,
+    /// Whether the attributes of the file are followed by a slot for `mtime`, see
+    /// [`OpenOptions::open_with_attributes_and_then`][].
+    #[cfg(feature = "timestamps")]
+    mtime_reserved: bool,
 }
 
 impl<S: driver::Storage> Default for FileAllocation<S> {
@@ -554,11 +2046,58 @@ impl<S: driver::Storage> FileAllocation<S> {
     }
 }
 
+/// A custom attribute that is read and written together with the contents of a file.
+///
+/// See [`OpenOptions::open_with_attributes_and_then`][].
+#[repr(transparent)]
+pub struct FileAttribute<'a> {
+    attr: ll::lfs_attr,
+    buffer: PhantomData<&'a mut [u8]>,
+}
+
+impl<'a> FileAttribute<'a> {
+    /// Creates an attribute with the given ID that uses `buffer` for its value.
+    ///
+    /// The size of the attribute is the length of the buffer.  Attributes that are written must
+    /// not be larger than [`Attribute::MAX_SIZE`][].
+    pub fn new(id: u8, buffer: &'a mut [u8]) -> Self {
+        Self {
+            attr: ll::lfs_attr {
+                type_: id,
+                buffer: buffer.as_mut_ptr().cast(),
+                size: u32::try_from(buffer.len()).unwrap_or(u32::MAX),
+            },
+            buffer: PhantomData,
+        }
+    }
+
+    pub fn id(&self) -> u8 {
+        self.attr.type_
+    }
+
+    pub fn data(&self) -> &[u8] {
+        // the buffer is borrowed for 'a, and its length fits into a u32
+        unsafe { slice::from_raw_parts(self.attr.buffer as *const u8, self.attr.size as usize) }
+    }
+
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        // the buffer is borrowed mutably for 'a, and its length fits into a u32
+        unsafe { slice::from_raw_parts_mut(self.attr.buffer as *mut u8, self.attr.size as usize) }
+    }
+}
+
 pub struct File<'a, 'b, S: driver::Storage> {
     // We must store a raw pointer here since the FFI retains a copy of a pointer
     // to the field alloc.state, so we cannot assert unique mutable access.
     alloc: RefCell<*mut FileAllocation<S>>,
     fs: &'b Filesystem<'a, S>,
+    rolled_back: Cell<bool>,
+    /// Hash of the path if the file has been opened for writing.
+    #[cfg(feature = "change-tracking")]
+    path_hash: Option<u32>,
+    /// Whether the file has been modified since it was opened.
+    #[cfg(feature = "timestamps")]
+    modified: Cell<bool>,
 }
 
 impl<'a, 'b, Storage: driver::Storage> File<'a, 'b, Storage> {
@@ -628,6 +2167,7 @@ impl<'a, 'b, Storage: driver::Storage> File<'a, 'b, Storage> {
     ///
     /// This must not be called twice.
     pub unsafe fn close(self) -> Result<()> {
+        self.fs.invalidate_used_blocks();
         let return_code = ll::lfs_file_close(
             &mut self.fs.alloc.borrow_mut().state,
             // We need to use addr_of_mut! here instead of & mut since
@@ -640,6 +2180,8 @@ impl<'a, 'b, Storage: driver::Storage> File<'a, 'b, Storage> {
 
     /// Synchronize file contents to storage.
     pub fn sync(&self) -> Result<()> {
+        self.check_rolled_back()?;
+        self.fs.invalidate_used_blocks();
         let return_code = unsafe {
             // We need to use addr_of_mut! here instead of & mut since
             // the FFI stores a copy of a pointer to the field state,
@@ -654,6 +2196,7 @@ impl<'a, 'b, Storage: driver::Storage> File<'a, 'b, Storage> {
 
     /// Size of the file in bytes.
     pub fn len(&self) -> Result<usize> {
+        self.check_rolled_back()?;
         let return_code = unsafe {
             // We need to use addr_of_mut! here instead of & mut since
             // the FFI stores a copy of a pointer to the field state,
@@ -663,70 +2206,266 @@ impl<'a, 'b, Storage: driver::Storage> File<'a, 'b, Storage> {
                 addr_of_mut!((*(*self.alloc.borrow_mut())).state),
             )
         };
-        u32_result(return_code).map(|n| n as usize)
+        usize_result(return_code)
     }
 
     pub fn is_empty(&self) -> Result<bool> {
         self.len().map(|l| l == 0)
     }
 
-    /// Truncates or extends the underlying file, updating the size of this file to become size.
+    /// Current position in the file in bytes.
     ///
-    /// If the size is less than the current file's size, then the file will be shrunk. If it is
-    /// greater than the current file's size, then the file will be extended to size and have all
-    /// of the intermediate data filled in with 0s.
-    pub fn set_len(&self, size: usize) -> Result<()> {
+    /// This is equivalent to `seek(SeekFrom::Current(0))` but does not modify the file.
+    pub fn tell(&self) -> Result<usize> {
+        self.check_rolled_back()?;
         let return_code = unsafe {
             // We need to use addr_of_mut! here instead of & mut since
             // the FFI stores a copy of a pointer to the field state,
             // so we cannot assert unique mutable access.
-            ll::lfs_file_truncate(
+            ll::lfs_file_tell(
                 &mut self.fs.alloc.borrow_mut().state,
                 addr_of_mut!((*(*self.alloc.borrow_mut())).state),
-                size as u32,
             )
         };
-        result_from((), return_code)
+        usize_result(return_code)
     }
 
-    // This belongs in `io::Read` but really don't want that to have a generic parameter
-    pub fn read_to_end<const N: usize>(&self, buf: &mut heapless::Vec<u8, N>) -> Result<usize> {
-        // My understanding of
-        // https://github.com/littlefs-project/littlefs/blob/4c9146ea539f72749d6cc3ea076372a81b12cb11/lfs.c#L2816
-        // is that littlefs keeps reading until either the buffer is full, or the file is exhausted
+    /// Moves the position to the start of the file.
+    ///
+    /// This is equivalent to `seek(SeekFrom::Start(0))`.
+    pub fn rewind(&self) -> Result<()> {
+        self.check_rolled_back()?;
+        let return_code = unsafe {
+            // We need to use addr_of_mut! here instead of & mut since
+            // the FFI stores a copy of a pointer to the field state,
+            // so we cannot assert unique mutable access.
+            ll::lfs_file_rewind(
+                &mut self.fs.alloc.borrow_mut().state,
+                addr_of_mut!((*(*self.alloc.borrow_mut())).state),
+            )
+        };
+        result_from((), return_code)
+    }
 
-        let had = buf.len();
-        // no panic by construction
-        buf.resize_default(buf.capacity()).unwrap();
-        // use io::Read;
-        let read = self.read(&mut buf[had..])?;
-        // no panic by construction
-        buf.resize_default(had + read).unwrap();
-        Ok(read)
+    /// Returns whether an operation on this file failed in a way that prevents littlefs from
+    /// committing the file.
+    ///
+    /// If a write fails, for example because the storage returned an error, littlefs marks the
+    /// file as erred.  Syncing or closing an erred file does not write anything to the storage,
+    /// so the changes since the last successful sync are lost.
+    pub fn is_erred(&self) -> bool {
+        let flags = unsafe { (*(*self.alloc.borrow())).state.flags };
+        flags & ll::lfs_open_flags_LFS_F_ERRED != 0
     }
 
-    pub fn read(&self, buf: &mut [u8]) -> Result<usize> {
-        <Self as io::Read>::read(self, buf)
+    /// Discard all changes since the file was opened or last synced.
+    ///
+    /// The file is marked as erred so that closing it does not commit anything to the storage.
+    /// Note that a file that was created when it was opened is not removed again.
+    /// Afterwards, all other operations on this file fail with [`Error::BAD_FILE_DESCRIPTOR`][]
+    /// and the file must be closed.  To retry the modification, open the file again.
+    pub fn rollback(&self) {
+        unsafe {
+            // We need to use addr_of_mut! here instead of & mut since
+            // the FFI stores a copy of a pointer to the field state,
+            // so we cannot assert unique mutable access.
+            let state = addr_of_mut!((*(*self.alloc.borrow_mut())).state);
+            (*state).flags |= ll::lfs_open_flags_LFS_F_ERRED;
+        }
+        self.rolled_back.set(true);
+        #[cfg(feature = "timestamps")]
+        self.modified.set(false);
     }
 
-    pub fn seek(&self, pos: io::SeekFrom) -> Result<usize> {
-        <Self as io::Seek>::seek(self, pos)
+    fn check_writable(&self) -> Result<()> {
+        self.fs.check_writable()?;
+        #[cfg(feature = "change-tracking")]
+        if let Some(hash) = self.path_hash {
+            self.fs.record_change(hash);
+        }
+        #[cfg(feature = "timestamps")]
+        self.modified.set(true);
+        Ok(())
     }
 
-    pub fn write(&self, buf: &[u8]) -> Result<usize> {
-        <Self as io::Write>::write(self, buf)
+    /// Close the file and, with the `timestamps` feature, update its modification time if it has
+    /// been modified.
+    ///
+    /// The time is written as an attribute of the file in the same commit as its contents.
+    unsafe fn close_and_touch(self) -> Result<()> {
+        #[cfg(feature = "timestamps")]
+        if let (true, Some(now)) = (self.modified.get(), self.fs.now()) {
+            self.attach_mtime(now);
+        }
+        self.close()
     }
-}
 
-/// Options and flags which can be used to configure how a file is opened.
-///
-/// This builder exposes the ability to configure how a File is opened and what operations
-/// are permitted on the open file. The File::open and File::create methods are aliases
-/// for commonly used options using this builder.
-///
-/// Consider `File::with_options()` to avoid having to `use` OpenOptions.
-#[derive(Clone, Debug, Eq, PartialEq)]
-pub struct OpenOptions(FileOpenFlags);
+    /// Add the modification time to the attributes that are written when the file is synced or
+    /// closed.
+    #[cfg(feature = "timestamps")]
+    unsafe fn attach_mtime(&self, time: u64) {
+        let alloc = *self.alloc.borrow_mut();
+        (*alloc).mtime = time.to_le_bytes();
+        let config = addr_of_mut!((*alloc).config);
+        if (*alloc).mtime_reserved {
+            // the slot after the attributes passed to open_with_attributes_and_then
+            (*config).attr_count += 1;
+        } else if (*config).attr_count == 0 {
+            (*alloc).mtime_attr = ll::lfs_attr {
+                type_: crate::consts::MTIME_ATTRIBUTE_ID,
+                buffer: addr_of_mut!((*alloc).mtime).cast(),
+                size: 8,
+            };
+            (*config).attrs = addr_of_mut!((*alloc).mtime_attr);
+            (*config).attr_count = 1;
+        } else {
+            // the caller has passed a modification time attribute
+            return;
+        }
+        // littlefs only commits the attributes of a dirty file
+        (*alloc).state.flags |= ll::lfs_open_flags_LFS_F_DIRTY;
+    }
+
+    fn check_rolled_back(&self) -> Result<()> {
+        if self.rolled_back.get() {
+            Err(Error::BAD_FILE_DESCRIPTOR)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Truncates or extends the underlying file, updating the size of this file to become size.
+    ///
+    /// If the size is less than the current file's size, then the file will be shrunk. If it is
+    /// greater than the current file's size, then the file will be extended to size and have all
+    /// of the intermediate data filled in with 0s.
+    pub fn set_len(&self, size: usize) -> Result<()> {
+        self.check_rolled_back()?;
+        self.check_writable()?;
+        let size = u32::try_from(size).map_err(|_| Error::FILE_TOO_BIG)?;
+        let return_code = unsafe {
+            // We need to use addr_of_mut! here instead of & mut since
+            // the FFI stores a copy of a pointer to the field state,
+            // so we cannot assert unique mutable access.
+            ll::lfs_file_truncate(
+                &mut self.fs.alloc.borrow_mut().state,
+                addr_of_mut!((*(*self.alloc.borrow_mut())).state),
+                size,
+            )
+        };
+        result_from((), return_code)
+    }
+
+    /// Overwrites `len` bytes starting at `offset` with zeros.
+    ///
+    /// The zeros are written in chunks of `CACHE_SIZE` bytes.  If the range ends after the end
+    /// of the file, the file is extended.  littlefs cannot free blocks in the middle of a file,
+    /// so this does not reclaim any space by itself, but zeroing the end of a file allows
+    /// [`File::rewrite_compacted`][] to truncate it.  The position of the file is not changed.
+    ///
+    /// Fails with [`Error::INVALID`][] if the file was opened in append mode.
+    pub fn zero_range(&self, offset: usize, len: usize) -> Result<()> {
+        self.check_rolled_back()?;
+        let flags = unsafe { (*(*self.alloc.borrow())).state.flags };
+        if flags & ll::lfs_open_flags_LFS_O_APPEND != 0 {
+            return Err(Error::INVALID);
+        }
+        let end = offset.checked_add(len).ok_or(Error::FILE_TOO_BIG)?;
+        let offset = u32::try_from(offset).map_err(|_| Error::FILE_TOO_BIG)?;
+        u32::try_from(end).map_err(|_| Error::FILE_TOO_BIG)?;
+
+        let pos = self.tell()?;
+        let zeros: Bytes<Storage::CACHE_SIZE> = Default::default();
+        let result = self.seek(io::SeekFrom::Start(offset)).and_then(|_| {
+            let mut remaining = len;
+            while remaining > 0 {
+                let n = remaining.min(zeros.len());
+                io::Write::write_all(self, &zeros[..n])?;
+                remaining -= n;
+            }
+            Ok(())
+        });
+        self.restore_position(pos, result)
+    }
+
+    /// Truncates the trailing zeros of the file, returning the new size.
+    ///
+    /// littlefs frees the blocks after the new end of the file, so together with
+    /// [`File::zero_range`][], this reclaims the space at the end of files that are trimmed
+    /// from the back, for example logs or append-only databases.  The file is read from the end
+    /// in chunks of `CACHE_SIZE` bytes.  The position of the file is not changed.
+    pub fn rewrite_compacted(&self) -> Result<usize> {
+        self.check_rolled_back()?;
+        let pos = self.tell()?;
+        let mut buf: Bytes<Storage::CACHE_SIZE> = Default::default();
+        let result = self.len().and_then(|len| {
+            let mut end = len;
+            while end > 0 {
+                let start = end.saturating_sub(buf.len());
+                // positions fit into u32 because the file length does
+                self.seek(io::SeekFrom::Start(start as u32))?;
+                let chunk = &mut buf[..end - start];
+                io::Read::read_exact(self, chunk)?;
+                if let Some(i) = chunk.iter().rposition(|&byte| byte != 0) {
+                    end = start + i + 1;
+                    break;
+                }
+                end = start;
+            }
+            if end < len {
+                self.set_len(end)?;
+            }
+            Ok(end)
+        });
+        self.restore_position(pos, result)
+    }
+
+    fn restore_position<T>(&self, pos: usize, result: Result<T>) -> Result<T> {
+        // pos was returned by seek, so it fits into u32
+        let restored = self.seek(io::SeekFrom::Start(pos as u32));
+        let value = result?;
+        restored?;
+        Ok(value)
+    }
+
+    // This belongs in `io::Read` but really don't want that to have a generic parameter
+    pub fn read_to_end<const N: usize>(&self, buf: &mut heapless::Vec<u8, N>) -> Result<usize> {
+        // My understanding of
+        // https://github.com/littlefs-project/littlefs/blob/4c9146ea539f72749d6cc3ea076372a81b12cb11/lfs.c#L2816
+        // is that littlefs keeps reading until either the buffer is full, or the file is exhausted
+
+        let had = buf.len();
+        // no panic by construction
+        buf.resize_default(buf.capacity()).unwrap();
+        // use io::Read;
+        let read = self.read(&mut buf[had..])?;
+        // no panic by construction
+        buf.resize_default(had + read).unwrap();
+        Ok(read)
+    }
+
+    pub fn read(&self, buf: &mut [u8]) -> Result<usize> {
+        <Self as io::Read>::read(self, buf)
+    }
+
+    pub fn seek(&self, pos: io::SeekFrom) -> Result<usize> {
+        <Self as io::Seek>::seek(self, pos)
+    }
+
+    pub fn write(&self, buf: &[u8]) -> Result<usize> {
+        <Self as io::Write>::write(self, buf)
+    }
+}
+
+/// Options and flags which can be used to configure how a file is opened.
+///
+/// This builder exposes the ability to configure how a File is opened and what operations
+/// are permitted on the open file. The File::open and File::create methods are aliases
+/// for commonly used options using this builder.
+///
+/// Consider `File::with_options()` to avoid having to `use` OpenOptions.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct OpenOptions(FileOpenFlags);
 
 impl Default for OpenOptions {
     fn default() -> Self {
@@ -752,6 +2491,13 @@ impl OpenOptions {
         alloc: &mut FileAllocation<S>,
         path: &Path,
     ) -> Result<File<'a, 'b, S>> {
+        let writing = FileOpenFlags::WRITE
+            | FileOpenFlags::CREATE
+            | FileOpenFlags::TRUNCATE
+            | FileOpenFlags::APPEND;
+        if self.0.intersects(writing) {
+            fs.check_path(path)?;
+        }
         alloc.config.buffer = alloc.cache.get() as *mut _;
         // We need to use addr_of_mut! here instead of & mut since
         // the FFI stores a copy of a pointer to the field state,
@@ -767,11 +2513,40 @@ impl OpenOptions {
         let file = File {
             alloc: RefCell::new(alloc),
             fs,
+            rolled_back: Cell::new(false),
+            #[cfg(feature = "change-tracking")]
+            path_hash: self.0.intersects(writing).then(|| path_hash(path)),
+            #[cfg(feature = "timestamps")]
+            modified: Cell::new(false),
         };
 
         result_from(file, return_code)
     }
 
+    /// Open the file with the options previously specified, storing its allocation on the heap.
+    ///
+    /// The returned [`OwnedFile`][] is closed when it is dropped.  This requires the `alloc`
+    /// feature.
+    #[cfg(feature = "alloc")]
+    pub fn open_owned<'a, 'b, S: driver::Storage>(
+        &self,
+        fs: &'b Filesystem<'a, S>,
+        path: &Path,
+    ) -> Result<OwnedFile<'a, 'b, S>> {
+        let alloc = alloc::boxed::Box::into_raw(alloc::boxed::Box::new(FileAllocation::new()));
+        match unsafe { self.open(fs, &mut *alloc, path) } {
+            Ok(file) => Ok(OwnedFile {
+                file: Some(file),
+                alloc,
+            }),
+            Err(error) => {
+                // littlefs does not keep a reference to a file that could not be opened
+                drop(unsafe { alloc::boxed::Box::from_raw(alloc) });
+                Err(error)
+            }
+        }
+    }
+
     /// (Hopefully) safe abstraction around `open`.
     pub fn open_and_then<'a, R, S: driver::Storage>(
         &self,
@@ -786,7 +2561,87 @@ impl OpenOptions {
         // Or if closing fails because something is broken and
         // we'd already know that from an Err res.
         let res = f(&mut file);
-        unsafe { file.close()? };
+        unsafe { file.close_and_touch()? };
+        res
+    }
+
+    /// Like [`open_and_then`](Self::open_and_then), but reads and writes custom attributes
+    /// together with the file.
+    ///
+    /// If the file is opened for reading, the stored values of the attributes are read into their
+    /// buffers when the file is opened.  Attributes that are not set keep the value of their
+    /// buffer.  If the file is opened for writing, the values of the buffers are written when the
+    /// file is synced or closed, in the same commit as the contents of the file.  So a file that
+    /// is opened for reading and writing keeps its attributes.  To replace them, open the file
+    /// write-only.
+    ///
+    /// With the `timestamps` feature, the modification time is written together with the other
+    /// attributes unless one of them uses [`MTIME_ATTRIBUTE_ID`][crate::consts::MTIME_ATTRIBUTE_ID].
+    /// In this case, at most 15 attributes can be passed for a file that is opened for writing.
+    ///
+    /// ```
+    ///# use littlefs2::{driver, fs::{FileAttribute, Filesystem, OpenOptions}, io::Result, path, ram_storage};
+    ///# ram_storage!(tiny);
+    ///# let mut ram = Ram::default();
+    ///# let mut storage = RamStorage::new(&mut ram);
+    ///# Filesystem::format(&mut storage).unwrap();
+    ///# Filesystem::mount_and_then(&mut storage, |fs| {
+    /// let mut version = 2u32.to_le_bytes();
+    /// OpenOptions::new()
+    ///     .write(true)
+    ///     .create(true)
+    ///     .truncate(true)
+    ///     .open_with_attributes_and_then(
+    ///         fs,
+    ///         path!("config"),
+    ///         &mut [FileAttribute::new(1, &mut version)],
+    ///         |file| file.write(b"new config").map(drop),
+    ///     )?;
+    ///
+    /// let mut buffer = [0; 4];
+    /// let attribute = fs.attribute(path!("config"), 1, &mut buffer)?.unwrap();
+    /// assert_eq!(attribute.data(), 2u32.to_le_bytes());
+    ///# Ok(())
+    ///# }).unwrap();
+    /// ```
+    pub fn open_with_attributes_and_then<'a, R, S: driver::Storage>(
+        &self,
+        fs: &Filesystem<'a, S>,
+        path: &Path,
+        attributes: &mut [FileAttribute<'_>],
+        f: impl FnOnce(&File<'a, '_, S>) -> Result<R>,
+    ) -> Result<R> {
+        let mut alloc = FileAllocation::new();
+        // FileAttribute is a transparent wrapper around lfs_attr
+        alloc.config.attrs = attributes.as_mut_ptr().cast();
+        alloc.config.attr_count = u32::try_from(attributes.len()).map_err(|_| Error::INVALID)?;
+        // attributes followed by a slot for the modification time, see File::attach_mtime
+        #[cfg(feature = "timestamps")]
+        let mut with_mtime = heapless::Vec::<ll::lfs_attr, 16>::new();
+        #[cfg(feature = "timestamps")]
+        if self.0.contains(FileOpenFlags::WRITE)
+            && !attributes
+                .iter()
+                .any(|attribute| attribute.id() == crate::consts::MTIME_ATTRIBUTE_ID)
+        {
+            let mtime = ll::lfs_attr {
+                type_: crate::consts::MTIME_ATTRIBUTE_ID,
+                buffer: addr_of_mut!(alloc.mtime).cast(),
+                size: 8,
+            };
+            for attr in attributes
+                .iter()
+                .map(|attribute| attribute.attr)
+                .chain([mtime])
+            {
+                with_mtime.push(attr).map_err(|_| Error::INVALID)?;
+            }
+            alloc.config.attrs = with_mtime.as_mut_ptr();
+            alloc.mtime_reserved = true;
+        }
+        let mut file = unsafe { self.open(fs, &mut alloc, path)? };
+        let res = f(&mut file);
+        unsafe { file.close_and_touch()? };
         res
     }
 
@@ -859,6 +2714,7 @@ impl From<FileOpenFlags> for OpenOptions {
 
 impl<S: driver::Storage> io::Read for File<'_, '_, S> {
     fn read(&self, buf: &mut [u8]) -> Result<usize> {
+        self.check_rolled_back()?;
         let return_code = unsafe {
             // We need to use addr_of_mut! here instead of & mut since
             // the FFI stores a copy of a pointer to the field state,
@@ -867,15 +2723,16 @@ impl<S: driver::Storage> io::Read for File<'_, '_, S> {
                 &mut self.fs.alloc.borrow_mut().state,
                 addr_of_mut!((*(*self.alloc.borrow_mut())).state),
                 buf.as_mut_ptr() as *mut c_void,
-                buf.len() as u32,
+                u32::try_from(buf.len()).unwrap_or(u32::MAX),
             )
         };
-        u32_result(return_code).map(|n| n as usize)
+        usize_result(return_code)
     }
 }
 
 impl<S: driver::Storage> io::Seek for File<'_, '_, S> {
     fn seek(&self, pos: io::SeekFrom) -> Result<usize> {
+        self.check_rolled_back()?;
         let return_code = unsafe {
             // We need to use addr_of_mut! here instead of & mut since
             // the FFI stores a copy of a pointer to the field state,
@@ -887,12 +2744,14 @@ impl<S: driver::Storage> io::Seek for File<'_, '_, S> {
                 pos.whence(),
             )
         };
-        u32_result(return_code).map(|n| n as usize)
+        usize_result(return_code)
     }
 }
 
 impl<S: driver::Storage> io::Write for File<'_, '_, S> {
     fn write(&self, buf: &[u8]) -> Result<usize> {
+        self.check_rolled_back()?;
+        self.check_writable()?;
         let return_code = unsafe {
             // We need to use addr_of_mut! here instead of & mut since
             // the FFI stores a copy of a pointer to the field state,
@@ -901,10 +2760,10 @@ impl<S: driver::Storage> io::Write for File<'_, '_, S> {
                 &mut self.fs.alloc.borrow_mut().state,
                 addr_of_mut!((*(*self.alloc.borrow_mut())).state),
                 buf.as_ptr() as *const c_void,
-                buf.len() as u32,
+                u32::try_from(buf.len()).unwrap_or(u32::MAX),
             )
         };
-        u32_result(return_code).map(|n| n as usize)
+        usize_result(return_code)
     }
 
     fn flush(&self) -> Result<()> {
@@ -912,6 +2771,98 @@ impl<S: driver::Storage> io::Write for File<'_, '_, S> {
     }
 }
 
+/// A file that owns its allocation and is closed when it is dropped.
+///
+/// In contrast to the closure-based APIs like [`OpenOptions::open_and_then`][], an `OwnedFile`
+/// can be kept open across function calls, for example in the state of an async task or a state
+/// machine.  Its [`FileAllocation`][] is stored on the heap, so this type requires the `alloc`
+/// feature.  Dropping the file ignores errors when closing it; use [`OwnedFile::close`][] to
+/// handle them.
+///
+/// ```
+///# use littlefs2::{driver, fs::{Filesystem, OpenOptions}, io::{Result, Write as _}, path, ram_storage};
+///# ram_storage!(tiny);
+///# let mut ram = Ram::default();
+///# let mut storage = RamStorage::new(&mut ram);
+///# Filesystem::format(&mut storage).unwrap();
+///# Filesystem::mount_and_then(&mut storage, |fs| {
+/// let log = OpenOptions::new()
+///     .write(true)
+///     .create(true)
+///     .open_owned(fs, path!("log"))?;
+/// for line in [&b"first\n"[..], b"second\n"] {
+///     log.write_all(line)?;
+/// }
+/// log.close()?;
+/// assert_eq!(fs.metadata(path!("log"))?.len(), 13);
+///# Ok(())
+///# }).unwrap();
+/// ```
+#[cfg(feature = "alloc")]
+pub struct OwnedFile<'a, 'b, S: driver::Storage> {
+    // `Some` until the file is closed
+    file: Option<File<'a, 'b, S>>,
+    // The file keeps a pointer to the allocation, so it is only freed after closing the file.
+    alloc: *mut FileAllocation<S>,
+}
+
+#[cfg(feature = "alloc")]
+impl<S: driver::Storage> OwnedFile<'_, '_, S> {
+    /// Close the file and return the errors that are ignored when it is dropped.
+    pub fn close(mut self) -> Result<()> {
+        match self.file.take() {
+            Some(file) => unsafe { file.close_and_touch() },
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<'a, 'b, S: driver::Storage> core::ops::Deref for OwnedFile<'a, 'b, S> {
+    type Target = File<'a, 'b, S>;
+
+    fn deref(&self) -> &Self::Target {
+        // the file is only taken when closing or dropping it
+        self.file.as_ref().unwrap()
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<S: driver::Storage> Drop for OwnedFile<'_, '_, S> {
+    fn drop(&mut self) {
+        if let Some(file) = self.file.take() {
+            unsafe { file.close_and_touch() }.ok();
+        }
+        // the allocation was created with Box::into_raw in OpenOptions::open_owned
+        drop(unsafe { alloc::boxed::Box::from_raw(self.alloc) });
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<S: driver::Storage> io::Read for OwnedFile<'_, '_, S> {
+    fn read(&self, buf: &mut [u8]) -> Result<usize> {
+        io::Read::read(&**self, buf)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<S: driver::Storage> io::Seek for OwnedFile<'_, '_, S> {
+    fn seek(&self, pos: io::SeekFrom) -> Result<usize> {
+        io::Seek::seek(&**self, pos)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<S: driver::Storage> io::Write for OwnedFile<'_, '_, S> {
+    fn write(&self, buf: &[u8]) -> Result<usize> {
+        io::Write::write(&**self, buf)
+    }
+
+    fn flush(&self) -> Result<()> {
+        io::Write::flush(&**self)
+    }
+}
+
 pub struct ReadDirAllocation {
     state: ll::lfs_dir_t,
 }
@@ -960,8 +2911,9 @@ impl<'a, 'b, S: driver::Storage> Iterator for ReadDir<'a, 'b, S> {
 
             let path = self.path.join(&file_name);
 
-            let dir_entry = DirEntry::new(file_name, metadata, path);
-            return Some(Ok(dir_entry));
+            // littlefs does not return names longer than LFS_NAME_MAX
+            let dir_entry = DirEntry::new(file_name, metadata, path).map_err(|_| Error::CORRUPTION);
+            return Some(dir_entry);
         }
 
         if return_code == 0 {
@@ -972,7 +2924,227 @@ impl<'a, 'b, S: driver::Storage> Iterator for ReadDir<'a, 'b, S> {
     }
 }
 
+/// A filesystem that owns its storage and allocation.
+///
+/// [`Filesystem`][] borrows the storage and the allocation, which makes it hard to store a mounted
+/// filesystem in a `static` or in a resource struct of a task.  `OwnedFilesystem` has no
+/// lifetime and can be moved freely, also while it is mounted.  The filesystem is accessed with
+/// [`OwnedFilesystem::with`][], which provides a [`Filesystem`][] without mounting it again.
+/// [`OwnedFilesystem::new`][] is a `const fn`, so the filesystem can also be created in a
+/// `static` initializer.
+///
+/// Settings of the [`Filesystem`][], like `Filesystem::set_read_only` and
+/// `Filesystem::set_path_validator`, are kept between calls of [`OwnedFilesystem::with`][].  A
+/// clock that is installed with `Filesystem::set_clock` only applies within the closure; use
+/// `OwnedFilesystem::set_clock` to keep one.
+///
+/// ```
+///# use littlefs2::{driver, fs::OwnedFilesystem, io::Result, path, ram_storage};
+///# ram_storage!(tiny);
+///# let mut ram = Ram::default();
+///# let storage = RamStorage::new(&mut ram);
+/// let mut fs = OwnedFilesystem::new(storage);
+/// fs.format().unwrap();
+/// fs.mount().unwrap();
+/// fs.with(|fs| fs.write(path!("/file"), b"data")).unwrap();
+///
+/// let mut moved = fs;
+/// let data = moved.with(|fs| fs.read::<10>(path!("/file"))).unwrap();
+/// assert_eq!(data, b"data");
+/// ```
+pub struct OwnedFilesystem<S: driver::Storage> {
+    alloc: Allocation<S>,
+    storage: S,
+    mounted: bool,
+    settings: Settings,
+    #[cfg(feature = "timestamps")]
+    clock: Option<&'static (dyn driver::Clock + Sync)>,
+}
+
+// SAFETY: The raw pointers in the allocation only point to the allocation itself and to the
+// storage, which are owned by the `OwnedFilesystem`, and they are updated before every access.
+unsafe impl<S: driver::Storage + Send> Send for OwnedFilesystem<S> {}
+
+// SAFETY: The allocation is only accessed by methods that take `&mut self`.  A shared reference
+// only gives access to the storage, which is `Sync`.
+unsafe impl<S: driver::Storage + Sync> Sync for OwnedFilesystem<S> {}
+
+impl<S: driver::Storage> OwnedFilesystem<S> {
+    /// Creates an unmounted filesystem for the given storage.
+    pub const fn new(storage: S) -> Self {
+        Self {
+            alloc: Allocation::new(),
+            storage,
+            mounted: false,
+            settings: Settings::new(),
+            #[cfg(feature = "timestamps")]
+            clock: None,
+        }
+    }
+
+    /// Install a clock that is used within every call of [`OwnedFilesystem::with`][], or remove
+    /// it with `None`, see [`Filesystem::set_clock`][].
+    #[cfg(feature = "timestamps")]
+    pub fn set_clock(&mut self, clock: Option<&'static (dyn driver::Clock + Sync)>) {
+        self.clock = clock;
+    }
+
+    /// Format the storage, see [`Filesystem::format`][].
+    ///
+    /// The filesystem is unmounted afterwards.
+    pub fn format(&mut self) -> Result<()> {
+        self.mounted = false;
+        Filesystem::format(&mut self.storage)
+    }
+
+    /// Mount the filesystem, if it is not mounted yet.
+    pub fn mount(&mut self) -> Result<()> {
+        if !self.mounted {
+            Filesystem::mount(&mut self.alloc, &mut self.storage)?;
+            self.mounted = true;
+            // the cached state belongs to the previous mount
+            #[cfg(feature = "usage-cache")]
+            {
+                self.settings.used_blocks = None;
+            }
+            #[cfg(feature = "change-tracking")]
+            {
+                self.settings.changes = Changes::new();
+            }
+        }
+        Ok(())
+    }
+
+    /// Unmount the filesystem, if it is mounted.
+    pub fn unmount(&mut self) -> Result<()> {
+        if self.mounted {
+            self.mounted = false;
+            let fs = Filesystem::rebind(&mut self.alloc, &mut self.storage);
+            let return_code = unsafe { ll::lfs_unmount(&mut fs.alloc.borrow_mut().state) };
+            result_from((), return_code)?;
+        }
+        Ok(())
+    }
+
+    pub fn is_mounted(&self) -> bool {
+        self.mounted
+    }
+
+    /// Call `f` with the mounted filesystem.
+    ///
+    /// Returns [`Error::INVALID`][] if the filesystem is not mounted.
+    pub fn with<R>(&mut self, f: impl FnOnce(&Filesystem<'_, S>) -> Result<R>) -> Result<R> {
+        if !self.mounted {
+            return Err(Error::INVALID);
+        }
+        let fs = Filesystem::rebind(&mut self.alloc, &mut self.storage);
+        fs.apply_settings(self.settings);
+        #[cfg(feature = "timestamps")]
+        if let Some(clock) = self.clock {
+            fs.set_clock(Some(clock));
+        }
+        let result = f(&fs);
+        self.settings = fs.settings();
+        result
+    }
+
+    pub fn storage(&self) -> &S {
+        &self.storage
+    }
+
+    /// Returns the storage.  The filesystem does not need to be unmounted before.
+    pub fn into_inner(self) -> S {
+        self.storage
+    }
+}
+
+/// Iterator adapter that removes the `.` and `..` entries from an iterator over directory
+/// entries.
+///
+/// littlefs currently returns these entries first, but this adapter checks the names of the
+/// entries instead of relying on their position.  Errors are passed through.
+///
+/// ```
+///# use littlefs2::{driver, fs::{Filesystem, SkipDots}, io::Result, path, ram_storage};
+///# ram_storage!(tiny);
+///# let mut ram = Ram::default();
+///# let mut storage = RamStorage::new(&mut ram);
+///# Filesystem::format(&mut storage).unwrap();
+///# Filesystem::mount_and_then(&mut storage, |fs| {
+/// fs.write(path!("/file"), b"")?;
+/// fs.read_dir_and_then(path!("/"), |read_dir| {
+///     let entry = read_dir.skip_dots().next().unwrap()?;
+///     assert_eq!(entry.path(), path!("/file"));
+///     Ok(())
+/// })?;
+///# Ok(())
+///# }).unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct SkipDots<I> {
+    iter: I,
+}
+
+impl<I> SkipDots<I> {
+    pub fn new(iter: I) -> Self {
+        Self { iter }
+    }
+
+    pub fn into_inner(self) -> I {
+        self.iter
+    }
+}
+
+impl<I: Iterator<Item = Result<DirEntry>>> Iterator for SkipDots<I> {
+    type Item = Result<DirEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.find(|entry| {
+            entry.as_ref().map_or(true, |entry| {
+                let name = entry.file_name().as_str();
+                name != "." && name != ".."
+            })
+        })
+    }
+}
+
 impl<'a, 'b, S: driver::Storage> ReadDir<'a, 'b, S> {
+    /// Returns an iterator over the entries of this directory without the `.` and `..` entries.
+    ///
+    /// See [`SkipDots`][].
+    pub fn skip_dots(&mut self) -> SkipDots<&mut Self> {
+        SkipDots::new(self)
+    }
+
+    /// Returns the current position of the iteration.
+    ///
+    /// This wraps `lfs_dir_tell`.  The position can be passed to [`ReadDir::seek`][] or
+    /// [`Filesystem::read_dir_from_and_then`][] to continue the iteration after the last entry
+    /// that has been returned.
+    pub fn tell(&self) -> Result<DirIterationTell> {
+        let return_code = unsafe {
+            ll::lfs_dir_tell(
+                &mut self.fs.alloc.borrow_mut().state,
+                addr_of_mut!((*(*self.alloc.borrow_mut())).state),
+            )
+        };
+        u32_result(return_code).map(DirIterationTell::from_raw)
+    }
+
+    /// Continues the iteration at a position returned by [`ReadDir::tell`][].
+    ///
+    /// This wraps `lfs_dir_seek`.
+    pub fn seek(&mut self, tell: DirIterationTell) -> Result<()> {
+        let return_code = unsafe {
+            ll::lfs_dir_seek(
+                &mut self.fs.alloc.borrow_mut().state,
+                addr_of_mut!((*(*self.alloc.borrow_mut())).state),
+                tell.into_raw(),
+            )
+        };
+        result_from((), return_code)
+    }
+
     // Safety-hatch to experiment with missing parts of API
     pub unsafe fn borrow_filesystem<'c>(&'c mut self) -> &'c Filesystem<'a, S> {
         self.fs
@@ -1001,51 +3173,267 @@ impl<S: driver::Storage> ReadDir<'_, '_, S> {
     }
 }
 
-impl<'a, Storage: driver::Storage> Filesystem<'a, Storage> {
-    pub fn read_dir_and_then<R>(
-        &self,
-        path: &Path,
-        // *not* &ReadDir, as Iterator takes &mut
-        f: impl FnOnce(&mut ReadDir<'_, '_, Storage>) -> Result<R>,
-    ) -> Result<R> {
-        let mut alloc = ReadDirAllocation::new();
-        let mut read_dir = unsafe { self.read_dir(&mut alloc, path)? };
-        let res = f(&mut read_dir);
-        // unsafe { read_dir.close()? };
-        read_dir.close()?;
-        res
-    }
-
-    /// Returns a pseudo-iterator over the entries within a directory.
-    ///
-    /// This is unsafe since it can induce UB just like File::open.
-    pub unsafe fn read_dir<'b>(
-        &'b self,
-        alloc: &'b mut ReadDirAllocation,
-        path: &'b Path,
-    ) -> Result<ReadDir<'a, 'b, Storage>> {
-        // ll::lfs_dir_open stores a copy of the pointer to alloc.state, so
-        // we must use addr_of_mut! here, since &mut alloc.state asserts unique
-        // mutable access, and we need shared mutable access.
-        let return_code = ll::lfs_dir_open(
-            &mut self.alloc.borrow_mut().state,
-            addr_of_mut!(alloc.state),
-            path.as_ptr(),
-        );
-
-        let read_dir = ReadDir {
-            alloc: RefCell::new(alloc),
-            fs: self,
-            path,
-        };
+/// Depth-first iterator over the entries of a directory tree, see
+/// [`Filesystem::walk_dir_and_then`][].
+///
+/// Every directory is yielded before its contents.  No directory is kept open between two calls
+/// of [`Iterator::next`][]: the iterator stores the position of the walk in each level and seeks
+/// to it when it reopens the current directory.  It stops after the first error.
+pub struct WalkDir<'a, 'b, S: driver::Storage> {
+    fs: &'b Filesystem<'a, S>,
+    // For every level of the walk, the number of visited entries that are still present.
+    positions: heapless::Vec<usize, { crate::consts::DIR_DEPTH_MAX + 1 }>,
+    // The current directory, or the directory that is entered on the next step if `descend` is
+    // set.
+    dir: PathBuf,
+    descend: bool,
+    // Length of the path of the starting point.
+    root_len: usize,
+}
 
-        result_from(read_dir, return_code)
-    }
+/// A step of a [`WalkDir`][].
+#[allow(clippy::large_enum_variant)]
+pub(crate) enum WalkStep {
+    /// The next entry of the walk.
+    Entry(DirEntry),
+    /// All entries of a directory have been visited.  Contains the path of the directory and the
+    /// number of entries that are still present.
+    Leave(PathBuf, usize),
 }
 
-impl<'a, Storage: driver::Storage> Filesystem<'a, Storage> {
-    pub fn mount(alloc: &'a mut Allocation<Storage>, storage: &'a mut Storage) -> Result<Self> {
-        let fs = Self::new(alloc, storage);
+impl<S: driver::Storage> WalkDir<'_, '_, S> {
+    /// Like [`Iterator::next`][], but also reports when a directory has been left.
+    ///
+    /// If reading a directory fails, the walk stays in the directory, see [`WalkDir::leave`][].
+    /// A directory that is nested too deeply is not entered and results in
+    /// [`Error::NO_MEMORY`][].
+    pub(crate) fn step(&mut self) -> Option<Result<WalkStep>> {
+        if self.descend {
+            self.descend = false;
+            if self.positions.push(0).is_err() {
+                self.enter_parent();
+                return Some(Err(Error::NO_MEMORY));
+            }
+        }
+        let position = *self.positions.last()?;
+        let next = self.fs.read_dir_and_then(&self.dir, |read_dir| {
+            if position > 0 {
+                // the positions of the iteration include the . and .. entries
+                let tell = u32::try_from(position + 2).map_err(|_| Error::NO_MEMORY)?;
+                match read_dir.seek(DirIterationTell::from_raw(tell)) {
+                    // littlefs rejects seeking to the end of a directory
+                    Err(error) if error == Error::INVALID => return Ok(None),
+                    result => result?,
+                }
+            }
+            read_dir.skip_dots().next().transpose()
+        });
+        match next {
+            Ok(Some(entry)) => {
+                *self.positions.last_mut().unwrap() += 1;
+                if entry.file_type().is_dir() {
+                    self.dir = entry.path().into();
+                    self.descend = true;
+                }
+                Some(Ok(WalkStep::Entry(entry)))
+            }
+            Ok(None) => {
+                let dir = self.dir.clone();
+                let remaining = self.leave();
+                Some(Ok(WalkStep::Leave(dir, remaining)))
+            }
+            Err(error) => Some(Err(error)),
+        }
+    }
+
+    /// The level of the directory that contains the last entry, starting at zero for the
+    /// starting point of the walk.
+    pub(crate) fn level(&self) -> usize {
+        self.positions.len().saturating_sub(1)
+    }
+
+    /// The current directory of the walk.
+    pub(crate) fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The part of a path of the walk below the starting point.
+    pub(crate) fn relative<'p>(&self, path: &'p Path) -> &'p Path {
+        let relative = &path.as_str_ref_with_trailing_nul()[self.root_len..];
+        Path::from_str_with_nul(relative.trim_start_matches('/')).unwrap_or(crate::path!(""))
+    }
+
+    /// Do not enter the directory that has been returned last.
+    pub(crate) fn skip_dir(&mut self) {
+        if self.descend {
+            self.descend = false;
+            self.enter_parent();
+        }
+    }
+
+    /// Report that the entry of the last step has been removed or moved out of its directory.
+    ///
+    /// The entry is not counted as visited and, if it is a directory, not entered.
+    pub(crate) fn removed(&mut self) {
+        self.skip_dir();
+        if let Some(position) = self.positions.last_mut() {
+            *position = position.saturating_sub(1);
+        }
+    }
+
+    /// Leave the current directory without visiting its remaining entries, and return the number
+    /// of visited entries that are still present.
+    pub(crate) fn leave(&mut self) -> usize {
+        self.skip_dir();
+        let remaining = self.positions.pop().unwrap_or_default();
+        if !self.positions.is_empty() {
+            self.enter_parent();
+        }
+        remaining
+    }
+
+    /// Replace `dir` with its parent directory.
+    fn enter_parent(&mut self) {
+        // All paths below the starting point are created with `Path::join`, so the parent of a
+        // nested directory is everything before its last separator.
+        let bytes = self.dir.as_str().as_bytes();
+        let end = if self.positions.len() == 1 {
+            self.root_len
+        } else {
+            bytes.iter().rposition(|byte| *byte == b'/').unwrap_or(0)
+        };
+        self.dir = PathBuf::try_from(&bytes[..end]).unwrap_or_default();
+    }
+}
+
+impl<S: driver::Storage> Iterator for WalkDir<'_, '_, S> {
+    type Item = Result<DirEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.step()? {
+                Ok(WalkStep::Entry(entry)) => return Some(Ok(entry)),
+                Ok(WalkStep::Leave(..)) => continue,
+                Err(error) => {
+                    self.positions.clear();
+                    return Some(Err(error));
+                }
+            }
+        }
+    }
+}
+
+impl<'a, Storage: driver::Storage> Filesystem<'a, Storage> {
+    pub fn read_dir_and_then<R>(
+        &self,
+        path: &Path,
+        // *not* &ReadDir, as Iterator takes &mut
+        f: impl FnOnce(&mut ReadDir<'_, '_, Storage>) -> Result<R>,
+    ) -> Result<R> {
+        let mut alloc = ReadDirAllocation::new();
+        let mut read_dir = unsafe { self.read_dir(&mut alloc, path)? };
+        let res = f(&mut read_dir);
+        // unsafe { read_dir.close()? };
+        read_dir.close()?;
+        res
+    }
+
+    /// Like [`Filesystem::read_dir_and_then`][], but starts the iteration at `tell`.
+    ///
+    /// `tell` must have been returned by [`ReadDir::tell`][] for the same directory, and the
+    /// directory must not have been modified since then.  This can be used to list a large
+    /// directory in pages without keeping it open in between.
+    ///
+    /// ```
+    ///# use littlefs2::{fs::{DirIterationTell, Filesystem}, path, ram_storage, driver, io::Result};
+    ///# ram_storage!(tiny);
+    ///# let mut ram = Ram::default();
+    ///# let mut storage = RamStorage::new(&mut ram);
+    ///# Filesystem::format(&mut storage).unwrap();
+    ///# Filesystem::mount_and_then(&mut storage, |fs| {
+    ///# fs.write(path!("a"), b"")?;
+    ///# fs.write(path!("b"), b"")?;
+    /// let tell = fs.read_dir_and_then(path!("/"), |dir| {
+    ///     // skip . and .. and the first file
+    ///     dir.nth(2).transpose()?;
+    ///     dir.tell()
+    /// })?;
+    /// fs.read_dir_from_and_then(path!("/"), tell, |dir| {
+    ///     assert_eq!(dir.next().unwrap()?.file_name(), "b");
+    ///     Ok(())
+    /// })?;
+    ///# Ok(())
+    ///# }).unwrap();
+    /// ```
+    pub fn read_dir_from_and_then<R>(
+        &self,
+        path: &Path,
+        tell: DirIterationTell,
+        f: impl FnOnce(&mut ReadDir<'_, '_, Storage>) -> Result<R>,
+    ) -> Result<R> {
+        self.read_dir_and_then(path, |read_dir| {
+            read_dir.seek(tell)?;
+            f(read_dir)
+        })
+    }
+
+    /// Call `f` with a depth-first iterator over all entries below `path`, see [`WalkDir`][].
+    ///
+    /// The `.` and `..` entries are skipped, and the entry for `path` itself is not included.
+    /// The tree must not be modified during the walk.  If it is nested deeper than
+    /// [`DIR_DEPTH_MAX`](crate::consts::DIR_DEPTH_MAX), the iterator returns
+    /// [`Error::NO_MEMORY`][].
+    pub fn walk_dir_and_then<R>(
+        &self,
+        path: &Path,
+        f: impl FnOnce(&mut WalkDir<'_, '_, Storage>) -> Result<R>,
+    ) -> Result<R> {
+        f(&mut self.walk_dir(path))
+    }
+
+    fn walk_dir(&self, path: &Path) -> WalkDir<'a, '_, Storage> {
+        let mut positions = heapless::Vec::new();
+        // cannot fail as the capacity is at least one
+        positions.push(0).ok();
+        WalkDir {
+            fs: self,
+            positions,
+            dir: path.into(),
+            descend: false,
+            root_len: path.as_str().len(),
+        }
+    }
+
+    /// Returns a pseudo-iterator over the entries within a directory.
+    ///
+    /// This is unsafe since it can induce UB just like File::open.
+    pub unsafe fn read_dir<'b>(
+        &'b self,
+        alloc: &'b mut ReadDirAllocation,
+        path: &'b Path,
+    ) -> Result<ReadDir<'a, 'b, Storage>> {
+        // ll::lfs_dir_open stores a copy of the pointer to alloc.state, so
+        // we must use addr_of_mut! here, since &mut alloc.state asserts unique
+        // mutable access, and we need shared mutable access.
+        let return_code = ll::lfs_dir_open(
+            &mut self.alloc.borrow_mut().state,
+            addr_of_mut!(alloc.state),
+            path.as_ptr(),
+        );
+
+        let read_dir = ReadDir {
+            alloc: RefCell::new(alloc),
+            fs: self,
+            path,
+        };
+
+        result_from(read_dir, return_code)
+    }
+}
+
+impl<'a, Storage: driver::Storage> Filesystem<'a, Storage> {
+    pub fn mount(alloc: &'a mut Allocation<Storage>, storage: &'a mut Storage) -> Result<Self> {
+        let fs = Self::new(alloc, storage);
         fs.raw_mount()?;
         Ok(fs)
     }
@@ -1067,7 +3455,57 @@ impl<'a, Storage: driver::Storage> Filesystem<'a, Storage> {
         Ok(fs)
     }
 
+    /// Mount the filesystem and protect the given paths and all paths below them.
+    ///
+    /// Operations that would create, write, rename or remove a protected path, or rename or
+    /// recursively remove one of its ancestors, fail with [`Error::PATH_PROTECTED`][].  The
+    /// returned [`Privilege`][] allows to bypass the protection with
+    /// [`Filesystem::privileged_and_then`][], for example to update factory data.
+    ///
+    /// ```
+    ///# use littlefs2::{fs::Filesystem, io::Error, path, ram_storage, driver, io::Result};
+    ///# ram_storage!(tiny);
+    ///# let mut ram = Ram::default();
+    ///# let mut storage = RamStorage::new(&mut ram);
+    ///# Filesystem::format(&mut storage).unwrap();
+    /// let mut alloc = Filesystem::allocate();
+    /// let (fs, privilege) =
+    ///     Filesystem::mount_protected(&mut alloc, &mut storage, &[path!("/factory")]).unwrap();
+    /// assert_eq!(fs.create_dir(path!("/factory")), Err(Error::PATH_PROTECTED));
+    /// fs.privileged_and_then(&privilege, |fs| fs.create_dir(path!("/factory"))).unwrap();
+    /// fs.write(path!("/app"), b"data").unwrap();
+    /// ```
+    #[cfg(feature = "protected-paths")]
+    pub fn mount_protected(
+        alloc: &'a mut Allocation<Storage>,
+        storage: &'a mut Storage,
+        protected: &'a [&'a Path],
+    ) -> Result<(Self, Privilege)> {
+        let fs = Self::mount(alloc, storage)?;
+        fs.protected.set(protected);
+        Ok((fs, Privilege { _private: () }))
+    }
+
+    /// Mount the filesystem or, if it is corrupted or not formatted, format it and mount it
+    /// again.
+    ///
+    /// Only [`Error::CORRUPTION`][] leads to formatting the storage.  All other errors are
+    /// returned.
+    pub fn mount_or_format(
+        alloc: &'a mut Allocation<Storage>,
+        storage: &'a mut Storage,
+    ) -> Result<Self> {
+        Self::mount_or_else(alloc, storage, |err, storage| {
+            if err == Error::CORRUPTION {
+                Filesystem::format(storage)
+            } else {
+                Err(err)
+            }
+        })
+    }
+
     fn raw_mount(&self) -> Result<()> {
+        Allocation::<Storage>::check_config()?;
         let mut alloc = self.alloc.borrow_mut();
         let return_code = unsafe { ll::lfs_mount(&mut alloc.state, &alloc.config) };
         drop(alloc);
@@ -1085,9 +3523,153 @@ impl<'a, Storage: driver::Storage> Filesystem<'a, Storage> {
         Filesystem {
             alloc: RefCell::new(alloc),
             storage,
+            #[cfg(feature = "read-only")]
+            read_only: Cell::new(false),
+            #[cfg(feature = "path-validator")]
+            path_validator: Cell::new(None),
+            #[cfg(feature = "usage-cache")]
+            used_blocks: Cell::new(None),
+            #[cfg(feature = "timestamps")]
+            clock: Cell::new(None),
+            #[cfg(feature = "change-tracking")]
+            changes: Cell::new(Changes::new()),
+            #[cfg(feature = "protected-paths")]
+            protected: Cell::new(&[]),
+            #[cfg(feature = "protected-paths")]
+            privileged: Cell::new(false),
+        }
+    }
+
+    fn settings(&self) -> Settings {
+        Settings {
+            #[cfg(feature = "read-only")]
+            read_only: self.read_only.get(),
+            #[cfg(feature = "path-validator")]
+            path_validator: self.path_validator.get(),
+            #[cfg(feature = "usage-cache")]
+            used_blocks: self.used_blocks.get(),
+            #[cfg(feature = "change-tracking")]
+            changes: self.changes.get(),
         }
     }
 
+    fn apply_settings(&self, settings: Settings) {
+        let Settings {
+            #[cfg(feature = "read-only")]
+            read_only,
+            #[cfg(feature = "path-validator")]
+            path_validator,
+            #[cfg(feature = "usage-cache")]
+            used_blocks,
+            #[cfg(feature = "change-tracking")]
+            changes,
+        } = settings;
+        #[cfg(feature = "read-only")]
+        self.read_only.set(read_only);
+        #[cfg(feature = "path-validator")]
+        self.path_validator.set(path_validator);
+        #[cfg(feature = "usage-cache")]
+        self.used_blocks.set(used_blocks);
+        #[cfg(feature = "change-tracking")]
+        self.changes.set(changes);
+    }
+
+    /// Install a clock for features that record timestamps, or remove it with `None`.
+    ///
+    /// With the `test-doubles` feature, `test_doubles::TestClock` provides a clock that can be
+    /// controlled by tests.
+    #[cfg(feature = "timestamps")]
+    pub fn set_clock(&self, clock: Option<&'a dyn driver::Clock>) {
+        self.clock.set(clock);
+    }
+
+    /// Returns the current time of the installed clock, or `None` if no clock is installed.
+    #[cfg(feature = "timestamps")]
+    pub fn now(&self) -> Option<u64> {
+        self.clock.get().map(|clock| clock.now())
+    }
+
+    /// Returns the modification time of `path`, or `None` if none has been recorded.
+    ///
+    /// With the `timestamps` feature and an installed clock, files that are modified with
+    /// [`Filesystem::write`][], [`Filesystem::write_chunk`][] or any of the `*_and_then` methods
+    /// for opening files get the current time of the clock as their modification time when they
+    /// are closed.  The time is written in the same commit as the contents of the file, so a
+    /// power loss cannot leave new contents with an old time.  The time is stored as a
+    /// little-endian `u64` in the attribute
+    /// [`MTIME_ATTRIBUTE_ID`](crate::consts::MTIME_ATTRIBUTE_ID).  Files that are opened with the
+    /// unsafe `open` methods are not tracked.
+    ///
+    /// ```
+    ///# use littlefs2::{driver, fs::Filesystem, io::Result, path, ram_storage};
+    ///# ram_storage!(tiny);
+    ///# let mut ram = Ram::default();
+    ///# let mut storage = RamStorage::new(&mut ram);
+    ///# Filesystem::format(&mut storage).unwrap();
+    /// struct Clock;
+    ///
+    /// impl driver::Clock for Clock {
+    ///     fn now(&self) -> u64 {
+    ///         1_000
+    ///     }
+    /// }
+    ///
+    ///# Filesystem::mount_and_then(&mut storage, |fs| {
+    /// fs.set_clock(Some(&Clock));
+    /// fs.write(path!("log"), b"first entry")?;
+    /// assert_eq!(fs.mtime(path!("log"))?, Some(1_000));
+    ///
+    /// fs.set_mtime(path!("log"), 0)?;
+    /// fs.append(path!("log"), b"second entry")?;
+    /// assert_eq!(fs.mtime(path!("log"))?, Some(1_000));
+    ///# Ok(())
+    ///# }).unwrap();
+    /// ```
+    #[cfg(feature = "timestamps")]
+    pub fn mtime(&self, path: &Path) -> Result<Option<u64>> {
+        let mut buffer = [0; 8];
+        let Some(attribute) =
+            self.attribute(path, crate::consts::MTIME_ATTRIBUTE_ID, &mut buffer)?
+        else {
+            return Ok(None);
+        };
+        let data = attribute.data().try_into().map_err(|_| Error::CORRUPTION)?;
+        Ok(Some(u64::from_le_bytes(data)))
+    }
+
+    /// Sets the modification time of `path`, see [`Filesystem::mtime`][].
+    #[cfg(feature = "timestamps")]
+    pub fn set_mtime(&self, path: &Path, time: u64) -> Result<()> {
+        self.set_attribute(path, crate::consts::MTIME_ATTRIBUTE_ID, &time.to_le_bytes())
+    }
+
+    /// Sets the modification time of `path` to the current time if a clock is installed.
+    #[cfg(feature = "timestamps")]
+    fn update_mtime(&self, path: &Path) -> Result<()> {
+        match self.now() {
+            Some(now) => self.set_mtime(path, now),
+            None => Ok(()),
+        }
+    }
+
+    /// Create a `Filesystem` for an allocation that has already been mounted and may have been
+    /// moved since then.
+    ///
+    /// The littlefs state contains pointers to the configuration and the buffers of the
+    /// allocation.  These are updated to the current address of the allocation.
+    fn rebind(alloc: &'a mut Allocation<Storage>, storage: &'a mut Storage) -> Self {
+        let fs = Self::new(alloc, storage);
+        {
+            let mut alloc = fs.alloc.borrow_mut();
+            let alloc = &mut **alloc;
+            alloc.state.cfg = &alloc.config;
+            alloc.state.rcache.buffer = alloc.config.read_buffer as _;
+            alloc.state.pcache.buffer = alloc.config.prog_buffer as _;
+            alloc.state.free.buffer = alloc.config.lookahead_buffer as _;
+        }
+        fs
+    }
+
     /// Deconstruct `Filesystem`, intention is to allow access to
     /// the underlying Flash peripheral in driver::Storage etc.
     ///
@@ -1096,61 +3678,78 @@ impl<'a, Storage: driver::Storage> Filesystem<'a, Storage> {
         (self.alloc.into_inner(), self.storage)
     }
 
+    /// Creates an empty file at the provided path if no file or directory exists at that path.
+    ///
+    /// Existing entries are only looked up and their contents are not modified.  With the
+    /// `timestamps` feature and an installed clock, the modification time of an existing file is
+    /// set to the current time, see `Filesystem::mtime`.  A new file gets the current time in
+    /// the same commit that creates it.  Note that littlefs needs a file cache to create a file,
+    /// so creating a file uses as much stack as opening it with [`OpenOptions`][].
+    pub fn touch(&self, path: &Path) -> Result<()> {
+        self.check_path(path)?;
+        match self.metadata(path) {
+            #[cfg_attr(not(feature = "timestamps"), allow(unused_variables))]
+            Ok(metadata) => {
+                #[cfg(feature = "timestamps")]
+                if metadata.is_file() {
+                    self.update_mtime(path)?;
+                }
+                return Ok(());
+            }
+            Err(error) if error == Error::NO_SUCH_ENTRY => {}
+            Err(error) => return Err(error),
+        }
+        let mut alloc = FileAllocation::new();
+        let file = unsafe {
+            OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(self, &mut alloc, path)?
+        };
+        #[cfg(feature = "timestamps")]
+        file.modified.set(true);
+        unsafe { file.close_and_touch() }
+    }
+
     /// Creates a new, empty directory at the provided path.
     pub fn create_dir(&self, path: &Path) -> Result<()> {
         #[cfg(test)]
         println!("creating {:?}", path);
+        self.check_path(path)?;
         let return_code =
             unsafe { ll::lfs_mkdir(&mut self.alloc.borrow_mut().state, path.as_ptr()) };
         result_from((), return_code)
     }
 
     /// Recursively create a directory and all of its parent components if they are missing.
+    ///
+    /// Components that already exist are left untouched.  If one of them is not a directory,
+    /// [`Error::PATH_NOT_DIR`][] is returned.
     pub fn create_dir_all(&self, path: &Path) -> Result<()> {
-        // Placeholder implementation!
-        // - Path should gain a few methods
-        // - Maybe should pull in `heapless-bytes` (and merge upstream into `heapless`)
-        // - All kinds of sanity checks and possible logic errors possible...
-
-        let path_slice = path.as_ref().as_bytes();
-        for i in 0..path_slice.len() {
-            if path_slice[i] == b'/' {
-                let dir = PathBuf::try_from(&path_slice[..i]).map_err(|_| Error::IO)?;
-                #[cfg(test)]
-                println!("generated PathBuf dir {:?} using i = {}", &dir, i);
-                if let Err(error) = self.create_dir(&dir) {
-                    if error != Error::ENTRY_ALREADY_EXISTED {
-                        return Err(error);
-                    }
+        self.check_path(path)?;
+        let bytes = path.as_str().as_bytes();
+        // every separator that terminates a component, followed by the end of the path
+        let ends = bytes
+            .iter()
+            .enumerate()
+            .filter(|&(i, byte)| *byte == b'/' && i > 0 && bytes[i - 1] != b'/')
+            .map(|(i, _)| i)
+            .chain(core::iter::once(bytes.len()));
+
+        for end in ends {
+            let dir = PathBuf::try_from(&bytes[..end]).map_err(|_| Error::INVALID)?;
+            #[cfg(test)]
+            println!("generated PathBuf dir {:?} using end = {}", &dir, end);
+            if let Err(error) = self.create_dir(&dir) {
+                if error != Error::ENTRY_ALREADY_EXISTED {
+                    return Err(error);
+                }
+                if !self.metadata(&dir)?.is_dir() {
+                    return Err(Error::PATH_NOT_DIR);
                 }
-            }
-        }
-        if let Err(error) = self.create_dir(path) {
-            if error != Error::ENTRY_ALREADY_EXISTED {
-                return Err(error);
             }
         }
         Ok(())
-
-        // if path.as_ref() == "" {
-        //     return Ok(());
-        // }
-
-        // match self.create_dir(path) {
-        //     Ok(()) => return Ok(()),
-        //     Err(_) if path.is_dir() => return Ok(()),
-        //     Err(e) => return Err(e),
-        // }
-
-        // match path.parent() {
-        //     Some(p) => self.create_dir(p)?,
-        //     None => panic!("unexpected"),
-        // }
-
-        // match self.create_dir(path) {
-        //     Ok(()) => return Ok(()),
-        //     Err(e) => return Err(e),
-        // }
     }
 
     /// Read the entire contents of a file into a bytes vector.
@@ -1182,6 +3781,40 @@ impl<'a, Storage: driver::Storage> Filesystem<'a, Storage> {
         Ok((contents, file_len))
     }
 
+    /// Compute the digest of the contents of a file.
+    ///
+    /// The file is read in chunks of `CACHE_SIZE` bytes, so files of any size can be hashed with
+    /// a small, fixed amount of memory.  Requires the `digest` feature.
+    ///
+    /// ```
+    ///# use littlefs2::{driver, fs::Filesystem, io::Result, path, ram_storage};
+    ///# use sha2::{Digest, Sha256};
+    ///# ram_storage!(tiny);
+    ///# let mut ram = Ram::default();
+    ///# let mut storage = RamStorage::new(&mut ram);
+    ///# Filesystem::format(&mut storage).unwrap();
+    ///# Filesystem::mount_and_then(&mut storage, |fs| {
+    /// let firmware = [0x42; 1000];
+    /// fs.write(path!("firmware.bin"), &firmware)?;
+    /// let digest = fs.hash_file::<Sha256>(path!("firmware.bin"))?;
+    /// assert_eq!(digest, Sha256::digest(firmware));
+    ///# Ok(())
+    ///# }).unwrap();
+    /// ```
+    #[cfg(feature = "digest")]
+    pub fn hash_file<H: digest::Digest>(&self, path: &Path) -> Result<digest::Output<H>> {
+        let mut hasher = H::new();
+        let mut buf: Bytes<Storage::CACHE_SIZE> = Default::default();
+        self.open_file_and_then(path, |file| loop {
+            let n = file.read(&mut buf)?;
+            if n == 0 {
+                return Ok(());
+            }
+            hasher.update(&buf[..n]);
+        })?;
+        Ok(hasher.finalize())
+    }
+
     /// Write a slice as the entire contents of a file.
     ///
     /// This function will create a file if it does not exist,
@@ -1196,19 +3829,294 @@ impl<'a, Storage: driver::Storage> Filesystem<'a, Storage> {
         Ok(())
     }
 
+    /// Append a slice to the end of a file.
+    ///
+    /// This function will create a file if it does not exist.
+    pub fn append(&self, path: &Path, contents: &[u8]) -> Result<()> {
+        OpenOptions::new()
+            .write(true)
+            .create(true)
+            .append(true)
+            .open_and_then(self, path, |file| {
+                use io::Write;
+                file.write_all(contents)
+            })
+    }
+
+    /// Write a file imported from a host system and remember its original name.
+    ///
+    /// Host file names may be longer than `FILENAME_MAX` or contain characters that are
+    /// inconvenient on the device.  The file is stored in `dir` with a name that only contains
+    /// ASCII letters, digits, `.`, `-` and `_`.  If `host_name` is not such a name already, it is
+    /// derived from a sanitized prefix of `host_name`, a hash of `host_name` and, if it is short,
+    /// its extension.  The original name is stored in the attribute
+    /// [`ORIGINAL_NAME_ATTRIBUTE_ID`](crate::consts::ORIGINAL_NAME_ATTRIBUTE_ID) and can be read
+    /// with [`Filesystem::original_name`][].  Returns the path of the written file.
+    ///
+    /// Host names that are longer than [`Attribute::MAX_SIZE`][] are rejected with
+    /// [`Error::FILENAME_TOO_LONG`][].
+    ///
+    /// ```
+    ///# use littlefs2::{driver, fs::Filesystem, io::Result, path, ram_storage};
+    ///# ram_storage!(tiny);
+    ///# let mut ram = Ram::default();
+    ///# let mut storage = RamStorage::new(&mut ram);
+    ///# Filesystem::format(&mut storage).unwrap();
+    ///# Filesystem::mount_and_then(&mut storage, |fs| {
+    /// let path = fs.import_file(path!("/"), "Übersicht März.pdf", b"%PDF")?;
+    /// assert_eq!(path.as_str(), "/_bersicht_M_rz~83f58ce4.pdf");
+    /// let mut buffer = [0; 64];
+    /// assert_eq!(fs.original_name(&path, &mut buffer)?, Some("Übersicht März.pdf"));
+    ///# Ok(())
+    ///# }).unwrap();
+    /// ```
+    pub fn import_file(&self, dir: &Path, host_name: &str, data: &[u8]) -> Result<PathBuf> {
+        if host_name.len() > Attribute::MAX_SIZE as usize {
+            return Err(Error::FILENAME_TOO_LONG);
+        }
+        let path = dir.join(&short_name(host_name, Storage::FILENAME_MAX)?);
+        self.write(&path, data)?;
+        self.set_attribute(
+            &path,
+            crate::consts::ORIGINAL_NAME_ATTRIBUTE_ID,
+            host_name.as_bytes(),
+        )?;
+        Ok(path)
+    }
+
+    /// Read the original name of a file written by [`Filesystem::import_file`][].
+    ///
+    /// Returns `None` if no original name is stored for the file.  Fails with
+    /// [`Error::INVALID`][] if the stored name is not valid UTF-8 and with
+    /// [`Error::NO_MEMORY`][] if `buffer` is too small.
+    pub fn original_name<'b>(&self, path: &Path, buffer: &'b mut [u8]) -> Result<Option<&'b str>> {
+        let id = crate::consts::ORIGINAL_NAME_ATTRIBUTE_ID;
+        let Some(attribute) = self.attribute(path, id, buffer)? else {
+            return Ok(None);
+        };
+        if attribute.total_size() > attribute.data().len() {
+            return Err(Error::NO_MEMORY);
+        }
+        let len = attribute.data().len();
+        let name = core::str::from_utf8(&buffer[..len]).map_err(|_| Error::INVALID)?;
+        Ok(Some(name))
+    }
+
+    /// Atomically replace the contents of a file.
+    ///
+    /// The contents are written to a temporary file in the same directory that is then renamed
+    /// to `path`.  As renames are atomic in littlefs, `path` contains either the old or the new
+    /// contents after a power loss, never a mix of both.  The name of the temporary file is
+    /// derived from a hash of `path` and starts with a dot.  It is removed again if an error
+    /// occurs, but it may be left behind after a power loss.
+    pub fn write_atomic(&self, path: &Path, contents: &[u8]) -> Result<()> {
+        self.replace_with_temp(path, |temp| self.write(temp, contents))
+    }
+
+    /// Rewrite a file into newly allocated blocks to defragment it.
+    ///
+    /// Files that are extended over time, for example logs, may end up scattered across the
+    /// storage, which slows down sequential reads.  This function copies the file with its
+    /// attributes to a temporary file and renames it over `path` like
+    /// [`Filesystem::write_atomic`][], so a power loss leaves either the old or the new copy.
+    /// Enough space for a second copy of the file is required.  Returns the size of the file.
+    ///
+    /// Use [`Filesystem::needs_rewrite`][] to decide whether rewriting a file is worthwhile.
+    pub fn rewrite_file(&self, path: &Path) -> Result<usize> {
+        let mut size = 0;
+        self.replace_with_temp(path, |temp| {
+            size = copy_file(self, path, self, temp)?;
+            Ok(())
+        })?;
+        Ok(size)
+    }
+
+    /// Remove the first `n` bytes of a file, for example to prune the oldest entries of a log.
+    ///
+    /// littlefs cannot remove data from the start of a file, so the remaining data is copied
+    /// with the attributes of the file to a temporary file that is renamed over `path` like
+    /// [`Filesystem::write_atomic`][].  This rewrites all remaining data and requires enough
+    /// space for it, so it is best done rarely and for a large `n`.  A power loss leaves either
+    /// the old or the new file.  If `n` is not smaller than the size of the file, the file is
+    /// emptied.
+    ///
+    /// Returns the new size of the file.
+    ///
+    /// ```
+    ///# use littlefs2::{driver, fs::Filesystem, io::Result, path, ram_storage};
+    ///# ram_storage!(tiny);
+    ///# let mut ram = Ram::default();
+    ///# let mut storage = RamStorage::new(&mut ram);
+    ///# Filesystem::format(&mut storage).unwrap();
+    ///# Filesystem::mount_and_then(&mut storage, |fs| {
+    /// fs.write(path!("log"), b"old\nnew\n")?;
+    /// assert_eq!(fs.truncate_front(path!("log"), 4)?, 4);
+    /// assert_eq!(fs.read::<8>(path!("log"))?, b"new\n");
+    ///# Ok(())
+    ///# }).unwrap();
+    /// ```
+    pub fn truncate_front(&self, path: &Path, n: usize) -> Result<usize> {
+        let len = self.metadata(path)?.len();
+        if n == 0 {
+            return Ok(len);
+        }
+        let offset = u32::try_from(n.min(len)).map_err(|_| Error::FILE_TOO_BIG)?;
+        let mut buf: Bytes<Storage::CACHE_SIZE> = Default::default();
+        let mut remaining = 0;
+        self.replace_with_temp(path, |temp| {
+            self.open_file_and_then(path, |source| {
+                source.seek(io::SeekFrom::Start(offset))?;
+                self.create_file_and_then(temp, |target| {
+                    let copied = io::copy(source, target, &mut buf)?;
+                    remaining = usize::try_from(copied).map_err(|_| Error::UNKNOWN)?;
+                    Ok(())
+                })
+            })?;
+            copy_attributes(self, path, self, temp)?;
+            #[cfg(feature = "timestamps")]
+            self.update_mtime(temp)?;
+            Ok(())
+        })?;
+        Ok(remaining)
+    }
+
+    /// Check whether the blocks of a file are scattered across the storage.
+    ///
+    /// The block list of the file is read from the storage and `true` is returned if more than
+    /// half of the blocks do not directly follow their predecessor.  Inline files, which are
+    /// stored in the metadata of their directory, and files with a single block never need to be
+    /// rewritten.  This is a heuristic: the allocator of littlefs does not guarantee that a
+    /// rewritten file uses consecutive blocks.
+    pub fn needs_rewrite(&self, path: &Path) -> Result<bool> {
+        let (ctz, flags) = self.open_file_and_then(path, |file| {
+            let state = unsafe { &(*(*file.alloc.borrow())).state };
+            Ok((state.ctz, state.flags))
+        })?;
+        if flags & ll::lfs_open_flags_LFS_F_INLINE != 0 || ctz.size == 0 {
+            return Ok(false);
+        }
+
+        // index of the last block in the CTZ skip-list, see lfs_ctz_index
+        let data_size = Storage::BLOCK_SIZE as u32 - 2 * 4;
+        let last = ctz.size - 1;
+        let mut index = match last / data_size {
+            0 => 0,
+            i => (last - 4 * ((i - 1).count_ones() + 2)) / data_size,
+        };
+        let transitions = index;
+
+        // follow the pointers to the previous blocks that are stored at the start of each block
+        let mut buf: Bytes<Storage::CACHE_SIZE> = Default::default();
+        let len = 4usize.next_multiple_of(Storage::READ_SIZE);
+        if len > buf.len() {
+            return Err(Error::INVALID);
+        }
+        let mut block = ctz.head;
+        let mut gaps = 0;
+        while index > 0 {
+            let return_code = {
+                let alloc = self.alloc.borrow();
+                Self::lfs_config_read(
+                    &alloc.config,
+                    block,
+                    0,
+                    buf.as_mut_ptr() as *mut c_void,
+                    len as u32,
+                )
+            };
+            result_from((), return_code)?;
+            let previous = u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]);
+            if previous as usize >= Storage::BLOCK_COUNT {
+                return Err(Error::CORRUPTION);
+            }
+            if previous.wrapping_add(1) != block {
+                gaps += 1;
+            }
+            block = previous;
+            index -= 1;
+        }
+        Ok(gaps * 2 > transitions)
+    }
+
+    /// Write a temporary file in the directory of `path` with `write` and rename it to `path`.
+    fn replace_with_temp(
+        &self,
+        path: &Path,
+        write: impl FnOnce(&Path) -> Result<()>,
+    ) -> Result<()> {
+        let mut name = *b".00000000.tmp";
+        name[1..9].copy_from_slice(&hex_u32(path_hash(path)));
+        let name = PathBuf::try_from(&name[..]).map_err(|_| Error::INVALID)?;
+        let mut temp = path.parent().unwrap_or_default();
+        temp.push(&name);
+
+        let result = write(&temp).and_then(|()| self.rename(&temp, path));
+        if result.is_err() {
+            self.remove(&temp).ok();
+        }
+        result
+    }
+
     /// Write a slice as a chunk of a file.
     ///
-    /// This function will not create a file if it does not exist,
-    /// it will fail if the file is not already large enough with regard to the `pos` parameter
+    /// This function will not create a file if it does not exist.  If the chunk starts after the
+    /// end of the file, the gap is filled with zeros.  Use
+    /// [`Filesystem::write_chunk_with_options`][] to reject such chunks instead.
     pub fn write_chunk(&self, path: &Path, contents: &[u8], pos: OpenSeekFrom) -> Result<()> {
+        self.write_chunk_with_options(path, contents, pos, WriteChunkOptions::new().extend(true))
+    }
+
+    /// Write a slice as a chunk of a file with the given options.
+    ///
+    /// With [`WriteChunkOptions::create`][], the file is created if it does not exist.  With
+    /// [`WriteChunkOptions::extend`][], a chunk may start after the end of the file, and the gap
+    /// is filled with zeros, like with [`Filesystem::write_chunk`][].  Together, they allow writing
+    /// the chunks of a transfer in any order.  Without `extend`, a chunk that starts after the end
+    /// of the file is rejected with [`Error::INVALID`][].
+    ///
+    /// ```
+    ///# use littlefs2::{driver, fs::{Filesystem, WriteChunkOptions}, io::{OpenSeekFrom, Result}, path, ram_storage};
+    ///# ram_storage!(tiny);
+    ///# let mut ram = Ram::default();
+    ///# let mut storage = RamStorage::new(&mut ram);
+    ///# Filesystem::format(&mut storage).unwrap();
+    ///# Filesystem::mount_and_then(&mut storage, |fs| {
+    /// let options = WriteChunkOptions::new().create(true).extend(true);
+    /// fs.write_chunk_with_options(path!("upload"), b"world", OpenSeekFrom::Start(6), options)?;
+    /// fs.write_chunk_with_options(path!("upload"), b"hello", OpenSeekFrom::Start(0), options)?;
+    /// assert_eq!(fs.read::<16>(path!("upload"))?, b"hello\0world");
+    ///# Ok(())
+    ///# }).unwrap();
+    /// ```
+    pub fn write_chunk_with_options(
+        &self,
+        path: &Path,
+        contents: &[u8],
+        pos: OpenSeekFrom,
+        options: WriteChunkOptions,
+    ) -> Result<()> {
         #[cfg(test)]
         println!("writing {:?}", path);
         OpenOptions::new()
             .read(true)
             .write(true)
+            .create(options.is_create())
             .truncate(false)
             .open_and_then(self, path, |file| {
                 use io::Write;
+                let len = file.len()?;
+                let start = match pos {
+                    OpenSeekFrom::Start(offset) => usize::try_from(offset).ok(),
+                    OpenSeekFrom::End(offset) => isize::try_from(offset)
+                        .ok()
+                        .and_then(|offset| len.checked_add_signed(offset)),
+                };
+                if let Some(start) = start.filter(|&start| start > len) {
+                    if !options.is_extend() {
+                        return Err(Error::INVALID);
+                    }
+                    file.set_len(start)?;
+                }
                 file.seek(pos.into())?;
                 file.write_all(contents)
             })?;
@@ -1216,6 +4124,526 @@ impl<'a, Storage: driver::Storage> Filesystem<'a, Storage> {
     }
 }
 
+/// Validator for paths, see [`Filesystem::set_path_validator`][].
+#[cfg(feature = "path-validator")]
+pub type PathValidator = fn(&Path) -> bool;
+
+/// Common restrictions for paths that can be used with [`Filesystem::set_path_validator`][].
+#[cfg(feature = "path-validator")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PathPolicy<'a> {
+    max_depth: Option<usize>,
+    reserved: &'a [&'a Path],
+    forbidden: &'a [char],
+}
+
+#[cfg(feature = "path-validator")]
+impl<'a> PathPolicy<'a> {
+    /// Creates a policy that allows all paths.
+    pub const fn new() -> Self {
+        Self {
+            max_depth: None,
+            reserved: &[],
+            forbidden: &[],
+        }
+    }
+
+    /// Rejects paths with more than `max_depth` components, not counting the root directory.
+    pub const fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    /// Rejects the given paths and all paths below them.
+    pub const fn reserved(mut self, reserved: &'a [&'a Path]) -> Self {
+        self.reserved = reserved;
+        self
+    }
+
+    /// Rejects paths that contain one of the given characters.
+    pub const fn forbidden(mut self, forbidden: &'a [char]) -> Self {
+        self.forbidden = forbidden;
+        self
+    }
+
+    /// Returns whether the policy allows the given path.
+    pub fn allows(&self, path: &Path) -> bool {
+        if let Some(max_depth) = self.max_depth {
+            if path.iter().filter(|component| &**component != "/").count() > max_depth {
+                return false;
+            }
+        }
+        if path.as_str().contains(self.forbidden) {
+            return false;
+        }
+        !path
+            .ancestors()
+            .any(|ancestor| self.reserved.iter().any(|reserved| **reserved == *ancestor))
+    }
+}
+
+/// Decides how [`Filesystem::move_merge`][] handles an entry that already exists in the target
+/// directory.
+#[allow(clippy::large_enum_variant)]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ConflictPolicy {
+    /// Keep both entries in place.
+    Skip,
+    /// Replace the existing entry with the moved entry.
+    Overwrite,
+    /// Move the entry to the given path instead.
+    Rename(PathBuf),
+}
+
+/// Disk usage of a directory reported by [`Filesystem::usage_by_dir`][].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DirUsage<'a> {
+    /// Path of the directory.
+    pub path: &'a Path,
+    /// Number of files.
+    pub files: usize,
+    /// Total size of the files in bytes.
+    pub bytes: usize,
+}
+
+/// A file reported by [`Filesystem::find_duplicates`][].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Duplicate<'a> {
+    /// Index of the group of files with the same size and hash, starting at 0.
+    pub group: usize,
+    /// Path of the file.
+    pub path: &'a Path,
+    /// Size of the file in bytes.
+    pub size: usize,
+    /// Hash of the file contents.
+    pub hash: u64,
+}
+
+/// Progress of [`Filesystem::migrate`][], passed to the progress callback after every copied file
+/// or directory.
+#[derive(Clone, Copy, Debug)]
+pub struct MigrationProgress<'a> {
+    /// The file or directory that was just copied
+    pub path: &'a Path,
+    /// Number of files copied so far
+    pub files: usize,
+    /// Number of directories copied so far, not including the root directory
+    pub dirs: usize,
+    /// Number of file content bytes copied so far
+    pub bytes: usize,
+}
+
+/// Result of a consistency check with [`Filesystem::check`][].
+#[derive(Clone, Debug, PartialEq)]
+pub struct FsckReport {
+    /// Error returned when mounting the filesystem, if it could not be mounted.
+    ///
+    /// If the filesystem could not be mounted, only the block scan has been performed.
+    pub mount_error: Option<Error>,
+    /// Error returned when traversing the blocks of the filesystem, if any.
+    pub traverse_error: Option<Error>,
+    /// Number of blocks that could not be read from the storage.
+    pub unreadable_blocks: usize,
+    /// The first block that could not be read from the storage, if any.
+    pub first_unreadable_block: Option<u32>,
+    /// Number of unreadable blocks that are in use by the filesystem.
+    pub unreadable_used_blocks: usize,
+    /// Number of blocks reported by the traversal, see [`Filesystem::traverse_blocks`][].
+    pub used_blocks: usize,
+    /// Number of directories found, including the root directory.
+    pub dirs: usize,
+    /// Number of files found.
+    pub files: usize,
+    /// Number of files and directories that could not be read completely.
+    pub damaged_entries: usize,
+    /// Path of the first file or directory that could not be read, and the error.
+    pub first_damaged_entry: Option<(PathBuf, Error)>,
+}
+
+impl FsckReport {
+    /// Returns `true` if the check did not find any problems.
+    ///
+    /// Unreadable blocks that are not used by the filesystem are not considered a problem.
+    pub fn is_clean(&self) -> bool {
+        self.mount_error.is_none()
+            && self.traverse_error.is_none()
+            && self.unreadable_used_blocks == 0
+            && self.damaged_entries == 0
+    }
+
+    fn add_damaged_entry(&mut self, path: &Path, error: Error) {
+        self.damaged_entries += 1;
+        self.first_damaged_entry
+            .get_or_insert_with(|| (path.into(), error));
+    }
+}
+
+impl<Storage: driver::Storage> Filesystem<'_, Storage> {
+    /// Copy the contents of the filesystem on `storage` to a new filesystem on `target`.
+    ///
+    /// This is intended for changes of the storage geometry, for example if the flash part is
+    /// replaced with one with a larger erase size.  `target` is formatted, so it must not overlap
+    /// with `storage`.  All files and directories are copied with their paths, contents and
+    /// attributes.  `progress` is called after every copied file or directory.
+    ///
+    /// The directory tree is walked iteratively.  At most
+    /// [`DIR_DEPTH_MAX`](crate::consts::DIR_DEPTH_MAX) levels of nested directories are
+    /// supported, deeper trees result in [`Error::NO_MEMORY`][].
+    ///
+    /// If the migration fails, `target` is left in an unspecified state and should be formatted
+    /// again before retrying.
+    pub fn migrate<Target: driver::Storage>(
+        storage: &mut Storage,
+        target: &mut Target,
+        mut progress: impl FnMut(&MigrationProgress<'_>),
+    ) -> Result<()> {
+        Filesystem::format(target)?;
+        Filesystem::mount_and_then(storage, |source| {
+            Filesystem::mount_and_then(target, |target| migrate_tree(source, target, &mut progress))
+        })
+    }
+}
+
+fn migrate_tree<S: driver::Storage, T: driver::Storage>(
+    source: &Filesystem<'_, S>,
+    target: &Filesystem<'_, T>,
+    progress: &mut dyn FnMut(&MigrationProgress<'_>),
+) -> Result<()> {
+    use crate::path;
+
+    copy_tree(source, path!("/"), target, path!("/"), progress)
+}
+
+/// Magic bytes and format version of the streams written by [`Filesystem::backup_to`][].
+const BACKUP_MAGIC: &[u8; 5] = b"LFSB\x01";
+
+/// Write the record of a directory or file and its attributes, see [`Filesystem::backup_to`][].
+fn write_backup_entry<S: driver::Storage>(
+    fs: &Filesystem<'_, S>,
+    writer: &dyn io::Write,
+    path: &Path,
+    is_dir: bool,
+) -> Result<()> {
+    let path_len = path.as_str().len() as u16;
+    writer.write_all(if is_dir { b"D" } else { b"F" })?;
+    writer.write_all(&path_len.to_le_bytes())?;
+    writer.write_all(path.as_str().as_bytes())?;
+    if !is_dir {
+        fs.open_file_and_then(path, |file| {
+            let len = u32::try_from(file.len()?).map_err(|_| Error::FILE_TOO_BIG)?;
+            writer.write_all(&len.to_le_bytes())?;
+            let mut buffer = [0; Attribute::MAX_SIZE as _];
+            let copied = io::copy(file, writer, &mut buffer)?;
+            // the file must not change during the backup
+            if copied != u64::from(len) {
+                return Err(Error::IO);
+            }
+            Ok(())
+        })?;
+    }
+    fs.attributes(path, |id, value| {
+        writer.write_all(b"A")?;
+        writer.write_all(&[id])?;
+        writer.write_all(&(value.len() as u16).to_le_bytes())?;
+        writer.write_all(value)
+    })
+}
+
+/// Fill `buf` from `reader`, failing with [`Error::IO`][] if the stream ends first.
+fn read_backup_exact(reader: &dyn io::Read, mut buf: &mut [u8]) -> Result<()> {
+    while !buf.is_empty() {
+        match reader.read(buf)? {
+            0 => return Err(Error::IO),
+            n => buf = &mut buf[n..],
+        }
+    }
+    Ok(())
+}
+
+/// Read the path of a directory or file record, see [`Filesystem::backup_to`][].
+fn read_backup_path(reader: &dyn io::Read, buffer: &mut [u8]) -> Result<PathBuf> {
+    read_backup_exact(reader, &mut buffer[..2])?;
+    let len = usize::from(u16::from_le_bytes([buffer[0], buffer[1]]));
+    if len > PathBuf::MAX_SIZE {
+        return Err(Error::CORRUPTION);
+    }
+    read_backup_exact(reader, &mut buffer[..len])?;
+    let path = PathBuf::try_from(&buffer[..len]).map_err(|_| Error::CORRUPTION)?;
+    if !path.as_str().starts_with('/') {
+        return Err(Error::CORRUPTION);
+    }
+    Ok(path)
+}
+
+/// Copy the directory tree at `from` in `source` to the existing directory `to` in `target`,
+/// including all attributes.
+fn copy_tree<S: driver::Storage, T: driver::Storage>(
+    source: &Filesystem<'_, S>,
+    from: &Path,
+    target: &Filesystem<'_, T>,
+    to: &Path,
+    progress: &mut dyn FnMut(&MigrationProgress<'_>),
+) -> Result<()> {
+    let mut files = 0;
+    let mut dirs = 0;
+    let mut bytes = 0;
+
+    copy_attributes(source, from, target, to)?;
+
+    let mut walk = source.walk_dir(from);
+    while let Some(step) = walk.step() {
+        let WalkStep::Entry(entry) = step? else {
+            continue;
+        };
+        let target_path = to.join(walk.relative(entry.path()));
+        if entry.file_type().is_dir() {
+            if let Err(error) = target.create_dir(&target_path) {
+                // merge into existing directories
+                if error != Error::ENTRY_ALREADY_EXISTED || !target.metadata(&target_path)?.is_dir()
+                {
+                    return Err(error);
+                }
+            }
+            copy_attributes(source, entry.path(), target, &target_path)?;
+            dirs += 1;
+        } else {
+            bytes += copy_file(source, entry.path(), target, &target_path)?;
+            files += 1;
+        }
+        progress(&MigrationProgress {
+            path: entry.path(),
+            files,
+            dirs,
+            bytes,
+        });
+    }
+    Ok(())
+}
+
+/// Copy the contents and attributes of a file, returning the number of copied bytes.
+fn copy_file<S: driver::Storage, T: driver::Storage>(
+    source: &Filesystem<'_, S>,
+    from: &Path,
+    target: &Filesystem<'_, T>,
+    to: &Path,
+) -> Result<usize> {
+    let mut buf: Bytes<T::CACHE_SIZE> = Default::default();
+    let copied = source.open_file_and_then(from, |from| {
+        target.create_file_and_then(to, |to| {
+            let mut copied = 0;
+            loop {
+                let n = from.read(&mut buf)?;
+                if n == 0 {
+                    return Ok(copied);
+                }
+                io::Write::write_all(to, &buf[..n])?;
+                copied += n;
+            }
+        })
+    })?;
+    copy_attributes(source, from, target, to)?;
+    Ok(copied)
+}
+
+fn copy_attributes<S: driver::Storage, T: driver::Storage>(
+    source: &Filesystem<'_, S>,
+    from: &Path,
+    target: &Filesystem<'_, T>,
+    to: &Path,
+) -> Result<()> {
+    source.attributes(from, |id, data| target.set_attribute(to, id, data))
+}
+
+/// Buffer for small appends to a file.
+///
+/// Every write to a file that is closed or synced afterwards results in a metadata commit.  For
+/// writers that append many small records, for example telemetry logs, `AppendBuffer` collects the
+/// appended data in RAM and only writes it to the file once the buffer is full or when
+/// [`AppendBuffer::flush`][] is called.
+///
+/// # Power loss
+///
+/// Data that has not been flushed yet is lost on power loss or if the buffer is dropped.  Each
+/// flush appends the buffered data in a single file operation, so after a power loss the file
+/// contains either all or none of the data from the interrupted flush, but is never torn.
+///
+/// ```
+/// # use littlefs2::{driver, fs::{AppendBuffer, Filesystem}, io::Result, path, ram_storage};
+/// # ram_storage!(tiny);
+/// # let mut ram = Ram::default();
+/// # let mut storage = RamStorage::new(&mut ram);
+/// # Filesystem::format(&mut storage).unwrap();
+/// # Filesystem::mount_and_then(&mut storage, |fs| {
+/// let mut log = AppendBuffer::<64>::new(path!("log"));
+/// for i in 0..100u8 {
+///     log.append(fs, &[i])?;
+/// }
+/// log.flush(fs)?;
+/// assert_eq!(fs.metadata(path!("log"))?.len(), 100);
+/// # Ok(())
+/// # }).unwrap();
+/// ```
+pub struct AppendBuffer<const N: usize> {
+    path: PathBuf,
+    buf: heapless::Vec<u8, N>,
+}
+
+impl<const N: usize> AppendBuffer<N> {
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.into(),
+            buf: Default::default(),
+        }
+    }
+
+    /// The path of the file that the data is appended to.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The number of bytes that have not been flushed yet.
+    pub fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    /// Append data, flushing the buffer first if the data does not fit.
+    ///
+    /// Data that is larger than the buffer is written to the file directly.
+    pub fn append(&mut self, fs: &dyn DynFilesystem, data: &[u8]) -> Result<()> {
+        if self.buf.len() + data.len() > N {
+            self.flush(fs)?;
+        }
+        if data.len() > N {
+            self.write(fs, data)
+        } else {
+            // no panic by construction
+            self.buf.extend_from_slice(data).unwrap();
+            Ok(())
+        }
+    }
+
+    /// Append the buffered data to the file, creating it if necessary.
+    ///
+    /// If this fails, the data is kept in the buffer.
+    pub fn flush(&mut self, fs: &dyn DynFilesystem) -> Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        self.write(fs, &self.buf)?;
+        self.buf.clear();
+        Ok(())
+    }
+
+    fn write(&self, fs: &dyn DynFilesystem, data: &[u8]) -> Result<()> {
+        let flags = FileOpenFlags::WRITE | FileOpenFlags::CREATE | FileOpenFlags::APPEND;
+        fs.open_file_with_flags_and_then(flags, &self.path, &mut |file| file.write_all(data))
+    }
+}
+
+/// Reader for a file in chunks of `N` bytes.
+///
+/// Transports like USB or CTAP send files in pieces of a fixed size over several requests.
+/// `ChunkReader` keeps track of the offset between these requests.  It does not keep the file
+/// open, so the filesystem is passed to every call and may be used in between.  If the file is
+/// modified while it is read, the chunks are read from the current contents at the offset.
+///
+/// ```
+/// # use littlefs2::{driver, fs::{ChunkReader, Filesystem}, io::Result, path, ram_storage};
+/// # ram_storage!(tiny);
+/// # let mut ram = Ram::default();
+/// # let mut storage = RamStorage::new(&mut ram);
+/// # Filesystem::format(&mut storage).unwrap();
+/// # Filesystem::mount_and_then(&mut storage, |fs| {
+/// fs.write(path!("cert"), &[0x42; 100])?;
+/// let mut reader = ChunkReader::<64>::new(path!("cert"));
+/// assert_eq!(reader.next_chunk(fs)?.unwrap().len(), 64);
+/// assert_eq!(reader.next_chunk(fs)?.unwrap().len(), 36);
+/// assert!(reader.next_chunk(fs)?.is_none());
+///
+/// // start over, for example to repeat a transfer
+/// reader.set_offset(0);
+/// let mut total = 0;
+/// for chunk in reader.chunks(fs) {
+///     total += chunk?.len();
+/// }
+/// assert_eq!(total, 100);
+/// # Ok(())
+/// # }).unwrap();
+/// ```
+pub struct ChunkReader<const N: usize> {
+    path: PathBuf,
+    offset: usize,
+}
+
+impl<const N: usize> ChunkReader<N> {
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.into(),
+            offset: 0,
+        }
+    }
+
+    /// The path of the file that is read.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The offset of the next chunk.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Set the offset of the next chunk.
+    pub fn set_offset(&mut self, offset: usize) {
+        self.offset = offset;
+    }
+
+    /// Read the next chunk and advance the offset.
+    ///
+    /// The chunk has `N` bytes unless the end of the file is reached.  Returns `None` if there is
+    /// no data after the offset.  If this fails, the offset is not changed.
+    pub fn next_chunk(&mut self, fs: &dyn DynFilesystem) -> Result<Option<heapless::Vec<u8, N>>> {
+        let offset = u32::try_from(self.offset).map_err(|_| Error::FILE_TOO_BIG)?;
+        let mut chunk: heapless::Vec<u8, N> = Default::default();
+        // no panic by construction
+        chunk.resize_default(N).unwrap();
+        let mut n = 0;
+        fs.open_file_and_then_unit(&self.path, &mut |file| {
+            file.seek(io::SeekFrom::Start(offset))?;
+            n = file.read(&mut chunk)?;
+            Ok(())
+        })?;
+        if n == 0 {
+            return Ok(None);
+        }
+        chunk.truncate(n);
+        self.offset += n;
+        Ok(Some(chunk))
+    }
+
+    /// Iterate over the remaining chunks.
+    ///
+    /// The iteration stops after the first error.
+    pub fn chunks<'a>(
+        &'a mut self,
+        fs: &'a dyn DynFilesystem,
+    ) -> impl Iterator<Item = Result<heapless::Vec<u8, N>>> + 'a {
+        let mut failed = false;
+        core::iter::from_fn(move || {
+            if failed {
+                return None;
+            }
+            let chunk = self.next_chunk(fs).transpose();
+            failed = matches!(chunk, Some(Err(_)));
+            chunk
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
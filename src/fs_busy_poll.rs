@@ -0,0 +1,179 @@
+//! Filesystem API with `async` functions for storages with an async driver.
+//!
+//! littlefs is implemented in C and calls the storage driver synchronously, so a filesystem
+//! operation cannot be suspended while it waits for the storage.  The functions in this module
+//! are `async` so that they can be called from async code with an [`AsyncStorage`][], but they
+//! do not yield while an operation is running: every call runs the operation to completion and
+//! busy polls the futures of the storage driver with [`BusyPoll`][].  The driver must meet the
+//! [requirements](crate::driver::AsyncStorage#requirements) of this execution model, otherwise
+//! an operation never completes.
+//!
+//! # Cancellation safety
+//!
+//! The futures returned by the filesystem operations in this module complete on their first
+//! poll, so they never yield while littlefs is in the middle of an operation.  The only points
+//! where a future of this module can be suspended are the `.await`s in the closure passed to
+//! [`Filesystem::mount_and_then`][], i. e. between two complete filesystem operations.  Files and
+//! directories are only open within the synchronous closures passed to functions like
+//! [`Filesystem::open_file_and_then`][], so no file is open at these points.
+//!
+//! If such a future is dropped, all operations that have completed before are committed, and
+//! operations that have not been started have no effect.  The filesystem remains mountable and
+//! an interrupted sequence of operations can be retried.  As with power loss, writes that span
+//! multiple operations, for example multiple calls of [`Filesystem::write_chunk`][], may be
+//! partially applied.
+//!
+//! ```
+//!# use littlefs2::{driver::AsyncStorage, fs_busy_poll::Filesystem, io::Result, path};
+//! async fn log<S: AsyncStorage>(flash: &mut S) -> Result<()> {
+//!     Filesystem::mount_and_then(flash, async |fs| {
+//!         fs.create_dir_all(path!("/log")).await?;
+//!         fs.write(path!("/log/boot"), b"ok").await
+//!     })
+//!     .await
+//! }
+//! ```
+
+use crate::{
+    driver::{AsyncStorage, BusyPoll},
+    fs::{self, Allocation, File, Metadata, ReadDir},
+    io::{OpenSeekFrom, Result},
+    path::Path,
+};
+
+/// Async wrapper around a mounted [`fs::Filesystem`][].
+///
+/// See the [module documentation](self) for the execution model.
+pub struct Filesystem<'f, 'a, S: AsyncStorage> {
+    fs: &'f fs::Filesystem<'a, BusyPoll<S>>,
+}
+
+impl<'f, 'a, S: AsyncStorage> Filesystem<'f, 'a, S> {
+    /// See [`fs::Filesystem::format`][].
+    pub async fn format(storage: S) -> Result<()> {
+        fs::Filesystem::format(&mut BusyPoll::new(storage))
+    }
+
+    /// See [`fs::Filesystem::is_mountable`][].
+    pub async fn is_mountable(storage: S) -> bool {
+        fs::Filesystem::is_mountable(&mut BusyPoll::new(storage))
+    }
+
+    /// Mount the filesystem and call `f` with it, see [`fs::Filesystem::mount_and_then`][].
+    pub async fn mount_and_then<R>(
+        storage: S,
+        f: impl AsyncFnOnce(&Filesystem<'_, '_, S>) -> Result<R>,
+    ) -> Result<R> {
+        let mut storage = BusyPoll::new(storage);
+        let mut alloc = Allocation::new();
+        let fs = fs::Filesystem::mount(&mut alloc, &mut storage)?;
+        f(&Filesystem { fs: &fs }).await
+    }
+
+    /// Returns the synchronous filesystem for operations that are not available in this module.
+    pub fn inner(&self) -> &'f fs::Filesystem<'a, BusyPoll<S>> {
+        self.fs
+    }
+
+    /// See [`fs::Filesystem::available_blocks`][].
+    pub async fn available_blocks(&self) -> Result<usize> {
+        self.fs.available_blocks()
+    }
+
+    /// See [`fs::Filesystem::create_dir`][].
+    pub async fn create_dir(&self, path: &Path) -> Result<()> {
+        self.fs.create_dir(path)
+    }
+
+    /// See [`fs::Filesystem::create_dir_all`][].
+    pub async fn create_dir_all(&self, path: &Path) -> Result<()> {
+        self.fs.create_dir_all(path)
+    }
+
+    /// See [`fs::Filesystem::exists`][].
+    pub async fn exists(&self, path: &Path) -> bool {
+        self.fs.exists(path)
+    }
+
+    /// See [`fs::Filesystem::metadata`][].
+    pub async fn metadata(&self, path: &Path) -> Result<Metadata> {
+        self.fs.metadata(path)
+    }
+
+    /// See [`fs::Filesystem::read`][].
+    pub async fn read<const N: usize>(&self, path: &Path) -> Result<heapless::Vec<u8, N>> {
+        self.fs.read(path)
+    }
+
+    /// See [`fs::Filesystem::read_chunk`][].
+    pub async fn read_chunk<const N: usize>(
+        &self,
+        path: &Path,
+        pos: OpenSeekFrom,
+    ) -> Result<(heapless::Vec<u8, N>, usize)> {
+        self.fs.read_chunk(path, pos)
+    }
+
+    /// See [`fs::Filesystem::write`][].
+    pub async fn write(&self, path: &Path, contents: &[u8]) -> Result<()> {
+        self.fs.write(path, contents)
+    }
+
+    /// See [`fs::Filesystem::write_chunk`][].
+    pub async fn write_chunk(&self, path: &Path, contents: &[u8], pos: OpenSeekFrom) -> Result<()> {
+        self.fs.write_chunk(path, contents, pos)
+    }
+
+    /// See [`fs::Filesystem::remove`][].
+    pub async fn remove(&self, path: &Path) -> Result<()> {
+        self.fs.remove(path)
+    }
+
+    /// See [`fs::Filesystem::remove_dir`][].
+    pub async fn remove_dir(&self, path: &Path) -> Result<()> {
+        self.fs.remove_dir(path)
+    }
+
+    /// See [`fs::Filesystem::remove_dir_all`][].
+    pub async fn remove_dir_all(&self, path: &Path) -> Result<()> {
+        self.fs.remove_dir_all(path)
+    }
+
+    /// See [`fs::Filesystem::rename`][].
+    pub async fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+        self.fs.rename(from, to)
+    }
+
+    /// See [`fs::Filesystem::read_dir_and_then`][].
+    ///
+    /// `f` is synchronous as every step of the iteration accesses the storage.
+    pub async fn read_dir_and_then<R>(
+        &self,
+        path: &Path,
+        f: impl FnOnce(&mut ReadDir<'_, '_, BusyPoll<S>>) -> Result<R>,
+    ) -> Result<R> {
+        self.fs.read_dir_and_then(path, f)
+    }
+
+    /// See [`fs::Filesystem::open_file_and_then`][].
+    ///
+    /// `f` is synchronous as every operation on the file may access the storage.
+    pub async fn open_file_and_then<R>(
+        &self,
+        path: &Path,
+        f: impl FnOnce(&File<'_, '_, BusyPoll<S>>) -> Result<R>,
+    ) -> Result<R> {
+        self.fs.open_file_and_then(path, f)
+    }
+
+    /// See [`fs::Filesystem::create_file_and_then`][].
+    ///
+    /// `f` is synchronous as every operation on the file may access the storage.
+    pub async fn create_file_and_then<R>(
+        &self,
+        path: &Path,
+        f: impl FnOnce(&File<'_, '_, BusyPoll<S>>) -> Result<R>,
+    ) -> Result<R> {
+        self.fs.create_file_and_then(path, f)
+    }
+}
@@ -0,0 +1,584 @@
+//! Mount littlefs images on the host with FUSE.
+//!
+//! This module requires the `fuse` feature and is only available on Unix systems.  It uses the
+//! [`fuser`] crate without libfuse, so mounting requires the `fusermount3` helper.
+//!
+//! [`mount`][] mounts the filesystem on a storage at a directory of the host and blocks until it
+//! is unmounted, for example with `fusermount3 -u <mountpoint>`.  Files and directories can be
+//! read, created, written, truncated, renamed and removed with the usual tools.  The attributes of
+//! littlefs files and directories are exposed as the extended attributes `user.littlefs.<id>`, like
+//! in the [`tools`](crate::tools) module.
+//!
+//! littlefs does not store owners, permissions or timestamps.  All entries belong to the user
+//! that accesses them, directories have the mode `0755`, files `0644`, and all timestamps are the
+//! Unix epoch.
+//!
+//! ```no_run
+//!# use littlefs2::{fuse, host::FileStorage};
+//! let mut storage = FileStorage::<4096, 256>::open("dump.lfs")?;
+//! fuse::mount(&mut storage, "/mnt/lfs".as_ref(), false)?;
+//!# Ok::<(), std::io::Error>(())
+//! ```
+
+use core::ffi::c_int;
+use std::{
+    collections::HashMap,
+    ffi::OsStr,
+    format, io,
+    string::{String, ToString},
+    time::{Duration, UNIX_EPOCH},
+    vec,
+    vec::Vec,
+};
+
+use fuser::{
+    FileAttr, KernelConfig, MountOption, ReplyAttr, ReplyCreate, ReplyData, ReplyDirectory,
+    ReplyEmpty, ReplyEntry, ReplyOpen, ReplyWrite, ReplyXattr, Request, TimeOrNow,
+};
+
+use crate::{
+    fs::{FileOpenFlags, FileType, WriteChunkOptions},
+    io::{Error, OpenSeekFrom, SeekFrom},
+    object_safe::{DynFilesystem, DynStorage},
+    path::PathBuf,
+};
+
+/// Attributes and entries are not cached by the kernel, as the image may be small and slow.
+const TTL: Duration = Duration::ZERO;
+
+const ROOT_INODE: u64 = 1;
+
+#[cfg(target_os = "macos")]
+const NO_XATTR: c_int = libc::ENOATTR;
+#[cfg(not(target_os = "macos"))]
+const NO_XATTR: c_int = libc::ENODATA;
+
+#[cfg(target_os = "macos")]
+const RENAME_NOREPLACE: u32 = libc::RENAME_EXCL;
+#[cfg(not(target_os = "macos"))]
+const RENAME_NOREPLACE: u32 = libc::RENAME_NOREPLACE;
+
+/// Mounts the littlefs filesystem on `storage` at `mountpoint` and serves it until it is
+/// unmounted.
+///
+/// If `read_only` is set, the kernel rejects all modifications of the mounted filesystem.  Errors
+/// from mounting littlefs are returned as [`io::Error`][]s with the corresponding OS error code.
+pub fn mount(
+    storage: &mut dyn DynStorage,
+    mountpoint: &std::path::Path,
+    read_only: bool,
+) -> io::Result<()> {
+    let mut result = Ok(());
+    storage
+        .mount_and_then(&mut |fs| {
+            let mut options = vec![
+                MountOption::FSName(String::from("littlefs")),
+                MountOption::DefaultPermissions,
+            ];
+            if read_only {
+                options.push(MountOption::RO);
+            }
+            result = fuser::mount2(FuseFilesystem::new(fs), mountpoint, &options);
+            Ok(())
+        })
+        .map_err(|error| io::Error::from_raw_os_error(errno(error)))?;
+    result
+}
+
+/// Converts a littlefs error to an `errno` value of the host.
+fn errno(error: Error) -> c_int {
+    match error {
+        Error::NO_SUCH_ENTRY => libc::ENOENT,
+        Error::ENTRY_ALREADY_EXISTED => libc::EEXIST,
+        Error::PATH_NOT_DIR => libc::ENOTDIR,
+        Error::PATH_IS_DIR => libc::EISDIR,
+        Error::DIR_NOT_EMPTY => libc::ENOTEMPTY,
+        Error::BAD_FILE_DESCRIPTOR => libc::EBADF,
+        Error::FILE_TOO_BIG => libc::EFBIG,
+        Error::INVALID => libc::EINVAL,
+        Error::NO_SPACE => libc::ENOSPC,
+        Error::NO_MEMORY => libc::ENOMEM,
+        Error::NO_ATTRIBUTE => NO_XATTR,
+        Error::FILENAME_TOO_LONG => libc::ENAMETOOLONG,
+        Error::READ_ONLY => libc::EROFS,
+        Error::PATH_REJECTED => libc::EACCES,
+        Error::PATH_PROTECTED => libc::EPERM,
+        _ => libc::EIO,
+    }
+}
+
+/// Parses the name of an extended attribute that stores a littlefs attribute.
+fn attribute_id(name: &OsStr) -> Option<u8> {
+    name.to_str()?
+        .strip_prefix(crate::tools::HOST_ATTRIBUTE_PREFIX)?
+        .parse()
+        .ok()
+}
+
+/// Adapter that implements [`fuser::Filesystem`][] for a mounted [`DynFilesystem`][].
+///
+/// littlefs has no inode numbers, so the adapter assigns them to paths when they are looked up.
+/// The numbers are not stored on the filesystem and are only valid while the adapter exists.
+pub struct FuseFilesystem<'a> {
+    fs: &'a dyn DynFilesystem,
+    // path of every inode, indexed by the inode number minus one
+    paths: Vec<String>,
+    inodes: HashMap<String, u64>,
+}
+
+impl<'a> FuseFilesystem<'a> {
+    pub fn new(fs: &'a dyn DynFilesystem) -> Self {
+        let root = String::from("/");
+        Self {
+            fs,
+            paths: vec![root.clone()],
+            inodes: HashMap::from([(root, ROOT_INODE)]),
+        }
+    }
+
+    fn path(&self, ino: u64) -> Result<PathBuf, c_int> {
+        let path = usize::try_from(ino)
+            .ok()
+            .and_then(|ino| self.paths.get(ino.checked_sub(1)?))
+            .ok_or(libc::ENOENT)?;
+        PathBuf::try_from(path.as_str()).map_err(|_| libc::EINVAL)
+    }
+
+    fn child(&self, parent: u64, name: &OsStr) -> Result<PathBuf, c_int> {
+        let name = name.to_str().ok_or(libc::EINVAL)?;
+        let name = PathBuf::try_from(name).map_err(|_| libc::ENAMETOOLONG)?;
+        Ok(self.path(parent)?.join(&name))
+    }
+
+    fn inode(&mut self, path: &PathBuf) -> u64 {
+        if let Some(ino) = self.inodes.get(path.as_str()) {
+            return *ino;
+        }
+        self.paths.push(path.as_str().to_string());
+        let ino = self.paths.len() as u64;
+        self.inodes.insert(path.as_str().to_string(), ino);
+        ino
+    }
+
+    fn attr(&self, req: &Request<'_>, ino: u64, path: &PathBuf) -> Result<FileAttr, c_int> {
+        let metadata = self.fs.metadata(path).map_err(errno)?;
+        let (kind, perm) = match metadata.file_type() {
+            FileType::Dir => (fuser::FileType::Directory, 0o755),
+            FileType::File => (fuser::FileType::RegularFile, 0o644),
+        };
+        let block_size = self.fs.fs_info().block_size;
+        let size = metadata.len() as u64;
+        Ok(FileAttr {
+            ino,
+            size,
+            blocks: size.div_ceil(512),
+            atime: UNIX_EPOCH,
+            mtime: UNIX_EPOCH,
+            ctime: UNIX_EPOCH,
+            crtime: UNIX_EPOCH,
+            kind,
+            perm,
+            nlink: 1,
+            uid: req.uid(),
+            gid: req.gid(),
+            rdev: 0,
+            blksize: u32::try_from(block_size).unwrap_or(u32::MAX),
+            flags: 0,
+        })
+    }
+
+    fn entry(&mut self, req: &Request<'_>, path: PathBuf) -> Result<FileAttr, c_int> {
+        let ino = self.inode(&path);
+        self.attr(req, ino, &path)
+    }
+
+    /// Updates the paths of the inodes of `from` and all entries below it after a rename.
+    fn renamed(&mut self, from: &str, to: &str) {
+        self.inodes.remove(to);
+        let prefix = format!("{from}/");
+        for (ino, path) in self.paths.iter_mut().enumerate() {
+            let suffix = if path == from {
+                ""
+            } else if let Some(suffix) = path.strip_prefix(&prefix) {
+                suffix
+            } else {
+                continue;
+            };
+            let new = if suffix.is_empty() {
+                to.to_string()
+            } else {
+                format!("{to}/{suffix}")
+            };
+            self.inodes.remove(path.as_str());
+            self.inodes.insert(new.clone(), ino as u64 + 1);
+            *path = new;
+        }
+    }
+}
+
+impl fuser::Filesystem for FuseFilesystem<'_> {
+    fn init(&mut self, _req: &Request<'_>, _config: &mut KernelConfig) -> Result<(), c_int> {
+        Ok(())
+    }
+
+    fn lookup(&mut self, req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEntry) {
+        match self
+            .child(parent, name)
+            .and_then(|path| self.entry(req, path))
+        {
+            Ok(attr) => reply.entry(&TTL, &attr, 0),
+            Err(errno) => reply.error(errno),
+        }
+    }
+
+    fn getattr(&mut self, req: &Request<'_>, ino: u64, reply: ReplyAttr) {
+        match self.path(ino).and_then(|path| self.attr(req, ino, &path)) {
+            Ok(attr) => reply.attr(&TTL, &attr),
+            Err(errno) => reply.error(errno),
+        }
+    }
+
+    fn setattr(
+        &mut self,
+        req: &Request<'_>,
+        ino: u64,
+        _mode: Option<u32>,
+        _uid: Option<u32>,
+        _gid: Option<u32>,
+        size: Option<u64>,
+        _atime: Option<TimeOrNow>,
+        _mtime: Option<TimeOrNow>,
+        _ctime: Option<std::time::SystemTime>,
+        _fh: Option<u64>,
+        _crtime: Option<std::time::SystemTime>,
+        _chgtime: Option<std::time::SystemTime>,
+        _bkuptime: Option<std::time::SystemTime>,
+        _flags: Option<u32>,
+        reply: ReplyAttr,
+    ) {
+        let result = self.path(ino).and_then(|path| {
+            if let Some(size) = size {
+                let size = usize::try_from(size).map_err(|_| libc::EFBIG)?;
+                self.fs
+                    .open_file_with_flags_and_then_unit(FileOpenFlags::WRITE, &path, &mut |file| {
+                        file.set_len(size)
+                    })
+                    .map_err(errno)?;
+            }
+            self.attr(req, ino, &path)
+        });
+        match result {
+            Ok(attr) => reply.attr(&TTL, &attr),
+            Err(errno) => reply.error(errno),
+        }
+    }
+
+    fn mkdir(
+        &mut self,
+        req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        _mode: u32,
+        _umask: u32,
+        reply: ReplyEntry,
+    ) {
+        let result = self.child(parent, name).and_then(|path| {
+            self.fs.create_dir(&path).map_err(errno)?;
+            self.entry(req, path)
+        });
+        match result {
+            Ok(attr) => reply.entry(&TTL, &attr, 0),
+            Err(errno) => reply.error(errno),
+        }
+    }
+
+    fn unlink(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        let result = self.child(parent, name).and_then(|path| {
+            if self.fs.metadata(&path).map_err(errno)?.is_dir() {
+                return Err(libc::EISDIR);
+            }
+            self.fs.remove(&path).map_err(errno)
+        });
+        match result {
+            Ok(()) => reply.ok(),
+            Err(errno) => reply.error(errno),
+        }
+    }
+
+    fn rmdir(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        let result = self.child(parent, name).and_then(|path| {
+            if !self.fs.metadata(&path).map_err(errno)?.is_dir() {
+                return Err(libc::ENOTDIR);
+            }
+            self.fs.remove_dir(&path).map_err(errno)
+        });
+        match result {
+            Ok(()) => reply.ok(),
+            Err(errno) => reply.error(errno),
+        }
+    }
+
+    fn rename(
+        &mut self,
+        _req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        newparent: u64,
+        newname: &OsStr,
+        flags: u32,
+        reply: ReplyEmpty,
+    ) {
+        let result = self.child(parent, name).and_then(|from| {
+            let to = self.child(newparent, newname)?;
+            if flags & RENAME_NOREPLACE != 0 {
+                self.fs.rename_noreplace(&from, &to).map_err(errno)?;
+            } else if flags == 0 {
+                self.fs.rename(&from, &to).map_err(errno)?;
+            } else {
+                return Err(libc::EINVAL);
+            }
+            self.renamed(from.as_str(), to.as_str());
+            Ok(())
+        });
+        match result {
+            Ok(()) => reply.ok(),
+            Err(errno) => reply.error(errno),
+        }
+    }
+
+    fn open(&mut self, _req: &Request<'_>, ino: u64, _flags: i32, reply: ReplyOpen) {
+        // Files are opened for every read and write, so no handle is needed.
+        match self.path(ino) {
+            Ok(_) => reply.opened(0, 0),
+            Err(errno) => reply.error(errno),
+        }
+    }
+
+    fn read(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        size: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: ReplyData,
+    ) {
+        let result = self.path(ino).and_then(|path| {
+            let offset = u32::try_from(offset).map_err(|_| libc::EINVAL)?;
+            let mut data = vec![0; size as usize];
+            let mut len = 0;
+            self.fs
+                .open_file_and_then_unit(&path, &mut |file| {
+                    file.seek(SeekFrom::Start(offset))?;
+                    while len < data.len() {
+                        let n = file.read(&mut data[len..])?;
+                        if n == 0 {
+                            break;
+                        }
+                        len += n;
+                    }
+                    Ok(())
+                })
+                .map_err(errno)?;
+            data.truncate(len);
+            Ok(data)
+        });
+        match result {
+            Ok(data) => reply.data(&data),
+            Err(errno) => reply.error(errno),
+        }
+    }
+
+    fn write(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        data: &[u8],
+        _write_flags: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: ReplyWrite,
+    ) {
+        let result = self.path(ino).and_then(|path| {
+            let offset = u32::try_from(offset).map_err(|_| libc::EFBIG)?;
+            let written = u32::try_from(data.len()).map_err(|_| libc::EFBIG)?;
+            self.fs
+                .write_chunk_with_options(
+                    &path,
+                    data,
+                    OpenSeekFrom::Start(offset),
+                    WriteChunkOptions::new().extend(true),
+                )
+                .map_err(errno)?;
+            Ok(written)
+        });
+        match result {
+            Ok(written) => reply.written(written),
+            Err(errno) => reply.error(errno),
+        }
+    }
+
+    fn readdir(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        mut reply: ReplyDirectory,
+    ) {
+        let result = self.path(ino).and_then(|path| {
+            let mut entries = Vec::new();
+            self.fs
+                .read_dir_and_then_unit(&path, &mut |read_dir| {
+                    for entry in read_dir {
+                        let entry = entry?;
+                        entries.push((
+                            PathBuf::from(entry.path()),
+                            entry.file_type(),
+                            entry.file_name().as_str().to_string(),
+                        ));
+                    }
+                    Ok(())
+                })
+                .map_err(errno)?;
+            Ok(entries)
+        });
+        let entries = match result {
+            Ok(entries) => entries,
+            Err(errno) => return reply.error(errno),
+        };
+        let skip = usize::try_from(offset).unwrap_or_default();
+        for (i, (path, file_type, name)) in entries.into_iter().enumerate().skip(skip) {
+            let ino = match name.as_str() {
+                "." => ino,
+                ".." => self
+                    .path(ino)
+                    .ok()
+                    .and_then(|path| path.parent())
+                    .map(|parent| self.inode(&parent))
+                    .unwrap_or(ROOT_INODE),
+                _ => self.inode(&path),
+            };
+            let kind = match file_type {
+                FileType::Dir => fuser::FileType::Directory,
+                FileType::File => fuser::FileType::RegularFile,
+            };
+            // the offset of an entry is the offset of the next entry
+            if reply.add(ino, i as i64 + 1, kind, name) {
+                break;
+            }
+        }
+        reply.ok();
+    }
+
+    fn create(
+        &mut self,
+        req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        _mode: u32,
+        _umask: u32,
+        flags: i32,
+        reply: ReplyCreate,
+    ) {
+        let result = self.child(parent, name).and_then(|path| {
+            let mut open_flags = FileOpenFlags::WRITE | FileOpenFlags::CREATE;
+            if flags & libc::O_EXCL != 0 {
+                open_flags |= FileOpenFlags::EXCL;
+            }
+            if flags & libc::O_TRUNC != 0 {
+                open_flags |= FileOpenFlags::TRUNCATE;
+            }
+            self.fs
+                .open_file_with_flags_and_then_unit(open_flags, &path, &mut |_| Ok(()))
+                .map_err(errno)?;
+            self.entry(req, path)
+        });
+        match result {
+            Ok(attr) => reply.created(&TTL, &attr, 0, 0, 0),
+            Err(errno) => reply.error(errno),
+        }
+    }
+
+    fn getxattr(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        name: &OsStr,
+        size: u32,
+        reply: ReplyXattr,
+    ) {
+        let result = self.path(ino).and_then(|path| {
+            let id = attribute_id(name).ok_or(NO_XATTR)?;
+            let mut buffer = [0; crate::fs::Attribute::MAX_SIZE as _];
+            let attribute = self
+                .fs
+                .attribute(&path, id, &mut buffer)
+                .map_err(errno)?
+                .ok_or(NO_XATTR)?;
+            Ok(attribute.data().to_vec())
+        });
+        match result {
+            Ok(data) if size == 0 => reply.size(data.len() as u32),
+            Ok(data) if data.len() <= size as usize => reply.data(&data),
+            Ok(_) => reply.error(libc::ERANGE),
+            Err(errno) => reply.error(errno),
+        }
+    }
+
+    fn listxattr(&mut self, _req: &Request<'_>, ino: u64, size: u32, reply: ReplyXattr) {
+        let result = self.path(ino).and_then(|path| {
+            let mut names = Vec::new();
+            self.fs
+                .attributes(&path, &mut |id, _| {
+                    names.extend_from_slice(
+                        format!("{}{id}\0", crate::tools::HOST_ATTRIBUTE_PREFIX).as_bytes(),
+                    );
+                    Ok(())
+                })
+                .map_err(errno)?;
+            Ok(names)
+        });
+        match result {
+            Ok(names) if size == 0 => reply.size(names.len() as u32),
+            Ok(names) if names.len() <= size as usize => reply.data(&names),
+            Ok(_) => reply.error(libc::ERANGE),
+            Err(errno) => reply.error(errno),
+        }
+    }
+
+    fn setxattr(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        name: &OsStr,
+        value: &[u8],
+        _flags: i32,
+        _position: u32,
+        reply: ReplyEmpty,
+    ) {
+        let result = self.path(ino).and_then(|path| {
+            let id = attribute_id(name).ok_or(libc::ENOTSUP)?;
+            self.fs.set_attribute(&path, id, value).map_err(errno)
+        });
+        match result {
+            Ok(()) => reply.ok(),
+            Err(errno) => reply.error(errno),
+        }
+    }
+
+    fn removexattr(&mut self, _req: &Request<'_>, ino: u64, name: &OsStr, reply: ReplyEmpty) {
+        let result = self.path(ino).and_then(|path| {
+            let id = attribute_id(name).ok_or(NO_XATTR)?;
+            self.fs.remove_attribute(&path, id).map_err(errno)
+        });
+        match result {
+            Ok(()) => reply.ok(),
+            Err(errno) => reply.error(errno),
+        }
+    }
+}
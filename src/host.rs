@@ -0,0 +1,362 @@
+//! Storage backends for littlefs images on a host.
+//!
+//! This module requires the `std` feature.  [`FileStorage`][] stores an image in a host file, so
+//! that images can be created, inspected and modified by host-side tools and integration tests
+//! with the same APIs as on a device.  With the `mmap` feature, [`MmapStorage`][] maps an image
+//! file into memory instead, which is considerably faster for random accesses to large images.
+
+use core::marker::PhantomData;
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::Path,
+    vec,
+};
+
+use generic_array::{typenum::consts, ArrayLength};
+
+use crate::{
+    driver::Storage,
+    io::{Error, Result},
+};
+
+/// The value of erased bytes of the storages in this module.
+const ERASE_VALUE: u8 = 0xff;
+
+/// Storage backed by a file on the host.
+///
+/// The geometry is set with the same parameters as for
+/// [`RomStorage`](crate::driver::RomStorage), with read and write sizes of one byte.  The file
+/// grows as littlefs writes to the storage: bytes beyond the end of the file are read as erased
+/// bytes with the value `0xff`, so a new image only occupies the space that has been written.
+/// Use [`pad_to_capacity`](Self::pad_to_capacity) to extend the file to the full size of the
+/// storage, for example before flashing it to a device.
+///
+/// All accesses go directly to the file without buffering.  Host errors are reported as
+/// [`Error::IO`][], and accesses outside of the storage fail with [`Error::INVALID`][].
+///
+/// ```no_run
+///# use littlefs2::{fs::Filesystem, host::FileStorage, path};
+/// let mut storage = FileStorage::<4096, 256>::create("image.lfs")?;
+/// Filesystem::format(&mut storage).unwrap();
+/// Filesystem::mount_and_then(&mut storage, |fs| fs.write(path!("hello"), b"world")).unwrap();
+///
+/// let mut storage = FileStorage::<4096, 256>::open("image.lfs")?;
+/// let hello: heapless::Vec<u8, 5> =
+///     Filesystem::mount_and_then(&mut storage, |fs| fs.read(path!("hello"))).unwrap();
+///# Ok::<(), std::io::Error>(())
+/// ```
+pub struct FileStorage<
+    const BLOCK_SIZE: usize,
+    const BLOCK_COUNT: usize,
+    CacheSize = consts::U128,
+    LookaheadSize = consts::U1,
+> {
+    file: File,
+    _sizes: PhantomData<(CacheSize, LookaheadSize)>,
+}
+
+impl<const BLOCK_SIZE: usize, const BLOCK_COUNT: usize, CacheSize, LookaheadSize>
+    FileStorage<BLOCK_SIZE, BLOCK_COUNT, CacheSize, LookaheadSize>
+{
+    const CAPACITY: u64 = (BLOCK_SIZE * BLOCK_COUNT) as u64;
+
+    /// Creates an empty image file at `path`, replacing an existing file.
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        Self::from_file(file)
+    }
+
+    /// Opens the existing image file at `path` for reading and writing.
+    ///
+    /// Fails with [`io::ErrorKind::InvalidInput`][] if the file is larger than the storage.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        Self::from_file(file)
+    }
+
+    /// Uses `file` as the image file.
+    ///
+    /// The file must be readable, and writable if the filesystem is modified.  Fails with
+    /// [`io::ErrorKind::InvalidInput`][] if the file is larger than the storage.
+    pub fn from_file(file: File) -> io::Result<Self> {
+        if file.metadata()?.len() > Self::CAPACITY {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "image file is larger than the storage",
+            ));
+        }
+        Ok(Self {
+            file,
+            _sizes: PhantomData,
+        })
+    }
+
+    pub fn inner(&self) -> &File {
+        &self.file
+    }
+
+    pub fn into_inner(self) -> File {
+        self.file
+    }
+
+    /// Extends the image file to the full size of the storage with erased bytes.
+    pub fn pad_to_capacity(&mut self) -> io::Result<()> {
+        let len = self.file.metadata()?.len();
+        if len < Self::CAPACITY {
+            self.file.seek(SeekFrom::Start(len))?;
+            io::copy(
+                &mut io::repeat(ERASE_VALUE).take(Self::CAPACITY - len),
+                &mut self.file,
+            )?;
+        }
+        Ok(())
+    }
+
+    fn check_range(off: usize, len: usize) -> Result<u64> {
+        match off.checked_add(len) {
+            Some(end) if end as u64 <= Self::CAPACITY => Ok(off as u64),
+            _ => Err(Error::INVALID),
+        }
+    }
+}
+
+impl<const BLOCK_SIZE: usize, const BLOCK_COUNT: usize, CacheSize, LookaheadSize> Storage
+    for FileStorage<BLOCK_SIZE, BLOCK_COUNT, CacheSize, LookaheadSize>
+where
+    CacheSize: ArrayLength<u8>,
+    LookaheadSize: ArrayLength<u64>,
+{
+    const READ_SIZE: usize = 1;
+    const WRITE_SIZE: usize = 1;
+    const BLOCK_SIZE: usize = BLOCK_SIZE;
+    const BLOCK_COUNT: usize = BLOCK_COUNT;
+    type CACHE_SIZE = CacheSize;
+    type LOOKAHEAD_SIZE = LookaheadSize;
+
+    fn read(&mut self, off: usize, buf: &mut [u8]) -> Result<usize> {
+        let off = Self::check_range(off, buf.len())?;
+        self.file
+            .seek(SeekFrom::Start(off))
+            .map_err(|_| Error::IO)?;
+        let mut read = 0;
+        while read < buf.len() {
+            match self.file.read(&mut buf[read..]) {
+                Ok(0) => break,
+                Ok(n) => read += n,
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+                Err(_) => return Err(Error::IO),
+            }
+        }
+        // the bytes beyond the end of the file have not been written yet
+        buf[read..].fill(ERASE_VALUE);
+        Ok(buf.len())
+    }
+
+    fn write(&mut self, off: usize, data: &[u8]) -> Result<usize> {
+        let off = Self::check_range(off, data.len())?;
+        self.file
+            .seek(SeekFrom::Start(off))
+            .map_err(|_| Error::IO)?;
+        self.file.write_all(data).map_err(|_| Error::IO)?;
+        Ok(data.len())
+    }
+
+    fn erase(&mut self, off: usize, len: usize) -> Result<usize> {
+        let start = Self::check_range(off, len)?;
+        let file_len = self.file.metadata().map_err(|_| Error::IO)?.len();
+        // erased bytes beyond the end of the file do not need to be written
+        let end = file_len.min(start + len as u64);
+        if start < end {
+            self.file
+                .seek(SeekFrom::Start(start))
+                .map_err(|_| Error::IO)?;
+            self.file
+                .write_all(&vec![ERASE_VALUE; (end - start) as usize])
+                .map_err(|_| Error::IO)?;
+        }
+        Ok(len)
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        self.file.sync_data().map_err(|_| Error::IO)
+    }
+}
+
+/// Storage backed by a memory-mapped file on the host.
+///
+/// This type requires the `mmap` feature.  In contrast to [`FileStorage`][], the image file must
+/// have exactly the size of the storage, and all accesses are plain memory copies, so this
+/// storage is suited for analyzing large flash dumps.  The geometry is set with the same
+/// parameters as for [`RomStorage`](crate::driver::RomStorage), with read and write sizes of one
+/// byte.  Accesses outside of the storage fail with [`Error::INVALID`][].
+///
+/// Changes are written back to the file by the operating system.  Use [`flush`](Self::flush) to
+/// wait until they have been written, or [`open_copy`](Self::open_copy) to mount an image
+/// without modifying the file.
+///
+/// The file must not be modified by other processes while it is mapped, see
+/// [`memmap2::MmapMut`][].
+///
+/// ```no_run
+///# use littlefs2::{fs::Filesystem, host::MmapStorage, path};
+/// // inspect a dump without modifying it
+/// let mut storage = MmapStorage::<4096, 65536>::open_copy("dump.bin")?;
+/// Filesystem::mount_and_then(&mut storage, |fs| {
+///     fs.read_dir_and_then(path!("/"), |dir| {
+///         for entry in dir {
+///             println!("{}", entry?.path());
+///         }
+///         Ok(())
+///     })
+/// })
+/// .unwrap();
+///# Ok::<(), std::io::Error>(())
+/// ```
+#[cfg(feature = "mmap")]
+pub struct MmapStorage<
+    const BLOCK_SIZE: usize,
+    const BLOCK_COUNT: usize,
+    CacheSize = consts::U128,
+    LookaheadSize = consts::U1,
+> {
+    map: memmap2::MmapMut,
+    _sizes: PhantomData<(CacheSize, LookaheadSize)>,
+}
+
+#[cfg(feature = "mmap")]
+impl<const BLOCK_SIZE: usize, const BLOCK_COUNT: usize, CacheSize, LookaheadSize>
+    MmapStorage<BLOCK_SIZE, BLOCK_COUNT, CacheSize, LookaheadSize>
+{
+    const CAPACITY: usize = BLOCK_SIZE * BLOCK_COUNT;
+
+    /// Creates an erased image file at `path` and maps it, replacing an existing file.
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        file.set_len(Self::CAPACITY as u64)?;
+        let mut storage = Self::from_file(&file)?;
+        storage.map.fill(ERASE_VALUE);
+        Ok(storage)
+    }
+
+    /// Maps the existing image file at `path` for reading and writing.
+    ///
+    /// Fails with [`io::ErrorKind::InvalidInput`][] if the file does not have the size of the
+    /// storage.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        Self::from_file(&file)
+    }
+
+    /// Maps the existing image file at `path` copy-on-write.
+    ///
+    /// The filesystem can be modified, but the changes are only kept in memory and are never
+    /// written back to the file.  Fails with [`io::ErrorKind::InvalidInput`][] if the file does
+    /// not have the size of the storage.
+    pub fn open_copy(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = File::open(path)?;
+        Self::check_len(&file)?;
+        // SAFETY: As documented for the type, the file must not be modified while it is mapped.
+        let map = unsafe { memmap2::MmapOptions::new().map_copy(&file)? };
+        Ok(Self::from_map(map))
+    }
+
+    /// Maps `file` for reading and writing.
+    ///
+    /// The file must be readable and writable.  Fails with [`io::ErrorKind::InvalidInput`][] if
+    /// the file does not have the size of the storage.
+    pub fn from_file(file: &File) -> io::Result<Self> {
+        Self::check_len(file)?;
+        // SAFETY: As documented for the type, the file must not be modified while it is mapped.
+        let map = unsafe { memmap2::MmapMut::map_mut(file)? };
+        Ok(Self::from_map(map))
+    }
+
+    fn from_map(map: memmap2::MmapMut) -> Self {
+        Self {
+            map,
+            _sizes: PhantomData,
+        }
+    }
+
+    fn check_len(file: &File) -> io::Result<()> {
+        if file.metadata()?.len() != Self::CAPACITY as u64 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "image file does not have the size of the storage",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Returns the content of the storage.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.map
+    }
+
+    /// Returns the content of the storage for modification.
+    pub fn as_bytes_mut(&mut self) -> &mut [u8] {
+        &mut self.map
+    }
+
+    /// Writes outstanding changes to the file and waits until they have been written.
+    ///
+    /// This has no effect for storages created with [`open_copy`](Self::open_copy).
+    pub fn flush(&self) -> io::Result<()> {
+        self.map.flush()
+    }
+
+    fn range(off: usize, len: usize) -> Result<core::ops::Range<usize>> {
+        match off.checked_add(len) {
+            Some(end) if end <= Self::CAPACITY => Ok(off..end),
+            _ => Err(Error::INVALID),
+        }
+    }
+}
+
+#[cfg(feature = "mmap")]
+impl<const BLOCK_SIZE: usize, const BLOCK_COUNT: usize, CacheSize, LookaheadSize> Storage
+    for MmapStorage<BLOCK_SIZE, BLOCK_COUNT, CacheSize, LookaheadSize>
+where
+    CacheSize: ArrayLength<u8>,
+    LookaheadSize: ArrayLength<u64>,
+{
+    const READ_SIZE: usize = 1;
+    const WRITE_SIZE: usize = 1;
+    const BLOCK_SIZE: usize = BLOCK_SIZE;
+    const BLOCK_COUNT: usize = BLOCK_COUNT;
+    type CACHE_SIZE = CacheSize;
+    type LOOKAHEAD_SIZE = LookaheadSize;
+
+    fn read(&mut self, off: usize, buf: &mut [u8]) -> Result<usize> {
+        let range = Self::range(off, buf.len())?;
+        buf.copy_from_slice(&self.map[range]);
+        Ok(buf.len())
+    }
+
+    fn write(&mut self, off: usize, data: &[u8]) -> Result<usize> {
+        let range = Self::range(off, data.len())?;
+        self.map[range].copy_from_slice(data);
+        Ok(data.len())
+    }
+
+    fn erase(&mut self, off: usize, len: usize) -> Result<usize> {
+        let range = Self::range(off, len)?;
+        self.map[range].fill(ERASE_VALUE);
+        Ok(len)
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        self.map.flush().map_err(|_| Error::IO)
+    }
+}
@@ -0,0 +1,193 @@
+//! Storage wrappers that collect statistics about the storage accesses of littlefs.
+//!
+//! ```
+//!# use littlefs2::{driver, fs::Filesystem, instrument::CountingStorage, io::Result, path, ram_storage};
+//!# ram_storage!(tiny);
+//!# let mut ram = Ram::default();
+//! let mut storage = CountingStorage::<_, 32>::new(RamStorage::new(&mut ram));
+//! Filesystem::format(&mut storage).unwrap();
+//! Filesystem::mount_and_then(&mut storage, |fs| {
+//!     for i in 0..10 {
+//!         fs.write(path!("counter"), &[i])?;
+//!     }
+//!     Ok(())
+//! })
+//! .unwrap();
+//!
+//! let totals = storage.totals();
+//! assert!(totals.erases > 0);
+//! assert_eq!(storage.erase_counts().iter().sum::<u32>() as usize, totals.erases);
+//! let (least_worn, most_worn) = storage.erase_count_range();
+//! assert!(least_worn <= most_worn);
+//! // every update of the one-byte file rewrites metadata
+//! assert!(totals.write_amplification(10).unwrap() > 1.0);
+//! ```
+//!
+//! The wrappers work with every storage, including the RAM storages created with
+//! [`ram_storage!`](crate::ram_storage) and [`const_ram_storage!`](crate::const_ram_storage), so
+//! they can be used to detect regressions of the wear distribution in tests.
+
+use crate::{driver::Storage, io::Result};
+
+/// Total numbers of the storage operations recorded by a [`CountingStorage`][].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Totals {
+    /// Number of read operations.
+    pub reads: usize,
+    /// Number of bytes read.
+    pub read_bytes: usize,
+    /// Number of write (program) operations.
+    pub programs: usize,
+    /// Number of bytes written.
+    pub program_bytes: usize,
+    /// Number of erased blocks.
+    pub erases: usize,
+}
+
+impl Totals {
+    /// Returns the ratio of the bytes written to the storage to `payload_bytes`, the number of
+    /// bytes written by the application in the same period.
+    ///
+    /// Returns `None` if `payload_bytes` is zero.
+    pub fn write_amplification(&self, payload_bytes: usize) -> Option<f32> {
+        if payload_bytes == 0 {
+            None
+        } else {
+            Some(self.program_bytes as f32 / payload_bytes as f32)
+        }
+    }
+}
+
+/// Storage wrapper that counts the erases and programs of every block.
+///
+/// The counts show how evenly littlefs distributes the wear over the storage for a given
+/// workload, which helps to choose [`Storage::BLOCK_CYCLES`][].  Per-block counts are kept for
+/// the first `N_BLOCKS` blocks, which should be [`Storage::BLOCK_COUNT`][] of the wrapped
+/// storage.  Accesses to blocks beyond `N_BLOCKS` are only included in the [`Totals`][].
+/// Failed operations are not counted.
+///
+/// An erase of several blocks counts as one erase of each block.  A program counts for every
+/// block that it touches.
+pub struct CountingStorage<S: Storage, const N_BLOCKS: usize> {
+    storage: S,
+    erases: [u32; N_BLOCKS],
+    programs: [u32; N_BLOCKS],
+    totals: Totals,
+}
+
+impl<S: Storage, const N_BLOCKS: usize> CountingStorage<S, N_BLOCKS> {
+    pub fn new(storage: S) -> Self {
+        Self {
+            storage,
+            erases: [0; N_BLOCKS],
+            programs: [0; N_BLOCKS],
+            totals: Default::default(),
+        }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.storage
+    }
+
+    /// Returns a mutable reference to the wrapped storage.
+    ///
+    /// Accesses through this reference are not counted.
+    pub fn inner_mut(&mut self) -> &mut S {
+        &mut self.storage
+    }
+
+    pub fn into_inner(self) -> S {
+        self.storage
+    }
+
+    /// Returns the number of erases of every block.
+    pub fn erase_counts(&self) -> &[u32; N_BLOCKS] {
+        &self.erases
+    }
+
+    /// Returns the number of programs of every block.
+    pub fn program_counts(&self) -> &[u32; N_BLOCKS] {
+        &self.programs
+    }
+
+    /// Returns the number of erases of `block`, or `None` if it is not tracked.
+    pub fn erase_count(&self, block: usize) -> Option<u32> {
+        self.erases.get(block).copied()
+    }
+
+    /// Returns the number of programs of `block`, or `None` if it is not tracked.
+    pub fn program_count(&self, block: usize) -> Option<u32> {
+        self.programs.get(block).copied()
+    }
+
+    /// Returns the lowest and the highest erase count of all tracked blocks.
+    ///
+    /// The difference between them shows how evenly the wear is distributed.
+    pub fn erase_count_range(&self) -> (u32, u32) {
+        let min = self.erases.iter().copied().min().unwrap_or_default();
+        let max = self.erases.iter().copied().max().unwrap_or_default();
+        (min, max)
+    }
+
+    pub fn totals(&self) -> Totals {
+        self.totals
+    }
+
+    /// Sets all counts to zero.
+    pub fn reset(&mut self) {
+        self.erases = [0; N_BLOCKS];
+        self.programs = [0; N_BLOCKS];
+        self.totals = Default::default();
+    }
+
+    fn blocks(off: usize, len: usize) -> core::ops::Range<usize> {
+        off / S::BLOCK_SIZE..(off + len).div_ceil(S::BLOCK_SIZE)
+    }
+}
+
+impl<S: Storage, const N_BLOCKS: usize> Storage for CountingStorage<S, N_BLOCKS> {
+    const READ_SIZE: usize = S::READ_SIZE;
+    const WRITE_SIZE: usize = S::WRITE_SIZE;
+    const BLOCK_SIZE: usize = S::BLOCK_SIZE;
+    const BLOCK_COUNT: usize = S::BLOCK_COUNT;
+    const BLOCK_CYCLES: isize = S::BLOCK_CYCLES;
+    const FILENAME_MAX: usize = S::FILENAME_MAX;
+    const FILEBYTES_MAX: u32 = S::FILEBYTES_MAX;
+    type CACHE_SIZE = S::CACHE_SIZE;
+    type LOOKAHEAD_SIZE = S::LOOKAHEAD_SIZE;
+
+    fn read(&mut self, off: usize, buf: &mut [u8]) -> Result<usize> {
+        let read = self.storage.read(off, buf)?;
+        self.totals.reads += 1;
+        self.totals.read_bytes += read;
+        Ok(read)
+    }
+
+    fn write(&mut self, off: usize, data: &[u8]) -> Result<usize> {
+        let written = self.storage.write(off, data)?;
+        self.totals.programs += 1;
+        self.totals.program_bytes += written;
+        for block in Self::blocks(off, written) {
+            if let Some(count) = self.programs.get_mut(block) {
+                *count = count.saturating_add(1);
+            }
+        }
+        Ok(written)
+    }
+
+    fn erase(&mut self, off: usize, len: usize) -> Result<usize> {
+        let erased = self.storage.erase(off, len)?;
+        let blocks = Self::blocks(off, erased);
+        self.totals.erases += blocks.len();
+        for block in blocks {
+            if let Some(count) = self.erases.get_mut(block) {
+                *count = count.saturating_add(1);
+            }
+        }
+        Ok(erased)
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        self.storage.sync()
+    }
+}
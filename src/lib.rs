@@ -1,4 +1,4 @@
-#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(any(test, feature = "std")), no_std)]
 // FIXME
 #![allow(clippy::missing_safety_doc)]
 
@@ -133,7 +133,7 @@ pub use littlefs2_core::path;
 
 /// Traits and types for core I/O functionality.
 pub mod io {
-    pub use littlefs2_core::{Error, OpenSeekFrom, Read, Result, Seek, SeekFrom, Write};
+    pub use littlefs2_core::{copy, Error, OpenSeekFrom, Read, Result, Seek, SeekFrom, Write};
 
     pub mod prelude {
         //! Export of the Read, Write and Seek traits for ease of use.
@@ -144,14 +144,46 @@ pub mod io {
 
 /// Paths
 pub mod path {
+    #[cfg(feature = "serde")]
+    pub use littlefs2_core::serde_str;
     pub use littlefs2_core::{Ancestors, Iter, Path, PathBuf, PathError as Error};
     pub type Result<T> = core::result::Result<T, Error>;
 }
 
+/// Export of commonly used traits and types.
+///
+/// ```
+/// use littlefs2::prelude::*;
+///
+/// fn read_config(fs: &dyn DynFilesystem) -> littlefs2::io::Result<()> {
+///     fs.open_file_and_then(path!("config"), &mut |file| {
+///         file.seek(SeekFrom::Start(0))?;
+///         let mut buf = [0; 4];
+///         file.read_exact(&mut buf)
+///     })
+/// }
+/// ```
+///
+/// [`DynStorage`](object_safe::DynStorage) is not exported as its methods clash with
+/// [`Storage`](driver::Storage).
+pub mod prelude {
+    pub use crate::{
+        driver::Storage,
+        fs::{File, Filesystem, OpenOptions},
+        io::{OpenSeekFrom, Read, Seek, SeekFrom, Write},
+        object_safe::{DynFile, DynFilesystem},
+        path,
+        path::{Path, PathBuf},
+    };
+}
+
 #[macro_use]
 extern crate delog;
 generate_macros!();
 
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
 /// cf. Macros documentation
 #[macro_use]
 pub mod macros;
@@ -161,8 +193,24 @@ mod c_stubs;
 
 pub mod consts;
 pub mod driver;
+pub mod encoding;
 pub mod fs;
+pub mod fs_busy_poll;
+#[cfg(all(feature = "fuse", unix))]
+pub mod fuse;
+#[cfg(feature = "std")]
+pub mod host;
+pub mod instrument;
 pub mod object_safe;
+pub mod store;
+#[cfg(feature = "sync")]
+pub mod sync;
+#[cfg(any(test, feature = "test-doubles"))]
+pub mod test_doubles;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+#[cfg(feature = "std")]
+pub mod tools;
 
 /// get information about the C backend
 pub fn version() -> Version {
@@ -2,6 +2,9 @@
 // data than requested, to emphasize the difference between
 // `io::Read::read` and `::read_exact`.
 /// A configurable implementation of the Storage trait in memory.
+///
+/// To record the erases and programs of every block, for example to test the wear distribution,
+/// wrap the storage in an [`instrument::CountingStorage`](crate::instrument::CountingStorage).
 #[macro_export]
 macro_rules! ram_storage { (
 
@@ -21,13 +24,21 @@ macro_rules! ram_storage { (
 
 ) => {
         pub struct $Backend {
-            buf: [u8; $block_size * $block_count],
+            storage: $crate::driver::RamStorage<
+                { $block_size },
+                { $block_count },
+                $cache_size,
+                $lookahead_size,
+                { $read_size },
+                { $write_size },
+                { $erase_value },
+            >,
         }
 
         impl Default for $Backend {
             fn default() -> Self {
                 $Backend {
-                    buf: [$erase_value; $block_size * $block_count],
+                    storage: $crate::driver::RamStorage::new(),
                 }
             }
         }
@@ -37,7 +48,6 @@ macro_rules! ram_storage { (
         }
 
         impl<'backend> $Name<'backend> {
-            const ERASE_VALUE: u8 = $erase_value;
             pub fn new(backend: &'backend mut $Backend) -> Self {
                 $Name { backend }
             }
@@ -52,33 +62,15 @@ macro_rules! ram_storage { (
             type LOOKAHEAD_SIZE = $lookahead_size;
 
             fn read(&mut self, offset: usize, buf: &mut [u8]) -> $Result<usize> {
-                let read_size: usize = Self::READ_SIZE;
-                debug_assert!(offset % read_size == 0);
-                debug_assert!(buf.len() % read_size == 0);
-                for (from, to) in self.backend.buf[offset..].iter().zip(buf.iter_mut()) {
-                    *to = *from;
-                }
-                Ok(buf.len())
+                $crate::driver::Storage::read(&mut self.backend.storage, offset, buf)
             }
 
             fn write(&mut self, offset: usize, data: &[u8]) -> $Result<usize> {
-                let write_size: usize = Self::WRITE_SIZE;
-                debug_assert!(offset % write_size == 0);
-                debug_assert!(data.len() % write_size == 0);
-                for (from, to) in data.iter().zip(self.backend.buf[offset..].iter_mut()) {
-                    *to = *from;
-                }
-                Ok(data.len())
+                $crate::driver::Storage::write(&mut self.backend.storage, offset, data)
             }
 
             fn erase(&mut self, offset: usize, len: usize) -> $Result<usize> {
-                let block_size: usize = Self::BLOCK_SIZE;
-                debug_assert!(offset % block_size == 0);
-                debug_assert!(len % block_size == 0);
-                for byte in self.backend.buf[offset..offset + len].iter_mut() {
-                    *byte = Self::ERASE_VALUE;
-                }
-                Ok(len)
+                $crate::driver::Storage::erase(&mut self.backend.storage, offset, len)
             }
         }
     };
@@ -153,22 +145,28 @@ macro_rules! const_ram_storage { (
 
 ) => {
         pub struct $Name {
-            buf: [u8; $block_size * $block_count],
+            storage: $crate::driver::RamStorage<
+                { $block_size },
+                { $block_count },
+                $cache_size,
+                $lookahead_size,
+                { $read_size },
+                { $write_size },
+                { $erase_value },
+            >,
         }
 
         impl $Name {
-            const ERASE_VALUE: u8 = $erase_value;
             pub const fn new() -> Self {
-                // Self::default()
-                Self { buf: [$erase_value; $block_size * $block_count] }
+                Self {
+                    storage: $crate::driver::RamStorage::new(),
+                }
             }
         }
 
         impl Default for $Name {
             fn default() -> Self {
-                Self {
-                    buf: [$erase_value; $block_size * $block_count],
-                }
+                Self::new()
             }
         }
 
@@ -181,33 +179,15 @@ macro_rules! const_ram_storage { (
             type LOOKAHEAD_SIZE = $lookahead_size;
 
             fn read(&mut self, offset: usize, buf: &mut [u8]) -> $Result<usize> {
-                let read_size: usize = Self::READ_SIZE;
-                debug_assert!(offset % read_size == 0);
-                debug_assert!(buf.len() % read_size == 0);
-                for (from, to) in self.buf[offset..].iter().zip(buf.iter_mut()) {
-                    *to = *from;
-                }
-                Ok(buf.len())
+                $crate::driver::Storage::read(&mut self.storage, offset, buf)
             }
 
             fn write(&mut self, offset: usize, data: &[u8]) -> $Result<usize> {
-                let write_size: usize = Self::WRITE_SIZE;
-                debug_assert!(offset % write_size == 0);
-                debug_assert!(data.len() % write_size == 0);
-                for (from, to) in data.iter().zip(self.buf[offset..].iter_mut()) {
-                    *to = *from;
-                }
-                Ok(data.len())
+                $crate::driver::Storage::write(&mut self.storage, offset, data)
             }
 
             fn erase(&mut self, offset: usize, len: usize) -> $Result<usize> {
-                let block_size: usize = Self::BLOCK_SIZE;
-                debug_assert!(offset % block_size == 0);
-                debug_assert!(len % block_size == 0);
-                for byte in self.buf[offset..offset + len].iter_mut() {
-                    *byte = Self::ERASE_VALUE;
-                }
-                Ok(len)
+                $crate::driver::Storage::erase(&mut self.storage, offset, len)
             }
         }
     };
@@ -1,20 +1,37 @@
 //! Object-safe traits for [`File`][], [`Filesystem`][] and [`Storage`][].
 
+#[cfg(feature = "alloc")]
+use alloc::boxed::Box;
+
 use generic_array::typenum::Unsigned as _;
 
+#[cfg(feature = "alloc")]
+use crate::fs::{DirEntry, OpenOptions, OwnedFile};
 use crate::{
     driver::Storage,
-    fs::{Attribute, File, FileOpenFlags, Filesystem, Metadata},
+    fs::{
+        Attribute, DirIterationTell, File, FileOpenFlags, Filesystem, FsInfo, Metadata,
+        WriteChunkOptions,
+    },
     io::{Error, OpenSeekFrom, Result},
     path::Path,
 };
 
-pub use littlefs2_core::{DirEntriesCallback, DynFile, DynFilesystem, FileCallback, Predicate};
+pub use littlefs2_core::{
+    DirEntriesCallback, DirEntriesTellCallback, DynFile, DynFilesystem, FileCallback, Predicate,
+};
 
 // Make sure that the traits actually are object safe.
 const _: Option<&dyn DynStorage> = None;
+#[cfg(feature = "alloc")]
+const _: Option<&dyn DynFilesystemAlloc> = None;
 
 pub type FilesystemCallback<'a, R = ()> = &'a mut dyn FnMut(&dyn DynFilesystem) -> Result<R>;
+#[cfg(feature = "alloc")]
+pub type FileOnceCallback<'a, R = ()> = Box<dyn FnOnce(&dyn DynFile) -> Result<R> + 'a>;
+#[cfg(feature = "alloc")]
+pub type DirEntriesOnceCallback<'a, R = ()> =
+    Box<dyn FnOnce(&mut dyn Iterator<Item = Result<DirEntry>>) -> Result<R> + 'a>;
 
 impl<S: Storage> DynFile for File<'_, '_, S> {
     fn sync(&self) -> Result<()> {
@@ -32,6 +49,41 @@ impl<S: Storage> DynFile for File<'_, '_, S> {
     fn set_len(&self, size: usize) -> Result<()> {
         File::set_len(self, size)
     }
+
+    fn tell(&self) -> Result<usize> {
+        File::tell(self)
+    }
+
+    fn rewind(&self) -> Result<()> {
+        File::rewind(self)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<S: Storage> DynFile for OwnedFile<'_, '_, S> {
+    fn sync(&self) -> Result<()> {
+        File::sync(self)
+    }
+
+    fn len(&self) -> Result<usize> {
+        File::len(self)
+    }
+
+    fn is_empty(&self) -> Result<bool> {
+        File::is_empty(self)
+    }
+
+    fn set_len(&self, size: usize) -> Result<()> {
+        File::set_len(self, size)
+    }
+
+    fn tell(&self) -> Result<usize> {
+        File::tell(self)
+    }
+
+    fn rewind(&self) -> Result<()> {
+        File::rewind(self)
+    }
 }
 
 impl<S: Storage> DynFilesystem for Filesystem<'_, S> {
@@ -51,6 +103,10 @@ impl<S: Storage> DynFilesystem for Filesystem<'_, S> {
         Filesystem::available_space(self)
     }
 
+    fn fs_info(&self) -> FsInfo {
+        Filesystem::fs_info(self)
+    }
+
     fn remove(&self, path: &Path) -> Result<()> {
         Filesystem::remove(self, path)
     }
@@ -67,10 +123,31 @@ impl<S: Storage> DynFilesystem for Filesystem<'_, S> {
         Filesystem::remove_dir_all_where(self, path, &|entry| predicate(entry))
     }
 
+    fn remove_dir_all_with_progress(
+        &self,
+        path: &Path,
+        predicate: Predicate<'_>,
+        on_removed: &mut dyn FnMut(&Path),
+    ) -> Result<usize> {
+        Filesystem::remove_dir_all_with_progress(self, path, &|entry| predicate(entry), on_removed)
+    }
+
     fn rename(&self, from: &Path, to: &Path) -> Result<()> {
         Filesystem::rename(self, from, to)
     }
 
+    fn rename_noreplace(&self, from: &Path, to: &Path) -> Result<()> {
+        Filesystem::rename_noreplace(self, from, to)
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> Result<usize> {
+        Filesystem::copy(self, from, to)
+    }
+
+    fn copy_dir_all(&self, from: &Path, to: &Path) -> Result<()> {
+        Filesystem::copy_dir_all(self, from, to)
+    }
+
     fn exists(&self, path: &Path) -> bool {
         Filesystem::exists(self, path)
     }
@@ -121,10 +198,33 @@ impl<S: Storage> DynFilesystem for Filesystem<'_, S> {
         Filesystem::set_attribute(self, path, id, data)
     }
 
+    fn attributes(&self, path: &Path, f: &mut dyn FnMut(u8, &[u8]) -> Result<()>) -> Result<()> {
+        Filesystem::attributes(self, path, f)
+    }
+
     fn read_dir_and_then_unit(&self, path: &Path, f: DirEntriesCallback<'_>) -> Result<()> {
         Filesystem::read_dir_and_then(self, path, |entries| f(entries))
     }
 
+    fn read_dir_from_and_then_unit(
+        &self,
+        path: &Path,
+        tell: DirIterationTell,
+        f: DirEntriesTellCallback<'_>,
+    ) -> Result<()> {
+        Filesystem::read_dir_from_and_then(self, path, tell, |read_dir| {
+            let mut entries = core::iter::from_fn(|| {
+                let entry = read_dir.next()?;
+                Some(entry.and_then(|entry| Ok((entry, read_dir.tell()?))))
+            });
+            f(&mut entries)
+        })
+    }
+
+    fn walk_dir_and_then_unit(&self, path: &Path, f: DirEntriesCallback<'_>) -> Result<()> {
+        Filesystem::walk_dir_and_then(self, path, |entries| f(entries))
+    }
+
     fn create_dir(&self, path: &Path) -> Result<()> {
         Filesystem::create_dir(self, path)
     }
@@ -137,9 +237,216 @@ impl<S: Storage> DynFilesystem for Filesystem<'_, S> {
         Filesystem::write(self, path, contents)
     }
 
+    fn append(&self, path: &Path, contents: &[u8]) -> Result<()> {
+        Filesystem::append(self, path, contents)
+    }
+
     fn write_chunk(&self, path: &Path, contents: &[u8], pos: OpenSeekFrom) -> Result<()> {
         Filesystem::write_chunk(self, path, contents, pos)
     }
+
+    fn write_chunk_with_options(
+        &self,
+        path: &Path,
+        contents: &[u8],
+        pos: OpenSeekFrom,
+        options: WriteChunkOptions,
+    ) -> Result<()> {
+        Filesystem::write_chunk_with_options(self, path, contents, pos, options)
+    }
+}
+
+/// Object-safe trait for files that are closed when they are dropped, see [`OwnedFile`][].
+///
+/// This trait requires the `alloc` feature.
+#[cfg(feature = "alloc")]
+pub trait DynFileOwned: DynFile {
+    /// Closes the file and returns the errors that are ignored when it is dropped.
+    fn close(self: Box<Self>) -> Result<()>;
+}
+
+#[cfg(feature = "alloc")]
+impl<S: Storage> DynFileOwned for OwnedFile<'_, '_, S> {
+    fn close(self: Box<Self>) -> Result<()> {
+        OwnedFile::close(*self)
+    }
+}
+
+/// Object-safe extension of [`DynFilesystem`][] for APIs that require heap allocations.
+///
+/// This trait requires the `alloc` feature.  The files returned by
+/// [`DynFilesystemAlloc::open_file`][] and similar methods can be kept open across function calls
+/// and are closed when they are dropped, see [`OwnedFile`][].
+///
+/// The following methods take boxed [`FnOnce`][] callbacks, so that the callbacks can consume
+/// the values that they capture, but they cannot support generic return types:
+/// - [`DynFilesystemAlloc::create_file_and_then_once_unit`][]
+/// - [`DynFilesystemAlloc::open_file_and_then_once_unit`][]
+/// - [`DynFilesystemAlloc::read_dir_and_then_once_unit`][]
+///
+/// Use these helper functions instead:
+/// - [`DynFilesystemAlloc::create_file_and_then_once`](#method.create_file_and_then_once)
+/// - [`DynFilesystemAlloc::open_file_and_then_once`](#method.open_file_and_then_once)
+/// - [`DynFilesystemAlloc::read_dir_and_then_once`](#method.read_dir_and_then_once)
+///
+/// ```
+///# use littlefs2::{driver, fs::Filesystem, io::{Read as _, Result, Write as _}, object_safe::{DynFileOwned, DynFilesystemAlloc}, path, ram_storage};
+///# ram_storage!(tiny);
+///# let mut ram = Ram::default();
+///# let mut storage = RamStorage::new(&mut ram);
+///# Filesystem::format(&mut storage).unwrap();
+///# Filesystem::mount_and_then(&mut storage, |fs| {
+/// let fs: &dyn DynFilesystemAlloc = fs;
+/// let file = fs.create_file(path!("data"))?;
+/// file.write_all(b"data")?;
+/// drop(file);
+///
+/// let file: Box<dyn DynFileOwned + '_> = fs.open_file(path!("data"))?;
+/// let mut buf = [0; 4];
+/// file.read_exact(&mut buf)?;
+/// assert_eq!(&buf, b"data");
+/// file.close()?;
+///# Ok(())
+///# }).unwrap();
+/// ```
+#[cfg(feature = "alloc")]
+pub trait DynFilesystemAlloc: DynFilesystem {
+    /// Opens the file at `path` for reading.
+    fn open_file<'a>(&'a self, path: &Path) -> Result<Box<dyn DynFileOwned + 'a>>;
+
+    /// Creates or truncates the file at `path` and opens it for writing.
+    fn create_file<'a>(&'a self, path: &Path) -> Result<Box<dyn DynFileOwned + 'a>>;
+
+    /// Opens the file at `path` with the given flags.
+    fn open_file_with_flags<'a>(
+        &'a self,
+        flags: FileOpenFlags,
+        path: &Path,
+    ) -> Result<Box<dyn DynFileOwned + 'a>>;
+
+    fn create_file_and_then_once_unit(&self, path: &Path, f: FileOnceCallback<'_>) -> Result<()>;
+    fn open_file_and_then_once_unit(&self, path: &Path, f: FileOnceCallback<'_>) -> Result<()>;
+    fn read_dir_and_then_once_unit(&self, path: &Path, f: DirEntriesOnceCallback<'_>)
+        -> Result<()>;
+}
+
+#[cfg(feature = "alloc")]
+impl<S: Storage> DynFilesystemAlloc for Filesystem<'_, S> {
+    fn open_file<'a>(&'a self, path: &Path) -> Result<Box<dyn DynFileOwned + 'a>> {
+        let file = OpenOptions::new().read(true).open_owned(self, path)?;
+        Ok(Box::new(file))
+    }
+
+    fn create_file<'a>(&'a self, path: &Path) -> Result<Box<dyn DynFileOwned + 'a>> {
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open_owned(self, path)?;
+        Ok(Box::new(file))
+    }
+
+    fn open_file_with_flags<'a>(
+        &'a self,
+        flags: FileOpenFlags,
+        path: &Path,
+    ) -> Result<Box<dyn DynFileOwned + 'a>> {
+        let file = OpenOptions::from(flags).open_owned(self, path)?;
+        Ok(Box::new(file))
+    }
+
+    fn create_file_and_then_once_unit(&self, path: &Path, f: FileOnceCallback<'_>) -> Result<()> {
+        Filesystem::create_file_and_then(self, path, |file| f(file))
+    }
+
+    fn open_file_and_then_once_unit(&self, path: &Path, f: FileOnceCallback<'_>) -> Result<()> {
+        Filesystem::open_file_and_then(self, path, |file| f(file))
+    }
+
+    fn read_dir_and_then_once_unit(
+        &self,
+        path: &Path,
+        f: DirEntriesOnceCallback<'_>,
+    ) -> Result<()> {
+        Filesystem::read_dir_and_then(self, path, |entries| f(entries))
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl dyn DynFilesystemAlloc + '_ {
+    /// Creates or truncates the file at `path` and calls `f` with it.
+    ///
+    /// ```
+    ///# use littlefs2::{driver, fs::Filesystem, io::{Result, Write as _}, object_safe::DynFilesystemAlloc, path, ram_storage};
+    ///# ram_storage!(tiny);
+    ///# let mut ram = Ram::default();
+    ///# let mut storage = RamStorage::new(&mut ram);
+    ///# Filesystem::format(&mut storage).unwrap();
+    ///# Filesystem::mount_and_then(&mut storage, |fs| {
+    /// let fs: &dyn DynFilesystemAlloc = fs;
+    /// let lines = vec![String::from("first"), String::from("second")];
+    /// // the callback takes ownership of the lines
+    /// let written = fs.create_file_and_then_once(path!("lines"), move |file| {
+    ///     let mut written = 0;
+    ///     for line in lines {
+    ///         file.write_all(line.as_bytes())?;
+    ///         written += line.len();
+    ///     }
+    ///     Ok(written)
+    /// })?;
+    /// assert_eq!(written, 11);
+    ///# Ok(())
+    ///# }).unwrap();
+    /// ```
+    pub fn create_file_and_then_once<R>(
+        &self,
+        path: &Path,
+        f: impl FnOnce(&dyn DynFile) -> Result<R>,
+    ) -> Result<R> {
+        let mut result = Err(Error::IO);
+        self.create_file_and_then_once_unit(
+            path,
+            Box::new(|file| {
+                result = Ok(f(file)?);
+                Ok(())
+            }),
+        )?;
+        result
+    }
+
+    /// Opens the file at `path` for reading and calls `f` with it.
+    pub fn open_file_and_then_once<R>(
+        &self,
+        path: &Path,
+        f: impl FnOnce(&dyn DynFile) -> Result<R>,
+    ) -> Result<R> {
+        let mut result = Err(Error::IO);
+        self.open_file_and_then_once_unit(
+            path,
+            Box::new(|file| {
+                result = Ok(f(file)?);
+                Ok(())
+            }),
+        )?;
+        result
+    }
+
+    /// Calls `f` with an iterator over the entries of the directory at `path`.
+    pub fn read_dir_and_then_once<R>(
+        &self,
+        path: &Path,
+        f: impl FnOnce(&mut dyn Iterator<Item = Result<DirEntry>>) -> Result<R>,
+    ) -> Result<R> {
+        let mut result = Err(Error::IO);
+        self.read_dir_and_then_once_unit(
+            path,
+            Box::new(|entries| {
+                result = Ok(f(entries)?);
+                Ok(())
+            }),
+        )?;
+        result
+    }
 }
 
 /// Object-safe trait for [`Storage`][].
@@ -148,14 +455,17 @@ impl<S: Storage> DynFilesystem for Filesystem<'_, S> {
 /// - [`DynStorage::format`][]
 /// - [`DynStorage::is_mountable`][]
 /// - [`DynStorage::mount_and_then`](#method.mount_and_then)
+/// - [`DynStorage::mount_or_format_and_then`](#method.mount_or_format_and_then)
 ///
 /// The following methods cannot support generic return types in the callbacks:
 /// - [`DynStorage::mount_and_then_unit`][]
+/// - [`DynStorage::mount_or_format_and_then_unit`][]
 ///
 /// Use these helper functions instead:
 /// - [`DynStorage::mount_and_then`](#method.mount_and_then)
+/// - [`DynStorage::mount_or_format_and_then`](#method.mount_or_format_and_then)
 ///
-/// The `read`, `write` and `erase` methods are mirrored directly.  The associated constants and
+/// The `read`, `write`, `erase` and `sync` methods are mirrored directly.  The associated constants and
 /// types are transformed into methods.  See the documentation for [`Storage`][] for more
 /// information.
 pub trait DynStorage {
@@ -169,9 +479,11 @@ pub trait DynStorage {
     fn read(&mut self, off: usize, buf: &mut [u8]) -> Result<usize>;
     fn write(&mut self, off: usize, data: &[u8]) -> Result<usize>;
     fn erase(&mut self, off: usize, len: usize) -> Result<usize>;
+    fn sync(&mut self) -> Result<()>;
     fn format(&mut self) -> Result<()>;
     fn is_mountable(&mut self) -> bool;
     fn mount_and_then_unit(&mut self, f: FilesystemCallback<'_>) -> Result<()>;
+    fn mount_or_format_and_then_unit(&mut self, f: FilesystemCallback<'_>) -> Result<()>;
 }
 
 impl<S: Storage> DynStorage for S {
@@ -215,6 +527,10 @@ impl<S: Storage> DynStorage for S {
         Storage::erase(self, off, len)
     }
 
+    fn sync(&mut self) -> Result<()> {
+        Storage::sync(self)
+    }
+
     fn format(&mut self) -> Result<()> {
         Filesystem::format(self)
     }
@@ -226,6 +542,12 @@ impl<S: Storage> DynStorage for S {
     fn mount_and_then_unit(&mut self, f: FilesystemCallback<'_>) -> Result<()> {
         Filesystem::mount_and_then(self, |fs| f(fs))
     }
+
+    fn mount_or_format_and_then_unit(&mut self, f: FilesystemCallback<'_>) -> Result<()> {
+        let mut alloc = Filesystem::allocate();
+        let fs = Filesystem::mount_or_format(&mut alloc, self)?;
+        f(&fs)
+    }
 }
 
 impl dyn DynStorage + '_ {
@@ -237,4 +559,16 @@ impl dyn DynStorage + '_ {
         })?;
         result
     }
+
+    /// Mount the filesystem, formatting it first if it is corrupted, and call `f` with it.
+    ///
+    /// See [`Filesystem::mount_or_format`][].
+    pub fn mount_or_format_and_then<R>(&mut self, f: FilesystemCallback<'_, R>) -> Result<R> {
+        let mut result = Err(Error::IO);
+        self.mount_or_format_and_then_unit(&mut |fs| {
+            result = Ok(f(fs)?);
+            Ok(())
+        })?;
+        result
+    }
 }
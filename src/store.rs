@@ -0,0 +1,179 @@
+//! A small, stable interface for key and file stores.
+//!
+//! Frameworks like Trussed only need a handful of filesystem operations to implement their file
+//! and key stores: reading, writing, deleting and renaming files, querying metadata and iterating
+//! over directories across several calls.  The [`Store`][] trait provides exactly these operations
+//! for every [`DynFilesystem`][] implementation, including [`Filesystem`](crate::fs::Filesystem),
+//! so that such frameworks can depend on this trait instead of the broader API of this crate.
+//!
+//! # Stability
+//!
+//! This module is subject to a stricter compatibility policy than the rest of this crate:
+//!
+//! - The existing methods of [`Store`][] and [`Cursor`][] are not changed or removed, not even in
+//!   a release that is allowed to break compatibility for other modules.  If such a change is
+//!   required, it is made in a new trait next to this one.
+//! - New methods are only added to [`Store`][] with a default implementation.
+//! - [`Store`][] stays object safe, so it can be used as `&dyn Store`.
+//!
+//! ```
+//!# use littlefs2::{driver, fs::Filesystem, io::Result, path, ram_storage, store::{Cursor, Store}};
+//!# ram_storage!(tiny);
+//!# let mut ram = Ram::default();
+//!# let mut storage = RamStorage::new(&mut ram);
+//!# Filesystem::format(&mut storage).unwrap();
+//!# Filesystem::mount_and_then(&mut storage, |fs| {
+//! let store: &dyn Store = fs;
+//! store.write(path!("a"), b"first")?;
+//! store.write(path!("b"), b"second")?;
+//!
+//! let mut buf = [0; 16];
+//! let len = store.read(path!("b"), &mut buf)?;
+//! assert_eq!(&buf[..len], b"second");
+//!
+//! let mut cursor = Cursor::new();
+//! let entry = store.read_dir_next(path!("/"), &mut cursor)?.unwrap();
+//! assert_eq!(entry.path(), path!("/a"));
+//!
+//! // the cursor keeps its position even if the last entry is removed
+//! store.delete(entry.path())?;
+//! let entry = store.read_dir_next(path!("/"), &mut cursor)?.unwrap();
+//! assert_eq!(entry.path(), path!("/b"));
+//! assert!(store.read_dir_next(path!("/"), &mut cursor)?.is_none());
+//!# Ok(())
+//!# }).unwrap();
+//! ```
+
+use crate::{
+    fs::{DirEntry, Metadata},
+    io::{Error, Result},
+    object_safe::DynFilesystem,
+    path::{Path, PathBuf},
+};
+
+// Make sure that the trait actually is object safe.
+const _: Option<&dyn Store> = None;
+
+/// The position of a directory iteration with [`Store::read_dir_next`][].
+///
+/// The cursor does not borrow the filesystem, so it can be kept between calls that each mount
+/// or lock the filesystem.  Removing the entry that was returned last does not skip any entries.
+#[derive(Clone, Debug, Default)]
+pub struct Cursor {
+    last: Option<PathBuf>,
+    position: usize,
+}
+
+impl Cursor {
+    /// Creates a cursor that starts at the first entry of a directory.
+    pub const fn new() -> Self {
+        Self {
+            last: None,
+            position: 0,
+        }
+    }
+
+    /// Returns the file name of the entry that was returned last, if any.
+    pub fn last(&self) -> Option<&Path> {
+        self.last.as_deref()
+    }
+}
+
+/// The filesystem operations required by key and file stores.
+///
+/// See the [module documentation](self) for the stability guarantees of this trait.
+pub trait Store {
+    /// Reads the complete file at `path` into `buf` and returns its length.
+    ///
+    /// Fails with [`Error::NO_MEMORY`][] if the file does not fit into `buf`.
+    fn read(&self, path: &Path, buf: &mut [u8]) -> Result<usize>;
+
+    /// Writes `data` to the file at `path`, replacing its previous content.
+    ///
+    /// The file is created if it does not exist.  Its parent directory must exist.
+    fn write(&self, path: &Path, data: &[u8]) -> Result<()>;
+
+    /// Deletes the file or empty directory at `path`.
+    fn delete(&self, path: &Path) -> Result<()>;
+
+    /// Renames `from` to `to`, replacing an existing file at `to`.
+    fn rename(&self, from: &Path, to: &Path) -> Result<()>;
+
+    /// Returns the metadata of the file or directory at `path`.
+    fn metadata(&self, path: &Path) -> Result<Metadata>;
+
+    /// Returns the entry of the directory at `dir` that follows the position of `cursor` and
+    /// advances the cursor.
+    ///
+    /// The special entries `.` and `..` are skipped.  Returns `None` once all entries have been
+    /// returned.
+    fn read_dir_next(&self, dir: &Path, cursor: &mut Cursor) -> Result<Option<DirEntry>>;
+}
+
+impl<T: DynFilesystem + ?Sized> Store for T {
+    fn read(&self, path: &Path, buf: &mut [u8]) -> Result<usize> {
+        let mut len = 0;
+        DynFilesystem::open_file_and_then_unit(self, path, &mut |file| {
+            len = file.len()?;
+            let buf = buf.get_mut(..len).ok_or(Error::NO_MEMORY)?;
+            file.read_exact(buf)
+        })?;
+        Ok(len)
+    }
+
+    fn write(&self, path: &Path, data: &[u8]) -> Result<()> {
+        DynFilesystem::write(self, path, data)
+    }
+
+    fn delete(&self, path: &Path) -> Result<()> {
+        DynFilesystem::remove(self, path)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+        DynFilesystem::rename(self, from, to)
+    }
+
+    fn metadata(&self, path: &Path) -> Result<Metadata> {
+        DynFilesystem::metadata(self, path)
+    }
+
+    fn read_dir_next(&self, dir: &Path, cursor: &mut Cursor) -> Result<Option<DirEntry>> {
+        let mut next = None;
+        DynFilesystem::read_dir_and_then_unit(self, dir, &mut |entries| {
+            let mut entries = entries
+                .filter(|entry| {
+                    !matches!(entry, Ok(entry) if matches!(entry.file_name().as_str(), "." | ".."))
+                })
+                .enumerate();
+            let Some(last) = &cursor.last else {
+                next = entries
+                    .next()
+                    .map(|(i, entry)| entry.map(|entry| (i, entry)))
+                    .transpose()?;
+                return Ok(());
+            };
+            // If the last entry has been removed, the entry after it has moved to its position.
+            let mut fallback = None;
+            while let Some((i, entry)) = entries.next() {
+                let entry = entry?;
+                if *entry.file_name() == **last {
+                    next = entries
+                        .next()
+                        .map(|(i, entry)| entry.map(|entry| (i, entry)))
+                        .transpose()?;
+                    return Ok(());
+                }
+                if fallback.is_none() && i + 1 >= cursor.position {
+                    fallback = Some((i, entry));
+                }
+            }
+            next = fallback;
+            Ok(())
+        })?;
+        Ok(next.map(|(i, entry)| {
+            cursor.last = Some(entry.file_name().as_path().into());
+            cursor.position = i + 1;
+            entry
+        }))
+    }
+}
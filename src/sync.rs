@@ -0,0 +1,153 @@
+//! Filesystem access from multiple tasks or threads.
+//!
+//! This module requires the `sync` feature.  [`Filesystem`][] uses interior mutability without
+//! synchronization, so it cannot be shared between tasks that may preempt each other, for
+//! example in RTIC or embassy applications with multiple priorities.  [`SharedFilesystem`][]
+//! owns a storage and serializes all accesses with a [`RawMutex`][], so it can be initialized
+//! once, for example in a `static` cell, and used from all tasks.
+//!
+//! By default, the accesses are protected with a critical section from the [`critical_section`][]
+//! crate.  This disables interrupts for the duration of every filesystem operation, which may be
+//! too long for some applications.  Implement [`RawMutex`][] to use another locking mechanism and
+//! pass it to [`SharedFilesystem::with_mutex`][].
+//!
+//! littlefs can be built with `LFS_THREADSAFE` to call lock and unlock callbacks around every
+//! operation.  The bundled littlefs from `littlefs2-sys` is built without this option, and the
+//! callbacks are not needed with [`SharedFilesystem`][]: every call into littlefs happens while
+//! the mutex is locked, including the storage callbacks, so the C library is never entered
+//! concurrently.
+
+use core::cell::RefCell;
+
+use crate::{
+    driver::Storage,
+    fs::{Filesystem, OwnedFilesystem},
+    io::Result,
+};
+
+/// A mutex that protects a [`SharedFilesystem`][].
+///
+/// # Safety
+///
+/// Implementations must guarantee that [`RawMutex::lock`][] never runs its closure while another
+/// call of `lock` on the same mutex runs its closure, on any thread or in any interrupt handler.
+pub unsafe trait RawMutex {
+    /// Locks the mutex, calls `f` and unlocks the mutex again.
+    fn lock<R>(&self, f: impl FnOnce() -> R) -> R;
+}
+
+/// A [`RawMutex`][] that uses a critical section from the [`critical_section`][] crate.
+pub struct CriticalSectionRawMutex {
+    _private: (),
+}
+
+impl CriticalSectionRawMutex {
+    pub const fn new() -> Self {
+        Self { _private: () }
+    }
+}
+
+impl Default for CriticalSectionRawMutex {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// SAFETY: Critical sections are never executed concurrently.
+unsafe impl RawMutex for CriticalSectionRawMutex {
+    fn lock<R>(&self, f: impl FnOnce() -> R) -> R {
+        critical_section::with(|_| f())
+    }
+}
+
+/// A filesystem that can be shared between tasks and threads.
+///
+/// This is an [`OwnedFilesystem`][] behind a mutex.  All methods take `&self`, and the type is
+/// `Send` and `Sync` if the storage is `Send`.  The mutex is held for the complete duration of
+/// [`SharedFilesystem::with`][] and the other methods.
+///
+/// ```
+///# use littlefs2::{driver, io::Result, path, ram_storage, sync::SharedFilesystem};
+///# ram_storage!(tiny);
+///# let mut ram = Ram::default();
+///# let storage = RamStorage::new(&mut ram);
+/// let fs = SharedFilesystem::new(storage);
+/// fs.format().unwrap();
+/// fs.mount().unwrap();
+/// std::thread::scope(|scope| {
+///     scope.spawn(|| fs.with(|fs| fs.write(path!("a"), b"a")).unwrap());
+///     scope.spawn(|| fs.with(|fs| fs.write(path!("b"), b"b")).unwrap());
+/// });
+/// assert!(fs.with(|fs| Ok(fs.exists(path!("a")) && fs.exists(path!("b")))).unwrap());
+/// ```
+pub struct SharedFilesystem<S: Storage, M: RawMutex = CriticalSectionRawMutex> {
+    mutex: M,
+    fs: RefCell<OwnedFilesystem<S>>,
+}
+
+// SAFETY: The filesystem is only accessed while the mutex is locked, and it can be sent to other
+// threads if the storage can be sent.
+unsafe impl<S: Storage + Send, M: RawMutex + Sync> Sync for SharedFilesystem<S, M> {}
+
+impl<S: Storage> SharedFilesystem<S> {
+    /// Creates an unmounted filesystem for the given storage that is protected by a critical
+    /// section.
+    pub fn new(storage: S) -> Self {
+        Self::with_mutex(storage, CriticalSectionRawMutex::new())
+    }
+}
+
+impl<S: Storage, M: RawMutex> SharedFilesystem<S, M> {
+    /// Creates an unmounted filesystem for the given storage that is protected by `mutex`.
+    pub fn with_mutex(storage: S, mutex: M) -> Self {
+        Self {
+            mutex,
+            fs: RefCell::new(OwnedFilesystem::new(storage)),
+        }
+    }
+
+    /// Call `f` with the owned filesystem while the mutex is locked.
+    ///
+    /// # Panics
+    ///
+    /// Panics if it is called from within another call for the same filesystem, for example from
+    /// the closure passed to [`SharedFilesystem::with`][].
+    fn lock<R>(&self, f: impl FnOnce(&mut OwnedFilesystem<S>) -> R) -> R {
+        self.mutex.lock(|| f(&mut self.fs.borrow_mut()))
+    }
+
+    /// Format the storage, see [`OwnedFilesystem::format`][].
+    pub fn format(&self) -> Result<()> {
+        self.lock(|fs| fs.format())
+    }
+
+    /// Mount the filesystem, if it is not mounted yet.
+    pub fn mount(&self) -> Result<()> {
+        self.lock(|fs| fs.mount())
+    }
+
+    /// Unmount the filesystem, if it is mounted.
+    pub fn unmount(&self) -> Result<()> {
+        self.lock(|fs| fs.unmount())
+    }
+
+    pub fn is_mounted(&self) -> bool {
+        self.lock(|fs| fs.is_mounted())
+    }
+
+    /// Call `f` with the mounted filesystem while the mutex is locked.
+    ///
+    /// Returns [`Error::INVALID`](crate::io::Error::INVALID) if the filesystem is not mounted.
+    ///
+    /// # Panics
+    ///
+    /// Panics if it is called from within `f`.
+    pub fn with<R>(&self, f: impl FnOnce(&Filesystem<'_, S>) -> Result<R>) -> Result<R> {
+        self.lock(|fs| fs.with(f))
+    }
+
+    /// Returns the storage.  The filesystem does not need to be unmounted before.
+    pub fn into_inner(self) -> S {
+        self.fs.into_inner().into_inner()
+    }
+}
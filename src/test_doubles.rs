@@ -0,0 +1,47 @@
+//! Deterministic implementation of the [`Clock`][] trait for tests.
+//!
+//! With this test double, tests of features that depend on the time produce the same results on
+//! every run.
+//!
+//! ```
+//!# use littlefs2::{driver::Clock, test_doubles::TestClock};
+//! let clock = TestClock::new(1_000);
+//! clock.advance(5);
+//! assert_eq!(clock.now(), 1_005);
+//! clock.advance(u64::MAX);
+//! assert_eq!(clock.now(), u64::MAX);
+//! ```
+
+use core::cell::Cell;
+
+use crate::driver::Clock;
+
+/// A clock that only changes when the test sets or advances it.
+#[derive(Debug, Default)]
+pub struct TestClock {
+    now: Cell<u64>,
+}
+
+impl TestClock {
+    pub const fn new(now: u64) -> Self {
+        Self {
+            now: Cell::new(now),
+        }
+    }
+
+    /// Sets the current time.
+    pub fn set(&self, now: u64) {
+        self.now.set(now);
+    }
+
+    /// Advances the current time by `delta`, saturating at `u64::MAX`.
+    pub fn advance(&self, delta: u64) {
+        self.now.set(self.now.get().saturating_add(delta));
+    }
+}
+
+impl Clock for TestClock {
+    fn now(&self) -> u64 {
+        self.now.get()
+    }
+}
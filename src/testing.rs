@@ -0,0 +1,222 @@
+//! Tools for testing storage implementations and the fail-safety of applications.
+//!
+//! littlefs is designed to be resilient against power loss: an interrupted operation leaves
+//! either the old or the new state on the storage.  [`FaultInjectStorage`][] simulates power loss
+//! and other faults on top of any [`Storage`][] implementation, for example the RAM storages
+//! created with [`ram_storage!`](crate::ram_storage), so that this can be verified in tests.
+//! [`powerloss::exhaustive`][] uses it to interrupt a sequence of operations at every write and
+//! erase in turn.  With the `std` feature, [`fuzz::run`][] compares the filesystem with
+//! a model for random sequences of operations.
+//!
+//! ```
+//!# use littlefs2::{driver, fs::Filesystem, io::{Error, Result}, path, ram_storage, testing::FaultInjectStorage};
+//!# ram_storage!(tiny);
+//!# let mut ram = Ram::default();
+//! let mut storage = FaultInjectStorage::new(RamStorage::new(&mut ram));
+//! Filesystem::format(&mut storage).unwrap();
+//! Filesystem::mount_and_then(&mut storage, |fs| fs.write(path!("file"), b"old")).unwrap();
+//!
+//! // lose power during the first write or erase of the update
+//! storage.power_loss_after(0, 0);
+//! let result = Filesystem::mount_and_then(&mut storage, |fs| fs.write(path!("file"), b"new"));
+//! assert_eq!(result, Err(Error::IO));
+//!
+//! storage.restore_power();
+//! let contents: heapless::Vec<u8, 3> =
+//!     Filesystem::mount_and_then(&mut storage, |fs| fs.read(path!("file"))).unwrap();
+//! assert_eq!(contents, b"old");
+//! ```
+
+#[cfg(any(test, feature = "std"))]
+pub mod fuzz;
+pub mod powerloss;
+
+use crate::{
+    driver::Storage,
+    io::{Error, Result},
+};
+
+#[derive(Clone, Copy, Debug)]
+enum Fault {
+    Fail,
+    PowerLoss { written: usize },
+}
+
+/// Storage wrapper that injects faults into the operations of the wrapped storage.
+///
+/// Faults are scheduled for the n-th following write or erase:
+///
+/// - [`fail_after`](Self::fail_after) lets a single write or erase fail with [`Error::IO`][]
+///   without modifying the storage, like a transient error of the device.
+/// - [`power_loss_after`](Self::power_loss_after) interrupts a write after a given number of
+///   bytes, as if the power was lost during the operation.  An interrupted erase leaves the
+///   storage unchanged.  Afterwards, all operations fail with [`Error::IO`][] until
+///   [`restore_power`](Self::restore_power) is called.
+///
+/// In addition, [`flip_bit`](Self::flip_bit) flips a bit in the data returned by all reads of a
+/// byte, which simulates a bit error of the storage without modifying it.
+pub struct FaultInjectStorage<S: Storage> {
+    storage: S,
+    // number of writes and erases before the fault, and the fault
+    scheduled: Option<(usize, Fault)>,
+    bit_flip: Option<(usize, u8)>,
+    powered: bool,
+    operations: usize,
+}
+
+impl<S: Storage> FaultInjectStorage<S> {
+    pub fn new(storage: S) -> Self {
+        Self {
+            storage,
+            scheduled: None,
+            bit_flip: None,
+            powered: true,
+            operations: 0,
+        }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.storage
+    }
+
+    pub fn inner_mut(&mut self) -> &mut S {
+        &mut self.storage
+    }
+
+    pub fn into_inner(self) -> S {
+        self.storage
+    }
+
+    /// Lets the write or erase after the next `n` writes and erases fail with [`Error::IO`][].
+    ///
+    /// The failed operation does not modify the storage.  This replaces the previously scheduled
+    /// fault, if any.
+    pub fn fail_after(&mut self, n: usize) {
+        self.scheduled = Some((n, Fault::Fail));
+    }
+
+    /// Simulates a power loss during the write or erase after the next `n` writes and erases.
+    ///
+    /// Only the first `written` bytes of the interrupted write, rounded down to a multiple of
+    /// [`Storage::WRITE_SIZE`][], are written to the storage.  This replaces the previously
+    /// scheduled fault, if any.
+    pub fn power_loss_after(&mut self, n: usize, written: usize) {
+        self.scheduled = Some((n, Fault::PowerLoss { written }));
+    }
+
+    /// Ends a simulated power loss so that the storage can be used again.
+    pub fn restore_power(&mut self) {
+        self.powered = true;
+    }
+
+    /// Returns `false` after a simulated power loss until the power is restored.
+    pub fn is_powered(&self) -> bool {
+        self.powered
+    }
+
+    /// Flips the bit `bit` of the byte at offset `off` in the data returned by reads.
+    ///
+    /// This replaces the previous bit flip, if any.
+    pub fn flip_bit(&mut self, off: usize, bit: u8) {
+        self.bit_flip = Some((off, bit % 8));
+    }
+
+    /// Removes the scheduled fault and the bit flip.
+    ///
+    /// This does not restore the power after a simulated power loss.
+    pub fn clear_faults(&mut self) {
+        self.scheduled = None;
+        self.bit_flip = None;
+    }
+
+    /// Returns the number of writes and erases that have been attempted since the creation of
+    /// the wrapper.
+    ///
+    /// This can be used to determine how many points of failure an operation has, for example to
+    /// interrupt it at every one of them in turn.
+    pub fn operations(&self) -> usize {
+        self.operations
+    }
+
+    /// Counts an attempted write or erase and returns the fault that applies to it, if any.
+    fn next_fault(&mut self) -> Result<Option<Fault>> {
+        if !self.powered {
+            return Err(Error::IO);
+        }
+        self.operations += 1;
+        match &mut self.scheduled {
+            Some((0, fault)) => {
+                let fault = *fault;
+                self.scheduled = None;
+                Ok(Some(fault))
+            }
+            Some((n, _)) => {
+                *n -= 1;
+                Ok(None)
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+impl<S: Storage> Storage for FaultInjectStorage<S> {
+    const READ_SIZE: usize = S::READ_SIZE;
+    const WRITE_SIZE: usize = S::WRITE_SIZE;
+    const BLOCK_SIZE: usize = S::BLOCK_SIZE;
+    const BLOCK_COUNT: usize = S::BLOCK_COUNT;
+    const BLOCK_CYCLES: isize = S::BLOCK_CYCLES;
+    const FILENAME_MAX: usize = S::FILENAME_MAX;
+    const FILEBYTES_MAX: u32 = S::FILEBYTES_MAX;
+    type CACHE_SIZE = S::CACHE_SIZE;
+    type LOOKAHEAD_SIZE = S::LOOKAHEAD_SIZE;
+
+    fn read(&mut self, off: usize, buf: &mut [u8]) -> Result<usize> {
+        if !self.powered {
+            return Err(Error::IO);
+        }
+        let read = self.storage.read(off, buf)?;
+        if let Some((flip_off, bit)) = self.bit_flip {
+            if let Some(byte) = flip_off
+                .checked_sub(off)
+                .and_then(|i| buf[..read].get_mut(i))
+            {
+                *byte ^= 1 << bit;
+            }
+        }
+        Ok(read)
+    }
+
+    fn write(&mut self, off: usize, data: &[u8]) -> Result<usize> {
+        match self.next_fault()? {
+            None => self.storage.write(off, data),
+            Some(Fault::Fail) => Err(Error::IO),
+            Some(Fault::PowerLoss { written }) => {
+                self.powered = false;
+                let len = written.min(data.len());
+                let len = len - len % S::WRITE_SIZE;
+                if len > 0 {
+                    self.storage.write(off, &data[..len])?;
+                }
+                Err(Error::IO)
+            }
+        }
+    }
+
+    fn erase(&mut self, off: usize, len: usize) -> Result<usize> {
+        match self.next_fault()? {
+            None => self.storage.erase(off, len),
+            Some(Fault::Fail) => Err(Error::IO),
+            Some(Fault::PowerLoss { .. }) => {
+                self.powered = false;
+                Err(Error::IO)
+            }
+        }
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        if !self.powered {
+            return Err(Error::IO);
+        }
+        self.storage.sync()
+    }
+}
@@ -0,0 +1,336 @@
+//! Randomized tests that compare the filesystem with a simple model.
+//!
+//! [`run`][] formats a storage and applies a random sequence of operations to it: creating
+//! directories, writing, renaming and removing files and directories, and setting and removing
+//! attributes.  The same operations are applied to a model of the filesystem in a
+//! [`BTreeMap`][].  After every operation, the fuzzer asserts that the operation succeeded on the
+//! filesystem if and only if it succeeded on the model, and that the complete directory tree,
+//! including the file contents and attributes, matches the model.  The sequence also contains
+//! remounts, and the tree is compared after every remount.
+//!
+//! The operations use a small set of paths so that they frequently collide with existing entries.
+//! The sequence only depends on the seed, so a failure can be reproduced by running the fuzzer
+//! with the seed from the panic message.  The files are at most 1 KiB large, so a storage with
+//! 64 KiB is sufficient.
+//!
+//! ```
+//!# use littlefs2::{driver, io::Result, ram_storage, testing::fuzz};
+//!# ram_storage!(large);
+//!# let mut ram = Ram::default();
+//! let mut storage = RamStorage::new(&mut ram);
+//! for seed in 0..4 {
+//!     fuzz::run(&mut storage, seed, 100);
+//! }
+//! ```
+
+use std::{collections::BTreeMap, format, string::String, vec, vec::Vec};
+
+use crate::{driver::Storage, fs::Filesystem, io::Result, path::PathBuf};
+
+/// Applies `steps` random operations generated from `seed` to a newly formatted filesystem on
+/// `storage` and to a model, see the [module documentation](self).
+///
+/// # Panics
+///
+/// Panics if the filesystem and the model differ, or if the storage returns an error.
+pub fn run<S: Storage>(storage: &mut S, seed: u64, steps: usize) {
+    Filesystem::format(storage).expect("failed to format storage");
+    let mut model = Model::new();
+    let mut rng = XorShift::new(seed);
+    let mut step = 0;
+    loop {
+        Filesystem::mount_and_then(storage, |fs| {
+            model.verify(fs, seed, step);
+            while step < steps {
+                step += 1;
+                let op = Op::random(&mut rng);
+                if let Op::Remount = op {
+                    break;
+                }
+                let actual = op.apply(fs);
+                let expected = model.apply(&op);
+                assert_eq!(
+                    actual.is_ok(),
+                    expected,
+                    "seed {seed}, step {step}: {op:?} returned {actual:?}"
+                );
+                model.verify(fs, seed, step);
+            }
+            Ok(())
+        })
+        .unwrap_or_else(|error| panic!("seed {seed}, step {step}: failed to mount: {error:?}"));
+        if step == steps {
+            break;
+        }
+    }
+    Filesystem::mount_and_then(storage, |fs| {
+        model.verify(fs, seed, step);
+        Ok(())
+    })
+    .unwrap_or_else(|error| panic!("seed {seed}: failed to mount: {error:?}"));
+}
+
+/// A xorshift64* generator, so that the sequence does not depend on an external crate.
+struct XorShift(u64);
+
+impl XorShift {
+    fn new(seed: u64) -> Self {
+        // the state must not be zero
+        Self(seed ^ 0x9e37_79b9_7f4a_7c15)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() >> 32) as usize % n
+    }
+
+    fn path(&mut self) -> String {
+        const NAMES: [&str; 3] = ["a", "b", "c"];
+        let mut path = String::new();
+        for _ in 0..=self.below(2) {
+            path.push('/');
+            path.push_str(NAMES[self.below(NAMES.len())]);
+        }
+        path
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Op {
+    CreateDir(String),
+    Write { path: String, len: usize, byte: u8 },
+    Remove(String),
+    Rename(String, String),
+    SetAttribute { path: String, id: u8, len: usize },
+    RemoveAttribute { path: String, id: u8 },
+    Remount,
+}
+
+impl Op {
+    fn random(rng: &mut XorShift) -> Self {
+        match rng.below(100) {
+            0..=29 => Self::Write {
+                path: rng.path(),
+                len: rng.below(1024),
+                byte: rng.next() as u8,
+            },
+            30..=44 => Self::CreateDir(rng.path()),
+            45..=59 => Self::Remove(rng.path()),
+            60..=74 => loop {
+                let from = rng.path();
+                let to = rng.path();
+                // littlefs does not support moving a directory into itself
+                if !to.starts_with(&format!("{from}/")) {
+                    break Self::Rename(from, to);
+                }
+            },
+            75..=89 => Self::SetAttribute {
+                path: rng.path(),
+                id: rng.below(4) as u8,
+                len: 1 + rng.below(32),
+            },
+            90..=94 => Self::RemoveAttribute {
+                path: rng.path(),
+                id: rng.below(4) as u8,
+            },
+            _ => Self::Remount,
+        }
+    }
+
+    fn apply<S: Storage>(&self, fs: &Filesystem<'_, S>) -> Result<()> {
+        let path = |path: &str| PathBuf::try_from(path).expect("invalid path");
+        match self {
+            Self::CreateDir(p) => fs.create_dir(&path(p)),
+            Self::Write { path: p, len, byte } => fs.write(&path(p), &vec![*byte; *len]),
+            Self::Remove(p) => fs.remove(&path(p)),
+            Self::Rename(from, to) => fs.rename(&path(from), &path(to)),
+            Self::SetAttribute { path: p, id, len } => {
+                fs.set_attribute(&path(p), *id, &vec![*id; *len])
+            }
+            Self::RemoveAttribute { path: p, id } => fs.remove_attribute(&path(p), *id),
+            Self::Remount => Ok(()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Kind {
+    Dir,
+    File(Vec<u8>),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Node {
+    kind: Kind,
+    attributes: BTreeMap<u8, Vec<u8>>,
+}
+
+impl Node {
+    fn new(kind: Kind) -> Self {
+        Self {
+            kind,
+            attributes: BTreeMap::new(),
+        }
+    }
+}
+
+/// The expected state of the filesystem, indexed by the absolute path of every entry.
+struct Model {
+    entries: BTreeMap<String, Node>,
+}
+
+impl Model {
+    fn new() -> Self {
+        let mut entries = BTreeMap::new();
+        entries.insert(String::from("/"), Node::new(Kind::Dir));
+        Self { entries }
+    }
+
+    fn parent(path: &str) -> &str {
+        match path.rfind('/') {
+            Some(0) | None => "/",
+            Some(i) => &path[..i],
+        }
+    }
+
+    fn is_dir(&self, path: &str) -> bool {
+        matches!(
+            self.entries.get(path),
+            Some(Node {
+                kind: Kind::Dir,
+                ..
+            })
+        )
+    }
+
+    fn has_children(&self, path: &str) -> bool {
+        let prefix = format!("{path}/");
+        self.entries.keys().any(|key| key.starts_with(&prefix))
+    }
+
+    /// Applies `op` to the model and returns whether it succeeds.
+    fn apply(&mut self, op: &Op) -> bool {
+        match op {
+            Op::CreateDir(path) => {
+                if self.entries.contains_key(path) || !self.is_dir(Self::parent(path)) {
+                    return false;
+                }
+                self.entries.insert(path.clone(), Node::new(Kind::Dir));
+            }
+            Op::Write { path, len, byte } => {
+                if self.is_dir(path) || !self.is_dir(Self::parent(path)) {
+                    return false;
+                }
+                let data = vec![*byte; *len];
+                self.entries
+                    .entry(path.clone())
+                    .and_modify(|node| node.kind = Kind::File(data.clone()))
+                    .or_insert_with(|| Node::new(Kind::File(data)));
+            }
+            Op::Remove(path) => {
+                if !self.entries.contains_key(path) || self.has_children(path) {
+                    return false;
+                }
+                self.entries.remove(path);
+            }
+            Op::Rename(from, to) => {
+                let Some(node) = self.entries.get(from) else {
+                    return false;
+                };
+                if !self.is_dir(Self::parent(to)) {
+                    return false;
+                }
+                if let Some(existing) = self.entries.get(to) {
+                    if (existing.kind == Kind::Dir) != (node.kind == Kind::Dir)
+                        || self.has_children(to) && from != to
+                    {
+                        return false;
+                    }
+                }
+                if from == to {
+                    return true;
+                }
+                self.entries.remove(to);
+                let prefix = format!("{from}/");
+                let moved: Vec<String> = self
+                    .entries
+                    .keys()
+                    .filter(|key| *key == from || key.starts_with(&prefix))
+                    .cloned()
+                    .collect();
+                for key in moved {
+                    let node = self.entries.remove(&key).unwrap();
+                    self.entries
+                        .insert(format!("{to}{}", &key[from.len()..]), node);
+                }
+            }
+            Op::SetAttribute { path, id, len } => {
+                let Some(node) = self.entries.get_mut(path) else {
+                    return false;
+                };
+                node.attributes.insert(*id, vec![*id; *len]);
+            }
+            Op::RemoveAttribute { path, id } => {
+                let Some(node) = self.entries.get_mut(path) else {
+                    return false;
+                };
+                node.attributes.remove(id);
+            }
+            Op::Remount => {}
+        }
+        true
+    }
+
+    /// Asserts that the directory tree of `fs` matches the model.
+    fn verify<S: Storage>(&self, fs: &Filesystem<'_, S>, seed: u64, step: usize) {
+        let read_node = |path: &crate::path::Path, is_dir: bool| -> Result<Node> {
+            let kind = if is_dir {
+                Kind::Dir
+            } else {
+                fs.open_file_and_then(path, |file| {
+                    let mut data = Vec::new();
+                    let mut buf = [0; 256];
+                    loop {
+                        let n = file.read(&mut buf)?;
+                        if n == 0 {
+                            return Ok(Kind::File(data));
+                        }
+                        data.extend_from_slice(&buf[..n]);
+                    }
+                })?
+            };
+            let mut node = Node::new(kind);
+            fs.attributes(path, |id, data| {
+                node.attributes.insert(id, data.to_vec());
+                Ok(())
+            })?;
+            Ok(node)
+        };
+
+        let mut actual = BTreeMap::new();
+        let result = read_node(crate::path!("/"), true).and_then(|root| {
+            actual.insert(String::from("/"), root);
+            fs.walk_dir_and_then(crate::path!("/"), |walk| {
+                for entry in walk {
+                    let entry = entry?;
+                    let node = read_node(entry.path(), entry.file_type().is_dir())?;
+                    actual.insert(String::from(entry.path().as_str()), node);
+                }
+                Ok(())
+            })
+        });
+        if let Err(error) = result {
+            panic!("seed {seed}, step {step}: failed to read the filesystem: {error:?}");
+        }
+        assert_eq!(
+            actual, self.entries,
+            "seed {seed}, step {step}: filesystem differs from the model"
+        );
+    }
+}
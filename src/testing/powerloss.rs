@@ -0,0 +1,111 @@
+//! Exhaustive power-loss tests.
+//!
+//! [`exhaustive`][] runs a sequence of filesystem operations on a [`FaultInjectStorage`][] again
+//! and again, and simulates a power loss at a different write or erase in every run: first
+//! during the first write or erase, then during the second one, and so on until the operations
+//! complete without interruption.  After every interruption, the filesystem is mounted again and
+//! an invariant is checked.
+//!
+//! This is repeated for several amounts of data that reach the storage before the power loss:
+//! none of the interrupted write, its first [`Storage::WRITE_SIZE`][] bytes and its first half
+//! block, so that torn writes are tested as well.
+//!
+//! As the runs are not reset, every run continues with the state that the previous interrupted
+//! run has left behind, just like a device that is restarted after a power loss.  The operations
+//! must therefore be able to start from every state that is accepted by the check.
+//!
+//! ```
+//!# use littlefs2::{driver, fs::Filesystem, io::Result, path, ram_storage, testing::{powerloss, FaultInjectStorage}};
+//!# ram_storage!(tiny);
+//!# let mut ram = Ram::default();
+//! let mut storage = FaultInjectStorage::new(RamStorage::new(&mut ram));
+//! Filesystem::format(&mut storage).unwrap();
+//!
+//! let interruptions = powerloss::exhaustive(
+//!     &mut storage,
+//!     |fs| fs.write_atomic(path!("config"), &[0xab; 100]),
+//!     |fs| {
+//!         // the file is either missing or complete
+//!         if fs.exists(path!("config")) {
+//!             let config: heapless::Vec<u8, 100> = fs.read(path!("config"))?;
+//!             assert!(config.iter().all(|byte| *byte == 0xab));
+//!         }
+//!         Ok(())
+//!     },
+//! )
+//! .unwrap();
+//! assert!(interruptions > 0);
+//! ```
+
+use super::FaultInjectStorage;
+use crate::{driver::Storage, fs::Filesystem, io::Error};
+
+/// A failure found by [`exhaustive`][].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Failure {
+    /// Number of bytes of the interrupted write that reached the storage, see
+    /// [`FaultInjectStorage::power_loss_after`][].
+    pub written: usize,
+    /// Number of writes and erases of the run that completed before the simulated power loss.
+    ///
+    /// `None` if the failure occurred in the final run that was not interrupted.
+    pub interrupted_after: Option<usize>,
+    /// The error returned by mounting the filesystem, by the check or by the operations.
+    pub error: Error,
+}
+
+/// Run `ops` with a simulated power loss during every write and erase and call `check` after every
+/// run, see the [module documentation](self).
+///
+/// The filesystem on `storage` must already be formatted.  Errors returned by `ops` in an
+/// interrupted run are ignored.  The testing stops at the first error returned by mounting the
+/// filesystem or by `check`, or by `ops` in the final run, which is returned as a [`Failure`][].
+/// Otherwise, the number of interrupted runs is returned.
+pub fn exhaustive<S: Storage>(
+    storage: &mut FaultInjectStorage<S>,
+    mut ops: impl FnMut(&Filesystem<'_, FaultInjectStorage<S>>) -> crate::io::Result<()>,
+    mut check: impl FnMut(&Filesystem<'_, FaultInjectStorage<S>>) -> crate::io::Result<()>,
+) -> Result<usize, Failure> {
+    let mut interruptions = 0;
+    for written in [0, S::WRITE_SIZE, S::BLOCK_SIZE / 2] {
+        interruptions += exhaustive_with(storage, written, &mut ops, &mut check)?;
+    }
+    Ok(interruptions)
+}
+
+fn exhaustive_with<S: Storage>(
+    storage: &mut FaultInjectStorage<S>,
+    written: usize,
+    ops: &mut impl FnMut(&Filesystem<'_, FaultInjectStorage<S>>) -> crate::io::Result<()>,
+    check: &mut impl FnMut(&Filesystem<'_, FaultInjectStorage<S>>) -> crate::io::Result<()>,
+) -> Result<usize, Failure> {
+    storage.restore_power();
+    let mut n = 0;
+    loop {
+        storage.power_loss_after(n, written);
+        let result = Filesystem::mount_and_then(storage, &mut *ops);
+        let interrupted_after = if storage.is_powered() {
+            // cancel the power loss that was not reached
+            storage.scheduled = None;
+            result.map_err(|error| Failure {
+                written,
+                interrupted_after: None,
+                error,
+            })?;
+            None
+        } else {
+            storage.restore_power();
+            Some(n)
+        };
+
+        Filesystem::mount_and_then(storage, &mut *check).map_err(|error| Failure {
+            written,
+            interrupted_after,
+            error,
+        })?;
+        if interrupted_after.is_none() {
+            return Ok(n);
+        }
+        n += 1;
+    }
+}
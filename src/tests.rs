@@ -3,9 +3,15 @@ use generic_array::typenum::consts;
 
 use crate::{
     driver,
-    fs::{Attribute, File, Filesystem},
+    fs::{
+        Allocation, AppendBuffer, Attribute, ChunkReader, ConfigError, ConflictPolicy, DirEntry,
+        File, FileAttribute, FileType, Filesystem, Metadata, OpenOptions, SkipDots,
+        WriteChunkOptions,
+    },
     io::{Error, OpenSeekFrom, Read, Result, SeekFrom},
+    object_safe::{DynFile, DynFilesystem},
     path,
+    path::PathBuf,
 };
 
 ram_storage!(
@@ -46,6 +52,152 @@ fn version() {
     assert_eq!(crate::version().backend, (2, 2));
 }
 
+// The error codes in `consts::LFS_ERRORS` are those of littlefs 2.2.  When upgrading the backend,
+// add the new `LFS_ERR_*` codes of `lfs.h` to the table before updating this version.
+const _: () = assert!(
+    crate::ll::LFS_VERSION == 0x0002_0002,
+    "check consts::LFS_ERRORS for new LFS_ERR_* codes"
+);
+
+#[test]
+fn error_codes() {
+    use crate::{consts::LFS_ERRORS, ll};
+    use core::ffi::c_int;
+
+    assert_eq!(Error::new(ll::lfs_error_LFS_ERR_OK), None);
+    for (i, (code, error)) in LFS_ERRORS.iter().enumerate() {
+        assert_eq!(Error::new(*code), Some(*error));
+        assert_eq!(c_int::from(*error), *code);
+        assert!(LFS_ERRORS[..i].iter().all(|(other, _)| other != code));
+    }
+
+    // errors of this crate must not be confused with errors of littlefs
+    let errors = [
+        Error::READ_ONLY,
+        Error::PATH_REJECTED,
+        Error::PATH_PROTECTED,
+        Error::UNKNOWN,
+    ];
+    for error in errors {
+        assert!(LFS_ERRORS.iter().all(|(code, _)| *code != error.code()));
+    }
+}
+
+#[test]
+fn u32_result() {
+    use crate::fs::u32_result;
+
+    assert_eq!(u32_result(0), Ok(0));
+    assert_eq!(u32_result(1), Ok(1));
+    assert_eq!(u32_result(i32::MAX), Ok(i32::MAX as u32));
+    assert_eq!(u32_result(-5), Err(Error::IO));
+    assert_eq!(u32_result(-1).map_err(|error| error.code()), Err(-1));
+    assert_eq!(u32_result(-999).map_err(|error| error.code()), Err(-999));
+    assert_eq!(u32_result(i32::MIN), Err(Error::UNKNOWN));
+    assert_ne!(u32_result(i32::MIN + 1), Err(Error::UNKNOWN));
+}
+
+#[test]
+fn invalid_config() {
+    ram_storage!(
+        name=BadStorage,
+        backend=BadRam,
+        trait=driver::Storage,
+        erase_value=0xff,
+        read_size=1,
+        write_size=48,
+        cache_size_ty=consts::U32,
+        block_size=256,
+        block_count=16,
+        lookahead_size_ty=consts::U1,
+        filename_max_plus_one_ty=consts::U256,
+        path_max_plus_one_ty=consts::U256,
+        result=Result,
+    );
+
+    assert_eq!(Allocation::<OtherRamStorage>::validate_config(), Ok(()));
+    assert_eq!(
+        Allocation::<BadStorage>::validate_config(),
+        Err(ConfigError::CacheSize)
+    );
+
+    let mut backend = BadRam::default();
+    let mut storage = BadStorage::new(&mut backend);
+    assert_eq!(Filesystem::format(&mut storage), Err(Error::INVALID));
+    assert_eq!(
+        Filesystem::mount_and_then(&mut storage, |_| Ok(())),
+        Err(Error::INVALID)
+    );
+}
+
+/// Storage with a custom limit for the length of file names.
+struct NameMax<'a, const N: usize>(OtherRamStorage<'a>);
+
+impl<const N: usize> driver::Storage for NameMax<'_, N> {
+    const READ_SIZE: usize = <OtherRamStorage<'static> as driver::Storage>::READ_SIZE;
+    const WRITE_SIZE: usize = <OtherRamStorage<'static> as driver::Storage>::WRITE_SIZE;
+    const BLOCK_SIZE: usize = <OtherRamStorage<'static> as driver::Storage>::BLOCK_SIZE;
+    const BLOCK_COUNT: usize = <OtherRamStorage<'static> as driver::Storage>::BLOCK_COUNT;
+    const FILENAME_MAX: usize = N;
+    type CACHE_SIZE = <OtherRamStorage<'static> as driver::Storage>::CACHE_SIZE;
+    type LOOKAHEAD_SIZE = <OtherRamStorage<'static> as driver::Storage>::LOOKAHEAD_SIZE;
+
+    fn read(&mut self, off: usize, buf: &mut [u8]) -> Result<usize> {
+        self.0.read(off, buf)
+    }
+
+    fn write(&mut self, off: usize, data: &[u8]) -> Result<usize> {
+        self.0.write(off, data)
+    }
+
+    fn erase(&mut self, off: usize, len: usize) -> Result<usize> {
+        self.0.erase(off, len)
+    }
+}
+
+#[test]
+fn filename_max() {
+    assert_eq!(
+        Allocation::<NameMax<'_, 0>>::validate_config(),
+        Err(ConfigError::FilenameMax)
+    );
+    assert_eq!(
+        Allocation::<NameMax<'_, 256>>::validate_config(),
+        Err(ConfigError::FilenameMax)
+    );
+
+    let mut backend = OtherRam::default();
+    let mut storage = NameMax::<8>(OtherRamStorage::new(&mut backend));
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        fs.write(path!("/8-chars!"), b"ok")?;
+        assert_eq!(
+            fs.write(path!("/9-chars!!"), b"too long"),
+            Err(Error::FILENAME_TOO_LONG)
+        );
+        Ok(())
+    })
+    .unwrap();
+
+    // the limit is stored in the superblock and also applies with a larger configured limit
+    let mut storage = storage.0;
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        assert_eq!(
+            fs.write(path!("/9-chars!!"), b"too long"),
+            Err(Error::FILENAME_TOO_LONG)
+        );
+        Ok(())
+    })
+    .unwrap();
+
+    // a smaller configured limit is rejected
+    let mut storage = NameMax::<4>(storage);
+    assert_eq!(
+        Filesystem::mount_and_then(&mut storage, |_| Ok(())),
+        Err(Error::INVALID)
+    );
+}
+
 #[test]
 fn format() {
     let mut backend = OtherRam::default();
@@ -66,6 +218,37 @@ fn format() {
     // check there are no segfaults
 }
 
+#[test]
+fn mount_or_format() {
+    use crate::object_safe::DynStorage;
+
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    {
+        let mut alloc = Filesystem::allocate();
+        let fs = Filesystem::mount_or_format(&mut alloc, &mut storage).unwrap();
+        fs.write(path!("/file"), b"data").unwrap();
+    }
+
+    // an existing filesystem is not formatted
+    let storage: &mut dyn DynStorage = &mut storage;
+    let exists = storage
+        .mount_or_format_and_then(&mut |fs| Ok(fs.exists(path!("/file"))))
+        .unwrap();
+    assert!(exists);
+
+    // errors other than corruption are returned
+    let mut backend = OtherRam::default();
+    let mut storage = NameMax::<4>(OtherRamStorage::new(&mut backend));
+    Filesystem::format(&mut storage).unwrap();
+    let mut storage = NameMax::<2>(storage.0);
+    let mut alloc = Filesystem::allocate();
+    assert_eq!(
+        Filesystem::mount_or_format(&mut alloc, &mut storage).map(drop),
+        Err(Error::INVALID)
+    );
+}
+
 // #[macro_use]
 // macro_rules! setup_fs {
 //     () => {
@@ -344,6 +527,64 @@ fn test_chunked() {
     .unwrap();
 }
 
+#[test]
+fn write_chunk_with_options() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        let path = path!("upload");
+        let options = WriteChunkOptions::new().create(true).extend(true);
+        assert_eq!(
+            fs.write_chunk(path, b"data", OpenSeekFrom::Start(0)),
+            Err(Error::NO_SUCH_ENTRY)
+        );
+
+        // chunks in any order, across block boundaries
+        fs.write_chunk_with_options(path, &[3; 300], OpenSeekFrom::Start(600), options)?;
+        assert_eq!(fs.metadata(path)?.len(), 900);
+        fs.write_chunk_with_options(path, &[1; 300], OpenSeekFrom::Start(0), options)?;
+        fs.write_chunk_with_options(path, &[2; 300], OpenSeekFrom::Start(300), options)?;
+        fs.write_chunk_with_options(path, &[4; 10], OpenSeekFrom::End(10), options)?;
+        let contents: heapless::Vec<u8, 1024> = fs.read(path)?;
+        assert_eq!(contents.len(), 920);
+        for (i, chunk) in contents[..900].chunks(300).enumerate() {
+            assert!(chunk.iter().all(|&b| usize::from(b) == i + 1));
+        }
+        assert_eq!(contents[900..910], [0; 10]);
+        assert_eq!(contents[910..], [4; 10]);
+
+        // without extend, chunks must not start after the end
+        let strict = WriteChunkOptions::new();
+        assert_eq!(
+            fs.write_chunk_with_options(path, b"data", OpenSeekFrom::Start(921), strict),
+            Err(Error::INVALID)
+        );
+        assert_eq!(
+            fs.write_chunk_with_options(path, b"data", OpenSeekFrom::End(1), strict),
+            Err(Error::INVALID)
+        );
+        fs.write_chunk_with_options(path, b"data", OpenSeekFrom::Start(920), strict)?;
+        assert_eq!(fs.metadata(path)?.len(), 924);
+
+        // write_chunk fills the gap with zeros
+        fs.write_chunk(path, b"data", OpenSeekFrom::End(2))?;
+        let contents: heapless::Vec<u8, 1024> = fs.read(path)?;
+        assert_eq!(contents[924..], *b"\0\0data");
+
+        let fs: &dyn DynFilesystem = fs;
+        fs.write_chunk_with_options(
+            path!("other"),
+            b"data",
+            OpenSeekFrom::Start(2),
+            WriteChunkOptions::new().create(true).extend(true),
+        )?;
+        assert_eq!(fs.metadata(path!("other"))?.len(), 6);
+        Ok(())
+    })
+    .unwrap();
+}
+
 #[test]
 fn test_file_set_len() {
     let mut backend = OtherRam::default();
@@ -368,6 +609,32 @@ fn test_file_set_len() {
     .unwrap();
 }
 
+#[test]
+fn test_file_tell_rewind() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        fs.create_file_and_then(path!("test_tell.txt"), |file| {
+            file.write(b"hello littlefs")?;
+            assert_eq!(file.tell()?, 14);
+            file.seek(SeekFrom::Start(5))?;
+            assert_eq!(file.tell()?, 5);
+            let file: &dyn DynFile = file;
+            assert_eq!(file.tell()?, 5);
+
+            file.rewind()?;
+            assert_eq!(file.tell()?, 0);
+            file.write(b"J")?;
+            Ok(())
+        })?;
+        assert_eq!(fs.read::<16>(path!("test_tell.txt"))?, b"Jello littlefs");
+        Ok(())
+    })
+    .unwrap();
+}
+
 #[test]
 fn test_fancy_open() {
     let mut backend = Ram::default();
@@ -425,115 +692,3765 @@ fn remove_dir_all_where() {
 }
 
 #[test]
-fn attributes() {
-    let mut backend = Ram::default();
-    let mut storage = RamStorage::new(&mut backend);
+fn skip_dots() {
+    let entry = |name: &str| {
+        let file_name = PathBuf::try_from(name).unwrap();
+        let path = path!("/dir").join(&file_name);
+        Ok(DirEntry::new(file_name, Metadata::new(FileType::File, 0), path).unwrap())
+    };
+    let entries = [
+        entry("a"),
+        entry(".."),
+        Err(Error::IO),
+        entry("..."),
+        entry("."),
+        entry(".b"),
+    ];
+    let names: heapless::Vec<_, 6> = SkipDots::new(entries.into_iter())
+        .map(|entry| entry.map(|entry| PathBuf::from(entry.file_name().as_path())))
+        .collect();
+    assert_eq!(
+        names,
+        [
+            Ok(PathBuf::from(path!("a"))),
+            Err(Error::IO),
+            Ok(PathBuf::from(path!("..."))),
+            Ok(PathBuf::from(path!(".b"))),
+        ]
+    );
+
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
     Filesystem::format(&mut storage).unwrap();
     Filesystem::mount_and_then(&mut storage, |fs| {
-        let mut buffer = [0; Attribute::MAX_SIZE as _];
-
-        let filename = b"some.file\0".try_into().unwrap();
-        fs.write(filename, &[])?;
-        assert!(fs.attribute(filename, 37, &mut buffer)?.is_none());
+        fs.create_dir(path!("/dir"))?;
+        fs.write(path!("/dir/file"), b"")?;
+        fs.read_dir_and_then(path!("/dir"), |read_dir| {
+            let entries: heapless::Vec<_, 2> = read_dir.skip_dots().collect::<Result<_>>()?;
+            assert_eq!(entries.len(), 1);
+            assert_eq!(entries[0].path(), path!("/dir/file"));
+            Ok(())
+        })
+    })
+    .unwrap();
+}
 
-        let data = b"top secret";
-        fs.set_attribute(filename, 37, data).unwrap();
-        let attribute = fs.attribute(filename, 37, &mut buffer)?.unwrap();
-        assert_eq!(data, attribute.data());
-        assert_eq!(data.len(), attribute.total_size());
+#[test]
+fn create_dir_all() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
 
-        // if the buffer is smaller than the attribute, it is truncated and the
-        // full size is returned
-        let mut small_buffer = [0; 5];
-        let attribute = fs.attribute(filename, 37, &mut small_buffer)?.unwrap();
-        assert_eq!(&data[..5], attribute.data());
-        assert_eq!(data.len(), attribute.total_size());
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        fs.create_dir_all(path!("/a/b/c"))?;
+        assert!(fs.metadata(path!("/a/b/c"))?.is_dir());
+        // existing directories are fine, as are trailing and repeated separators
+        fs.create_dir_all(path!("/a/b/c/"))?;
+        fs.create_dir_all(path!("a//b/d"))?;
+        assert!(fs.metadata(path!("/a/b/d"))?.is_dir());
+        fs.create_dir_all(path!(""))?;
+        fs.create_dir_all(path!("/"))?;
 
-        // if the input data is too long, an error is returned
-        let long_data = &[0xff; 1024];
-        assert!(long_data.len() > Attribute::MAX_SIZE as _);
+        fs.write(path!("/a/file"), b"not a directory")?;
         assert_eq!(
-            Err(Error::NO_SPACE),
-            fs.set_attribute(filename, 37, long_data)
+            fs.create_dir_all(path!("/a/file/e")),
+            Err(Error::PATH_NOT_DIR)
+        );
+        assert_eq!(
+            fs.create_dir_all(path!("/a/file")),
+            Err(Error::PATH_NOT_DIR)
         );
+        Ok(())
+    })
+    .unwrap();
+}
 
-        // // not sure if we should have this method (may be quite expensive)
-        // let attributes = unsafe { fs.attributes("some.file", &mut storage).unwrap() };
-        // assert!(attributes[37]);
-        // assert_eq!(attributes.iter().fold(0, |sum, i| sum + (*i as u8)), 1);
+#[test]
+fn remove_dir_all_deep() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
 
-        fs.remove_attribute(filename, 37)?;
-        assert!(fs.attribute(filename, 37, &mut buffer)?.is_none());
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        let mut dir = PathBuf::from(path!("/deep"));
+        for _ in 0..crate::consts::DIR_DEPTH_MAX {
+            dir.push(path!("d"));
+        }
+        fs.create_dir_all(&dir)?;
+        fs.write(&dir.join(path!("file")), b"at the bottom")?;
+        fs.write(path!("/deep/d/file"), b"near the top")?;
+        fs.create_dir_all(path!("/deep/e/f"))?;
 
-        // // Directories can have attributes too
-        let tmp_dir = b"/tmp\0".try_into().unwrap();
-        fs.create_dir(tmp_dir)?;
+        fs.remove_dir_all(path!("/deep"))?;
+        assert!(!fs.exists(path!("/deep")));
 
-        let data = b"temporary directory";
-        fs.set_attribute(tmp_dir, 37, data)?;
+        // one level too deep
+        let mut dir = PathBuf::from(path!("/deep"));
+        for _ in 0..=crate::consts::DIR_DEPTH_MAX {
+            dir.push(path!("d"));
+        }
+        fs.create_dir_all(&dir)?;
+        assert_eq!(fs.remove_dir_all(path!("/deep")), Err(Error::NO_MEMORY));
 
-        let attribute = fs.attribute(tmp_dir, 37, &mut buffer)?.unwrap();
-        assert_eq!(data, attribute.data());
-        assert_eq!(data.len(), attribute.total_size());
+        fs.create_dir_all(path!("/shallow/a/b"))?;
+        fs.write(path!("/shallow/a/b/file"), b"")?;
+        assert_eq!(
+            fs.remove_dir_all_where_with_depth(path!("/shallow"), 1, &|_| true),
+            Err(Error::NO_MEMORY)
+        );
+        assert_eq!(
+            fs.remove_dir_all_where_with_depth(path!("/shallow"), 2, &|_| true),
+            Ok(1)
+        );
+        assert!(!fs.exists(path!("/shallow")));
+        assert_eq!(
+            fs.remove_dir_all_where_with_depth(
+                path!("/deep"),
+                crate::consts::DIR_DEPTH_MAX + 1,
+                &|_| true
+            ),
+            Err(Error::INVALID)
+        );
+        Ok(())
+    })
+    .unwrap();
+}
 
-        fs.remove_attribute(tmp_dir, 37)?;
-        assert!(fs.attribute(tmp_dir, 37, &mut buffer)?.is_none());
+#[test]
+fn remove_dir_all_where_nested() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        fs.create_dir_all(path!("/x/a/keep"))?;
+        fs.create_dir_all(path!("/x/b/c"))?;
+        fs.create_dir_all(path!("/x/d"))?;
+        fs.write(path!("/x/a/keep/file"), b"keep me")?;
+        fs.write(path!("/x/a/file"), b"remove me")?;
+        fs.write(path!("/x/b/c/file"), b"remove me")?;
+        fs.write(path!("/x/d/file"), b"remove me")?;
+
+        let removed = fs.remove_dir_all_where(path!("/x"), &|entry| {
+            entry.path() != path!("/x/a/keep/file")
+        })?;
+        assert_eq!(removed, 3);
+        assert!(fs.metadata(path!("/x/a/keep/file"))?.is_file());
+        assert!(!fs.exists(path!("/x/a/file")));
+        assert!(!fs.exists(path!("/x/b")));
+        assert!(!fs.exists(path!("/x/d")));
 
+        fs.create_dir_all(path!("/y/a"))?;
+        fs.create_dir_all(path!("/y/b"))?;
+        fs.write(path!("/y/a/file"), b"")?;
+        fs.write(path!("/y/b/file"), b"")?;
+        let mut progress = std::vec::Vec::new();
+        let fs: &dyn DynFilesystem = fs;
+        let removed = fs.remove_dir_all_with_progress(path!("/y"), &|_| true, &mut |path| {
+            progress.push(PathBuf::from(path))
+        })?;
+        assert_eq!(removed, 2);
+        let progress: std::vec::Vec<&str> = progress.iter().map(|path| path.as_str()).collect();
+        assert_eq!(progress, ["/y/a/file", "/y/a", "/y/b/file", "/y/b", "/y"]);
         Ok(())
     })
     .unwrap();
 }
 
 #[test]
-fn test_iter_dirs() {
-    let mut backend = Ram::default();
-    let mut storage = RamStorage::new(&mut backend);
+fn move_merge() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+
     Filesystem::format(&mut storage).unwrap();
     Filesystem::mount_and_then(&mut storage, |fs| {
-        fs.create_dir(path!("/tmp"))?;
+        fs.create_dir_all(path!("/from/sub/new"))?;
+        fs.create_dir_all(path!("/to/sub"))?;
+        fs.write(path!("/from/skip"), b"source")?;
+        fs.write(path!("/from/overwrite"), b"source")?;
+        fs.write(path!("/from/sub/rename"), b"source")?;
+        fs.write(path!("/from/sub/new/file"), b"source")?;
+        fs.write(path!("/to/skip"), b"target")?;
+        fs.write(path!("/to/overwrite"), b"target")?;
+        fs.write(path!("/to/sub/rename"), b"target")?;
 
-        // TODO: we might want "multi-open"
-        fs.create_file_and_then(path!("/tmp/file.a"), |file| {
-            file.set_len(37)?;
-            fs.create_file_and_then(path!("/tmp/file.b"), |file| file.set_len(42))
+        let mut conflicts = 0;
+        fs.move_merge(path!("/from"), path!("/to"), |entry, target| {
+            conflicts += 1;
+            assert_eq!(target.file_name(), Some(entry.file_name().as_path()));
+            match entry.file_name().as_str() {
+                "skip" => ConflictPolicy::Skip,
+                "overwrite" => ConflictPolicy::Overwrite,
+                _ => ConflictPolicy::Rename(path!("/to/sub/renamed").into()),
+            }
         })?;
+        assert_eq!(conflicts, 3);
 
-        fs.read_dir_and_then(path!("/tmp"), |dir| {
-            let mut found_files: usize = 0;
-            let mut sizes = [0usize; 4];
+        assert_eq!(fs.read::<10>(path!("/from/skip"))?, b"source");
+        assert_eq!(fs.read::<10>(path!("/to/skip"))?, b"target");
+        assert_eq!(fs.read::<10>(path!("/to/overwrite"))?, b"source");
+        assert_eq!(fs.read::<10>(path!("/to/sub/rename"))?, b"target");
+        assert_eq!(fs.read::<10>(path!("/to/sub/renamed"))?, b"source");
+        assert_eq!(fs.read::<10>(path!("/to/sub/new/file"))?, b"source");
+        assert!(!fs.exists(path!("/from/overwrite")));
+        assert!(!fs.exists(path!("/from/sub")));
 
-            for (i, entry) in dir.enumerate() {
-                let entry = entry?;
+        fs.remove(path!("/from/skip"))?;
+        fs.create_dir(path!("/from/sub"))?;
+        fs.write(path!("/from/sub/file"), b"source")?;
+        fs.move_merge(path!("/from"), path!("/to"), |_, _| ConflictPolicy::Skip)?;
+        assert!(!fs.exists(path!("/from")));
+        assert_eq!(fs.read::<10>(path!("/to/sub/file"))?, b"source");
 
-                // assert_eq!(entry.file_name(), match i {
-                //     0 => b".\0",
-                //     1 => b"..\0",
-                //     2 => b"file.a\0",
-                //     3 => b"file.b\0",
-                //     _ => panic!("oh noes"),
-                // });
+        fs.move_merge(path!("/to"), path!("/moved"), |_, _| ConflictPolicy::Skip)?;
+        assert!(!fs.exists(path!("/to")));
+        assert!(fs.metadata(path!("/moved/sub/new"))?.is_dir());
 
-                sizes[i] = entry.metadata().len();
-                found_files += 1;
+        // rename targets inside the source tree are rejected
+        fs.create_dir_all(path!("/from/sub"))?;
+        fs.write(path!("/from/sub/file"), b"other")?;
+        let result = fs.move_merge(path!("/from"), path!("/moved"), |_, _| {
+            ConflictPolicy::Rename(path!("/from/renamed").into())
+        });
+        assert_eq!(result, Err(Error::INVALID));
+        let result = fs.move_merge(path!("/from"), path!("/moved"), |_, _| {
+            ConflictPolicy::Rename(path!("/./from/sub/../renamed").into())
+        });
+        assert_eq!(result, Err(Error::INVALID));
+
+        // conflicting rename targets are passed to on_conflict again, a limited number of times
+        let mut targets = std::vec::Vec::new();
+        fs.move_merge(path!("/from"), path!("/moved"), |_, target| {
+            targets.push(PathBuf::from(target));
+            match targets.len() {
+                1 => ConflictPolicy::Rename(path!("/moved/sub/new").into()),
+                _ => ConflictPolicy::Rename(path!("/moved/sub/other").into()),
             }
+        })?;
+        assert_eq!(
+            targets,
+            [
+                PathBuf::from(path!("/moved/sub/file")),
+                PathBuf::from(path!("/moved/sub/new"))
+            ]
+        );
+        assert_eq!(fs.read::<10>(path!("/moved/sub/other"))?, b"other");
 
-            assert_eq!(sizes, [0, 0, 37, 42]);
-            assert_eq!(found_files, 4);
+        fs.write(path!("/from/sub/file"), b"other")?;
+        let mut conflicts = 0;
+        let result = fs.move_merge(path!("/from"), path!("/moved"), |_, _| {
+            conflicts += 1;
+            ConflictPolicy::Rename(path!("/moved/sub/other").into())
+        });
+        assert_eq!(result, Err(Error::ENTRY_ALREADY_EXISTED));
+        assert_eq!(conflicts, crate::consts::MOVE_MERGE_RENAMES_MAX);
+        assert_eq!(fs.read::<10>(path!("/from/sub/file"))?, b"other");
+        Ok(())
+    })
+    .unwrap();
+}
 
-            Ok(())
-        })
+#[test]
+fn move_merge_nested() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        fs.create_dir_all(path!("/a/b/b"))?;
+        fs.write(path!("/a/file"), b"a")?;
+        fs.write(path!("/a/b/file"), b"b")?;
+        fs.write(path!("/a/b/b/file"), b"bb")?;
+
+        for (from, to) in [
+            (path!("/a"), path!("/a")),
+            (path!("/a"), path!("/./a/")),
+            (path!("/a"), path!("/a/b")),
+            (path!("/a"), path!("/a/new")),
+            (path!("/a/b"), path!("/a")),
+            (path!("/a/b"), path!("/x/../a")),
+        ] {
+            for policy in [ConflictPolicy::Skip, ConflictPolicy::Overwrite] {
+                let result = fs.move_merge(from, to, |_, _| policy.clone());
+                assert_eq!(result, Err(Error::INVALID), "{from} -> {to}");
+            }
+        }
+
+        // the source tree is unchanged
+        assert_eq!(fs.read::<10>(path!("/a/file"))?, b"a");
+        assert_eq!(fs.read::<10>(path!("/a/b/file"))?, b"b");
+        assert_eq!(fs.read::<10>(path!("/a/b/b/file"))?, b"bb");
+        assert!(!fs.exists(path!("/a/new")));
+        Ok(())
     })
     .unwrap();
 }
 
-// // These are some tests that ensure our type constructions
-// // actually do what we intend them to do.
-// // Since dev-features cannot be optional, trybuild is not `no_std`,
-// // and we want to actually test `no_std`...
-// #[test]
-// #[cfg(feature = "ui-tests")]
-// fn test_api_safety() {
-//     let t = trybuild::TestCases::new();
-//     t.compile_fail("tests/ui/*-fail.rs");
-//     t.pass("tests/ui/*-pass.rs");
-// }
+#[test]
+fn ensure_version() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        assert_eq!(fs.layout_version()?, None);
+
+        let mut steps = heapless::Vec::<u32, 4>::new();
+        let found = fs.ensure_version(2, |_, version| {
+            steps.push(version).unwrap();
+            Ok(())
+        })?;
+        assert_eq!(found, 0);
+        assert_eq!(steps, [0, 1]);
+        assert_eq!(fs.layout_version()?, Some(2));
+
+        // failed steps are repeated on the next call
+        steps.clear();
+        let result = fs.ensure_version(4, |_, version| {
+            steps.push(version).unwrap();
+            if version == 3 {
+                Err(Error::IO)
+            } else {
+                Ok(())
+            }
+        });
+        assert_eq!(result, Err(Error::IO));
+        assert_eq!(fs.layout_version()?, Some(3));
+        assert_eq!(fs.ensure_version(4, |_, _| Ok(()))?, 3);
+
+        assert_eq!(fs.ensure_version(4, |_, _| unreachable!())?, 4);
+        assert_eq!(
+            fs.ensure_version(3, |_, _| unreachable!()),
+            Err(Error::INVALID)
+        );
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[derive(Clone, Copy)]
+struct Fnv(u64);
+
+impl core::hash::Hasher for Fnv {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 ^ u64::from(*byte)).wrapping_mul(0x100000001b3);
+        }
+    }
+}
+
+#[test]
+fn find_duplicates() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        fs.create_dir_all(path!("/certs/sub"))?;
+        fs.write(path!("/certs/a"), b"certificate 1")?;
+        fs.write(path!("/certs/b"), b"certificate 2")?;
+        fs.write(path!("/certs/c"), b"certificate 1")?;
+        fs.write(path!("/certs/sub/d"), b"certificate 1")?;
+        fs.write(path!("/certs/sub/e"), b"certificate 2")?;
+        fs.write(path!("/certs/sub/f"), b"unique")?;
+        fs.write(path!("/certs/empty1"), b"")?;
+        fs.write(path!("/certs/empty2"), b"")?;
+
+        let hasher = Fnv(0xcbf29ce484222325);
+        let mut seen = [false; 2];
+        let mut reported = 0;
+        let groups = fs.find_duplicates::<_, 8>(path!("/certs"), hasher, |duplicate| {
+            reported += 1;
+            assert_eq!(duplicate.size, 13);
+            // keep the first file of every group
+            if seen[duplicate.group] {
+                fs.remove(duplicate.path)
+            } else {
+                seen[duplicate.group] = true;
+                Ok(())
+            }
+        })?;
+        assert_eq!(groups, 2);
+        assert_eq!(reported, 5);
+
+        let groups = fs.find_duplicates::<_, 8>(path!("/certs"), hasher, |_| Ok(()))?;
+        assert_eq!(groups, 0);
+        assert_eq!(
+            fs.find_duplicates::<_, 2>(path!("/certs"), hasher, |_| Ok(())),
+            Err(Error::NO_MEMORY)
+        );
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn provision() {
+    const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+
+    fn populate(fs: &Filesystem<'_, OtherRamStorage<'_>>) -> Result<()> {
+        fs.create_dir_all(path!("/etc/keys"))?;
+        fs.create_dir(path!("/empty"))?;
+        fs.write(path!("/etc/config"), b"config")?;
+        fs.write(path!("/etc/keys/device"), &[0x42; 600])
+    }
+
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    let digest = Filesystem::provision(&mut storage, Fnv(FNV_OFFSET), populate).unwrap();
+
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        assert_eq!(fs.read::<10>(path!("/etc/config"))?, b"config");
+        assert!(fs.metadata(path!("/empty"))?.is_dir());
+        Ok(())
+    })
+    .unwrap();
+
+    // provisioning formats the storage and is reproducible
+    let mut other_backend = OtherRam::default();
+    let mut other_storage = OtherRamStorage::new(&mut other_backend);
+    Filesystem::format(&mut other_storage).unwrap();
+    Filesystem::mount_and_then(&mut other_storage, |fs| fs.write(path!("/stale"), b"stale"))
+        .unwrap();
+    let other_digest =
+        Filesystem::provision(&mut other_storage, Fnv(FNV_OFFSET), populate).unwrap();
+    assert_eq!(digest, other_digest);
+
+    let different = Filesystem::provision(&mut other_storage, Fnv(FNV_OFFSET), |fs| {
+        populate(fs)?;
+        fs.write(path!("/etc/config"), b"other")
+    })
+    .unwrap();
+    assert_ne!(digest, different);
+
+    // errors of the closure are returned
+    assert_eq!(
+        Filesystem::provision(&mut other_storage, Fnv(FNV_OFFSET), |fs| {
+            fs.read::<10>(path!("/missing")).map(drop)
+        }),
+        Err(Error::NO_SUCH_ENTRY)
+    );
+}
+
+#[test]
+fn open_files_and_then() {
+    use crate::{fs::FileOpenFlags, io::Write};
+
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        fs.write(path!("/a"), b"first")?;
+        fs.write(path!("/b"), b"second")?;
+
+        let read = FileOpenFlags::READ;
+        let write = FileOpenFlags::WRITE | FileOpenFlags::CREATE | FileOpenFlags::TRUNCATE;
+        let files = [
+            (path!("/a"), read),
+            (path!("/b"), read),
+            (path!("/c"), write),
+        ];
+        fs.open_files_and_then(files, |[a, b, c]| {
+            let mut buf = [0; 10];
+            let n = a.read(&mut buf)?;
+            c.write_all(&buf[..n])?;
+            let n = b.read(&mut buf)?;
+            c.write_all(&buf[..n])
+        })?;
+        assert_eq!(fs.read::<20>(path!("/c"))?, b"firstsecond");
+
+        // all files are closed if the closure fails
+        let files = [(path!("/a"), write), (path!("/c"), write)];
+        assert_eq!(
+            fs.open_files_and_then(files, |[a, _]| {
+                a.write_all(b"new")?;
+                Err::<(), _>(Error::IO)
+            }),
+            Err(Error::IO)
+        );
+        assert_eq!(fs.read::<10>(path!("/a"))?, b"new");
+        assert!(fs.read::<10>(path!("/c"))?.is_empty());
+
+        // opened files are closed if a file cannot be opened
+        let files = [(path!("/a"), write), (path!("/missing"), read)];
+        assert_eq!(
+            fs.open_files_and_then(files, |_| Ok(())),
+            Err(Error::NO_SUCH_ENTRY)
+        );
+        assert!(fs.read::<10>(path!("/a"))?.is_empty());
+        fs.remove(path!("/a"))?;
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn touch() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        fs.touch(path!("/marker"))?;
+        let metadata = fs.metadata(path!("/marker"))?;
+        assert!(metadata.is_file());
+        assert_eq!(metadata.len(), 0);
+
+        fs.write(path!("/file"), b"content")?;
+        fs.touch(path!("/file"))?;
+        assert_eq!(fs.read::<10>(path!("/file"))?, b"content");
+
+        fs.create_dir(path!("/dir"))?;
+        fs.touch(path!("/dir"))?;
+        assert!(fs.metadata(path!("/dir"))?.is_dir());
+
+        assert_eq!(fs.touch(path!("/missing/file")), Err(Error::NO_SUCH_ENTRY));
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[cfg(feature = "path-validator")]
+#[test]
+fn path_validator() {
+    use crate::fs::PathPolicy;
+
+    const POLICY: PathPolicy = PathPolicy::new()
+        .max_depth(2)
+        .reserved(&[path!("/.lfs"), path!("/system")])
+        .forbidden(&['*', ':']);
+
+    assert!(POLICY.allows(path!("/")));
+    assert!(POLICY.allows(path!("/a/b")));
+    assert!(POLICY.allows(path!("/.lfsx")));
+    assert!(POLICY.allows(path!("/a/system")));
+    assert!(!POLICY.allows(path!("/a/b/c")));
+    assert!(!POLICY.allows(path!("/.lfs")));
+    assert!(!POLICY.allows(path!("/system/file")));
+    assert!(!POLICY.allows(path!("/a*")));
+    assert!(!POLICY.allows(path!("/a/b:c")));
+
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        fs.create_dir(path!("/system"))?;
+        fs.write(path!("/system/config"), b"config")?;
+
+        fs.set_path_validator(Some(|path| POLICY.allows(path)));
+        let rejected = Err(Error::PATH_REJECTED);
+        assert_eq!(fs.write(path!("/system/config"), b"changed"), rejected);
+        assert_eq!(fs.remove(path!("/system/config")), rejected);
+        assert_eq!(
+            fs.rename(path!("/system/config"), path!("/config")),
+            rejected
+        );
+        assert_eq!(fs.set_attribute(path!("/system"), 1, b""), rejected);
+        assert_eq!(fs.touch(path!("/a:b")), rejected);
+        assert_eq!(fs.create_dir_all(path!("/a/b/c")), rejected);
+        assert!(!fs.exists(path!("/a")));
+        assert_eq!(fs.read::<10>(path!("/system/config"))?, b"config");
+
+        fs.create_dir_all(path!("/a/b"))?;
+        fs.write(path!("/a/file"), b"data")?;
+
+        fs.set_path_validator(None);
+        fs.remove(path!("/system/config"))?;
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[cfg(feature = "protected-paths")]
+#[test]
+fn protected_paths() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        fs.create_dir_all(path!("/data/factory"))?;
+        fs.write(path!("/data/factory/key"), b"key")
+    })
+    .unwrap();
+
+    let protected = [path!("/data/factory")];
+    let mut alloc = Filesystem::allocate();
+    let (fs, privilege) =
+        Filesystem::mount_protected(&mut alloc, &mut storage, &protected).unwrap();
+    let denied = Err(Error::PATH_PROTECTED);
+
+    assert!(fs.is_protected(path!("/data/factory")));
+    assert!(fs.is_protected(path!("/data/factory/key")));
+    assert!(!fs.is_protected(path!("/data/factory2")));
+    assert!(!fs.is_protected(path!("/data")));
+
+    // littlefs resolves these paths to the protected file
+    for path in [
+        path!("data/factory/key"),
+        path!("/data//factory/key"),
+        path!("/data/./factory/key"),
+        path!("/x/../data/factory/key"),
+        path!("/data/factory/x/../key"),
+    ] {
+        assert!(fs.is_protected(path));
+        assert_eq!(fs.write(path, b"new"), denied);
+        assert_eq!(fs.remove(path), denied);
+    }
+    assert!(!fs.is_protected(path!("/data/factory/../key")));
+    assert_eq!(fs.rename(path!("data/"), path!("/old")), denied);
+    assert_eq!(fs.rename(path!("/x/../data"), path!("/old")), denied);
+    assert_eq!(fs.remove_dir_all(path!("/data/.")), denied);
+
+    assert_eq!(fs.write(path!("/data/factory/key"), b"new"), denied);
+    assert_eq!(fs.write(path!("/data/factory/other"), b"new"), denied);
+    assert_eq!(fs.remove(path!("/data/factory/key")), denied);
+    assert_eq!(fs.set_attribute(path!("/data/factory"), 1, b"a"), denied);
+    assert_eq!(
+        fs.rename(path!("/data/factory/key"), path!("/data/key")),
+        denied
+    );
+    assert_eq!(fs.rename(path!("/data"), path!("/old")), denied);
+    assert_eq!(fs.remove_dir_all(path!("/data")), denied);
+    assert_eq!(
+        fs.open_file_with_options_and_then(
+            |o| o.write(true),
+            path!("/data/factory/key"),
+            |_| Ok(())
+        ),
+        denied
+    );
+    assert_eq!(fs.read::<10>(path!("/data/factory/key")).unwrap(), b"key");
+
+    // other paths can be modified
+    fs.write(path!("/data/app"), b"app").unwrap();
+    fs.write(path!("/data/factory2"), b"app").unwrap();
+    fs.remove(path!("/data/factory2")).unwrap();
+
+    fs.privileged_and_then(&privilege, |fs| {
+        fs.write(path!("/data/factory/key"), b"new")?;
+        fs.rename(path!("/data/factory/key"), path!("/data/factory/old"))
+    })
+    .unwrap();
+    assert_eq!(fs.read::<10>(path!("/data/factory/old")).unwrap(), b"new");
+    assert_eq!(fs.remove(path!("/data/factory/old")), denied);
+}
+
+#[test]
+fn traverse_blocks() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        fs.write(path!("/file"), &[0; 1024])?;
+
+        let mut used = [false; 512];
+        fs.traverse_blocks(|block| {
+            used[block as usize] = true;
+            Ok(())
+        })?;
+        let used = used.iter().filter(|used| **used).count();
+        assert_eq!(fs.total_blocks() - used, fs.available_blocks()?);
+
+        let mut calls = 0;
+        assert_eq!(
+            fs.traverse_blocks(|_| {
+                calls += 1;
+                Err(Error::IO)
+            }),
+            Err(Error::IO)
+        );
+        assert_eq!(calls, 1);
+        Ok(())
+    })
+    .unwrap();
+}
+
+/// Storage that fails all reads from the blocks in `bad`.
+struct BadBlocks<'a> {
+    storage: OtherRamStorage<'a>,
+    bad: &'a [usize],
+}
+
+impl driver::Storage for BadBlocks<'_> {
+    const READ_SIZE: usize = <OtherRamStorage<'static> as driver::Storage>::READ_SIZE;
+    const WRITE_SIZE: usize = <OtherRamStorage<'static> as driver::Storage>::WRITE_SIZE;
+    const BLOCK_SIZE: usize = <OtherRamStorage<'static> as driver::Storage>::BLOCK_SIZE;
+    const BLOCK_COUNT: usize = <OtherRamStorage<'static> as driver::Storage>::BLOCK_COUNT;
+    type CACHE_SIZE = <OtherRamStorage<'static> as driver::Storage>::CACHE_SIZE;
+    type LOOKAHEAD_SIZE = <OtherRamStorage<'static> as driver::Storage>::LOOKAHEAD_SIZE;
+
+    fn read(&mut self, off: usize, buf: &mut [u8]) -> Result<usize> {
+        if self.bad.contains(&(off / Self::BLOCK_SIZE)) {
+            return Err(Error::IO);
+        }
+        self.storage.read(off, buf)
+    }
+
+    fn write(&mut self, off: usize, data: &[u8]) -> Result<usize> {
+        self.storage.write(off, data)
+    }
+
+    fn erase(&mut self, off: usize, len: usize) -> Result<usize> {
+        self.storage.erase(off, len)
+    }
+}
+
+#[test]
+fn check() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    let mut used = std::vec::Vec::new();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        fs.create_dir_all(path!("/a/b"))?;
+        fs.write(path!("/a/b/file"), &[1; 1024])?;
+        fs.write(path!("/c"), b"c")?;
+        fs.traverse_blocks(|block| {
+            used.push(block as usize);
+            Ok(())
+        })
+    })
+    .unwrap();
+
+    let report = Filesystem::check(&mut storage).unwrap();
+    assert!(report.is_clean());
+    assert_eq!(report.dirs, 3);
+    assert_eq!(report.files, 2);
+    assert_eq!(report.used_blocks, used.len());
+    assert_eq!(report.unreadable_blocks, 0);
+
+    // unreadable blocks that are not in use are not a problem
+    let unused = (0..512).rev().find(|block| !used.contains(block)).unwrap();
+    let bad = [unused];
+    let mut bad_blocks = BadBlocks {
+        storage: OtherRamStorage::new(&mut backend),
+        bad: &bad,
+    };
+    let report = Filesystem::check(&mut bad_blocks).unwrap();
+    assert!(report.is_clean());
+    assert_eq!(report.unreadable_blocks, 1);
+    assert_eq!(report.first_unreadable_block, Some(unused as u32));
+    assert_eq!(report.unreadable_used_blocks, 0);
+
+    // the data blocks of the file are in use
+    let bad = [*used.iter().filter(|block| **block > 1).max().unwrap()];
+    let mut bad_blocks = BadBlocks {
+        storage: OtherRamStorage::new(&mut backend),
+        bad: &bad,
+    };
+    let report = Filesystem::check(&mut bad_blocks).unwrap();
+    assert!(!report.is_clean());
+    assert_eq!(report.unreadable_blocks, 1);
+    assert_eq!(report.unreadable_used_blocks, 1);
+    assert!(report.damaged_entries > 0 || report.traverse_error.is_some());
+
+    // both superblocks are unreadable
+    let bad = [0, 1];
+    let mut bad_blocks = BadBlocks {
+        storage: OtherRamStorage::new(&mut backend),
+        bad: &bad,
+    };
+    let report = Filesystem::check(&mut bad_blocks).unwrap();
+    assert!(!report.is_clean());
+    assert_eq!(report.unreadable_blocks, 2);
+    assert!(report.mount_error.is_some());
+    assert_eq!(report.files, 0);
+}
+
+#[test]
+fn scrub() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    let mut used = std::vec::Vec::new();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        fs.write(path!("/file"), &[1; 1024])?;
+        fs.traverse_blocks(|block| {
+            used.push(block as usize);
+            Ok(())
+        })?;
+        assert_eq!(fs.scrub(|_| panic!("unexpected bad block"))?, 0);
+        Ok(())
+    })
+    .unwrap();
+
+    let data_block = *used.iter().filter(|block| **block > 1).max().unwrap();
+    let unused = (0..512).rev().find(|block| !used.contains(block)).unwrap();
+    let bad = [unused, data_block];
+    let mut bad_blocks = BadBlocks {
+        storage: OtherRamStorage::new(&mut backend),
+        bad: &bad,
+    };
+    let mut reported = std::vec::Vec::new();
+    Filesystem::mount_and_then(&mut bad_blocks, |fs| {
+        fs.scrub(|block| reported.push(block as usize))
+    })
+    .unwrap();
+    assert_eq!(reported, [data_block]);
+}
+
+#[cfg(feature = "usage-cache")]
+#[test]
+fn available_blocks_cached() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        assert_eq!(fs.available_blocks_cached(), None);
+        let available = fs.available_blocks()?;
+        assert_eq!(fs.available_blocks_cached(), Some(available));
+        fs.read_dir_and_then(path!("/"), |read_dir| Ok(read_dir.count()))?;
+        assert_eq!(fs.available_blocks_cached(), Some(available));
+
+        fs.write(path!("/file"), &[0; 1024])?;
+        assert_eq!(fs.available_blocks_cached(), None);
+        let after_write = fs.available_blocks()?;
+        assert!(after_write < available);
+        assert_eq!(fs.available_blocks_cached(), Some(after_write));
+
+        fs.open_file_with_options_and_then(
+            |options| options.write(true),
+            path!("/file"),
+            |file| {
+                file.set_len(0)?;
+                assert_eq!(fs.available_blocks_cached(), None);
+                fs.available_blocks()?;
+                file.sync()?;
+                assert_eq!(fs.available_blocks_cached(), None);
+                Ok(())
+            },
+        )?;
+        assert!(fs.available_blocks()? > after_write);
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn rom_storage() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        fs.create_dir(path!("/www"))?;
+        fs.write(path!("/www/index.html"), b"<html></html>")
+    })
+    .unwrap();
+
+    let mut rom = driver::RomStorage::<'_, 256, 512>::new(backend.storage.as_bytes());
+    assert_eq!(
+        driver::Storage::erase(&mut rom, 0, 256),
+        Err(Error::READ_ONLY)
+    );
+    Filesystem::mount_and_then(&mut rom, |fs| {
+        assert_eq!(fs.read::<20>(path!("/www/index.html"))?, b"<html></html>");
+        let fs: &dyn DynFilesystem = fs;
+        assert!(fs.metadata(path!("/www"))?.is_dir());
+        Ok(())
+    })
+    .unwrap();
+
+    #[cfg(feature = "read-only")]
+    Filesystem::mount_read_only_and_then(&mut rom, |fs| {
+        assert!(fs.is_read_only());
+        assert_eq!(
+            fs.write(path!("/www/index.html"), b""),
+            Err(Error::READ_ONLY)
+        );
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[cfg(feature = "timestamps")]
+#[test]
+fn test_doubles() {
+    use crate::test_doubles::TestClock;
+
+    let clock = TestClock::new(100);
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    let mut alloc = Filesystem::allocate();
+    let fs = Filesystem::mount(&mut alloc, &mut storage).unwrap();
+
+    assert_eq!(fs.now(), None);
+
+    fs.set_clock(Some(&clock));
+    assert_eq!(fs.now(), Some(100));
+    clock.advance(20);
+    assert_eq!(fs.now(), Some(120));
+    clock.set(5);
+    assert_eq!(fs.now(), Some(5));
+    clock.advance(u64::MAX);
+    assert_eq!(fs.now(), Some(u64::MAX));
+}
+
+/// Storage that fails the next `failures` operations with `error`.
+struct Flaky<'a> {
+    storage: OtherRamStorage<'a>,
+    failures: usize,
+    error: Error,
+}
+
+impl Flaky<'_> {
+    fn fail(&mut self) -> Result<()> {
+        if self.failures > 0 {
+            self.failures -= 1;
+            Err(self.error)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl driver::Storage for Flaky<'_> {
+    const READ_SIZE: usize = <OtherRamStorage<'static> as driver::Storage>::READ_SIZE;
+    const WRITE_SIZE: usize = <OtherRamStorage<'static> as driver::Storage>::WRITE_SIZE;
+    const BLOCK_SIZE: usize = <OtherRamStorage<'static> as driver::Storage>::BLOCK_SIZE;
+    const BLOCK_COUNT: usize = <OtherRamStorage<'static> as driver::Storage>::BLOCK_COUNT;
+    type CACHE_SIZE = <OtherRamStorage<'static> as driver::Storage>::CACHE_SIZE;
+    type LOOKAHEAD_SIZE = <OtherRamStorage<'static> as driver::Storage>::LOOKAHEAD_SIZE;
+
+    fn read(&mut self, off: usize, buf: &mut [u8]) -> Result<usize> {
+        self.fail()?;
+        self.storage.read(off, buf)
+    }
+
+    fn write(&mut self, off: usize, data: &[u8]) -> Result<usize> {
+        self.fail()?;
+        self.storage.write(off, data)
+    }
+
+    fn erase(&mut self, off: usize, len: usize) -> Result<usize> {
+        self.fail()?;
+        self.storage.erase(off, len)
+    }
+}
+
+#[test]
+fn progress_hook() {
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    static CALLS: AtomicUsize = AtomicUsize::new(0);
+    fn hook() {
+        CALLS.fetch_add(1, Ordering::Relaxed);
+    }
+
+    let mut backend = OtherRam::default();
+    let mut storage = driver::ProgressHook::new(OtherRamStorage::new(&mut backend), hook);
+    Filesystem::format(&mut storage).unwrap();
+    let after_format = CALLS.load(Ordering::Relaxed);
+    assert!(after_format > 0);
+
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        fs.create_dir_all(path!("/a/b/c"))?;
+        fs.write(path!("/a/b/c/file"), &[0; 1000])?;
+        fs.remove_dir_all(path!("/a"))
+    })
+    .unwrap();
+    assert!(CALLS.load(Ordering::Relaxed) > after_format);
+}
+
+#[test]
+fn retry() {
+    use driver::{Retry, RetryStats, Storage as _};
+
+    static DELAYS: std::sync::Mutex<std::vec::Vec<usize>> = std::sync::Mutex::new(vec![]);
+
+    let mut backend = OtherRam::default();
+    let flaky = Flaky {
+        storage: OtherRamStorage::new(&mut backend),
+        failures: 0,
+        error: Error::IO,
+    };
+    let mut storage =
+        Retry::new(flaky, 2).with_delay(|attempt| DELAYS.lock().unwrap().push(attempt));
+    Filesystem::format(&mut storage).unwrap();
+    assert_eq!(storage.stats(), RetryStats::default());
+
+    // transient failures
+    storage.inner_mut().failures = 2;
+    Filesystem::mount_and_then(&mut storage, |fs| fs.write(path!("/file"), b"data")).unwrap();
+    assert_eq!(
+        storage.stats(),
+        RetryStats {
+            retries: 2,
+            recovered: 1,
+            failures: 0
+        }
+    );
+    assert_eq!(*DELAYS.lock().unwrap(), [1, 2]);
+
+    // persistent failures are not retried again
+    storage.reset_stats();
+    storage.inner_mut().failures = 4;
+    let mut buf = [0; 256];
+    assert_eq!(storage.read(0, &mut buf), Err(Error::IO));
+    assert_eq!(storage.read(0, &mut buf), Err(Error::IO));
+    assert_eq!(storage.inner().failures, 0);
+    assert_eq!(
+        storage.stats(),
+        RetryStats {
+            retries: 2,
+            recovered: 0,
+            failures: 2
+        }
+    );
+
+    // after a success, operations are retried again
+    storage.read(0, &mut buf).unwrap();
+    storage.inner_mut().failures = 1;
+    storage.read(0, &mut buf).unwrap();
+    assert_eq!(storage.stats().recovered, 1);
+
+    // other errors are not retried
+    storage.reset_stats();
+    storage.inner_mut().failures = 1;
+    storage.inner_mut().error = Error::CORRUPTION;
+    assert_eq!(storage.read(0, &mut buf), Err(Error::CORRUPTION));
+    assert_eq!(storage.stats(), RetryStats::default());
+
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        assert_eq!(fs.read::<10>(path!("/file"))?, b"data");
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn counting_storage() {
+    use crate::instrument::CountingStorage;
+    use driver::Storage as _;
+
+    let mut backend = OtherRam::default();
+    let mut storage = CountingStorage::<_, 512>::new(OtherRamStorage::new(&mut backend));
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        for i in 0..100 {
+            fs.write(path!("/counter"), &[i; 300])?;
+        }
+        Ok(())
+    })
+    .unwrap();
+
+    let totals = storage.totals();
+    assert!(totals.reads > 0);
+    assert!(totals.erases > 0);
+    assert!(totals.program_bytes >= 100 * 300);
+    assert_eq!(
+        storage.erase_counts().iter().sum::<u32>() as usize,
+        totals.erases
+    );
+    assert!(storage.program_counts().iter().sum::<u32>() as usize >= totals.programs);
+    // the superblock is rewritten, but the wear is spread over more than one pair of blocks
+    assert!(
+        storage
+            .erase_counts()
+            .iter()
+            .filter(|count| **count > 0)
+            .count()
+            > 2
+    );
+    assert_eq!(storage.erase_count(512), None);
+
+    storage.reset();
+    assert_eq!(storage.totals(), Default::default());
+    storage.erase(2 * 256, 2 * 256).unwrap();
+    assert_eq!(storage.totals().erases, 2);
+    assert_eq!(storage.erase_count(1), Some(0));
+    assert_eq!(storage.erase_count(2), Some(1));
+    assert_eq!(storage.erase_count(3), Some(1));
+    assert_eq!(storage.erase_count(4), Some(0));
+}
+
+#[test]
+fn fault_inject_storage() {
+    use crate::testing::FaultInjectStorage;
+    use driver::Storage as _;
+
+    let mut backend = OtherRam::default();
+    let mut storage = FaultInjectStorage::new(OtherRamStorage::new(&mut backend));
+    Filesystem::format(&mut storage).unwrap();
+
+    // transient failure
+    Filesystem::mount_and_then(&mut storage, |fs| fs.write(path!("/file"), b"old")).unwrap();
+    storage.fail_after(0);
+    let result = Filesystem::mount_and_then(&mut storage, |fs| fs.write(path!("/file"), b"new"));
+    assert_eq!(result, Err(Error::IO));
+    assert!(storage.is_powered());
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        assert_eq!(&fs.read::<3>(path!("/file"))?, b"old");
+        fs.write(path!("/file"), b"new")
+    })
+    .unwrap();
+
+    // interrupt the update at every write and erase
+    for n in 0.. {
+        Filesystem::mount_and_then(&mut storage, |fs| fs.write(path!("/file"), b"old")).unwrap();
+        let before = storage.operations();
+        storage.power_loss_after(n, 16);
+        let result =
+            Filesystem::mount_and_then(&mut storage, |fs| fs.write(path!("/file"), &[2; 600]));
+        if result.is_ok() {
+            assert!(storage.operations() - before <= n);
+            storage.clear_faults();
+            break;
+        }
+        assert!(!storage.is_powered());
+        assert!(!Filesystem::is_mountable(&mut storage));
+        storage.restore_power();
+        let contents =
+            Filesystem::mount_and_then(&mut storage, |fs| fs.read::<600>(path!("/file"))).unwrap();
+        assert!(contents == b"old" || contents[..] == [2; 600]);
+    }
+
+    // bit flips
+    let mut buf = [0; 4];
+    storage.read(0, &mut buf).unwrap();
+    let expected = buf;
+    storage.flip_bit(2, 3);
+    storage.read(0, &mut buf).unwrap();
+    assert_eq!(buf[2], expected[2] ^ 0b1000);
+    assert_eq!(buf[..2], expected[..2]);
+    storage.read(3, &mut buf[..1]).unwrap();
+    assert_eq!(buf[0], expected[3]);
+    storage.clear_faults();
+    storage.read(0, &mut buf).unwrap();
+    assert_eq!(buf, expected);
+}
+
+#[test]
+fn powerloss_exhaustive() {
+    use crate::testing::{
+        powerloss::{exhaustive, Failure},
+        FaultInjectStorage,
+    };
+
+    let mut backend = OtherRam::default();
+    let mut storage = FaultInjectStorage::new(OtherRamStorage::new(&mut backend));
+    Filesystem::format(&mut storage).unwrap();
+
+    let mut runs = 0;
+    let interruptions = exhaustive(
+        &mut storage,
+        |fs| {
+            runs += 1;
+            fs.create_dir_all(path!("/dir"))?;
+            fs.write(path!("/dir/file"), &[1; 600])?;
+            fs.write(path!("/dir/file"), &[2; 600])
+        },
+        |fs| {
+            if fs.exists(path!("/dir/file")) {
+                let contents = fs.read::<600>(path!("/dir/file"))?;
+                // a newly created file is committed before its contents
+                assert!(
+                    contents.is_empty() || contents[..] == [1; 600] || contents[..] == [2; 600]
+                );
+            }
+            Ok(())
+        },
+    )
+    .unwrap();
+    assert!(interruptions > 0);
+    // one run without interruption for every amount of written data
+    assert_eq!(runs, interruptions + 3);
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        assert_eq!(fs.read::<600>(path!("/dir/file"))?[..], [2; 600]);
+        fs.remove_dir_all(path!("/dir"))
+    })
+    .unwrap();
+
+    // an invariant that does not hold after the first interruption
+    let result = exhaustive(
+        &mut storage,
+        |fs| fs.write(path!("/file"), b"data"),
+        |fs| fs.metadata(path!("/file")).map(drop),
+    );
+    assert_eq!(
+        result,
+        Err(Failure {
+            written: 0,
+            interrupted_after: Some(0),
+            error: Error::NO_SUCH_ENTRY,
+        })
+    );
+}
+
+#[test]
+fn fuzz() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    for seed in 0..8 {
+        crate::testing::fuzz::run(&mut storage, seed, 200);
+    }
+}
+
+#[test]
+fn block_cache() {
+    use driver::Storage as _;
+
+    let mut backend = OtherRam::default();
+    let mut storage = driver::BlockCache::<_, 4>::new(OtherRamStorage::new(&mut backend));
+
+    // writes and erases invalidate cached data
+    let mut buf = [0; 64];
+    storage.write(0, &[1; 64]).unwrap();
+    storage.read(0, &mut buf).unwrap();
+    assert_eq!(buf, [1; 64]);
+    storage.read(16, &mut buf[..32]).unwrap();
+    assert_eq!(storage.misses(), 2);
+    assert_eq!(storage.hits(), 2);
+    storage.write(32, &[2; 32]).unwrap();
+    storage.read(0, &mut buf).unwrap();
+    assert_eq!(buf[..32], [1; 32]);
+    assert_eq!(buf[32..], [2; 32]);
+    storage.erase(0, 256).unwrap();
+    storage.read(0, &mut buf).unwrap();
+    assert_eq!(buf, [0xff; 64]);
+
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        for i in 0..8u8 {
+            let path = PathBuf::try_from(&[b'/', b'a' + i][..]).unwrap();
+            fs.write(&path, &[i; 100])?;
+        }
+        for i in 0..8u8 {
+            let path = PathBuf::try_from(&[b'/', b'a' + i][..]).unwrap();
+            assert_eq!(fs.read::<100>(&path)?, [i; 100]);
+        }
+        Ok(())
+    })
+    .unwrap();
+    assert!(storage.hits() > 0);
+}
+
+/// Async storage whose operations are pending once before they complete.
+struct AsyncRam<'a>(OtherRamStorage<'a>);
+
+async fn yield_once() {
+    let mut pending = true;
+    core::future::poll_fn(|_| {
+        if core::mem::take(&mut pending) {
+            core::task::Poll::Pending
+        } else {
+            core::task::Poll::Ready(())
+        }
+    })
+    .await
+}
+
+impl driver::AsyncStorage for AsyncRam<'_> {
+    const READ_SIZE: usize = <OtherRamStorage<'static> as driver::Storage>::READ_SIZE;
+    const WRITE_SIZE: usize = <OtherRamStorage<'static> as driver::Storage>::WRITE_SIZE;
+    const BLOCK_SIZE: usize = <OtherRamStorage<'static> as driver::Storage>::BLOCK_SIZE;
+    const BLOCK_COUNT: usize = <OtherRamStorage<'static> as driver::Storage>::BLOCK_COUNT;
+    type CACHE_SIZE = <OtherRamStorage<'static> as driver::Storage>::CACHE_SIZE;
+    type LOOKAHEAD_SIZE = <OtherRamStorage<'static> as driver::Storage>::LOOKAHEAD_SIZE;
+
+    async fn read(&mut self, off: usize, buf: &mut [u8]) -> Result<usize> {
+        yield_once().await;
+        driver::Storage::read(&mut self.0, off, buf)
+    }
+
+    async fn write(&mut self, off: usize, data: &[u8]) -> Result<usize> {
+        yield_once().await;
+        driver::Storage::write(&mut self.0, off, data)
+    }
+
+    async fn erase(&mut self, off: usize, len: usize) -> Result<usize> {
+        yield_once().await;
+        driver::Storage::erase(&mut self.0, off, len)
+    }
+}
+
+#[test]
+fn fs_busy_poll() {
+    use crate::fs_busy_poll::Filesystem;
+    use core::future::Future;
+
+    let mut backend = OtherRam::default();
+    let mut storage = AsyncRam(OtherRamStorage::new(&mut backend));
+
+    let future = async {
+        Filesystem::format(&mut storage).await?;
+        assert!(Filesystem::is_mountable(&mut storage).await);
+        Filesystem::mount_and_then(&mut storage, async |fs| {
+            fs.create_dir_all(path!("/log")).await?;
+            fs.write(path!("/log/boot"), b"ok").await?;
+            assert_eq!(fs.read::<10>(path!("/log/boot")).await?, b"ok");
+            let entries = fs
+                .read_dir_and_then(path!("/log"), |read_dir| Ok(read_dir.skip_dots().count()))
+                .await?;
+            assert_eq!(entries, 1);
+            fs.remove_dir_all(path!("/log")).await?;
+            assert!(!fs.exists(path!("/log")).await);
+            Ok(())
+        })
+        .await
+    };
+    let mut future = core::pin::pin!(future);
+    let mut context = core::task::Context::from_waker(core::task::Waker::noop());
+    loop {
+        if let core::task::Poll::Ready(result) = future.as_mut().poll(&mut context) {
+            result.unwrap();
+            break;
+        }
+    }
+}
+
+#[test]
+fn fs_async_cancellation() {
+    use crate::fs_busy_poll::Filesystem;
+    use core::{
+        future::Future,
+        task::{Context, Poll, Waker},
+    };
+
+    let mut context = Context::from_waker(Waker::noop());
+    let mut backend = OtherRam::default();
+    let mut storage = AsyncRam(OtherRamStorage::new(&mut backend));
+
+    // all operations complete on their first poll
+    {
+        let format = core::pin::pin!(Filesystem::format(&mut storage));
+        assert_eq!(format.poll(&mut context), Poll::Ready(Ok(())));
+    }
+
+    // dropping the future while the closure is suspended between operations
+    {
+        let future = Filesystem::mount_and_then(&mut storage, async |fs| {
+            fs.write(path!("/first"), b"first").await?;
+            yield_once().await;
+            fs.write(path!("/second"), b"second").await
+        });
+        let mut future = core::pin::pin!(future);
+        assert!(future.as_mut().poll(&mut context).is_pending());
+    }
+
+    // dropping a future that has not been polled has no effect
+    drop(Filesystem::format(&mut storage));
+
+    let retry = Filesystem::mount_and_then(&mut storage, async |fs| {
+        assert_eq!(fs.read::<10>(path!("/first")).await?, b"first");
+        assert!(!fs.exists(path!("/second")).await);
+        fs.write(path!("/second"), b"second").await
+    });
+    let retry = core::pin::pin!(retry);
+    assert_eq!(retry.poll(&mut context), Poll::Ready(Ok(())));
+}
+
+#[test]
+fn owned_filesystem() {
+    use crate::fs::OwnedFilesystem;
+
+    // can be created in constant contexts and shared between threads
+    const fn new_fs(storage: OtherRamStorage<'_>) -> OwnedFilesystem<OtherRamStorage<'_>> {
+        OwnedFilesystem::new(storage)
+    }
+    fn assert_sync<T: Sync>() {}
+    assert_sync::<OwnedFilesystem<OtherRamStorage<'static>>>();
+
+    let mut backend = OtherRam::default();
+    let mut fs = new_fs(OtherRamStorage::new(&mut backend));
+    assert_eq!(fs.with(|_| Ok(())), Err(Error::INVALID));
+
+    fs.format().unwrap();
+    fs.mount().unwrap();
+    assert!(fs.is_mounted());
+    fs.with(|fs| fs.write(path!("/file"), b"data")).unwrap();
+
+    // move the mounted filesystem to a different address
+    let mut fs = Box::new(fs);
+    fs.with(|fs| {
+        assert_eq!(fs.read::<10>(path!("/file"))?, b"data");
+        fs.create_dir(path!("/dir"))?;
+        fs.write(path!("/dir/file"), &[0xaa; 1000])
+    })
+    .unwrap();
+
+    // settings are kept between calls
+    #[cfg(feature = "read-only")]
+    {
+        fs.with(|fs| {
+            fs.set_read_only(true);
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(
+            fs.with(|fs| fs.write(path!("/other"), b"data")),
+            Err(Error::READ_ONLY)
+        );
+        fs.with(|fs| {
+            assert!(fs.is_read_only());
+            fs.set_read_only(false);
+            Ok(())
+        })
+        .unwrap();
+    }
+
+    let mut fs = *fs;
+    fs.unmount().unwrap();
+    assert!(!fs.is_mounted());
+    fs.mount().unwrap();
+    fs.with(|fs| {
+        assert_eq!(fs.read::<1000>(path!("/dir/file"))?, &[0xaa; 1000][..]);
+        Ok(())
+    })
+    .unwrap();
+
+    let mut storage = fs.into_inner();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        assert!(fs.exists(path!("/file")));
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[cfg(feature = "change-tracking")]
+#[test]
+fn changed_since() {
+    use crate::fs::ChangeToken;
+
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        let config = path!("/cfg/config");
+        let (changed, token) = fs.changed_since(config, ChangeToken::default());
+        assert!(!changed);
+
+        fs.create_dir(path!("/cfg"))?;
+        fs.write(config, b"a")?;
+        let (changed, token) = fs.changed_since(config, token);
+        assert!(changed);
+        let (changed, token) = fs.changed_since(config, token);
+        assert!(!changed);
+
+        // other paths and reads
+        fs.write(path!("/cfg/other"), b"b")?;
+        fs.read::<10>(config)?;
+        let (changed, token) = fs.changed_since(config, token);
+        assert!(!changed);
+
+        // writes through an open file
+        fs.open_file_with_options_and_then(
+            |o| o.write(true),
+            config,
+            |file| {
+                let (changed, _) = fs.changed_since(config, token);
+                assert!(!changed);
+                file.write(b"c")
+            },
+        )?;
+        let (changed, token) = fs.changed_since(config, token);
+        assert!(changed);
+
+        // files opened for reading
+        fs.open_file_and_then(config, |_| Ok(()))?;
+        let (changed, token) = fs.changed_since(config, token);
+        assert!(!changed);
+
+        // ancestors
+        fs.rename(path!("/cfg"), path!("/old"))?;
+        let (changed, token) = fs.changed_since(config, token);
+        assert!(changed);
+
+        // changes of many other paths lead to false positives
+        for name in [path!("a"), path!("b"), path!("c"), path!("d"), path!("e")] {
+            fs.touch(name)?;
+        }
+        let (changed, _) = fs.changed_since(config, token);
+        assert!(changed);
+        Ok(())
+    })
+    .unwrap();
+}
+
+/// Reader that returns at most `step` bytes per call.
+struct ShortReads<'a> {
+    data: core::cell::Cell<&'a [u8]>,
+    step: usize,
+}
+
+impl Read for ShortReads<'_> {
+    fn read(&self, buf: &mut [u8]) -> Result<usize> {
+        let data = self.data.get();
+        let n = data.len().min(buf.len()).min(self.step);
+        buf[..n].copy_from_slice(&data[..n]);
+        self.data.set(&data[n..]);
+        Ok(n)
+    }
+}
+
+/// Writer that collects the written data.
+#[derive(Default)]
+struct Collect(core::cell::RefCell<std::vec::Vec<u8>>);
+
+impl crate::io::Write for Collect {
+    fn write(&self, data: &[u8]) -> Result<usize> {
+        self.0.borrow_mut().extend_from_slice(data);
+        Ok(data.len())
+    }
+
+    fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn encoding() {
+    use crate::encoding::{encode, encode_to, Decoder, Encoding};
+
+    let data: std::vec::Vec<u8> = (0..=255).chain(0..100).collect();
+    for (encoding, empty, short) in [(Encoding::Hex, "", "0aff"), (Encoding::Base64, "", "Cv8=")] {
+        for step in [1, 2, 5, 47, 1000] {
+            let mut text = std::string::String::new();
+            let reader = ShortReads {
+                data: core::cell::Cell::new(&data),
+                step,
+            };
+            encode(&reader, encoding, &mut text).unwrap();
+            assert_eq!(text.len(), encoding.encoded_len(data.len()));
+
+            let bytes = Collect::default();
+            let reader = ShortReads {
+                data: core::cell::Cell::new(&data),
+                step,
+            };
+            encode_to(&reader, encoding, &bytes).unwrap();
+            assert_eq!(bytes.0.borrow().as_slice(), text.as_bytes());
+
+            // decode in chunks of `step` characters
+            let decoded = Collect::default();
+            let mut decoder = Decoder::new(&decoded, encoding);
+            for chunk in text.as_bytes().chunks(step) {
+                decoder
+                    .write_str(core::str::from_utf8(chunk).unwrap())
+                    .unwrap();
+            }
+            decoder.finish().unwrap();
+            assert_eq!(decoded.0.into_inner(), data);
+        }
+
+        let mut text = std::string::String::new();
+        let reader = ShortReads {
+            data: core::cell::Cell::new(&[]),
+            step: 1,
+        };
+        encode(&reader, encoding, &mut text).unwrap();
+        assert_eq!(text, empty);
+
+        let mut text = std::string::String::new();
+        let reader = ShortReads {
+            data: core::cell::Cell::new(&[0x0a, 0xff]),
+            step: 1,
+        };
+        encode(&reader, encoding, &mut text).unwrap();
+        assert_eq!(text, short);
+    }
+
+    let decode = |encoding, text: &str| {
+        let decoded = Collect::default();
+        let mut decoder = Decoder::new(&decoded, encoding);
+        decoder.write_str(text)?;
+        decoder.finish()?;
+        Ok(decoded.0.into_inner())
+    };
+    assert_eq!(decode(Encoding::Hex, "0A ff\n"), Ok(vec![0x0a, 0xff]));
+    assert_eq!(decode(Encoding::Hex, "0af"), Err(Error::INVALID));
+    assert_eq!(decode(Encoding::Hex, "0g"), Err(Error::INVALID));
+    assert_eq!(decode(Encoding::Base64, "Cv8="), Ok(vec![0x0a, 0xff]));
+    assert_eq!(decode(Encoding::Base64, "Cv8"), Ok(vec![0x0a, 0xff]));
+    assert_eq!(decode(Encoding::Base64, "Cg=="), Ok(vec![0x0a]));
+    assert_eq!(decode(Encoding::Base64, "Cg"), Ok(vec![0x0a]));
+    assert_eq!(decode(Encoding::Base64, "C"), Err(Error::INVALID));
+    assert_eq!(decode(Encoding::Base64, "Cg==Cg=="), Err(Error::INVALID));
+    assert_eq!(decode(Encoding::Base64, "Cg="), Err(Error::INVALID));
+    assert_eq!(decode(Encoding::Base64, "C==="), Err(Error::INVALID));
+    assert_eq!(decode(Encoding::Base64, "Cv-8"), Err(Error::INVALID));
+}
+
+/// Storage that cannot program the lowest bit of the bytes in its last block.
+struct StuckBits<'a>(OtherRamStorage<'a>);
+
+impl driver::Storage for StuckBits<'_> {
+    const READ_SIZE: usize = OtherRamStorage::READ_SIZE;
+    const WRITE_SIZE: usize = OtherRamStorage::WRITE_SIZE;
+    const BLOCK_SIZE: usize = OtherRamStorage::BLOCK_SIZE;
+    const BLOCK_COUNT: usize = OtherRamStorage::BLOCK_COUNT;
+    type CACHE_SIZE = <OtherRamStorage<'static> as driver::Storage>::CACHE_SIZE;
+    type LOOKAHEAD_SIZE = <OtherRamStorage<'static> as driver::Storage>::LOOKAHEAD_SIZE;
+
+    fn read(&mut self, off: usize, buf: &mut [u8]) -> Result<usize> {
+        self.0.read(off, buf)
+    }
+
+    fn write(&mut self, off: usize, data: &[u8]) -> Result<usize> {
+        if off >= (Self::BLOCK_COUNT - 1) * Self::BLOCK_SIZE {
+            let stuck: std::vec::Vec<u8> = data.iter().map(|byte| byte | 1).collect();
+            self.0.write(off, &stuck)
+        } else {
+            self.0.write(off, data)
+        }
+    }
+
+    fn erase(&mut self, off: usize, len: usize) -> Result<usize> {
+        self.0.erase(off, len)
+    }
+}
+
+#[test]
+fn verified_storage() {
+    use driver::{Storage as _, Verified};
+
+    let mut backend = OtherRam::default();
+    let mut storage = Verified::new(StuckBits(OtherRamStorage::new(&mut backend)));
+
+    let last_block = 511 * 256;
+    assert_eq!(storage.write(last_block, &[0xff; 32]), Ok(32));
+    assert_eq!(
+        storage.write(last_block + 32, &[0xfe; 32]),
+        Err(Error::CORRUPTION)
+    );
+    assert_eq!(storage.write(0, &[0xfe; 32]), Ok(32));
+    storage.erase(0, 256).unwrap();
+    storage.erase(last_block, 256).unwrap();
+
+    // littlefs relocates data from the bad block if it ends up using it
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        fs.write(path!("data.bin"), &[0xfe; 1024])?;
+        let contents: heapless::Vec<u8, 1024> = fs.read(path!("data.bin"))?;
+        assert_eq!(contents, [0xfe; 1024]);
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[cfg(feature = "read-only")]
+#[test]
+fn read_only() {
+    let mut backend = Ram::default();
+    let mut storage = RamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        fs.create_dir(path!("dir"))?;
+        fs.write(path!("dir/file"), b"data")?;
+
+        fs.create_file_and_then(path!("open"), |file| {
+            file.write(b"before")?;
+            fs.set_read_only(true);
+            assert_eq!(file.write(b"after"), Err(Error::READ_ONLY));
+            assert_eq!(file.set_len(0), Err(Error::READ_ONLY));
+            Ok(())
+        })?;
+        assert!(fs.is_read_only());
+
+        assert_eq!(fs.write(path!("file"), b"data"), Err(Error::READ_ONLY));
+        assert_eq!(
+            fs.write_chunk(path!("dir/file"), b"data", OpenSeekFrom::End(0)),
+            Err(Error::READ_ONLY)
+        );
+        assert_eq!(fs.create_dir(path!("other")), Err(Error::READ_ONLY));
+        assert_eq!(fs.create_dir_all(path!("a/b")), Err(Error::READ_ONLY));
+        assert_eq!(fs.remove(path!("dir/file")), Err(Error::READ_ONLY));
+        assert_eq!(fs.remove_dir_all(path!("dir")), Err(Error::READ_ONLY));
+        assert_eq!(
+            fs.rename(path!("dir"), path!("other")),
+            Err(Error::READ_ONLY)
+        );
+        assert_eq!(
+            fs.set_attribute(path!("dir"), 37, b"data"),
+            Err(Error::READ_ONLY)
+        );
+        assert_eq!(fs.remove_attribute(path!("dir"), 37), Err(Error::READ_ONLY));
+
+        // reading still works
+        let contents: heapless::Vec<u8, 16> = fs.read(path!("dir/file"))?;
+        assert_eq!(contents, b"data");
+        let contents: heapless::Vec<u8, 16> = fs.read(path!("open"))?;
+        assert_eq!(contents, b"before");
+
+        fs.set_read_only(false);
+        fs.remove_dir_all(path!("dir"))?;
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn append_buffer() {
+    let mut backend = Ram::default();
+    let mut storage = RamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        let mut buffer = AppendBuffer::<8>::new(path!("log"));
+        buffer.append(fs, b"abc")?;
+        buffer.append(fs, b"def")?;
+        assert_eq!(buffer.len(), 6);
+        assert!(!fs.exists(path!("log")));
+
+        // does not fit, flushes the buffered data
+        buffer.append(fs, b"ghi")?;
+        assert_eq!(buffer.len(), 3);
+        let contents: heapless::Vec<u8, 32> = fs.read(path!("log"))?;
+        assert_eq!(contents, b"abcdef");
+
+        // larger than the buffer, written directly
+        buffer.append(fs, b"0123456789")?;
+        assert!(buffer.is_empty());
+        buffer.append(fs, b"!")?;
+        buffer.flush(fs)?;
+        let contents: heapless::Vec<u8, 32> = fs.read(path!("log"))?;
+        assert_eq!(contents, b"abcdefghi0123456789!");
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn chunk_reader() {
+    let mut backend = Ram::default();
+    let mut storage = RamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        let data: std::vec::Vec<u8> = (0..32).collect();
+        fs.write(path!("file"), &data)?;
+
+        let mut reader = ChunkReader::<16>::new(path!("file"));
+        assert_eq!(reader.next_chunk(fs)?.unwrap(), &data[..16]);
+        assert_eq!(reader.offset(), 16);
+        assert_eq!(reader.next_chunk(fs)?.unwrap(), &data[16..]);
+        assert!(reader.next_chunk(fs)?.is_none());
+        assert_eq!(reader.offset(), 32);
+
+        // data that is appended later is read, too
+        fs.append(path!("file"), b"more")?;
+        assert_eq!(reader.next_chunk(fs)?.unwrap(), b"more");
+
+        reader.set_offset(30);
+        let chunks: std::vec::Vec<_> = reader.chunks(fs).collect::<Result<_>>()?;
+        assert_eq!(chunks, [&[30, 31, b'm', b'o', b'r', b'e'][..]]);
+
+        // the iteration stops after an error and the offset is kept
+        let mut reader = ChunkReader::<16>::new(path!("missing"));
+        let results: std::vec::Vec<_> = reader.chunks(fs).collect();
+        assert_eq!(results, [Err(Error::NO_SUCH_ENTRY)]);
+        assert_eq!(reader.offset(), 0);
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn migrate() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        fs.set_attribute(path!("/"), 1, b"root")?;
+        fs.write(path!("/a.txt"), b"first file")?;
+        fs.create_dir_all(path!("/dir/subdir"))?;
+        fs.set_attribute(path!("/dir"), 2, b"directory")?;
+        fs.write(path!("/dir/b.txt"), &[0x42; 2000])?;
+        fs.set_attribute(path!("/dir/b.txt"), 3, b"file")?;
+        fs.write(path!("/dir/subdir/c.txt"), b"")?;
+        fs.write(path!("/z.txt"), b"last file")?;
+        Ok(())
+    })
+    .unwrap();
+
+    let mut target_backend = Ram::default();
+    let mut target = RamStorage::new(&mut target_backend);
+    let mut calls = 0;
+    let mut last = (0, 0, 0);
+    Filesystem::migrate(&mut storage, &mut target, |progress| {
+        calls += 1;
+        last = (progress.files, progress.dirs, progress.bytes);
+    })
+    .unwrap();
+    assert_eq!(calls, 6);
+    assert_eq!(last, (4, 2, 2019));
+
+    Filesystem::mount_and_then(&mut target, |fs| {
+        let mut buffer = [0; Attribute::MAX_SIZE as _];
+        let attribute = fs.attribute(path!("/"), 1, &mut buffer)?.unwrap();
+        assert_eq!(attribute.data(), b"root");
+        let attribute = fs.attribute(path!("/dir"), 2, &mut buffer)?.unwrap();
+        assert_eq!(attribute.data(), b"directory");
+        let attribute = fs.attribute(path!("/dir/b.txt"), 3, &mut buffer)?.unwrap();
+        assert_eq!(attribute.data(), b"file");
+        assert!(fs.attribute(path!("/a.txt"), 3, &mut buffer)?.is_none());
+
+        let contents: heapless::Vec<u8, 2048> = fs.read(path!("/a.txt"))?;
+        assert_eq!(contents, b"first file");
+        let contents: heapless::Vec<u8, 2048> = fs.read(path!("/dir/b.txt"))?;
+        assert_eq!(contents, [0x42; 2000]);
+        assert!(fs.metadata(path!("/dir/subdir"))?.is_dir());
+        assert!(fs.metadata(path!("/dir/subdir/c.txt"))?.is_empty());
+        let contents: heapless::Vec<u8, 2048> = fs.read(path!("/z.txt"))?;
+        assert_eq!(contents, b"last file");
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn copy() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        fs.write(path!("/a.txt"), &[0x42; 2000])?;
+        fs.set_attribute(path!("/a.txt"), 1, b"file")?;
+        assert_eq!(fs.copy(path!("/a.txt"), path!("/b.txt"))?, 2000);
+        let contents: heapless::Vec<u8, 2048> = fs.read(path!("/b.txt"))?;
+        assert_eq!(contents, [0x42; 2000]);
+        let mut buffer = [0; Attribute::MAX_SIZE as _];
+        let attribute = fs.attribute(path!("/b.txt"), 1, &mut buffer)?.unwrap();
+        assert_eq!(attribute.data(), b"file");
+        for to in [
+            path!("/a.txt"),
+            path!("/./a.txt"),
+            path!("a.txt"),
+            path!("/x/../a.txt"),
+        ] {
+            assert_eq!(fs.copy(path!("/a.txt"), to), Err(Error::INVALID));
+        }
+        assert_eq!(fs.metadata(path!("/a.txt"))?.len(), 2000);
+        assert_eq!(
+            fs.copy(path!("/missing"), path!("/c.txt")),
+            Err(Error::NO_SUCH_ENTRY)
+        );
+
+        fs.create_dir_all(path!("/dir/subdir"))?;
+        fs.set_attribute(path!("/dir/subdir"), 2, b"directory")?;
+        fs.write(path!("/dir/b.txt"), b"second file")?;
+        fs.write(path!("/dir/subdir/c.txt"), b"third file")?;
+        fs.create_dir_all(path!("/copy/subdir"))?;
+        fs.write(path!("/copy/b.txt"), b"old")?;
+        fs.write(path!("/copy/other.txt"), b"other")?;
+
+        let dyn_fs: &dyn DynFilesystem = fs;
+        dyn_fs.copy_dir_all(path!("/dir"), path!("/copy"))?;
+        assert_eq!(fs.read::<64>(path!("/copy/b.txt"))?, b"second file");
+        assert_eq!(fs.read::<64>(path!("/copy/subdir/c.txt"))?, b"third file");
+        assert_eq!(fs.read::<64>(path!("/copy/other.txt"))?, b"other");
+        let attribute = fs
+            .attribute(path!("/copy/subdir"), 2, &mut buffer)?
+            .unwrap();
+        assert_eq!(attribute.data(), b"directory");
+
+        fs.copy_dir_all(path!("/dir/subdir"), path!("/new/nested"))?;
+        assert_eq!(fs.read::<64>(path!("/new/nested/c.txt"))?, b"third file");
+
+        assert_eq!(
+            fs.copy_dir_all(path!("/dir"), path!("/dir/subdir/copy")),
+            Err(Error::INVALID)
+        );
+        for to in [
+            path!("/dir"),
+            path!("/./dir"),
+            path!("dir/"),
+            path!("/dir//subdir/copy"),
+            path!("/x/../dir/copy"),
+        ] {
+            assert_eq!(fs.copy_dir_all(path!("/dir"), to), Err(Error::INVALID));
+        }
+        assert_eq!(
+            fs.copy_dir_all(path!("/a.txt"), path!("/d")),
+            Err(Error::PATH_NOT_DIR)
+        );
+        assert!(!fs.exists(path!("/d")));
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn append() {
+    let mut backend = Ram::default();
+    let mut storage = RamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        fs.append(path!("log"), b"first\n")?;
+        fs.append(path!("log"), b"second\n")?;
+        assert_eq!(fs.read::<16>(path!("log"))?, b"first\nsecond\n");
+
+        let fs: &dyn DynFilesystem = fs;
+        fs.append(path!("log"), b"third\n")?;
+        assert_eq!(fs.metadata(path!("log"))?.len(), 19);
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn write_atomic() {
+    let mut backend = Ram::default();
+    let mut storage = RamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        fs.create_dir(path!("dir"))?;
+        fs.write_atomic(path!("dir/file"), b"old contents")?;
+        fs.write_atomic(path!("dir/file"), b"new contents")?;
+        assert_eq!(fs.read::<16>(path!("dir/file"))?, b"new contents");
+        fs.write_atomic(path!("file"), b"relative")?;
+        assert_eq!(fs.read::<16>(path!("/file"))?, b"relative");
+
+        // the temporary file is removed if the rename fails
+        fs.create_dir(path!("dir/sub"))?;
+        assert_eq!(
+            fs.write_atomic(path!("dir/sub"), b"data"),
+            Err(Error::PATH_IS_DIR)
+        );
+        let mut entries = 0;
+        fs.read_dir_and_then(path!("dir"), |read_dir| {
+            entries = read_dir.skip_dots().count();
+            Ok(())
+        })?;
+        assert_eq!(entries, 2);
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn copy_between_filesystems() {
+    let mut backend = Ram::default();
+    let mut storage = RamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    let mut other_backend = OtherRam::default();
+    let mut other_storage = OtherRamStorage::new(&mut other_backend);
+    Filesystem::format(&mut other_storage).unwrap();
+
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        fs.write(path!("file"), &[0x42; 1000])?;
+        Filesystem::mount_and_then(&mut other_storage, |other| {
+            let fs: &dyn DynFilesystem = fs;
+            assert_eq!(fs.copy_to(other, path!("file"), path!("copy"))?, 1000);
+            assert_eq!(
+                fs.copy_to(other, path!("missing"), path!("copy")),
+                Err(Error::NO_SUCH_ENTRY)
+            );
+            let contents: heapless::Vec<u8, 1024> = other.read(path!("copy"))?;
+            assert_eq!(contents, [0x42; 1000]);
+
+            let mut buf = [0; 7];
+            let copied = fs.open_file_and_then(path!("file"), &mut |from| {
+                other.create_file_and_then(path!("partial"), |to| {
+                    from.seek(SeekFrom::Start(900))?;
+                    crate::io::copy(from, to, &mut buf)
+                })
+            })?;
+            assert_eq!(copied, 100);
+            assert_eq!(other.metadata(path!("partial"))?.len(), 100);
+            Ok(())
+        })
+    })
+    .unwrap();
+}
+
+#[test]
+fn usage_by_dir() {
+    let mut backend = Ram::default();
+    let mut storage = RamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        fs.write(path!("/a.txt"), &[0; 10])?;
+        fs.create_dir_all(path!("/apps/x"))?;
+        fs.write(path!("/apps/x/1"), &[0; 100])?;
+        fs.write(path!("/apps/y"), &[0; 20])?;
+        fs.create_dir(path!("/data"))?;
+        fs.create_dir(path!("/logs"))?;
+        fs.write(path!("/logs/l1"), &[0; 5])?;
+
+        let usage = |depth| {
+            let mut usage = std::vec::Vec::new();
+            fs.usage_by_dir(path!("/"), depth, |dir| {
+                usage.push((PathBuf::from(dir.path), dir.files, dir.bytes));
+                Ok(())
+            })
+            .map(|()| usage)
+        };
+        assert_eq!(usage(0)?, [(PathBuf::from(path!("/")), 4, 135)]);
+        assert_eq!(
+            usage(1)?,
+            [
+                (PathBuf::from(path!("/")), 1, 10),
+                (PathBuf::from(path!("/apps")), 2, 120),
+                (PathBuf::from(path!("/data")), 0, 0),
+                (PathBuf::from(path!("/logs")), 1, 5),
+            ]
+        );
+        assert_eq!(
+            usage(2)?,
+            [
+                (PathBuf::from(path!("/")), 1, 10),
+                (PathBuf::from(path!("/apps")), 1, 20),
+                (PathBuf::from(path!("/apps/x")), 1, 100),
+                (PathBuf::from(path!("/logs")), 1, 5),
+            ]
+        );
+        assert_eq!(
+            usage(crate::consts::DIR_DEPTH_MAX + 1),
+            Err(Error::NO_MEMORY)
+        );
+
+        assert_eq!(fs.dir_size(path!("/"))?, 135);
+        assert_eq!(fs.dir_size(path!("/apps"))?, 120);
+        assert_eq!(fs.dir_size(path!("/data"))?, 0);
+        assert_eq!(fs.dir_entry_count(path!("/"))?, 8);
+        assert_eq!(fs.dir_entry_count(path!("/apps"))?, 3);
+        assert_eq!(fs.dir_entry_count(path!("/data"))?, 0);
+        assert_eq!(fs.dir_size(path!("/missing")), Err(Error::NO_SUCH_ENTRY));
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn walk_dir() {
+    let mut backend = Ram::default();
+    let mut storage = RamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        fs.write(path!("/a"), b"")?;
+        fs.create_dir_all(path!("/b/c"))?;
+        fs.write(path!("/b/c/d"), b"")?;
+        fs.write(path!("/b/e"), b"")?;
+        fs.write(path!("/f"), b"")?;
+
+        let paths = fs.walk_dir_and_then(path!("/"), |walk| {
+            walk.map(|entry| entry.map(|entry| PathBuf::from(entry.path())))
+                .collect::<Result<std::vec::Vec<_>>>()
+        })?;
+        let paths: std::vec::Vec<&path::Path> = paths.iter().map(|path| &**path).collect();
+        assert_eq!(
+            paths,
+            [
+                path!("/a"),
+                path!("/b"),
+                path!("/b/c"),
+                path!("/b/c/d"),
+                path!("/b/e"),
+                path!("/f")
+            ]
+        );
+
+        let fs: &dyn DynFilesystem = fs;
+        let files = fs.walk_dir_and_then(path!("/b"), &mut |entries| {
+            Ok(entries
+                .filter(|entry| {
+                    entry
+                        .as_ref()
+                        .map_or(true, |entry| entry.file_type().is_file())
+                })
+                .count())
+        })?;
+        assert_eq!(files, 2);
+        let result =
+            fs.walk_dir_and_then(path!("/missing"), &mut |entries| entries.next().transpose());
+        assert_eq!(result.map(drop), Err(Error::NO_SUCH_ENTRY));
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn import_file() {
+    let mut backend = OtherRam::default();
+    let mut storage = NameMax::<16>(OtherRamStorage::new(&mut backend));
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        fs.create_dir(path!("/import"))?;
+        let mut buffer = [0; 64];
+
+        let path = fs.import_file(path!("/import"), "short.txt", b"1")?;
+        assert_eq!(path.as_str(), "/import/short.txt");
+        assert_eq!(fs.original_name(&path, &mut buffer)?, Some("short.txt"));
+
+        let path = fs.import_file(path!("/import"), "a rather long name.txt", b"2")?;
+        assert_eq!(path.as_str().len(), "/import/".len() + 16);
+        assert!(path.as_str().starts_with("/import/a_r~"));
+        assert!(path.as_str().ends_with(".txt"));
+        assert_eq!(fs.read::<4>(&path)?, b"2");
+        assert_eq!(
+            fs.original_name(&path, &mut buffer)?,
+            Some("a rather long name.txt")
+        );
+
+        // names that only differ in invalid characters do not collide
+        let first = fs.import_file(path!("/import"), "ä", b"3")?;
+        let second = fs.import_file(path!("/import"), "ö", b"4")?;
+        assert_ne!(first, second);
+        let path = fs.import_file(path!("/import"), "..", b"5")?;
+        assert_eq!(fs.original_name(&path, &mut buffer)?, Some(".."));
+
+        assert_eq!(fs.original_name(&path, &mut [0; 1]), Err(Error::NO_MEMORY));
+        fs.write(path!("/plain"), b"")?;
+        assert_eq!(fs.original_name(path!("/plain"), &mut buffer)?, None);
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn rewrite_file() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        fs.write(path!("/small"), b"inline")?;
+        assert!(!fs.needs_rewrite(path!("/small"))?);
+        fs.write(path!("/contiguous"), &[1; 2000])?;
+        assert!(!fs.needs_rewrite(path!("/contiguous"))?);
+
+        // interleaved appends scatter the blocks of both files
+        for _ in 0..8 {
+            fs.append(path!("/a"), &[2; 128])?;
+            fs.append(path!("/b"), &[3; 128])?;
+        }
+        fs.set_attribute(path!("/a"), 1, b"log")?;
+        assert!(fs.needs_rewrite(path!("/a"))?);
+        assert_eq!(fs.rewrite_file(path!("/a"))?, 1024);
+        let contents: heapless::Vec<u8, 1024> = fs.read(path!("/a"))?;
+        assert_eq!(contents, [2; 1024]);
+        let mut buffer = [0; 8];
+        let attribute = fs.attribute(path!("/a"), 1, &mut buffer)?.unwrap();
+        assert_eq!(attribute.data(), b"log");
+
+        assert_eq!(
+            fs.needs_rewrite(path!("/missing")),
+            Err(Error::NO_SUCH_ENTRY)
+        );
+        assert_eq!(
+            fs.rewrite_file(path!("/missing")),
+            Err(Error::NO_SUCH_ENTRY)
+        );
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn truncate_front() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        fs.create_dir(path!("/logs"))?;
+        let data: std::vec::Vec<u8> = (0..1000).map(|i| i as u8).collect();
+        fs.write(path!("/logs/log"), &data)?;
+        fs.set_attribute(path!("/logs/log"), 1, b"log")?;
+
+        assert_eq!(fs.truncate_front(path!("/logs/log"), 0)?, 1000);
+        assert_eq!(fs.truncate_front(path!("/logs/log"), 300)?, 700);
+        let contents: heapless::Vec<u8, 1024> = fs.read(path!("/logs/log"))?;
+        assert_eq!(contents, data[300..]);
+        let mut buffer = [0; 8];
+        let attribute = fs.attribute(path!("/logs/log"), 1, &mut buffer)?.unwrap();
+        assert_eq!(attribute.data(), b"log");
+
+        assert_eq!(fs.truncate_front(path!("/logs/log"), 2000)?, 0);
+        assert_eq!(fs.metadata(path!("/logs/log"))?.len(), 0);
+
+        // no temporary files are left behind
+        let mut entries = 0;
+        fs.read_dir_and_then(path!("/logs"), |read_dir| {
+            entries = read_dir.skip_dots().count();
+            Ok(())
+        })?;
+        assert_eq!(entries, 1);
+
+        assert_eq!(
+            fs.truncate_front(path!("/missing"), 1),
+            Err(Error::NO_SUCH_ENTRY)
+        );
+        assert_eq!(
+            fs.truncate_front(path!("/logs"), 1),
+            Err(Error::PATH_IS_DIR)
+        );
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn rename_noreplace() {
+    let mut backend = Ram::default();
+    let mut storage = RamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        fs.write(path!("state"), b"old")?;
+        fs.write(path!("state.new"), b"new")?;
+        assert_eq!(
+            fs.rename_noreplace(path!("state.new"), path!("state")),
+            Err(Error::ENTRY_ALREADY_EXISTED)
+        );
+        assert_eq!(fs.read::<8>(path!("state"))?, b"old");
+        assert!(fs.exists(path!("state.new")));
+
+        fs.create_dir(path!("dir"))?;
+        assert_eq!(
+            fs.rename_noreplace(path!("state.new"), path!("dir")),
+            Err(Error::ENTRY_ALREADY_EXISTED)
+        );
+        assert_eq!(
+            fs.rename_noreplace(path!("missing"), path!("other")),
+            Err(Error::NO_SUCH_ENTRY)
+        );
+
+        let fs: &dyn DynFilesystem = fs;
+        fs.remove(path!("state"))?;
+        fs.rename_noreplace(path!("state.new"), path!("state"))?;
+        assert!(!fs.exists(path!("state.new")));
+        assert_eq!(fs.metadata(path!("state"))?.len(), 3);
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn find() {
+    let mut backend = Ram::default();
+    let mut storage = RamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        fs.create_dir_all(path!("/logs/2024"))?;
+        fs.write(path!("/logs/boot.log"), b"")?;
+        fs.write(path!("/logs/2024/app.log"), b"")?;
+        fs.write(path!("/logs/2024/app.txt"), b"")?;
+        fs.write(path!("/app.log"), b"")?;
+
+        let find = |path, pattern| {
+            let mut found = std::vec::Vec::new();
+            fs.find(path, pattern, |entry| {
+                found.push(PathBuf::from(entry.path()));
+                Ok(())
+            })
+            .map(|count| {
+                assert_eq!(count, found.len());
+                found
+                    .iter()
+                    .map(|path| std::string::String::from(path.as_str()))
+                    .collect::<std::vec::Vec<_>>()
+            })
+        };
+        assert_eq!(
+            find(path!("/"), "*.log")?,
+            ["/app.log", "/logs/2024/app.log", "/logs/boot.log"]
+        );
+        assert_eq!(find(path!("/logs"), "/logs/*.log")?, ["/logs/boot.log"]);
+        assert_eq!(
+            find(path!("/logs"), "/logs/**/app.*")?,
+            ["/logs/2024/app.log", "/logs/2024/app.txt"]
+        );
+        assert_eq!(find(path!("/"), "20??")?, ["/logs/2024"]);
+        assert!(find(path!("/"), "*.bin")?.is_empty());
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn zero_range() {
+    let mut backend = Ram::default();
+    let mut storage = RamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        fs.write(path!("file"), &[0x42; 1000])?;
+        fs.open_file_with_options_and_then(
+            |options| options.read(true).write(true),
+            path!("file"),
+            |file| {
+                file.seek(SeekFrom::Start(10))?;
+                file.zero_range(100, 900)?;
+                assert_eq!(file.tell()?, 10);
+                assert_eq!(file.len()?, 1000);
+                assert_eq!(file.rewrite_compacted()?, 100);
+                assert_eq!(file.len()?, 100);
+                assert_eq!(file.tell()?, 10);
+
+                file.zero_range(0, 10)?;
+                file.zero_range(100, 20)?;
+                assert_eq!(file.len()?, 120);
+                assert_eq!(file.rewrite_compacted()?, 100);
+                Ok(())
+            },
+        )?;
+        let contents: heapless::Vec<u8, 1024> = fs.read(path!("file"))?;
+        assert_eq!(contents[..10], [0; 10]);
+        assert_eq!(contents[10..], [0x42; 90]);
+
+        fs.write(path!("zeros"), &[0; 100])?;
+        fs.open_file_with_options_and_then(
+            |options| options.read(true).write(true),
+            path!("zeros"),
+            |file| file.rewrite_compacted(),
+        )?;
+        assert!(fs.metadata(path!("zeros"))?.is_empty());
+
+        fs.open_file_with_options_and_then(
+            |options| options.write(true).append(true),
+            path!("file"),
+            |file| {
+                assert_eq!(file.zero_range(0, 10), Err(Error::INVALID));
+                Ok(())
+            },
+        )?;
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn rollback() {
+    let mut backend = Ram::default();
+    let mut storage = RamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        fs.write(path!("file"), b"old contents")?;
+        fs.open_file_with_options_and_then(
+            |options| options.write(true).truncate(true),
+            path!("file"),
+            |file| {
+                file.write(b"new contents")?;
+                assert!(!file.is_erred());
+                file.rollback();
+                assert!(file.is_erred());
+                assert_eq!(file.write(b"more"), Err(Error::BAD_FILE_DESCRIPTOR));
+                assert_eq!(file.sync(), Err(Error::BAD_FILE_DESCRIPTOR));
+                Ok(())
+            },
+        )?;
+        let contents: heapless::Vec<u8, 16> = fs.read(path!("file"))?;
+        assert_eq!(contents, b"old contents");
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn attributes() {
+    let mut backend = Ram::default();
+    let mut storage = RamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        let mut buffer = [0; Attribute::MAX_SIZE as _];
+
+        let filename = b"some.file\0".try_into().unwrap();
+        fs.write(filename, &[])?;
+        assert!(fs.attribute(filename, 37, &mut buffer)?.is_none());
+
+        let data = b"top secret";
+        fs.set_attribute(filename, 37, data).unwrap();
+        let attribute = fs.attribute(filename, 37, &mut buffer)?.unwrap();
+        assert_eq!(data, attribute.data());
+        assert_eq!(data.len(), attribute.total_size());
+
+        // if the buffer is smaller than the attribute, it is truncated and the
+        // full size is returned
+        let mut small_buffer = [0; 5];
+        let attribute = fs.attribute(filename, 37, &mut small_buffer)?.unwrap();
+        assert_eq!(&data[..5], attribute.data());
+        assert_eq!(data.len(), attribute.total_size());
+
+        // if the input data is too long, an error is returned
+        let long_data = &[0xff; 1024];
+        assert!(long_data.len() > Attribute::MAX_SIZE as _);
+        assert_eq!(
+            Err(Error::NO_SPACE),
+            fs.set_attribute(filename, 37, long_data)
+        );
+
+        // probes all IDs, so it is quite expensive
+        fs.set_attribute(filename, 0, b"first")?;
+        fs.set_attribute(filename, 255, b"last")?;
+        let mut ids = std::vec::Vec::new();
+        fs.attributes(filename, |id, data| {
+            ids.push((id, data.len()));
+            Ok(())
+        })?;
+        assert_eq!(ids, [(0, 5), (37, 10), (255, 4)]);
+        let mut count = 0;
+        let dyn_fs: &dyn DynFilesystem = fs;
+        dyn_fs
+            .attributes(filename, &mut |_, _| {
+                count += 1;
+                if count == 2 {
+                    Err(Error::IO)
+                } else {
+                    Ok(())
+                }
+            })
+            .unwrap_err();
+        assert_eq!(count, 2);
+        fs.remove_attribute(filename, 0)?;
+        fs.remove_attribute(filename, 255)?;
+        assert_eq!(
+            fs.attributes(path!("missing"), |_, _| Ok(())),
+            Err(Error::NO_SUCH_ENTRY)
+        );
+
+        fs.remove_attribute(filename, 37)?;
+        assert!(fs.attribute(filename, 37, &mut buffer)?.is_none());
+
+        // // Directories can have attributes too
+        let tmp_dir = b"/tmp\0".try_into().unwrap();
+        fs.create_dir(tmp_dir)?;
+
+        let data = b"temporary directory";
+        fs.set_attribute(tmp_dir, 37, data)?;
+
+        let attribute = fs.attribute(tmp_dir, 37, &mut buffer)?.unwrap();
+        assert_eq!(data, attribute.data());
+        assert_eq!(data.len(), attribute.total_size());
+
+        fs.remove_attribute(tmp_dir, 37)?;
+        assert!(fs.attribute(tmp_dir, 37, &mut buffer)?.is_none());
+
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_iter_dirs() {
+    let mut backend = Ram::default();
+    let mut storage = RamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        fs.create_dir(path!("/tmp"))?;
+
+        // TODO: we might want "multi-open"
+        fs.create_file_and_then(path!("/tmp/file.a"), |file| {
+            file.set_len(37)?;
+            fs.create_file_and_then(path!("/tmp/file.b"), |file| file.set_len(42))
+        })?;
+
+        fs.read_dir_and_then(path!("/tmp"), |dir| {
+            let mut found_files: usize = 0;
+            let mut sizes = [0usize; 4];
+
+            for (i, entry) in dir.enumerate() {
+                let entry = entry?;
+
+                // assert_eq!(entry.file_name(), match i {
+                //     0 => b".\0",
+                //     1 => b"..\0",
+                //     2 => b"file.a\0",
+                //     3 => b"file.b\0",
+                //     _ => panic!("oh noes"),
+                // });
+
+                sizes[i] = entry.metadata().len();
+                found_files += 1;
+            }
+
+            assert_eq!(sizes, [0, 0, 37, 42]);
+            assert_eq!(found_files, 4);
+
+            Ok(())
+        })
+    })
+    .unwrap();
+}
+
+// // These are some tests that ensure our type constructions
+// // actually do what we intend them to do.
+// // Since dev-features cannot be optional, trybuild is not `no_std`,
+// // and we want to actually test `no_std`...
+// #[test]
+// #[cfg(feature = "ui-tests")]
+// fn test_api_safety() {
+//     let t = trybuild::TestCases::new();
+//     t.compile_fail("tests/ui/*-fail.rs");
+//     t.pass("tests/ui/*-pass.rs");
+// }
+
+#[test]
+fn store() {
+    use crate::store::{Cursor, Store};
+
+    let mut backend = Ram::default();
+    let mut storage = RamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        let store: &dyn Store = fs;
+        fs.create_dir(path!("keys"))?;
+        for name in [path!("keys/a"), path!("keys/b"), path!("keys/c")] {
+            store.write(name, b"key")?;
+        }
+        store.rename(path!("keys/c"), path!("keys/d"))?;
+        assert_eq!(store.metadata(path!("keys/d"))?.len(), 3);
+
+        let mut buf = [0; 3];
+        assert_eq!(store.read(path!("keys/a"), &mut buf)?, 3);
+        assert_eq!(&buf, b"key");
+        assert_eq!(
+            store.read(path!("keys/a"), &mut buf[..2]),
+            Err(Error::NO_MEMORY)
+        );
+        assert_eq!(
+            store.read(path!("keys/c"), &mut buf),
+            Err(Error::NO_SUCH_ENTRY)
+        );
+
+        // delete every returned entry while iterating
+        let mut cursor = Cursor::new();
+        let mut names = std::vec::Vec::new();
+        while let Some(entry) = store.read_dir_next(path!("keys"), &mut cursor)? {
+            assert_eq!(cursor.last(), Some(entry.file_name().as_path()));
+            names.push(PathBuf::from(entry.file_name().as_path()));
+            store.delete(entry.path())?;
+        }
+        let names: std::vec::Vec<_> = names.iter().map(|name| &**name).collect();
+        assert_eq!(names, [path!("a"), path!("b"), path!("d")]);
+        assert!(store
+            .read_dir_next(path!("keys"), &mut Cursor::new())?
+            .is_none());
+
+        // entries that are kept are returned once
+        for name in [path!("keys/a"), path!("keys/b")] {
+            store.write(name, b"key")?;
+        }
+        let mut cursor = Cursor::new();
+        let mut count = 0;
+        while store.read_dir_next(path!("keys"), &mut cursor)?.is_some() {
+            count += 1;
+        }
+        assert_eq!(count, 2);
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn file_attributes() {
+    let mut backend = Ram::default();
+    let mut storage = RamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        let mut version = *b"v1";
+        let mut flags = [0x01];
+        OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open_with_attributes_and_then(
+                fs,
+                path!("file"),
+                &mut [
+                    FileAttribute::new(1, &mut version),
+                    FileAttribute::new(2, &mut flags),
+                ],
+                |file| file.write(b"contents").map(drop),
+            )?;
+        let mut buffer = [0; 4];
+        let attribute = fs.attribute(path!("file"), 1, &mut buffer)?.unwrap();
+        assert_eq!(attribute.data(), b"v1");
+
+        // read on open, missing attributes keep the buffer
+        let mut version = [0; 2];
+        let mut missing = *b"default";
+        let mut attributes = [
+            FileAttribute::new(1, &mut version),
+            FileAttribute::new(3, &mut missing),
+        ];
+        OpenOptions::new()
+            .read(true)
+            .open_with_attributes_and_then(fs, path!("file"), &mut attributes, |_| Ok(()))?;
+        assert_eq!(attributes[0].id(), 1);
+        assert_eq!(attributes[0].data(), b"v1");
+        assert_eq!(attributes[1].data(), b"default");
+
+        // read and written back unchanged if opened for reading and writing
+        attributes[0].data_mut().copy_from_slice(b"v2");
+        OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open_with_attributes_and_then(fs, path!("file"), &mut attributes, |file| {
+                file.write(b"C").map(drop)
+            })?;
+        assert_eq!(attributes[0].data(), b"v1");
+        assert_eq!(fs.read::<16>(path!("file"))?, b"Contents");
+
+        // nothing is written if the file is rolled back
+        let mut version = *b"v3";
+        OpenOptions::new()
+            .write(true)
+            .open_with_attributes_and_then(
+                fs,
+                path!("file"),
+                &mut [FileAttribute::new(1, &mut version)],
+                |file| {
+                    file.rollback();
+                    Ok(())
+                },
+            )?;
+        let attribute = fs.attribute(path!("file"), 1, &mut buffer)?.unwrap();
+        assert_eq!(attribute.data(), b"v1");
+
+        // attributes are modifications
+        #[cfg(feature = "read-only")]
+        {
+            fs.set_read_only(true);
+            assert_eq!(
+                OpenOptions::new()
+                    .write(true)
+                    .open_with_attributes_and_then(
+                        fs,
+                        path!("file"),
+                        &mut [FileAttribute::new(1, &mut version)],
+                        |_| Ok(()),
+                    ),
+                Err(Error::READ_ONLY)
+            );
+        }
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[cfg(feature = "timestamps")]
+#[test]
+fn timestamps() {
+    use crate::test_doubles::TestClock;
+
+    let clock = TestClock::new(100);
+    let mut backend = Ram::default();
+    let mut storage = RamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        // not recorded without a clock
+        fs.write(path!("file"), b"data")?;
+        assert_eq!(fs.mtime(path!("file"))?, None);
+
+        fs.set_clock(Some(&clock));
+        fs.write(path!("file"), b"data")?;
+        assert_eq!(fs.mtime(path!("file"))?, Some(100));
+
+        clock.advance(10);
+        fs.write_chunk(path!("file"), b"D", OpenSeekFrom::Start(0))?;
+        assert_eq!(fs.mtime(path!("file"))?, Some(110));
+
+        // reading and rolled back modifications do not update the time
+        clock.advance(10);
+        assert_eq!(fs.read::<8>(path!("file"))?, b"Data");
+        fs.open_file_with_options_and_then(
+            |options| options.read(true).write(true),
+            path!("file"),
+            |_| Ok(()),
+        )?;
+        fs.open_file_with_options_and_then(
+            |options| options.write(true),
+            path!("file"),
+            |file| {
+                file.write(b"x")?;
+                file.rollback();
+                Ok(())
+            },
+        )?;
+        assert_eq!(fs.mtime(path!("file"))?, Some(110));
+
+        fs.open_file_with_options_and_then(
+            |options| options.write(true),
+            path!("file"),
+            |file| file.set_len(2),
+        )?;
+        assert_eq!(fs.mtime(path!("file"))?, Some(120));
+
+        // errors in the closure still update the time of a modified file
+        clock.advance(10);
+        let result: Result<()> = fs.create_file_and_then(path!("file"), |file| {
+            file.write(b"new")?;
+            Err(Error::IO)
+        });
+        assert_eq!(result, Err(Error::IO));
+        assert_eq!(fs.mtime(path!("file"))?, Some(130));
+
+        // copies keep the time of the source
+        clock.advance(10);
+        fs.copy(path!("file"), path!("copy"))?;
+        assert_eq!(fs.mtime(path!("copy"))?, Some(130));
+
+        // the time is written together with other attributes ...
+        let mut version = [2];
+        OpenOptions::new()
+            .write(true)
+            .open_with_attributes_and_then(
+                fs,
+                path!("file"),
+                &mut [FileAttribute::new(1, &mut version)],
+                |file| file.write(b"N").map(drop),
+            )?;
+        assert_eq!(fs.mtime(path!("file"))?, Some(140));
+        let mut buffer = [0; 1];
+        assert_eq!(
+            fs.attribute(path!("file"), 1, &mut buffer)?.unwrap().data(),
+            [2]
+        );
+
+        // ... unless it is passed as one of them
+        clock.advance(10);
+        let mut mtime = 5u64.to_le_bytes();
+        OpenOptions::new()
+            .write(true)
+            .open_with_attributes_and_then(
+                fs,
+                path!("file"),
+                &mut [FileAttribute::new(
+                    crate::consts::MTIME_ATTRIBUTE_ID,
+                    &mut mtime,
+                )],
+                |file| file.write(b"n").map(drop),
+            )?;
+        assert_eq!(fs.mtime(path!("file"))?, Some(5));
+
+        // touch sets the time of new and existing files, but not of directories
+        clock.advance(10);
+        fs.touch(path!("marker"))?;
+        assert_eq!(fs.mtime(path!("marker"))?, Some(160));
+        clock.advance(10);
+        fs.touch(path!("file"))?;
+        assert_eq!(fs.mtime(path!("file"))?, Some(170));
+        assert_eq!(fs.read::<8>(path!("file"))?, b"new");
+        fs.create_dir(path!("dir"))?;
+        fs.touch(path!("dir"))?;
+        assert_eq!(fs.mtime(path!("dir"))?, None);
+
+        fs.set_mtime(path!("file"), 7)?;
+        assert_eq!(fs.mtime(path!("file"))?, Some(7));
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[cfg(feature = "digest")]
+#[test]
+fn hash_file() {
+    use sha2::{Digest, Sha256};
+
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        // larger than a block and not a multiple of the cache size
+        let data: std::vec::Vec<u8> = (0..1000).map(|i| i as u8).collect();
+        fs.write(path!("large"), &data)?;
+        assert_eq!(
+            fs.hash_file::<Sha256>(path!("large"))?,
+            Sha256::digest(&data)
+        );
+
+        fs.write(path!("empty"), &[])?;
+        assert_eq!(fs.hash_file::<Sha256>(path!("empty"))?, Sha256::digest([]));
+
+        assert_eq!(
+            fs.hash_file::<Sha256>(path!("missing")),
+            Err(Error::NO_SUCH_ENTRY)
+        );
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn fs_info() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        let info = fs.fs_info();
+        assert_eq!(info.block_size, 256);
+        assert_eq!(info.block_count, 512);
+        assert_eq!(
+            info.name_max,
+            <OtherRamStorage<'static> as driver::Storage>::FILENAME_MAX
+        );
+        assert_eq!(info.file_max, crate::consts::FILEBYTES_MAX as usize);
+        assert_eq!(info.attr_max, Attribute::MAX_SIZE as usize);
+
+        let dyn_fs: &dyn DynFilesystem = fs;
+        assert_eq!(dyn_fs.fs_info(), info);
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn backup_restore() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    let stream = Collect::default();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        fs.create_dir_all(path!("/a/b/empty"))?;
+        fs.write(path!("/a/b/large"), &[7; 3000])?;
+        fs.write(path!("/a/empty-file"), b"")?;
+        fs.write(path!("/top"), b"top")?;
+        fs.set_attribute(path!("/top"), 3, &[3; Attribute::MAX_SIZE as usize])?;
+        fs.set_attribute(path!("/a/b"), 0, b"dir")?;
+        fs.set_attribute(path!("/"), 255, b"root")?;
+        fs.backup_to(&stream)
+    })
+    .unwrap();
+    let stream = stream.0.into_inner();
+    assert!(stream.starts_with(b"LFSB\x01D\x01\x00/A"));
+    assert!(stream.ends_with(b"Z"));
+
+    // restore into a different geometry, with partial reads
+    let mut ram = Ram::default();
+    let mut storage = RamStorage::new(&mut ram);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        fs.write(path!("/top"), b"old contents")?;
+        fs.restore_from(&ShortReads {
+            data: core::cell::Cell::new(&stream),
+            step: 100,
+        })?;
+        assert!(fs.metadata(path!("/a/b/empty"))?.is_dir());
+        assert_eq!(fs.read::<3000>(path!("/a/b/large"))?[..], [7; 3000]);
+        assert_eq!(fs.metadata(path!("/a/empty-file"))?.len(), 0);
+        assert_eq!(&fs.read::<12>(path!("/top"))?, b"top");
+        let mut buffer = [0; Attribute::MAX_SIZE as usize];
+        let attribute = fs.attribute(path!("/top"), 3, &mut buffer)?.unwrap();
+        assert_eq!(attribute.data(), [3; Attribute::MAX_SIZE as usize]);
+        let attribute = fs.attribute(path!("/a/b"), 0, &mut buffer)?.unwrap();
+        assert_eq!(attribute.data(), b"dir");
+        let attribute = fs.attribute(path!("/"), 255, &mut buffer)?.unwrap();
+        assert_eq!(attribute.data(), b"root");
+
+        let restore = |data: &[u8]| {
+            fs.restore_from(&ShortReads {
+                data: core::cell::Cell::new(data),
+                step: usize::MAX,
+            })
+        };
+        assert_eq!(restore(&stream[..stream.len() - 1]), Err(Error::IO));
+        assert_eq!(restore(b"LFSB\x02Z"), Err(Error::CORRUPTION));
+        assert_eq!(restore(b"LFSB\x01A\x00\x00\x00Z"), Err(Error::CORRUPTION));
+        assert_eq!(restore(b"LFSB\x01D\x01\x00aZ"), Err(Error::CORRUPTION));
+        assert_eq!(restore(b"LFSB\x01X"), Err(Error::CORRUPTION));
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn partition() {
+    use driver::{Partition, Storage as _};
+
+    let mut backend = OtherRam::default();
+    let storage = OtherRamStorage::new(&mut backend);
+    let mut first = Partition::<_, 128>::new(storage, 0);
+    Filesystem::format(&mut first).unwrap();
+    Filesystem::mount_and_then(&mut first, |fs| fs.write(path!("name"), b"first")).unwrap();
+
+    let mut second = Partition::<_, 384>::new(first.into_inner(), 128);
+    assert_eq!(second.first_block(), 128);
+    assert!(!Filesystem::is_mountable(&mut second));
+    Filesystem::format(&mut second).unwrap();
+    Filesystem::mount_and_then(&mut second, |fs| {
+        assert!(!fs.exists(path!("name")));
+        assert_eq!(fs.total_blocks(), 384);
+        fs.write(path!("name"), b"second")
+    })
+    .unwrap();
+
+    let mut buf = [0; 32];
+    assert_eq!(second.read(384 * 256, &mut buf), Err(Error::INVALID));
+    assert_eq!(second.read(384 * 256 - 16, &mut buf), Err(Error::INVALID));
+    assert_eq!(second.write(usize::MAX - 8, &buf), Err(Error::INVALID));
+    assert_eq!(second.erase(383 * 256, 512), Err(Error::INVALID));
+    assert_eq!(second.read(384 * 256 - 32, &mut buf), Ok(32));
+
+    let mut first = Partition::<_, 128>::new(second.into_inner(), 0);
+    let name: heapless::Vec<u8, 8> =
+        Filesystem::mount_and_then(&mut first, |fs| fs.read(path!("name"))).unwrap();
+    assert_eq!(name, b"first");
+}
+
+#[test]
+#[cfg(feature = "alloc")]
+fn owned_files() {
+    use crate::{fs::FileOpenFlags, object_safe::DynFilesystemAlloc};
+
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        let fs: &dyn DynFilesystemAlloc = fs;
+        assert_eq!(
+            fs.open_file(path!("missing")).err(),
+            Some(Error::NO_SUCH_ENTRY)
+        );
+
+        // several files can be open at the same time and outlive the calls that opened them
+        let files: std::vec::Vec<_> = [path!("a"), path!("b"), path!("c")]
+            .into_iter()
+            .map(|path| fs.create_file(path))
+            .collect::<Result<_>>()?;
+        for (i, file) in files.iter().enumerate() {
+            file.write_all(&[i as u8; 100])?;
+        }
+        let mut files = files.into_iter();
+        files.next().unwrap().close()?;
+        // dropping the other files closes them
+        drop(files);
+
+        for (i, path) in [path!("a"), path!("b"), path!("c")].into_iter().enumerate() {
+            let file = fs.open_file(path)?;
+            assert_eq!(file.len()?, 100);
+            let mut buf = [0; 100];
+            file.read_exact(&mut buf)?;
+            assert_eq!(buf, [i as u8; 100]);
+        }
+
+        let flags = FileOpenFlags::WRITE | FileOpenFlags::APPEND;
+        let file = fs.open_file_with_flags(flags, path!("a"))?;
+        file.write_all(b"tail")?;
+        file.close()?;
+        assert_eq!(fs.metadata(path!("a"))?.len(), 104);
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn dyn_filesystem_collect() {
+    #[derive(Default)]
+    struct Buffer<const N: usize>(heapless::Vec<u8, N>);
+
+    impl<const N: usize> AsRef<[u8]> for Buffer<N> {
+        fn as_ref(&self) -> &[u8] {
+            &self.0
+        }
+    }
+
+    impl<const N: usize> AsMut<[u8]> for Buffer<N> {
+        fn as_mut(&mut self) -> &mut [u8] {
+            &mut self.0
+        }
+    }
+
+    impl<const N: usize> littlefs2_core::Vec for Buffer<N> {
+        fn resize_to_capacity(&mut self) {
+            self.0.resize_default(N).unwrap();
+        }
+
+        fn truncate(&mut self, n: usize) {
+            self.0.truncate(n);
+        }
+    }
+
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        let fs: &dyn DynFilesystem = fs;
+        fs.create_dir_all(path!("/tmp/sub"))?;
+        fs.write(path!("/tmp/a"), b"a")?;
+        fs.write(path!("/tmp/sub/b"), b"b")?;
+        fs.write(path!("/tmp/keep"), b"keep")?;
+        fs.set_attribute(path!("/tmp/keep"), 1, b"one")?;
+        fs.set_attribute(path!("/tmp/keep"), 7, b"seven")?;
+
+        let value: Option<Buffer<8>> = fs.read_attribute(path!("/tmp/keep"), 7)?;
+        assert_eq!(value.unwrap().as_ref(), b"seven");
+        let value: Option<Buffer<2>> = fs.read_attribute(path!("/tmp/keep"), 7)?;
+        assert_eq!(value.unwrap().as_ref(), b"se");
+        let value: Option<Buffer<8>> = fs.read_attribute(path!("/tmp/keep"), 2)?;
+        assert!(value.is_none());
+
+        let sizes: std::vec::Vec<(u8, usize)> =
+            fs.collect_attributes(path!("/tmp/keep"), |id, data| Ok((id, data.len())))?;
+        assert_eq!(sizes, [(1, 3), (7, 5)]);
+
+        let removed: std::vec::Vec<PathBuf> = fs
+            .remove_dir_all_where_collect(path!("/tmp"), &|entry| {
+                entry.file_name() != path!("keep")
+            })?;
+        let mut removed: std::vec::Vec<&str> = removed.iter().map(|path| path.as_str()).collect();
+        removed.sort();
+        assert_eq!(removed, ["/tmp/a", "/tmp/sub", "/tmp/sub/b"]);
+        assert!(fs.exists(path!("/tmp/keep")));
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+#[cfg(feature = "alloc")]
+fn once_callbacks() {
+    use crate::object_safe::DynFilesystemAlloc;
+
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        let fs: &dyn DynFilesystemAlloc = fs;
+        let data: std::vec::Vec<u8> = (0..=255).collect();
+        fs.create_file_and_then_once(path!("data"), move |file| file.write_all(&data))?;
+
+        let mut data = std::vec::Vec::new();
+        let len = fs.open_file_and_then_once(path!("data"), |file| {
+            data.resize(file.len()?, 0);
+            file.read_exact(&mut data)?;
+            Ok(data.len())
+        })?;
+        assert_eq!(len, 256);
+        assert!(data.iter().copied().eq(0..=255));
+
+        // the builder is moved into the callback and returned from it
+        let names = std::vec::Vec::new();
+        let names = fs.read_dir_and_then_once(path!("/"), move |entries| {
+            let mut names = names;
+            for entry in entries {
+                names.push(entry?.file_name().to_string());
+            }
+            Ok(names)
+        })?;
+        assert_eq!(names, [".", "..", "data"]);
+
+        assert_eq!(
+            fs.open_file_and_then_once(path!("missing"), |_| Ok(())),
+            Err(Error::NO_SUCH_ENTRY)
+        );
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn read_dir_pages() {
+    use littlefs2_core::DirIterationTell;
+
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        fs.create_dir(path!("/dir"))?;
+        for i in 0..10 {
+            let path = PathBuf::try_from(std::format!("/dir/{i}").as_str()).unwrap();
+            fs.write(&path, b"")?;
+        }
+
+        // list the directory in pages of three entries
+        let fs: &dyn DynFilesystem = fs;
+        let mut names = std::vec::Vec::new();
+        let mut tell = DirIterationTell::default();
+        loop {
+            let page: std::vec::Vec<_> =
+                fs.read_dir_from_and_then(path!("/dir"), tell, &mut |entries| {
+                    entries.take(3).collect()
+                })?;
+            let Some((_, last)) = page.last() else {
+                break;
+            };
+            tell = *last;
+            names.extend(page.iter().map(|(entry, _)| entry.file_name().to_string()));
+        }
+        let expected: std::vec::Vec<_> = [".", ".."]
+            .into_iter()
+            .map(std::string::String::from)
+            .chain((0..10).map(|i| std::format!("{i}")))
+            .collect();
+        assert_eq!(names, expected);
+
+        // the raw value can be passed to a client and back
+        let tell = fs.read_dir_from_and_then(
+            path!("/dir"),
+            DirIterationTell::default(),
+            &mut |entries| Ok(entries.nth(4).unwrap()?.1.into_raw()),
+        )?;
+        let tell = DirIterationTell::from_raw(tell);
+        let next = fs.read_dir_from_and_then(path!("/dir"), tell, &mut |entries| {
+            Ok(entries.next().unwrap()?.0)
+        })?;
+        assert_eq!(next.file_name(), path!("3"));
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+#[cfg(feature = "sync")]
+fn shared_filesystem() {
+    use crate::sync::{RawMutex, SharedFilesystem};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static LOCKS: AtomicUsize = AtomicUsize::new(0);
+
+    struct CountingMutex(std::sync::Mutex<()>);
+
+    unsafe impl RawMutex for CountingMutex {
+        fn lock<R>(&self, f: impl FnOnce() -> R) -> R {
+            let _guard = self.0.lock().unwrap();
+            LOCKS.fetch_add(1, Ordering::Relaxed);
+            f()
+        }
+    }
+
+    fn assert_sync<T: Send + Sync>(_: &T) {}
+
+    let mut backend = OtherRam::default();
+    let storage = OtherRamStorage::new(&mut backend);
+    let fs = SharedFilesystem::with_mutex(storage, CountingMutex(std::sync::Mutex::new(())));
+    assert_sync(&fs);
+    assert_eq!(fs.with(|_| Ok(())), Err(Error::INVALID));
+    fs.format().unwrap();
+    fs.mount().unwrap();
+    assert!(fs.is_mounted());
+
+    std::thread::scope(|scope| {
+        for i in 0..4 {
+            let fs = &fs;
+            scope.spawn(move || {
+                let path = PathBuf::try_from(std::format!("/{i}").as_str()).unwrap();
+                for _ in 0..8 {
+                    fs.with(|fs| fs.append(&path, &[i; 16])).unwrap();
+                }
+            });
+        }
+    });
+    for i in 0..4 {
+        let path = PathBuf::try_from(std::format!("/{i}").as_str()).unwrap();
+        let data: heapless::Vec<u8, 256> = fs.with(|fs| fs.read(&path)).unwrap();
+        assert_eq!(data, [i; 128]);
+    }
+    fs.unmount().unwrap();
+    assert!(!fs.is_mounted());
+    assert_eq!(LOCKS.load(Ordering::Relaxed), 4 * 8 + 4 + 6);
+}
+
+#[test]
+fn storage_references() {
+    use crate::instrument::CountingStorage;
+    use driver::Partition;
+
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    // wrappers can borrow the storage instead of consuming it
+    let mut counting = CountingStorage::<_, 128>::new(Partition::<_, 128>::new(&mut storage, 0));
+    Filesystem::format(&mut counting).unwrap();
+    assert!(counting.totals().erases > 0);
+    Filesystem::format(&mut Partition::<_, 128>::new(&mut storage, 128)).unwrap();
+    assert!(Filesystem::is_mountable(&mut Partition::<_, 128>::new(
+        &mut storage,
+        0
+    )));
+}
+
+#[test]
+#[cfg(feature = "alloc")]
+fn boxed_storage() {
+    let mut backend = OtherRam::default();
+    let mut boxed = std::boxed::Box::new(OtherRamStorage::new(&mut backend));
+    Filesystem::format(&mut boxed).unwrap();
+    Filesystem::mount_and_then(&mut boxed, |fs| fs.write(path!("file"), b"boxed")).unwrap();
+    let contents: heapless::Vec<u8, 5> =
+        Filesystem::mount_and_then(&mut *boxed, |fs| fs.read(path!("file"))).unwrap();
+    assert_eq!(contents, b"boxed");
+}
+
+#[test]
+#[should_panic]
+fn partition_out_of_bounds() {
+    let mut backend = OtherRam::default();
+    let storage = OtherRamStorage::new(&mut backend);
+    driver::Partition::<_, 128>::new(storage, 400);
+}
+
+/// NOR flash with 1 KiB sectors that checks the alignment of all accesses.
+#[cfg(feature = "embedded-storage")]
+struct NorFlashMock {
+    data: std::vec::Vec<u8>,
+    erases: usize,
+}
+
+#[cfg(feature = "embedded-storage")]
+mod nor_flash_mock {
+    use embedded_storage::nor_flash::{
+        check_erase, check_read, check_write, ErrorType, NorFlash, NorFlashErrorKind, ReadNorFlash,
+    };
+
+    impl ErrorType for super::NorFlashMock {
+        type Error = NorFlashErrorKind;
+    }
+
+    impl ReadNorFlash for super::NorFlashMock {
+        const READ_SIZE: usize = 4;
+
+        fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+            check_read(self, offset, bytes.len())?;
+            let offset = offset as usize;
+            bytes.copy_from_slice(&self.data[offset..offset + bytes.len()]);
+            Ok(())
+        }
+
+        fn capacity(&self) -> usize {
+            self.data.len()
+        }
+    }
+
+    impl NorFlash for super::NorFlashMock {
+        const WRITE_SIZE: usize = 16;
+        const ERASE_SIZE: usize = 1024;
+
+        fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+            check_erase(self, from, to)?;
+            self.data[from as usize..to as usize].fill(0xff);
+            self.erases += 1;
+            Ok(())
+        }
+
+        fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+            check_write(self, offset, bytes.len())?;
+            // programming can only clear bits
+            for (byte, new) in self.data[offset as usize..].iter_mut().zip(bytes) {
+                *byte &= new;
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(feature = "embedded-storage-async")]
+mod async_nor_flash_mock {
+    use embedded_storage::nor_flash::{NorFlash as _, ReadNorFlash as _};
+    use embedded_storage_async::nor_flash::{NorFlash, ReadNorFlash};
+
+    /// Async NOR flash whose operations are pending once before they complete.
+    pub struct AsyncNorFlashMock(pub super::NorFlashMock);
+
+    impl embedded_storage_async::nor_flash::ErrorType for AsyncNorFlashMock {
+        type Error = embedded_storage_async::nor_flash::NorFlashErrorKind;
+    }
+
+    impl ReadNorFlash for AsyncNorFlashMock {
+        const READ_SIZE: usize = 4;
+
+        async fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+            super::yield_once().await;
+            self.0.read(offset, bytes)
+        }
+
+        fn capacity(&self) -> usize {
+            self.0.capacity()
+        }
+    }
+
+    impl NorFlash for AsyncNorFlashMock {
+        const WRITE_SIZE: usize = 16;
+        const ERASE_SIZE: usize = 1024;
+
+        async fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+            super::yield_once().await;
+            self.0.erase(from, to)
+        }
+
+        async fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+            super::yield_once().await;
+            self.0.write(offset, bytes)
+        }
+    }
+}
+
+#[test]
+#[cfg(feature = "embedded-storage-async")]
+fn async_nor_flash_storage() {
+    use crate::{driver::AsyncNorFlashStorage, fs_busy_poll::Filesystem};
+    use async_nor_flash_mock::AsyncNorFlashMock;
+    use core::future::Future;
+
+    let flash = AsyncNorFlashMock(NorFlashMock {
+        data: std::vec![0; 64 * 1024],
+        erases: 0,
+    });
+    let mut storage = AsyncNorFlashStorage::<_, 64, consts::U64>::new(flash);
+    {
+        let future = async {
+            Filesystem::format(&mut storage).await?;
+            Filesystem::mount_and_then(&mut storage, async |fs| {
+                fs.write(path!("/file"), &[9; 2000]).await?;
+                assert_eq!(fs.read::<2000>(path!("/file")).await?[..], [9; 2000]);
+                Ok(())
+            })
+            .await?;
+            let mut buf = [0; 4];
+            assert_eq!(
+                driver::AsyncStorage::read(&mut storage, 1, &mut buf).await,
+                Err(Error::INVALID)
+            );
+            Ok::<_, Error>(())
+        };
+        let mut future = core::pin::pin!(future);
+        let mut context = core::task::Context::from_waker(core::task::Waker::noop());
+        loop {
+            if let core::task::Poll::Ready(result) = future.as_mut().poll(&mut context) {
+                result.unwrap();
+                break;
+            }
+        }
+    }
+    assert!(storage.inner().0.erases > 0);
+}
+
+#[test]
+#[cfg(feature = "embedded-storage")]
+fn nor_flash_storage() {
+    use driver::{NorFlashStorage, Storage as _};
+
+    let flash = NorFlashMock {
+        data: std::vec![0; 80 * 1024],
+        erases: 0,
+    };
+    let mut storage = NorFlashStorage::<_, 64, consts::U64>::new(flash);
+    assert_eq!(
+        <NorFlashStorage<NorFlashMock, 64> as driver::Storage>::BLOCK_SIZE,
+        1024
+    );
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        fs.create_dir(path!("dir"))?;
+        fs.write(path!("dir/file"), &[5; 3000])
+    })
+    .unwrap();
+    let data: heapless::Vec<u8, 3000> =
+        Filesystem::mount_and_then(&mut storage, |fs| fs.read(path!("dir/file"))).unwrap();
+    assert_eq!(data[..], [5; 3000]);
+
+    let mut buf = [0; 4];
+    assert_eq!(storage.read(2, &mut buf), Err(Error::INVALID));
+    assert_eq!(storage.read(1 << 32, &mut buf), Err(Error::INVALID));
+    assert_eq!(storage.erase(0, 100), Err(Error::INVALID));
+    assert!(storage.inner().erases > 0);
+    // the filesystem only uses the first 64 sectors
+    assert!(storage.into_inner().data[64 * 1024..]
+        .iter()
+        .all(|byte| *byte == 0));
+}
+
+#[test]
+#[should_panic]
+#[cfg(feature = "embedded-storage")]
+fn nor_flash_storage_too_small() {
+    let flash = NorFlashMock {
+        data: std::vec![0; 32 * 1024],
+        erases: 0,
+    };
+    driver::NorFlashStorage::<_, 64>::new(flash);
+}
+
+/// Byte-addressable memory that counts the written bytes.
+#[cfg(feature = "embedded-storage")]
+struct FramMock {
+    data: std::vec::Vec<u8>,
+    written: usize,
+}
+
+#[cfg(feature = "embedded-storage")]
+impl embedded_storage::ReadStorage for FramMock {
+    type Error = ();
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> core::result::Result<(), ()> {
+        let data = self.data.get(offset as usize..).ok_or(())?;
+        bytes.copy_from_slice(data.get(..bytes.len()).ok_or(())?);
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize {
+        self.data.len()
+    }
+}
+
+#[cfg(feature = "embedded-storage")]
+impl embedded_storage::Storage for FramMock {
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> core::result::Result<(), ()> {
+        let data = self.data.get_mut(offset as usize..).ok_or(())?;
+        data.get_mut(..bytes.len())
+            .ok_or(())?
+            .copy_from_slice(bytes);
+        self.written += bytes.len();
+        Ok(())
+    }
+}
+
+#[test]
+#[cfg(feature = "embedded-storage")]
+fn byte_storage() {
+    use driver::{ByteStorage, Storage as _};
+
+    // erasing must not be required, so start with arbitrary contents
+    let fram = FramMock {
+        data: (0..8192).map(|i| i as u8).collect(),
+        written: 0,
+    };
+    let mut storage = ByteStorage::<_, 128, 64>::new(fram);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        fs.create_dir(path!("dir"))?;
+        fs.write(path!("dir/file"), &[3; 1000])
+    })
+    .unwrap();
+    let data: heapless::Vec<u8, 1000> =
+        Filesystem::mount_and_then(&mut storage, |fs| fs.read(path!("dir/file"))).unwrap();
+    assert_eq!(data[..], [3; 1000]);
+
+    let mut buf = [0; 4];
+    assert_eq!(storage.erase(0, 128), Ok(128));
+    assert_eq!(storage.read(8190, &mut buf), Err(Error::IO));
+    assert!(storage.inner().written >= 1000);
+}
+
+#[test]
+fn wear_distribution() {
+    use crate::instrument::CountingStorage;
+
+    let mut backend = OtherRam::default();
+    let mut storage = CountingStorage::<_, 512>::new(OtherRamStorage::new(&mut backend));
+    Filesystem::format(&mut storage).unwrap();
+    storage.reset();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        for i in 0..200u8 {
+            fs.write(path!("file"), &[i; 1000])?;
+        }
+        Ok(())
+    })
+    .unwrap();
+
+    // the copy-on-write updates of the file cycle through all free blocks
+    let erased = storage.erase_counts().iter().filter(|n| **n > 0).count();
+    assert!(erased > 256, "only {erased} blocks erased");
+    let (least_worn, most_worn) = storage.erase_count_range();
+    assert!(least_worn <= 1);
+    assert!(most_worn < 100, "most worn block erased {most_worn} times");
+    let amplification = storage.totals().write_amplification(200 * 1000).unwrap();
+    assert!(amplification >= 1.0);
+}
+
+#[test]
+fn const_generic_ram_storage() {
+    use driver::{RamStorage, Storage as _};
+
+    fn roundtrip<const BLOCK_SIZE: usize, const BLOCK_COUNT: usize>() {
+        let mut storage = std::boxed::Box::new(RamStorage::<BLOCK_SIZE, BLOCK_COUNT>::new());
+        Filesystem::format(&mut *storage).unwrap();
+        Filesystem::mount_and_then(&mut *storage, |fs| {
+            assert_eq!(fs.total_blocks(), BLOCK_COUNT);
+            fs.write(path!("file"), &[1; 300])?;
+            assert_eq!(fs.read::<300>(path!("file"))?[..], [1; 300]);
+            Ok(())
+        })
+        .unwrap();
+    }
+    roundtrip::<128, 16>();
+    roundtrip::<512, 8>();
+
+    let mut storage = RamStorage::<128, 2, consts::U32, consts::U1, 4, 8, 0>::default();
+    assert_eq!(storage.as_bytes(), [0; 256]);
+    assert_eq!(storage.write(8, &[1; 8]), Ok(8));
+    let mut buf = [0; 16];
+    assert_eq!(storage.read(4, &mut buf), Ok(16));
+    assert_eq!(buf, [0, 0, 0, 0, 1, 1, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0]);
+    assert_eq!(storage.read(248, &mut buf), Err(Error::INVALID));
+    assert_eq!(storage.erase(128, 256), Err(Error::INVALID));
+    assert_eq!(storage.erase(0, 128), Ok(128));
+    assert!(storage.as_bytes().iter().all(|byte| *byte == 0));
+}
+
+/// Creates an empty directory for a test in the temporary directory of the host.
+#[cfg(feature = "std")]
+fn host_temp_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("littlefs2-{}-{name}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn file_storage() {
+    use crate::host::FileStorage;
+    use driver::Storage as _;
+
+    type Image = FileStorage<256, 64>;
+
+    let host = host_temp_dir("file-storage");
+    let path = host.join("image.lfs");
+    let mut storage = Image::create(&path).unwrap();
+    let mut buf = [0; 16];
+    assert_eq!(storage.read(100, &mut buf), Ok(16));
+    assert_eq!(buf, [0xff; 16]);
+    assert_eq!(storage.read(64 * 256 - 8, &mut buf), Err(Error::INVALID));
+
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        fs.create_dir(path!("dir"))?;
+        fs.write(path!("dir/file"), &[4; 2000])
+    })
+    .unwrap();
+    let len = std::fs::metadata(&path).unwrap().len();
+    assert!(len > 0 && len < 64 * 256);
+    drop(storage);
+
+    let mut storage = Image::open(&path).unwrap();
+    let data: heapless::Vec<u8, 2000> =
+        Filesystem::mount_and_then(&mut storage, |fs| fs.read(path!("dir/file"))).unwrap();
+    assert_eq!(data[..], [4; 2000]);
+    storage.pad_to_capacity().unwrap();
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), 64 * 256);
+    assert_eq!(storage.read(64 * 256 - 16, &mut buf), Ok(16));
+    assert_eq!(buf, [0xff; 16]);
+    assert_eq!(storage.erase(0, 256), Ok(256));
+    assert_eq!(storage.read(0, &mut buf), Ok(16));
+    assert_eq!(buf, [0xff; 16]);
+    drop(storage);
+
+    // an image with a larger geometry does not fit
+    assert_eq!(
+        FileStorage::<256, 32>::open(&path).err().unwrap().kind(),
+        std::io::ErrorKind::InvalidInput
+    );
+    std::fs::remove_dir_all(&host).unwrap();
+}
+
+#[test]
+#[cfg(feature = "mmap")]
+fn mmap_storage() {
+    use crate::host::MmapStorage;
+    use driver::Storage as _;
+
+    type Image = MmapStorage<256, 64>;
+
+    let host = host_temp_dir("mmap-storage");
+    let path = host.join("image.lfs");
+    let mut storage = Image::create(&path).unwrap();
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), 64 * 256);
+    assert!(storage.as_bytes().iter().all(|&byte| byte == 0xff));
+    let mut buf = [0; 16];
+    assert_eq!(storage.read(64 * 256 - 8, &mut buf), Err(Error::INVALID));
+    assert_eq!(storage.write(64 * 256, &buf), Err(Error::INVALID));
+
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| fs.write(path!("file"), &[4; 2000])).unwrap();
+    storage.flush().unwrap();
+    drop(storage);
+
+    // changes to a copy are not written back
+    let image = std::fs::read(&path).unwrap();
+    let mut storage = Image::open_copy(&path).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| fs.remove(path!("file"))).unwrap();
+    drop(storage);
+    assert_eq!(std::fs::read(&path).unwrap(), image);
+
+    let mut storage = Image::open(&path).unwrap();
+    let data: heapless::Vec<u8, 2000> =
+        Filesystem::mount_and_then(&mut storage, |fs| fs.read(path!("file"))).unwrap();
+    assert_eq!(data[..], [4; 2000]);
+    drop(storage);
+
+    assert_eq!(
+        MmapStorage::<256, 32>::open(&path).err().unwrap().kind(),
+        std::io::ErrorKind::InvalidInput
+    );
+    std::fs::remove_dir_all(&host).unwrap();
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn image_builder() {
+    use crate::{driver::RomStorage, tools::ImageBuilder};
+
+    let host = host_temp_dir("image-builder");
+    std::fs::create_dir_all(host.join("sub/empty")).unwrap();
+    std::fs::write(host.join("a.txt"), b"a").unwrap();
+    std::fs::write(host.join("sub/b.bin"), [2; 1000]).unwrap();
+    #[cfg(unix)]
+    let attributes = xattr::set(host.join("a.txt"), "user.littlefs.7", b"seven").is_ok();
+
+    let mut builder = ImageBuilder::<256, 64>::new().unwrap();
+    builder.add_dir_all(&host, path!("/assets")).unwrap();
+    builder.add_file(path!("/etc/version"), b"1").unwrap();
+    builder.set_attribute(path!("/etc"), 1, b"dir").unwrap();
+    let image_path = host.join("image.lfs");
+    builder.write_to(&image_path).unwrap();
+    let image = builder.into_image();
+    assert_eq!(std::fs::read(&image_path).unwrap(), image);
+    std::fs::remove_dir_all(&host).unwrap();
+
+    let mut storage = RomStorage::<'_, 256, 64>::new(&image);
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        assert_eq!(&fs.read::<1>(path!("/assets/a.txt"))?, b"a");
+        assert_eq!(fs.read::<1000>(path!("/assets/sub/b.bin"))?[..], [2; 1000]);
+        assert!(fs.metadata(path!("/assets/sub/empty"))?.is_dir());
+        assert_eq!(&fs.read::<1>(path!("/etc/version"))?, b"1");
+        let mut buffer = [0; 8];
+        let attribute = fs.attribute(path!("/etc"), 1, &mut buffer)?.unwrap();
+        assert_eq!(attribute.data(), b"dir");
+        #[cfg(unix)]
+        if attributes {
+            let attribute = fs
+                .attribute(path!("/assets/a.txt"), 7, &mut buffer)?
+                .unwrap();
+            assert_eq!(attribute.data(), b"seven");
+        }
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn extract() {
+    let mut backend = OtherRam::default();
+    let mut storage = OtherRamStorage::new(&mut backend);
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        fs.create_dir_all(path!("/a/empty"))?;
+        fs.write(path!("/a/file"), &[1; 700])?;
+        fs.write(path!("/top"), b"top")?;
+        fs.set_attribute(path!("/top"), 3, b"three")?;
+        fs.set_attribute(path!("/"), 0, b"root")
+    })
+    .unwrap();
+
+    let host = host_temp_dir("extract");
+    crate::tools::extract(&mut storage, &host.join("out")).unwrap();
+    let out = host.join("out");
+    assert_eq!(std::fs::read(out.join("a/file")).unwrap(), [1; 700]);
+    assert_eq!(std::fs::read(out.join("top")).unwrap(), b"top");
+    assert!(out.join("a/empty").is_dir());
+    #[cfg(unix)]
+    if xattr::set(&host, "user.littlefs.test", b"").is_ok() {
+        assert_eq!(
+            xattr::get(out.join("top"), "user.littlefs.3").unwrap(),
+            Some(b"three".to_vec())
+        );
+        assert_eq!(
+            xattr::get(&out, "user.littlefs.0").unwrap(),
+            Some(b"root".to_vec())
+        );
+    }
+    std::fs::remove_dir_all(&host).unwrap();
+}
+
+struct Syncs<'a> {
+    storage: OtherRamStorage<'a>,
+    syncs: usize,
+}
+
+impl driver::Storage for Syncs<'_> {
+    const READ_SIZE: usize = <OtherRamStorage<'static> as driver::Storage>::READ_SIZE;
+    const WRITE_SIZE: usize = <OtherRamStorage<'static> as driver::Storage>::WRITE_SIZE;
+    const BLOCK_SIZE: usize = <OtherRamStorage<'static> as driver::Storage>::BLOCK_SIZE;
+    const BLOCK_COUNT: usize = <OtherRamStorage<'static> as driver::Storage>::BLOCK_COUNT;
+    type CACHE_SIZE = <OtherRamStorage<'static> as driver::Storage>::CACHE_SIZE;
+    type LOOKAHEAD_SIZE = <OtherRamStorage<'static> as driver::Storage>::LOOKAHEAD_SIZE;
+
+    fn read(&mut self, off: usize, buf: &mut [u8]) -> Result<usize> {
+        self.storage.read(off, buf)
+    }
+
+    fn write(&mut self, off: usize, data: &[u8]) -> Result<usize> {
+        self.storage.write(off, data)
+    }
+
+    fn erase(&mut self, off: usize, len: usize) -> Result<usize> {
+        self.storage.erase(off, len)
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        self.syncs += 1;
+        Ok(())
+    }
+}
+
+#[test]
+fn storage_sync() {
+    let mut backend = OtherRam::default();
+    let mut storage = Syncs {
+        storage: OtherRamStorage::new(&mut backend),
+        syncs: 0,
+    };
+    Filesystem::format(&mut storage).unwrap();
+    let after_format = storage.syncs;
+    assert!(after_format > 0);
+
+    let mut cached = driver::BlockCache::<_, 2>::new(driver::Retry::new(&mut storage, 3));
+    Filesystem::mount_and_then(&mut cached, |fs| {
+        fs.create_file_and_then(path!("file"), |file| {
+            file.write(b"data")?;
+            file.sync()
+        })
+    })
+    .unwrap();
+    assert!(storage.syncs > after_format);
+}
+
+struct AlignedWrites<'a>(OtherRamStorage<'a>);
+
+impl driver::Storage for AlignedWrites<'_> {
+    const READ_SIZE: usize = <OtherRamStorage<'static> as driver::Storage>::READ_SIZE;
+    const WRITE_SIZE: usize = <OtherRamStorage<'static> as driver::Storage>::WRITE_SIZE;
+    const BLOCK_SIZE: usize = <OtherRamStorage<'static> as driver::Storage>::BLOCK_SIZE;
+    const BLOCK_COUNT: usize = <OtherRamStorage<'static> as driver::Storage>::BLOCK_COUNT;
+    type CACHE_SIZE = <OtherRamStorage<'static> as driver::Storage>::CACHE_SIZE;
+    type LOOKAHEAD_SIZE = <OtherRamStorage<'static> as driver::Storage>::LOOKAHEAD_SIZE;
+
+    fn read(&mut self, off: usize, buf: &mut [u8]) -> Result<usize> {
+        self.0.read(off, buf)
+    }
+
+    fn write(&mut self, off: usize, data: &[u8]) -> Result<usize> {
+        // writes always go through one of the caches
+        assert!((data.as_ptr() as usize).is_multiple_of(crate::consts::BUFFER_ALIGNMENT));
+        self.0.write(off, data)
+    }
+
+    fn erase(&mut self, off: usize, len: usize) -> Result<usize> {
+        self.0.erase(off, len)
+    }
+}
+
+#[test]
+fn buffer_alignment() {
+    let mut backend = OtherRam::default();
+    let mut storage = AlignedWrites(OtherRamStorage::new(&mut backend));
+    Filesystem::format(&mut storage).unwrap();
+    Filesystem::mount_and_then(&mut storage, |fs| {
+        // unaligned source data is copied into the file cache
+        let data = [1; 1001];
+        fs.write(path!("file"), &data[1..])?;
+        fs.create_dir(path!("dir"))
+    })
+    .unwrap();
+}
+
+#[cfg(not(any(
+    feature = "read-only",
+    feature = "path-validator",
+    feature = "protected-paths",
+    feature = "change-tracking",
+    feature = "usage-cache",
+    feature = "timestamps"
+)))]
+#[test]
+fn filesystem_size() {
+    use core::cell::RefCell;
+    use core::mem::size_of;
+
+    // without the optional features, the filesystem only borrows the allocation and the storage
+    type Storage = OtherRamStorage<'static>;
+    assert_eq!(
+        size_of::<Filesystem<'static, Storage>>(),
+        size_of::<(RefCell<&mut Allocation<Storage>>, &mut Storage)>()
+    );
+}
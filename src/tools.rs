@@ -0,0 +1,370 @@
+//! Host-side tools for littlefs images.
+//!
+//! These tools require the `std` feature.  They are intended for build pipelines and for
+//! analyzing images on a host, for example to generate the factory contents of a partition with
+//! [`ImageBuilder`][], or to extract the contents of an image dumped from a device with
+//! [`extract`][].
+//!
+//! On Unix systems, the attributes of littlefs files and directories are mapped to the extended
+//! attributes `user.littlefs.<id>` of the host files and directories, see [`HOST_ATTRIBUTE_PREFIX`][].
+//! On other systems, and on host filesystems without support for extended attributes, attributes
+//! are not copied.
+
+use core::{fmt, marker::PhantomData};
+use std::{format, fs, io, path::PathBuf as HostPathBuf, vec, vec::Vec};
+
+use generic_array::{typenum::consts, ArrayLength};
+
+use crate::{
+    driver::Storage,
+    fs::Filesystem,
+    object_safe::{DynFilesystem, DynStorage},
+    path::{Path, PathBuf},
+};
+
+/// The prefix of the names of the extended attributes that store littlefs attributes on the host.
+///
+/// The littlefs attribute with the ID `id` is stored in the extended attribute with the name
+/// `user.littlefs.<id>`, for example `user.littlefs.42`.
+pub const HOST_ATTRIBUTE_PREFIX: &str = "user.littlefs.";
+
+/// The error type for the host-side tools.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// An operation on the host filesystem failed.
+    Host(io::Error),
+    /// An operation on the littlefs filesystem failed.
+    Fs(crate::io::Error),
+    /// A host path cannot be represented as a littlefs path, for example because it contains
+    /// non-ASCII characters or is too long.
+    InvalidPath(HostPathBuf),
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Self::Host(error)
+    }
+}
+
+impl From<crate::io::Error> for Error {
+    fn from(error: crate::io::Error) -> Self {
+        Self::Fs(error)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Host(error) => write!(f, "host filesystem error: {error}"),
+            Self::Fs(error) => write!(f, "littlefs error {}", error.code()),
+            Self::InvalidPath(path) => write!(f, "invalid path: {}", path.display()),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Host(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+/// In-memory storage for [`ImageBuilder`][].
+struct ImageStorage<const BLOCK_SIZE: usize, const BLOCK_COUNT: usize, CacheSize, LookaheadSize> {
+    data: Vec<u8>,
+    _sizes: PhantomData<(CacheSize, LookaheadSize)>,
+}
+
+impl<const BLOCK_SIZE: usize, const BLOCK_COUNT: usize, CacheSize, LookaheadSize> Storage
+    for ImageStorage<BLOCK_SIZE, BLOCK_COUNT, CacheSize, LookaheadSize>
+where
+    CacheSize: ArrayLength<u8>,
+    LookaheadSize: ArrayLength<u64>,
+{
+    const READ_SIZE: usize = 1;
+    const WRITE_SIZE: usize = 1;
+    const BLOCK_SIZE: usize = BLOCK_SIZE;
+    const BLOCK_COUNT: usize = BLOCK_COUNT;
+    type CACHE_SIZE = CacheSize;
+    type LOOKAHEAD_SIZE = LookaheadSize;
+
+    fn read(&mut self, off: usize, buf: &mut [u8]) -> crate::io::Result<usize> {
+        let data = self
+            .data
+            .get(off..off + buf.len())
+            .ok_or(crate::io::Error::INVALID)?;
+        buf.copy_from_slice(data);
+        Ok(buf.len())
+    }
+
+    fn write(&mut self, off: usize, data: &[u8]) -> crate::io::Result<usize> {
+        self.data
+            .get_mut(off..off + data.len())
+            .ok_or(crate::io::Error::INVALID)?
+            .copy_from_slice(data);
+        Ok(data.len())
+    }
+
+    fn erase(&mut self, off: usize, len: usize) -> crate::io::Result<usize> {
+        self.data
+            .get_mut(off..off + len)
+            .ok_or(crate::io::Error::INVALID)?
+            .fill(0xff);
+        Ok(len)
+    }
+}
+
+/// Builds a littlefs image from files on the host.
+///
+/// The geometry of the image is set with the same parameters as for
+/// [`RomStorage`](crate::driver::RomStorage), so the image can be bundled with a firmware and
+/// read with a `RomStorage` with the same parameters.  It is written with read and write sizes of
+/// one byte.  Unused blocks are erased to `0xff`.
+///
+/// ```no_run
+///# use littlefs2::{driver::RomStorage, fs::Filesystem, path, tools::ImageBuilder};
+///# fn f() -> littlefs2::tools::Result<()> {
+/// let mut builder = ImageBuilder::<512, 64>::new()?;
+/// builder.add_dir_all("assets".as_ref(), path!("/www"))?;
+/// builder.add_file(path!("/version"), b"1.2.3")?;
+/// builder.write_to("assets.lfs".as_ref())?;
+///
+/// let image = builder.into_image();
+/// let mut storage = RomStorage::<'_, 512, 64>::new(&image);
+/// Filesystem::mount_and_then(&mut storage, |fs| {
+///     assert_eq!(&fs.read::<5>(path!("/version"))?, b"1.2.3");
+///     Ok(())
+/// })?;
+///# Ok(())
+///# }
+/// ```
+pub struct ImageBuilder<
+    const BLOCK_SIZE: usize,
+    const BLOCK_COUNT: usize,
+    CacheSize = consts::U128,
+    LookaheadSize = consts::U1,
+> {
+    storage: ImageStorage<BLOCK_SIZE, BLOCK_COUNT, CacheSize, LookaheadSize>,
+}
+
+impl<const BLOCK_SIZE: usize, const BLOCK_COUNT: usize, CacheSize, LookaheadSize>
+    ImageBuilder<BLOCK_SIZE, BLOCK_COUNT, CacheSize, LookaheadSize>
+where
+    CacheSize: ArrayLength<u8>,
+    LookaheadSize: ArrayLength<u64>,
+{
+    /// Creates a builder with an empty, formatted image.
+    pub fn new() -> Result<Self> {
+        let mut storage = ImageStorage {
+            data: vec![0xff; BLOCK_SIZE * BLOCK_COUNT],
+            _sizes: PhantomData,
+        };
+        Filesystem::format(&mut storage)?;
+        Ok(Self { storage })
+    }
+
+    /// Copies the directory `host_dir` with all files and directories below it to `target`.
+    ///
+    /// `target` and its parents are created if they do not exist.  Existing files are replaced.
+    /// The entries of every directory are copied in the order of their names, so the same input
+    /// always results in the same image.  Symbolic links are followed, other special files are
+    /// skipped.
+    pub fn add_dir_all(&mut self, host_dir: &std::path::Path, target: &Path) -> Result<()> {
+        Filesystem::mount_and_then(&mut self.storage, |fs| {
+            fs.create_dir_all(target)?;
+            Ok(copy_dir_to_fs(fs, host_dir, target))
+        })?
+    }
+
+    /// Writes `data` to the file `target`, creating its parent directories if necessary.
+    pub fn add_file(&mut self, target: &Path, data: &[u8]) -> Result<()> {
+        Filesystem::mount_and_then(&mut self.storage, |fs| {
+            if let Some(parent) = target.parent() {
+                fs.create_dir_all(&parent)?;
+            }
+            fs.write(target, data)
+        })?;
+        Ok(())
+    }
+
+    /// Sets the attribute `id` of the file or directory `target`.
+    pub fn set_attribute(&mut self, target: &Path, id: u8, data: &[u8]) -> Result<()> {
+        Filesystem::mount_and_then(&mut self.storage, |fs| fs.set_attribute(target, id, data))?;
+        Ok(())
+    }
+
+    /// Returns the image.
+    pub fn image(&self) -> &[u8] {
+        &self.storage.data
+    }
+
+    pub fn into_image(self) -> Vec<u8> {
+        self.storage.data
+    }
+
+    /// Writes the image to the host file `path`, replacing it if it exists.
+    pub fn write_to(&self, path: &std::path::Path) -> Result<()> {
+        fs::write(path, self.image())?;
+        Ok(())
+    }
+}
+
+/// Converts the name of a host directory entry to a littlefs path below `dir`.
+fn child_path(dir: &Path, host_path: &std::path::Path) -> Result<PathBuf> {
+    let invalid = || Error::InvalidPath(host_path.into());
+    let name = host_path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(invalid)?;
+    let name = PathBuf::try_from(name).map_err(|_| invalid())?;
+    Ok(dir.join(&name))
+}
+
+fn copy_dir_to_fs<S: Storage>(
+    fs: &Filesystem<'_, S>,
+    host_dir: &std::path::Path,
+    target: &Path,
+) -> Result<()> {
+    copy_host_attributes(fs, host_dir, target)?;
+    let mut entries = fs::read_dir(host_dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    entries.sort();
+    for host_path in entries {
+        let path = child_path(target, &host_path)?;
+        let metadata = fs::metadata(&host_path)?;
+        if metadata.is_dir() {
+            if !fs.exists(&path) {
+                fs.create_dir(&path)?;
+            }
+            copy_dir_to_fs(fs, &host_path, &path)?;
+        } else if metadata.is_file() {
+            fs.write(&path, &fs::read(&host_path)?)?;
+            copy_host_attributes(fs, &host_path, &path)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn copy_host_attributes<S: Storage>(
+    fs: &Filesystem<'_, S>,
+    host_path: &std::path::Path,
+    path: &Path,
+) -> Result<()> {
+    // Listing fails if the host filesystem does not support extended attributes.
+    let Ok(names) = xattr::list(host_path) else {
+        return Ok(());
+    };
+    for name in names {
+        let Some(id) = name
+            .to_str()
+            .and_then(|name| name.strip_prefix(HOST_ATTRIBUTE_PREFIX))
+            .and_then(|id| id.parse::<u8>().ok())
+        else {
+            continue;
+        };
+        if let Some(data) = xattr::get(host_path, &name)? {
+            fs.set_attribute(path, id, &data)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn copy_host_attributes<S: Storage>(
+    _fs: &Filesystem<'_, S>,
+    _host_path: &std::path::Path,
+    _path: &Path,
+) -> Result<()> {
+    Ok(())
+}
+
+/// Copies all files and directories of the littlefs image on `image` to the host directory
+/// `out_dir`.
+///
+/// `out_dir` is created if it does not exist, and existing host files are replaced.  The
+/// attributes of the files and directories are stored as extended attributes, see the
+/// [module documentation](self); the attributes of the root directory are set on `out_dir`.
+///
+/// ```no_run
+///# use littlefs2::{driver::RomStorage, tools};
+///# fn f() -> littlefs2::tools::Result<()> {
+/// let image = std::fs::read("dump.lfs")?;
+/// let mut storage = RomStorage::<'_, 4096, 256>::new(&image);
+/// tools::extract(&mut storage, "dump".as_ref())?;
+///# Ok(())
+///# }
+/// ```
+pub fn extract(image: &mut dyn DynStorage, out_dir: &std::path::Path) -> Result<()> {
+    image.mount_and_then(&mut |fs| Ok(extract_fs(fs, out_dir)))?
+}
+
+fn extract_fs(fs: &dyn DynFilesystem, out_dir: &std::path::Path) -> Result<()> {
+    let mut entries = Vec::new();
+    fs.walk_dir_and_then(crate::path!("/"), &mut |walk| {
+        for entry in walk {
+            let entry = entry?;
+            entries.push((PathBuf::from(entry.path()), entry.file_type().is_dir()));
+        }
+        Ok(())
+    })?;
+
+    fs::create_dir_all(out_dir)?;
+    copy_fs_attributes(fs, crate::path!("/"), out_dir)?;
+    for (path, is_dir) in entries {
+        let host_path = out_dir.join(path.as_str().trim_start_matches('/'));
+        if is_dir {
+            fs::create_dir_all(&host_path)?;
+        } else {
+            let data = fs.open_file_and_then(&path, &mut |file| {
+                let mut data = Vec::new();
+                let mut buf = [0; 512];
+                loop {
+                    let n = file.read(&mut buf)?;
+                    if n == 0 {
+                        return Ok(data);
+                    }
+                    data.extend_from_slice(&buf[..n]);
+                }
+            })?;
+            fs::write(&host_path, data)?;
+        }
+        copy_fs_attributes(fs, &path, &host_path)?;
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn copy_fs_attributes(
+    fs: &dyn DynFilesystem,
+    path: &Path,
+    host_path: &std::path::Path,
+) -> Result<()> {
+    let mut attributes = Vec::new();
+    fs.attributes(path, &mut |id, data| {
+        attributes.push((id, data.to_vec()));
+        Ok(())
+    })?;
+    for (id, data) in attributes {
+        let name = format!("{HOST_ATTRIBUTE_PREFIX}{id}");
+        // Like the listing when building an image, this fails if the host filesystem does not
+        // support extended attributes.
+        xattr::set(host_path, name, &data).ok();
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn copy_fs_attributes(
+    _fs: &dyn DynFilesystem,
+    _path: &Path,
+    _host_path: &std::path::Path,
+) -> Result<()> {
+    Ok(())
+}